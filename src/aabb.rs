@@ -1,3 +1,5 @@
+use std::fmt;
+
 use crate::{interval::Interval, math::vec3::Vec3, ray::Ray};
 
 #[derive(Debug, Clone, Copy)]
@@ -19,14 +21,48 @@ pub struct AABB {
 }
 
 impl AABB {
+    /// Box with the given slabs, each padded to a minimum thickness. Use this (or `construct`)
+    /// for shapes that can be flat, such as quads and triangles, whose zero-thickness boxes
+    /// rays would otherwise slip past.
     pub fn new(x: Interval, y: Interval, z: Interval) -> Self {
-        let mut bbox = AABB { x, y, z };
+        let mut bbox = AABB::new_exact(x, y, z);
         bbox.pad_to_minimum_size();
         bbox
     }
 
+    /// Box with exactly the given slabs, for shapes that always have volume and for corners
+    /// that only get merged into a larger box. Padding those would inflate thin but solid
+    /// slabs and the boxes built from them.
+    pub fn new_exact(x: Interval, y: Interval, z: Interval) -> Self {
+        debug_assert!(
+            x.is_valid() && y.is_valid() && z.is_valid(),
+            "Inverted AABB interval: x {} y {} z {}",
+            x,
+            y,
+            z
+        );
+        AABB { x, y, z }
+    }
+
+    /// Box containing nothing, the identity for `combine`.
+    pub fn empty() -> Self {
+        AABB {
+            x: Interval::empty(),
+            y: Interval::empty(),
+            z: Interval::empty(),
+        }
+    }
+
+    /// Box spanned by two opposite corners, padded like `new`.
     pub fn construct(a: Vec3, b: Vec3) -> AABB {
-        let mut bbox = AABB {
+        let mut bbox = AABB::construct_exact(a, b);
+        bbox.pad_to_minimum_size();
+        bbox
+    }
+
+    /// Box spanned by two opposite corners, without padding; see `new_exact`.
+    pub fn construct_exact(a: Vec3, b: Vec3) -> AABB {
+        AABB {
             x: if a[0] <= b[0] {
                 Interval::new(a[0], b[0])
             } else {
@@ -42,11 +78,11 @@ impl AABB {
             } else {
                 Interval::new(b[2], a[2])
             },
-        };
-        bbox.pad_to_minimum_size();
-        bbox
+        }
     }
 
+    /// Smallest box around both boxes. It is never padded further, so merging flat boxes keeps
+    /// their own padding rather than adding to it.
     pub fn combine(a: &AABB, b: &AABB) -> AABB {
         AABB {
             x: Interval::combine(&a.x, &b.x),
@@ -64,14 +100,20 @@ impl AABB {
     }
 
     pub fn hit(&self, ray: &Ray, ray_t: Interval) -> bool {
+        self.hit_interval(ray, ray_t).is_some()
+    }
+
+    /// Clips `ray_t` against the three slabs and returns the parameters where the ray enters
+    /// and leaves the box, or `None` if it misses within `ray_t`.
+    pub fn hit_interval(&self, ray: &Ray, ray_t: Interval) -> Option<Interval> {
         let mut ray_t = ray_t;
         let ray_origin = ray.origin;
-        let ray_direction = ray.direction;
+        let inv_direction = ray.inv_direction();
 
         for axis in Axis::ALL {
             let idx = axis as usize;
             let ax = self.get_axis(axis);
-            let dir_inv = 1.0 / ray_direction[idx];
+            let dir_inv = inv_direction[idx];
 
             let t0 = (ax.min - ray_origin[idx]) * dir_inv;
             let t1 = (ax.max - ray_origin[idx]) * dir_inv;
@@ -93,10 +135,10 @@ impl AABB {
             }
 
             if ray_t.max <= ray_t.min {
-                return false;
+                return None;
             }
         }
-        true
+        Some(ray_t)
     }
 
     pub fn get_longest_axis(&self) -> Axis {
@@ -127,7 +169,184 @@ impl AABB {
     }
 }
 
+/// Writes the minimum and maximum corners, applying any precision to each component.
+impl fmt::Display for AABB {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let min = Vec3::new(self.x.min, self.y.min, self.z.min);
+        let max = Vec3::new(self.x.max, self.y.max, self.z.max);
+        match f.precision() {
+            Some(p) => write!(f, "{:.*} to {:.*}", p, min, p, max),
+            None => write!(f, "{} to {}", min, max),
+        }
+    }
+}
+
 pub trait HasAABB {
     fn get_aabb(&self) -> AABB;
     fn compute_aabb(&self) -> AABB;
+
+    /// False for shapes without a finite box, such as infinite planes, which have to be kept
+    /// out of BVHs.
+    fn is_bounded(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::Real;
+    use proptest::prelude::*;
+
+    fn point(extent: Real) -> impl Strategy<Value = Vec3> {
+        let coordinate = prop_oneof![9 => -extent..extent, 1 => Just(0.0 as Real)];
+        (coordinate.clone(), coordinate.clone(), coordinate)
+            .prop_map(|(x, y, z)| Vec3::new(x, y, z))
+    }
+
+    /// Entry and exit of `ray` through the box slabs in double precision, independent of
+    /// `hit_interval`. Rays parallel to a slab are inside it or miss it for every `t`; rays
+    /// lying in a face report an empty interval, which the grazing check then skips.
+    fn slab_interval(bbox: &AABB, ray: &Ray, ray_t: Interval) -> (f64, f64) {
+        let (mut t_min, mut t_max) = (ray_t.min as f64, ray_t.max as f64);
+        for axis in Axis::ALL {
+            let slab = bbox.get_axis(axis);
+            let origin = ray.origin[axis as usize] as f64;
+            let direction = ray.direction()[axis as usize] as f64;
+            if direction == 0.0 {
+                if origin == slab.min as f64 || origin == slab.max as f64 {
+                    // Sliding along a face, where hit or miss is a matter of convention
+                    return (0.0, 0.0);
+                }
+                if origin < slab.min as f64 || origin > slab.max as f64 {
+                    return (f64::INFINITY, f64::NEG_INFINITY);
+                }
+                continue;
+            }
+            let t0 = (slab.min as f64 - origin) / direction;
+            let t1 = (slab.max as f64 - origin) / direction;
+            t_min = t_min.max(t0.min(t1));
+            t_max = t_max.min(t0.max(t1));
+        }
+        (t_min, t_max)
+    }
+
+    proptest! {
+        #[test]
+        fn prop_hit_agrees_with_brute_force_slabs(
+            a in point(10.0),
+            b in point(10.0),
+            origin in point(20.0),
+            target in point(12.0),
+            t_start in prop_oneof![Just(0.0 as Real), 0.0 as Real..30.0],
+        ) {
+            let bbox = AABB::construct(a, b);
+            prop_assume!(target != origin);
+            let ray = Ray::new(origin, target - origin);
+            let ray_t = Interval::new(t_start, Real::MAX);
+            let (t_min, t_max) = slab_interval(&bbox, &ray, ray_t);
+            // Grazing rays are decided by rounding, so only clear hits and misses are compared
+            prop_assume!((t_max - t_min).abs() > 1e-3);
+
+            let hit = bbox.hit_interval(&ray, ray_t);
+            prop_assert_eq!(hit.is_some(), t_max > t_min, "box {:?}, slabs ({}, {})", bbox, t_min, t_max);
+            prop_assert_eq!(bbox.hit(&ray, ray_t), hit.is_some());
+            if let Some(t) = hit {
+                let eps = 1e-4 * t_max.abs().max(1.0);
+                prop_assert!((t.min as f64 - t_min).abs() < eps, "entry {} vs {}", t.min, t_min);
+                prop_assert!((t.max as f64 - t_max).abs() < eps, "exit {} vs {}", t.max, t_max);
+            }
+        }
+
+        #[test]
+        fn prop_construct_contains_both_corners(a in point(1e4), b in point(1e4)) {
+            let bbox = AABB::construct(a, b);
+            for corner in [a, b] {
+                for axis in Axis::ALL {
+                    prop_assert!(bbox.get_axis(axis).contains(corner[axis as usize]));
+                    // Padding keeps every slab thick enough to be hit
+                    prop_assert!(bbox.get_axis(axis).size() > 0.0);
+                }
+            }
+        }
+
+        #[test]
+        fn prop_combine_contains_both_boxes(
+            a in point(100.0),
+            b in point(100.0),
+            c in point(100.0),
+            d in point(100.0),
+        ) {
+            let (first, second) = (AABB::construct(a, b), AABB::construct(c, d));
+            let combined = AABB::combine(&first, &second);
+            for bbox in [first, second, AABB::combine(&second, &first)] {
+                for axis in Axis::ALL {
+                    let (outer, inner) = (combined.get_axis(axis), bbox.get_axis(axis));
+                    prop_assert!(outer.min <= inner.min && inner.max <= outer.max);
+                }
+            }
+            let with_empty = AABB::combine(&AABB::empty(), &first);
+            for axis in Axis::ALL {
+                let (a, b) = (with_empty.get_axis(axis), first.get_axis(axis));
+                prop_assert_eq!((a.min, a.max), (b.min, b.max));
+            }
+        }
+    }
+
+    #[test]
+    fn test_hit_interval_head_on() {
+        let bbox = AABB::construct(Vec3::zero(), Vec3::one());
+        let ray = Ray::new(Vec3::new(0.5, 0.5, -2.0), Vec3::new(0.0, 0.0, 1.0));
+        let t = bbox
+            .hit_interval(&ray, Interval::new(0.0, Real::MAX))
+            .unwrap();
+        assert!(
+            (t.min - 2.0).abs() < 1e-5 && (t.max - 3.0).abs() < 1e-5,
+            "{}",
+            t
+        );
+        assert!(bbox.hit(&ray, Interval::new(0.0, Real::MAX)));
+    }
+
+    #[test]
+    fn test_hit_interval_clips_to_ray_range() {
+        let bbox = AABB::construct(Vec3::zero(), Vec3::one());
+        let ray = Ray::new(Vec3::new(0.5, 0.5, 0.5), Vec3::new(0.0, 0.0, -1.0));
+        let t = bbox.hit_interval(&ray, Interval::new(0.1, 10.0)).unwrap();
+        assert!(
+            (t.min - 0.1).abs() < 1e-5 && (t.max - 0.5).abs() < 1e-5,
+            "{}",
+            t
+        );
+        assert!(bbox.hit_interval(&ray, Interval::new(0.6, 10.0)).is_none());
+    }
+
+    #[test]
+    fn test_only_flat_shapes_are_padded() {
+        use crate::entities::quad::Quad;
+        use crate::material::{Material, Normals};
+        use std::sync::Arc;
+
+        let material: Arc<dyn Material> = Arc::new(Normals);
+        let quad = |x: Real| {
+            Quad::new(
+                Vec3::new(x, 0.0, 0.0),
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+                Arc::clone(&material),
+            )
+            .get_aabb()
+        };
+        let (left, right) = (quad(0.0), quad(2.0));
+        assert!(left.z.size() > 0.0 && left.z.contains(0.0));
+
+        // Merging the flat boxes keeps their padding instead of adding to it
+        let scene = AABB::combine(&left, &right);
+        assert_eq!((scene.z.min, scene.z.max), (left.z.min, left.z.max));
+        assert_eq!((scene.x.min, scene.x.max), (0.0, 3.0));
+
+        let (min, max) = (Vec3::zero(), Vec3::new(1.0, 1.0, 1e-5));
+        assert_eq!(AABB::construct_exact(min, max).z.size(), 1e-5);
+        assert!(AABB::construct(min, max).z.size() > 1e-5);
+    }
 }
@@ -0,0 +1,247 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::thread;
+
+use crate::camera::Camera;
+use crate::entities::entity::EntityList;
+use crate::math::{vec3::Vec3, Real};
+use crate::render::{trace_sample, CancelToken};
+
+/// Sums are stored at the precision of `Real`, so each build reads only its own checkpoints.
+#[cfg(not(feature = "f64"))]
+const CHECKPOINT_MAGIC: &[u8; 4] = b"RTCK";
+#[cfg(feature = "f64")]
+const CHECKPOINT_MAGIC: &[u8; 4] = b"RTCD";
+
+/// Float framebuffer holding the running radiance sum and sample count of every pixel.
+/// Samples are seeded from `seed` and their pixel/sample index, which is all the RNG state a
+/// checkpoint needs to continue exactly where it stopped.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Accumulator {
+    pub width: u32,
+    pub height: u32,
+    pub seed: u64,
+    pub sums: Vec<Vec3>,
+    pub sample_counts: Vec<u32>,
+}
+
+/// Averaged image of a float render together with the samples behind each pixel, so that
+/// AOV and denoising tools can tell how far every pixel has converged. The counts are uniform
+/// unless sampling was adaptive or the render was cancelled partway.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderOutput {
+    pub width: u32,
+    pub height: u32,
+    pub colors: Vec<Vec3>,
+    pub sample_counts: Vec<u32>,
+}
+
+impl RenderOutput {
+    /// Fewest and most samples taken in any pixel.
+    pub fn sample_range(&self) -> (u32, u32) {
+        let fewest = self.sample_counts.iter().copied().min().unwrap_or(0);
+        let most = self.sample_counts.iter().copied().max().unwrap_or(0);
+        (fewest, most)
+    }
+}
+
+impl Accumulator {
+    pub fn new(width: u32, height: u32, seed: u64) -> Self {
+        let pixel_count = (width * height) as usize;
+        Self {
+            width,
+            height,
+            seed,
+            sums: vec![Vec3::zero(); pixel_count],
+            sample_counts: vec![0; pixel_count],
+        }
+    }
+
+    /// Traces samples until every pixel holds `target_samples`. Rows are handed out to
+    /// `thread_count` workers; cancellation is checked between rows.
+    pub fn accumulate(
+        &mut self,
+        thread_count: u32,
+        entities: &EntityList,
+        camera: &Camera,
+        cancel: &CancelToken,
+        target_samples: u32,
+    ) {
+        self.accumulate_by(thread_count, entities, camera, cancel, |_| target_samples);
+    }
+
+    /// Like `accumulate`, but pixel `index` (row-major) is traced up to `target(index)` samples.
+    pub fn accumulate_by(
+        &mut self,
+        thread_count: u32,
+        entities: &EntityList,
+        camera: &Camera,
+        cancel: &CancelToken,
+        target: impl Fn(usize) -> u32 + Sync,
+    ) {
+        let width = self.width as usize;
+        let seed = self.seed;
+        let rows = Mutex::new(
+            self.sums
+                .chunks_mut(width)
+                .zip(self.sample_counts.chunks_mut(width))
+                .enumerate(),
+        );
+
+        thread::scope(|scope| {
+            for _ in 0..thread_count {
+                scope.spawn(|| loop {
+                    if cancel.is_cancelled() {
+                        return;
+                    }
+                    let Some((y, (sums, counts))) = rows.lock().unwrap().next() else {
+                        return;
+                    };
+                    for (x, (sum, count)) in sums.iter_mut().zip(counts.iter_mut()).enumerate() {
+                        let target_samples = target(y * width + x);
+                        while *count < target_samples {
+                            *sum +=
+                                trace_sample(camera, entities, seed, x as u32, y as u32, *count);
+                            *count += 1;
+                        }
+                    }
+                });
+            }
+        });
+    }
+
+    /// Mean radiance of every pixel; pixels without samples are black.
+    pub fn average(&self) -> Vec<Vec3> {
+        self.sums
+            .iter()
+            .zip(&self.sample_counts)
+            .map(|(sum, &count)| {
+                if count > 0 {
+                    *sum / count as Real
+                } else {
+                    Vec3::zero()
+                }
+            })
+            .collect()
+    }
+
+    pub fn output(&self) -> RenderOutput {
+        RenderOutput {
+            width: self.width,
+            height: self.height,
+            colors: self.average(),
+            sample_counts: self.sample_counts.clone(),
+        }
+    }
+
+    /// Writes the buffer to `<path>.tmp` and then renames it over `path`, so a save that is
+    /// interrupted leaves the previous checkpoint intact.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut partial = path.as_os_str().to_owned();
+        partial.push(".tmp");
+        let partial = Path::new(&partial);
+        self.write_to(partial)?;
+        fs::rename(partial, path)
+    }
+
+    fn write_to(&self, path: &Path) -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(CHECKPOINT_MAGIC)?;
+        file.write_all(&self.width.to_le_bytes())?;
+        file.write_all(&self.height.to_le_bytes())?;
+        file.write_all(&self.seed.to_le_bytes())?;
+        for (sum, count) in self.sums.iter().zip(&self.sample_counts) {
+            file.write_all(&count.to_le_bytes())?;
+            for component in [sum.x, sum.y, sum.z] {
+                file.write_all(&component.to_le_bytes())?;
+            }
+        }
+        file.flush()
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let mut file = BufReader::new(File::open(path)?);
+        let mut magic = [0u8; 4];
+        file.read_exact(&mut magic)?;
+        if &magic != CHECKPOINT_MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Not a render checkpoint",
+            ));
+        }
+        let width = read_u32(&mut file)?;
+        let height = read_u32(&mut file)?;
+        let mut seed = [0u8; 8];
+        file.read_exact(&mut seed)?;
+        let mut accumulator = Self::new(width, height, u64::from_le_bytes(seed));
+        for (sum, count) in accumulator
+            .sums
+            .iter_mut()
+            .zip(accumulator.sample_counts.iter_mut())
+        {
+            *count = read_u32(&mut file)?;
+            sum.x = read_real(&mut file)?;
+            sum.y = read_real(&mut file)?;
+            sum.z = read_real(&mut file)?;
+        }
+        Ok(accumulator)
+    }
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_real(reader: &mut impl Read) -> io::Result<Real> {
+    let mut bytes = [0u8; std::mem::size_of::<Real>()];
+    reader.read_exact(&mut bytes)?;
+    Ok(Real::from_le_bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entities::sphere::Sphere;
+    use crate::material::Lambertian;
+    use crate::texture::Texture;
+    use std::sync::Arc;
+
+    fn test_scene() -> (EntityList, Camera) {
+        let mut entities = EntityList::new();
+        entities.add(Box::new(Sphere::new(
+            Vec3::zero(),
+            0.5,
+            Arc::new(Lambertian::new(Box::new(Texture::new(Vec3::new(
+                0.8, 0.3, 0.3,
+            ))))),
+        )));
+        let mut camera = Camera::new(12, 8, 60.0, &Vec3::new(0.0, 0.0, 2.0), &Vec3::zero());
+        camera.set_background_color(&Vec3::new(0.7, 0.8, 1.0));
+        camera.set_samples_per_pixel(4);
+        (entities, camera)
+    }
+
+    #[test]
+    fn test_resumed_render_matches_uninterrupted() {
+        let (entities, camera) = test_scene();
+        let cancel = CancelToken::new();
+
+        let mut full = Accumulator::new(12, 8, 7);
+        full.accumulate(3, &entities, &camera, &cancel, 4);
+
+        let mut first_half = Accumulator::new(12, 8, 7);
+        first_half.accumulate(2, &entities, &camera, &cancel, 2);
+        let path = std::env::temp_dir().join("rustrt_resume_test.ckpt");
+        first_half.save(&path).unwrap();
+        let mut resumed = Accumulator::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(resumed, first_half);
+
+        resumed.accumulate(4, &entities, &camera, &cancel, 4);
+        assert_eq!(resumed, full);
+    }
+}
@@ -0,0 +1,267 @@
+use crate::accumulator::Accumulator;
+use crate::camera::Camera;
+use crate::entities::entity::EntityList;
+use crate::math::vec3::{dot, Vec3};
+use crate::math::Real;
+use crate::render::CancelToken;
+
+/// Relative depth step between neighbouring pixels that counts as a full edge.
+const DEPTH_EDGE: Real = 0.1;
+/// Normal change (1 - cos) between neighbouring pixels that counts as a full edge.
+const NORMAL_EDGE: Real = 0.2;
+/// Edge strength from which `EdgeSupersampling` flags a pixel.
+const SUPERSAMPLE_EDGE: Real = 0.5;
+
+/// First-hit depth and normal of every pixel, from a single ray through its centre.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeometryBuffer {
+    pub depth: Vec<Real>,
+    pub normal: Vec<Vec3>,
+}
+
+pub fn capture_geometry(
+    camera: &Camera,
+    entities: &EntityList,
+    width: u32,
+    height: u32,
+) -> GeometryBuffer {
+    let center = camera.sqrt_spp() / 2;
+    let (depth, normal) = (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x, y)))
+        .map(|(x, y)| camera.first_hit_geometry(&camera.get_ray(x, y, center, center), entities))
+        .unzip();
+    GeometryBuffer { depth, normal }
+}
+
+/// Geometric edge strength in [0, 1] per pixel: the largest depth or normal discontinuity to
+/// any of its four neighbours. Silhouettes against the background are always full edges.
+pub fn edge_map(geometry: &GeometryBuffer, width: u32, height: u32) -> Vec<Real> {
+    let (width, height) = (width as i64, height as i64);
+    let discontinuity = |p: usize, q: usize| {
+        let (dp, dq) = (geometry.depth[p], geometry.depth[q]);
+        match (dp.is_finite(), dq.is_finite()) {
+            (false, false) => 0.0,
+            (true, true) => {
+                let depth = (dp - dq).abs() / dp.min(dq).max(Real::EPSILON) / DEPTH_EDGE;
+                let normal = (1.0 - dot(&geometry.normal[p], &geometry.normal[q])) / NORMAL_EDGE;
+                Real::min(1.0, depth.max(normal))
+            }
+            _ => 1.0,
+        }
+    };
+    (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x, y)))
+        .map(|(x, y)| {
+            let p = (y * width + x) as usize;
+            [(-1, 0), (1, 0), (0, -1), (0, 1)]
+                .iter()
+                .map(|(dx, dy)| (x + dx, y + dy))
+                .filter(|(qx, qy)| (0..width).contains(qx) && (0..height).contains(qy))
+                .map(|(qx, qy)| discontinuity(p, (qy * width + qx) as usize))
+                .fold(0.0, Real::max)
+        })
+        .collect()
+}
+
+/// Per-pixel sample counts from `min_samples` on flat regions up to `max_samples` on the
+/// strongest edges, in proportion to the edge strength.
+pub fn sample_targets(edges: &[Real], min_samples: u32, max_samples: u32) -> Vec<u32> {
+    let extra = max_samples.saturating_sub(min_samples) as Real;
+    edges
+        .iter()
+        .map(|edge| min_samples + (edge * extra).round() as u32)
+        .collect()
+}
+
+/// MSAA-style antialiasing: pixels on geometric edges are rendered as a 2x2 grid of
+/// subpixels with the camera's sample count each, while the rest keep that sample count. The
+/// subpixels come from a camera with twice the strata per axis, so a flagged pixel is
+/// stratified over its whole area rather than over four separate quarters.
+#[derive(Debug, Clone)]
+pub struct EdgeSupersampling {
+    pub pixels: Vec<bool>,
+    subpixel_camera: Camera,
+}
+
+impl EdgeSupersampling {
+    pub fn new(camera: &Camera, edges: &[Real]) -> Self {
+        let mut subpixel_camera = camera.clone();
+        subpixel_camera.set_samples_per_pixel(4 * camera.sqrt_spp() * camera.sqrt_spp());
+        Self {
+            pixels: edges.iter().map(|&edge| edge >= SUPERSAMPLE_EDGE).collect(),
+            subpixel_camera,
+        }
+    }
+
+    /// Per-pixel sample counts: the camera's count, four times that on flagged pixels.
+    pub fn sample_targets(&self) -> Vec<u32> {
+        let samples = self.subpixel_camera.samples_per_pixel();
+        self.pixels
+            .iter()
+            .map(|&flagged| if flagged { samples } else { samples / 4 })
+            .collect()
+    }
+
+    /// Like `Accumulator::accumulate_by`, but flagged pixels are traced through the subpixel
+    /// camera.
+    pub fn accumulate_by(
+        &self,
+        accumulator: &mut Accumulator,
+        thread_count: u32,
+        entities: &EntityList,
+        camera: &Camera,
+        cancel: &CancelToken,
+        target: impl Fn(usize) -> u32 + Sync,
+    ) {
+        accumulator.accumulate_by(thread_count, entities, camera, cancel, |index| {
+            if self.pixels[index] {
+                0
+            } else {
+                target(index)
+            }
+        });
+        accumulator.accumulate_by(
+            thread_count,
+            entities,
+            &self.subpixel_camera,
+            cancel,
+            |index| {
+                if self.pixels[index] {
+                    target(index)
+                } else {
+                    0
+                }
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entities::sphere::Sphere;
+    use crate::material::Normals;
+    use std::sync::Arc;
+
+    const WIDTH: u32 = 32;
+    const HEIGHT: u32 = 24;
+
+    fn mean_squared_error(a: &[Vec3], b: &[Vec3]) -> Real {
+        a.iter()
+            .zip(b)
+            .map(|(a, b)| (*a - *b).length_squared())
+            .sum::<Real>()
+            / a.len() as Real
+    }
+
+    /// Normal shading has no noise of its own, so all the error left is edge aliasing.
+    fn normals_sphere() -> (EntityList, Camera) {
+        let mut entities = EntityList::new();
+        entities.add(Box::new(Sphere::new(Vec3::zero(), 0.6, Arc::new(Normals))));
+        let mut camera = Camera::new(
+            WIDTH,
+            HEIGHT,
+            60.0,
+            &Vec3::new(0.0, 0.0, 2.0),
+            &Vec3::zero(),
+        );
+        camera.set_background_color(&Vec3::new(0.1, 0.1, 0.1));
+        camera.set_samples_per_pixel(4);
+        (entities, camera)
+    }
+
+    #[test]
+    fn test_edge_samples_beat_uniform_sampling_with_fewer_samples() {
+        let (entities, camera) = normals_sphere();
+        let cancel = CancelToken::new();
+        let render = |target: &(dyn Fn(usize) -> u32 + Sync)| {
+            let mut accumulator = Accumulator::new(WIDTH, HEIGHT, 5);
+            accumulator.accumulate_by(2, &entities, &camera, &cancel, target);
+            let samples = accumulator
+                .sample_counts
+                .iter()
+                .map(|&n| n as u64)
+                .sum::<u64>();
+            (accumulator.average(), samples)
+        };
+
+        let geometry = capture_geometry(&camera, &entities, WIDTH, HEIGHT);
+        let edges = edge_map(&geometry, WIDTH, HEIGHT);
+        let edge_pixels = edges.iter().filter(|&&edge| edge > 0.5).count();
+        assert!(edge_pixels > 20 && edge_pixels < edges.len() / 3);
+        let targets = sample_targets(&edges, 4, 64);
+
+        let (reference, _) = render(&|_| 1024);
+        let (uniform, uniform_samples) = render(&|_| 16);
+        let (adaptive, adaptive_samples) = render(&|index| targets[index]);
+        assert!(
+            adaptive_samples < uniform_samples,
+            "{} edge-aware samples vs {} uniform",
+            adaptive_samples,
+            uniform_samples
+        );
+        let uniform_error = mean_squared_error(&uniform, &reference);
+        let adaptive_error = mean_squared_error(&adaptive, &reference);
+        assert!(
+            adaptive_error < uniform_error,
+            "edge-aware error {} vs uniform {}",
+            adaptive_error,
+            uniform_error
+        );
+    }
+
+    #[test]
+    fn test_edge_supersampling_beats_doubling_the_sample_count() {
+        let (entities, camera) = normals_sphere();
+        let cancel = CancelToken::new();
+        let total = |accumulator: &Accumulator| {
+            accumulator
+                .sample_counts
+                .iter()
+                .map(|&n| n as u64)
+                .sum::<u64>()
+        };
+
+        let mut reference = Accumulator::new(WIDTH, HEIGHT, 5);
+        reference.accumulate(2, &entities, &camera, &cancel, 1024);
+        let mut doubled = Accumulator::new(WIDTH, HEIGHT, 5);
+        doubled.accumulate(2, &entities, &camera, &cancel, 8);
+
+        let geometry = capture_geometry(&camera, &entities, WIDTH, HEIGHT);
+        let supersampling = EdgeSupersampling::new(&camera, &edge_map(&geometry, WIDTH, HEIGHT));
+        let targets = supersampling.sample_targets();
+        assert!(targets.iter().all(|&n| n == 4 || n == 16));
+        let mut edge_aa = Accumulator::new(WIDTH, HEIGHT, 5);
+        supersampling.accumulate_by(&mut edge_aa, 2, &entities, &camera, &cancel, |index| {
+            targets[index]
+        });
+        assert_eq!(edge_aa.sample_counts, targets);
+
+        assert!(
+            total(&edge_aa) < total(&doubled),
+            "{} edge samples vs {} when doubling",
+            total(&edge_aa),
+            total(&doubled)
+        );
+        let doubled_error = mean_squared_error(&doubled.average(), &reference.average());
+        let edge_aa_error = mean_squared_error(&edge_aa.average(), &reference.average());
+        assert!(
+            edge_aa_error < doubled_error,
+            "edge supersampling error {} vs doubled {}",
+            edge_aa_error,
+            doubled_error
+        );
+    }
+
+    #[test]
+    fn test_flat_surfaces_get_the_minimum() {
+        let geometry = GeometryBuffer {
+            depth: vec![2.0; 9],
+            normal: vec![Vec3::new(0.0, 0.0, 1.0); 9],
+        };
+        let edges = edge_map(&geometry, 3, 3);
+        assert!(edges.iter().all(|&edge| edge == 0.0));
+        assert_eq!(sample_targets(&edges, 4, 64), vec![4; 9]);
+        assert_eq!(sample_targets(&[0.5, 1.0], 4, 64), vec![34, 64]);
+    }
+}
@@ -1,13 +1,23 @@
-use std::cmp::Ordering;
+use std::sync::atomic::{self, AtomicUsize};
 
+#[cfg(feature = "serde")]
+use crate::entities::entity::EntityDesc;
+use crate::math::Real;
 use crate::{
-    aabb::{Axis, HasAABB, AABB},
+    aabb::{HasAABB, AABB},
     entities::entity::{EntityList, HitRecord, Hittable, Transformable},
     interval::Interval,
     math::vec3::Vec3,
     ray::Ray,
 };
 
+/// Nodes created by every `BVH` and `IndexedBVH` built since the program started, for `--stats`.
+static NODES_BUILT: AtomicUsize = AtomicUsize::new(0);
+
+pub fn nodes_built() -> usize {
+    NODES_BUILT.load(atomic::Ordering::Relaxed)
+}
+
 enum BVHNode {
     Branch { left: Box<BVH>, right: Box<BVH> },
     Leaf(Box<dyn Hittable>),
@@ -29,6 +39,13 @@ impl HasAABB for BVH {
             BVHNode::Branch { left, right } => AABB::combine(&right.get_aabb(), &left.get_aabb()),
         }
     }
+
+    fn is_bounded(&self) -> bool {
+        match &self.tree {
+            BVHNode::Leaf(leaf) => leaf.is_bounded(),
+            BVHNode::Branch { left, right } => left.is_bounded() && right.is_bounded(),
+        }
+    }
 }
 
 impl Hittable for BVH {
@@ -53,53 +70,238 @@ impl Hittable for BVH {
             }
         }
     }
-}
 
-impl BVH {
-    pub fn new(entities: EntityList) -> Self {
-        Self::compute_bvh(entities.list)
+    fn summary(&self) -> String {
+        format!("BVH of {} primitives", self.children().len())
     }
 
-    fn compute_bvh(mut entities: Vec<Box<dyn Hittable>>) -> Self {
-        fn compare(axis: Axis) -> impl FnMut(&Box<dyn Hittable>, &Box<dyn Hittable>) -> Ordering {
-            move |a, b| {
-                let a_bbox = &a.get_aabb();
-                let b_bbox = &b.get_aabb();
+    /// The primitives in the leaves, without the hierarchy above them.
+    fn children(&self) -> Vec<&dyn Hittable> {
+        fn collect_leaves<'a>(bvh: &'a BVH, leaves: &mut Vec<&'a dyn Hittable>) {
+            match &bvh.tree {
+                BVHNode::Leaf(leaf) => leaves.push(leaf.as_ref()),
+                BVHNode::Branch { left, right } => {
+                    collect_leaves(left, leaves);
+                    collect_leaves(right, leaves);
+                }
+            }
+        }
+        let mut leaves = Vec::new();
+        collect_leaves(self, &mut leaves);
+        leaves
+    }
 
-                let a_int = a_bbox.get_axis(axis).min;
-                let b_int = b_bbox.get_axis(axis).min;
-                a_int.partial_cmp(&b_int).unwrap()
+    fn node_bounds(&self, max_depth: u32, bounds: &mut Vec<(AABB, u32)>) {
+        fn collect(bvh: &BVH, depth: u32, max_depth: u32, bounds: &mut Vec<(AABB, u32)>) {
+            if depth > max_depth {
+                return;
+            }
+            match &bvh.tree {
+                BVHNode::Branch { left, right } => {
+                    bounds.push((bvh.bbox, depth));
+                    collect(left, depth + 1, max_depth, bounds);
+                    collect(right, depth + 1, max_depth, bounds);
+                }
+                BVHNode::Leaf(leaf) => {
+                    // A hierarchy inside the leaf, e.g. a mesh, continues from the leaf's level
+                    let mut nested = Vec::new();
+                    leaf.node_bounds(max_depth - depth, &mut nested);
+                    if nested.is_empty() {
+                        bounds.push((bvh.bbox, depth));
+                    }
+                    bounds.extend(
+                        nested
+                            .into_iter()
+                            .map(|(bbox, level)| (bbox, level + depth)),
+                    );
+                }
             }
         }
+        collect(self, 0, max_depth, bounds);
+    }
 
-        let span = entities.len();
-        match span {
-            0 => panic!("No elements..."),
-            1 => {
-                let leaf = entities.pop().unwrap();
+    /// Exports the primitives in the leaves; the hierarchy itself is rebuilt on load.
+    #[cfg(feature = "serde")]
+    fn describe(&self) -> Option<Vec<EntityDesc>> {
+        fn collect_leaves(bvh: &BVH, children: &mut Vec<EntityDesc>) -> Option<()> {
+            match &bvh.tree {
+                BVHNode::Leaf(leaf) => {
+                    for desc in leaf.describe()? {
+                        match desc {
+                            EntityDesc::Bvh { children: nested } => children.extend(nested),
+                            desc => children.push(desc),
+                        }
+                    }
+                }
+                BVHNode::Branch { left, right } => {
+                    collect_leaves(left, children)?;
+                    collect_leaves(right, children)?;
+                }
+            }
+            Some(())
+        }
+        let mut children = Vec::new();
+        collect_leaves(self, &mut children)?;
+        Some(vec![EntityDesc::Bvh { children }])
+    }
+}
+
+impl BVH {
+    pub fn new(entities: EntityList) -> Self {
+        Self::compute_bvh(entities.into_iter().collect())
+    }
+
+    fn compute_bvh(entities: Vec<Box<dyn Hittable>>) -> Self {
+        build_median(
+            &mut (),
+            entities,
+            &|entity| entity.get_aabb(),
+            &|_, leaf| {
                 let bbox = leaf.get_aabb();
                 BVH {
                     tree: BVHNode::Leaf(leaf),
                     bbox,
                 }
+            },
+            &|_, bbox, left, right| BVH {
+                tree: BVHNode::Branch {
+                    left: Box::new(left),
+                    right: Box::new(right),
+                },
+                bbox,
+            },
+        )
+    }
+}
+
+/// Builds a hierarchy over `items` by splitting them at the median along the longest axis of
+/// their bounds, the scheme shared by `BVH` and `IndexedBVH`. `leaf` makes the node for a
+/// single item and `branch` joins two subtrees under their combined box, both given `nodes`
+/// to store into. Every node created is counted for `--stats`.
+fn build_median<T, N, C>(
+    nodes: &mut C,
+    mut items: Vec<T>,
+    aabb: &impl Fn(&T) -> AABB,
+    leaf: &impl Fn(&mut C, T) -> N,
+    branch: &impl Fn(&mut C, AABB, N, N) -> N,
+) -> N {
+    NODES_BUILT.fetch_add(1, atomic::Ordering::Relaxed);
+    match items.len() {
+        0 => panic!("No elements..."),
+        1 => leaf(nodes, items.pop().unwrap()),
+        span => {
+            let bbox = items.iter().fold(AABB::empty(), |bbox, item| {
+                AABB::combine(&bbox, &aabb(item))
+            });
+            let axis = bbox.get_longest_axis();
+            items.sort_unstable_by(|a, b| {
+                let a_min = aabb(a).get_axis(axis).min;
+                let b_min = aabb(b).get_axis(axis).min;
+                a_min.partial_cmp(&b_min).unwrap()
+            });
+            let upper = items.split_off(span / 2);
+            let left = build_median(nodes, items, aabb, leaf, branch);
+            let right = build_median(nodes, upper, aabb, leaf, branch);
+            branch(nodes, bbox, left, right)
+        }
+    }
+}
+
+/// Hierarchy over entities that stay in their own list and are referred to by index, which
+/// `EntityList` builds on demand to speed up `hit` on long lists. Unbounded entities are kept
+/// beside it and tested one by one.
+pub struct IndexedBVH {
+    nodes: Vec<IndexedNode>,
+    root: Option<usize>,
+    unbounded: Vec<usize>,
+}
+
+enum IndexedNode {
+    Branch {
+        bbox: AABB,
+        left: usize,
+        right: usize,
+    },
+    Leaf(usize),
+}
+
+impl IndexedBVH {
+    pub fn new(entities: &[Box<dyn Hittable>]) -> Self {
+        let (bounded, unbounded): (Vec<usize>, Vec<usize>) =
+            (0..entities.len()).partition(|&idx| entities[idx].is_bounded());
+        let mut bvh = IndexedBVH {
+            nodes: Vec::new(),
+            root: None,
+            unbounded,
+        };
+        if !bounded.is_empty() {
+            let push = |nodes: &mut Vec<IndexedNode>, node| {
+                nodes.push(node);
+                nodes.len() - 1
+            };
+            bvh.root = Some(build_median(
+                &mut bvh.nodes,
+                bounded,
+                &|&idx| entities[idx].get_aabb(),
+                &|nodes, idx| push(nodes, IndexedNode::Leaf(idx)),
+                &|nodes, bbox, left, right| push(nodes, IndexedNode::Branch { bbox, left, right }),
+            ));
+        }
+        bvh
+    }
+
+    /// Nearest hit among `entities`, which must be the list the hierarchy was built from.
+    pub fn hit<'a>(
+        &self,
+        entities: &'a [Box<dyn Hittable>],
+        ray: &Ray,
+        t_interval: &Interval,
+        record: &mut HitRecord<'a>,
+    ) -> bool {
+        let mut is_hit = false;
+        let mut closest_so_far = t_interval.max;
+        if let Some(root) = self.root {
+            if self.hit_node(root, entities, ray, t_interval, record) {
+                is_hit = true;
+                closest_so_far = record.t;
             }
-            _ => {
-                let mut bbox = AABB::default();
-                for entity in &entities {
-                    bbox = AABB::combine(&bbox, &entity.get_aabb());
-                }
-                let axis = bbox.get_longest_axis();
-                entities.sort_unstable_by(compare(axis));
-                let right = Self::compute_bvh(entities.drain(span / 2..).collect());
-                let left = Self::compute_bvh(entities);
-                let bbox = AABB::combine(&left.bbox, &right.bbox);
-                BVH {
-                    tree: BVHNode::Branch {
-                        left: Box::new(left),
-                        right: Box::new(right),
-                    },
-                    bbox,
+        }
+        for &idx in &self.unbounded {
+            let t_interval = Interval::new(t_interval.min, closest_so_far);
+            if entities[idx].hit(ray, &t_interval, record) {
+                is_hit = true;
+                closest_so_far = record.t;
+            }
+        }
+        is_hit
+    }
+
+    fn hit_node<'a>(
+        &self,
+        node: usize,
+        entities: &'a [Box<dyn Hittable>],
+        ray: &Ray,
+        t_interval: &Interval,
+        record: &mut HitRecord<'a>,
+    ) -> bool {
+        match &self.nodes[node] {
+            IndexedNode::Leaf(idx) => entities[*idx].hit(ray, t_interval, record),
+            IndexedNode::Branch { bbox, left, right } => {
+                if !bbox.hit(ray, *t_interval) {
+                    return false;
                 }
+                let hit_left = self.hit_node(*left, entities, ray, t_interval, record);
+                let hit_right = self.hit_node(
+                    *right,
+                    entities,
+                    ray,
+                    &Interval::new(
+                        t_interval.min,
+                        if hit_left { record.t } else { t_interval.max },
+                    ),
+                    record,
+                );
+                hit_left || hit_right
             }
         }
     }
@@ -117,7 +319,7 @@ impl Transformable for BVH {
         self.bbox = self.compute_aabb();
     }
 
-    fn rotate(&mut self, axis: Vec3, angle: f32) {
+    fn rotate(&mut self, axis: Vec3, angle: Real) {
         match &mut self.tree {
             BVHNode::Leaf(leaf) => leaf.rotate(axis, angle),
             BVHNode::Branch { left, right } => {
@@ -127,4 +329,134 @@ impl Transformable for BVH {
         }
         self.bbox = self.compute_aabb();
     }
+
+    fn rotate_about(&mut self, pivot: Vec3, axis: Vec3, angle: Real) {
+        match &mut self.tree {
+            BVHNode::Leaf(leaf) => leaf.rotate_about(pivot, axis, angle),
+            BVHNode::Branch { left, right } => {
+                left.rotate_about(pivot, axis, angle);
+                right.rotate_about(pivot, axis, angle);
+            }
+        }
+        self.bbox = self.compute_aabb();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entities::sphere::Sphere;
+    use crate::material::{Material, Normals};
+    use crate::math::rand::{rand_f32, rand_f32_range, seed_sample_rng};
+    use std::sync::Arc;
+
+    fn random_point(extent: Real) -> Vec3 {
+        Vec3::new(
+            rand_f32_range(-extent, extent),
+            rand_f32_range(-extent, extent),
+            rand_f32_range(-extent, extent),
+        )
+    }
+
+    #[test]
+    fn test_node_bounds_descend_into_nested_hierarchies() {
+        let material: Arc<dyn Material> = Arc::new(Normals);
+        let sphere = |x: Real| {
+            Box::new(Sphere::new(
+                Vec3::new(x, 0.0, 0.0),
+                0.5,
+                Arc::clone(&material),
+            ))
+        };
+        let mut inner = EntityList::new();
+        for x in [0.0, 2.0, 4.0, 6.0] {
+            inner.add(sphere(x));
+        }
+        let mut outer = EntityList::new();
+        outer.add(Box::new(BVH::new(inner)));
+        outer.add(sphere(20.0));
+        let bvh = BVH::new(outer);
+
+        let mut bounds = Vec::new();
+        bvh.node_bounds(u32::MAX, &mut bounds);
+        let levels: Vec<u32> = bounds.iter().map(|&(_, depth)| depth).collect();
+        // The outer root, the inner root and the far sphere, then the inner hierarchy below
+        assert_eq!(levels.iter().filter(|&&depth| depth == 1).count(), 2);
+        assert_eq!(levels.iter().filter(|&&depth| depth == 2).count(), 2);
+        assert_eq!(levels.iter().filter(|&&depth| depth == 3).count(), 4);
+        assert_eq!(bounds.len(), 9);
+
+        bounds.clear();
+        bvh.node_bounds(1, &mut bounds);
+        assert_eq!(bounds.len(), 3);
+    }
+
+    #[test]
+    fn test_bvh_matches_brute_force() {
+        seed_sample_rng(7, 0, 0, 0);
+        let material: Arc<dyn Material> = Arc::new(Normals);
+        for count in [1, 2, 3, 17, 300] {
+            let spheres: Vec<Sphere> = (0..count)
+                .map(|_| {
+                    let radius = rand_f32_range(0.1, 1.5);
+                    Sphere::new(random_point(10.0), radius, Arc::clone(&material))
+                })
+                .collect();
+            let mut nested = EntityList::new();
+            for sphere in &spheres {
+                nested.add(Box::new(sphere.clone()));
+            }
+            let bvh = BVH::new(nested);
+            // Every sphere in turn, so the reference shares no acceleration code with the BVH
+            let linear_scan = |ray: &Ray, record: &mut HitRecord| {
+                let mut closest = Real::MAX;
+                for sphere in &spheres {
+                    let mut candidate = HitRecord::new();
+                    if sphere.hit(ray, &Interval::new(0.001, closest), &mut candidate) {
+                        closest = candidate.t;
+                        record.t = candidate.t;
+                        record.position = candidate.position;
+                    }
+                }
+                closest < Real::MAX
+            };
+
+            let mut hits = 0;
+            for _ in 0..2000 {
+                // Half the rays start inside the scene, half aim at it from outside
+                let origin = if rand_f32() < 0.5 {
+                    random_point(12.0)
+                } else {
+                    random_point(1.0).normalize() * 30.0
+                };
+                let direction = random_point(1.0) + (random_point(8.0) - origin) * 0.05;
+                let ray = Ray::new(origin, direction);
+                let t_interval = Interval::new(0.001, Real::MAX);
+
+                let mut expected = HitRecord::new();
+                let mut actual = HitRecord::new();
+                let hit_flat = linear_scan(&ray, &mut expected);
+                let hit_bvh = bvh.hit(&ray, &t_interval, &mut actual);
+                assert_eq!(
+                    hit_flat,
+                    hit_bvh,
+                    "{} spheres, ray {:?}",
+                    count,
+                    ray.direction()
+                );
+                if hit_flat {
+                    hits += 1;
+                    assert!(
+                        (expected.t - actual.t).abs() <= 1e-4 * expected.t.max(1.0),
+                        "{} spheres: closest t {} vs BVH {}",
+                        count,
+                        expected.t,
+                        actual.t
+                    );
+                    assert!((expected.position - actual.position).length() < 1e-3);
+                }
+            }
+            assert!(hits > 0, "no ray hit any of {} spheres", count);
+        }
+    }
 }
@@ -1,62 +1,192 @@
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use crate::color::ColorSpace;
 use crate::entities::entity::*;
-use crate::interval::Interval;
-use crate::math::rand::{rand_f32, rand_f32_range};
-use crate::math::vec3::*;
-use crate::pdf::{CosinePDF, PDF};
-use crate::ray::Ray;
+use crate::environment::EnvironmentMap;
+use crate::material::material_name;
+use crate::math::rand::{rand_f32, with_camera_rng};
+use crate::math::{vec3::*, Real};
+use crate::pdf::{CosinePDF, HittableListPDF, PDF};
+use crate::ray::{Ray, RayDifferential, RAY_EPSILON};
 
 const UP: Vec3 = Vec3 {
     x: 0.0,
     y: 1.0,
     z: 0.0,
 };
+/// Paths whose largest throughput channel drops below this stop bouncing, since nothing they
+/// could still gather would show up in the pixel.
+const MIN_THROUGHPUT: Real = 1e-4;
+/// Default distance from the camera to the viewport plane, which is the plane in focus.
+const FOCUS_DIST: Real = 10.0;
+
+/// Rays intersected with the scene by `ray_color` since the program started. Each path adds
+/// its count once at the end, so the atomic is touched once per sample, not per bounce.
+static RAYS_TRACED: AtomicU64 = AtomicU64::new(0);
+
+pub fn rays_traced() -> u64 {
+    RAYS_TRACED.load(Ordering::Relaxed)
+}
+
+/// What the centre ray of a pixel hits, as reported by `Camera::pick`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PickInfo {
+    pub position: Vec3,
+    pub normal: Vec3,
+    pub distance: Real,
+    pub material: &'static str,
+}
+
+impl fmt::Display for PickInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} at ({:.3}, {:.3}, {:.3}), distance {:.3}, normal ({:.3}, {:.3}, {:.3})",
+            self.material,
+            self.position.x,
+            self.position.y,
+            self.position.z,
+            self.distance,
+            self.normal.x,
+            self.normal.y,
+            self.normal.z
+        )
+    }
+}
+
+/// Emitters sampled directly at every scattering hit. Compared by identity, since entities
+/// themselves cannot be compared.
+#[derive(Clone, Default)]
+pub struct LightList(Vec<Arc<dyn Hittable>>);
+
+impl fmt::Debug for LightList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "LightList({} lights)", self.0.len())
+    }
+}
 
-#[derive(Debug, Clone, Default)]
+impl PartialEq for LightList {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.len() == other.0.len()
+            && self
+                .0
+                .iter()
+                .zip(&other.0)
+                .all(|(a, b)| Arc::as_ptr(a) as *const () == Arc::as_ptr(b) as *const ())
+    }
+}
+
+/// Power heuristic (beta = 2) weight of a sample drawn with `pdf` against one other strategy.
+fn power_heuristic(pdf: Real, other_pdf: Real) -> Real {
+    let (pdf2, other2) = (pdf * pdf, other_pdf * other_pdf);
+    if pdf2 + other2 > 0.0 {
+        pdf2 / (pdf2 + other2)
+    } else {
+        0.0
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Camera {
-    pub camera_position: Vec3,
-    pub pixel_delta_x: Vec3,
-    pub pixel_delta_y: Vec3,
-    pub pixel_origin: Vec3,
-    pub samples_per_pixel: u32,
-    pub sqrt_spp: u32,
-    pub recip_sqrt_spp: f32,
-    pub pixel_samples_scale: f32,
+    camera_position: Vec3,
+    pixel_delta_x: Vec3,
+    pixel_delta_y: Vec3,
+    pixel_origin: Vec3,
+    /// Left at 0 by scene files that omit it, for `Scene::load` to pick from the lighting.
+    #[cfg_attr(feature = "serde", serde(default))]
+    samples_per_pixel: u32,
+    #[cfg_attr(feature = "serde", serde(default))]
+    sqrt_spp: u32,
+    #[cfg_attr(feature = "serde", serde(default))]
+    recip_sqrt_spp: Real,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pixel_samples_scale: Real,
     max_ray_bounces: u32,
-    defocus_angle: f32,
+    defocus_angle: Real,
     defocus_disk_u: Vec3,
     defocus_disk_v: Vec3,
+    /// Distance from the lens to the viewport, the only plane that a lens with a defocus angle
+    /// renders sharp.
+    #[cfg_attr(feature = "serde", serde(default = "default_focus_dist"))]
+    focus_dist: Real,
+    /// Rays leave at times spread evenly from 0 to this value, between 0 and 1, so animated
+    /// entities blur along their motion. At 0 every ray is frozen at time 0.
+    #[cfg_attr(feature = "serde", serde(default))]
+    shutter: Real,
     background_color: Vec3,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    environment: Option<Arc<EnvironmentMap>>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    lights: LightList,
+    sample_clamp: Option<Real>,
+    /// Self-intersection offset relative to the scale of hit coordinates; see `RAY_EPSILON`.
+    #[cfg_attr(feature = "serde", serde(default = "default_ray_epsilon"))]
+    ray_epsilon: Real,
+    /// Base seed of the per-sample random streams; equal seeds give identical images.
+    pub seed: u64,
+    /// Encoding of the final pixel values.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub color_space: ColorSpace,
 }
 
-fn random_disk_vec3() -> Vec3 {
-    loop {
-        let p = Vec3::new(rand_f32_range(-1.0, 1.0), rand_f32_range(-1.0, 1.0), 0.0);
-        if p.length_squared() < 1.0 {
-            return p;
-        }
+/// Shirley-Chiu concentric mapping of a point in the unit square onto the unit disk. Unlike
+/// rejection sampling it preserves stratification, since equal areas map to equal areas.
+fn concentric_disk_vec3(u: Real, v: Real) -> Vec3 {
+    let a = 2.0 * u - 1.0;
+    let b = 2.0 * v - 1.0;
+    if a == 0.0 && b == 0.0 {
+        return Vec3::zero();
     }
+    let quarter_pi = crate::math::consts::FRAC_PI_4;
+    let (r, theta) = if a.abs() > b.abs() {
+        (a, quarter_pi * (b / a))
+    } else {
+        (b, 2.0 * quarter_pi - quarter_pi * (a / b))
+    };
+    Vec3::new(r * theta.cos(), r * theta.sin(), 0.0)
+}
+
+#[cfg(feature = "serde")]
+fn default_ray_epsilon() -> Real {
+    RAY_EPSILON
 }
 
-fn degrees_to_radians(degrees: f32) -> f32 {
-    degrees * (std::f32::consts::PI / 180.0)
+#[cfg(feature = "serde")]
+fn default_focus_dist() -> Real {
+    FOCUS_DIST
+}
+
+fn gcd(a: u32, b: u32) -> u32 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+fn degrees_to_radians(degrees: Real) -> Real {
+    degrees * (crate::math::consts::PI / 180.0)
 }
 
 impl Camera {
     pub fn new(
         image_width: u32,
         image_height: u32,
-        vfov: f32,
+        vfov: Real,
         camera_pos: &Vec3,
         look_at: &Vec3,
     ) -> Self {
         // Camera setup:
-        let focus_dist = 10.0f32;
+        let focus_dist = FOCUS_DIST;
         let camera_position = camera_pos;
         //let focal_length = (look_at - camera_position).length();
         let theta = degrees_to_radians(vfov);
-        let h = f32::tan(theta / 2.0);
-        let viewport_height = 2.0f32 * h * focus_dist;
-        let viewport_width = viewport_height * (image_width as f32 / image_height as f32);
+        let h = Real::tan(theta / 2.0);
+        let viewport_height = 2.0 * h * focus_dist;
+        let viewport_width = viewport_height * (image_width as Real / image_height as Real);
 
         // compute camera basis vectors
         let w = (camera_pos - look_at).normalize();
@@ -66,58 +196,299 @@ impl Camera {
         let viewport_x = viewport_width * u;
         let viewport_y = viewport_height * -v;
 
-        let pixel_delta_x = viewport_x / image_width as f32;
-        let pixel_delta_y = viewport_y / image_height as f32;
+        let pixel_delta_x = viewport_x / image_width as Real;
+        let pixel_delta_y = viewport_y / image_height as Real;
         let viewport_upper_left =
             camera_position - (w * focus_dist) - viewport_x / 2.0 - viewport_y / 2.0;
 
         let defocus_angle = 0.0;
-        let defocus_radius = focus_dist * f32::tan(degrees_to_radians(defocus_angle / 2.0));
+        let defocus_radius = focus_dist * Real::tan(degrees_to_radians(defocus_angle / 2.0));
         let defocus_disk_u = u * defocus_radius;
         let defocus_disk_v = v * defocus_radius;
 
         let pixel_origin = viewport_upper_left + 0.5 * (pixel_delta_x + pixel_delta_y);
-        let sample_count = 1000;
-        Self {
+        let mut camera = Self {
             camera_position: *camera_position,
             pixel_delta_x,
             pixel_delta_y,
             pixel_origin,
-            samples_per_pixel: sample_count,
-            pixel_samples_scale: 1.0 / sample_count as f32,
-            sqrt_spp: (sample_count as f32).sqrt() as u32,
-            recip_sqrt_spp: 1.0 / (sample_count as f32).sqrt(),
+            samples_per_pixel: 0,
+            pixel_samples_scale: 0.0,
+            sqrt_spp: 0,
+            recip_sqrt_spp: 0.0,
             max_ray_bounces: 50,
             defocus_angle,
             defocus_disk_u,
             defocus_disk_v,
+            focus_dist,
+            shutter: 0.0,
             background_color: Vec3::new(0.0, 0.0, 0.0),
+            environment: None,
+            lights: LightList::default(),
+            sample_clamp: None,
+            ray_epsilon: RAY_EPSILON,
+            seed: 0,
+            color_space: ColorSpace::default(),
+        };
+        camera.set_samples_per_pixel(1000);
+        camera
+    }
+
+    /// Pixels are sampled on a `sqrt_spp` x `sqrt_spp` grid of jittered strata, so the count
+    /// is rounded to the nearest perfect square, and to at least 1: 1000 becomes 1024 and 10
+    /// becomes 9. `samples_per_pixel` and `pixel_samples_scale` then match the samples
+    /// actually taken, and a single sample is jittered over the whole pixel.
+    pub fn set_samples_per_pixel(&mut self, sample_count: u32) {
+        let strata = ((sample_count as Real).sqrt().round() as u32).max(1);
+        self.sqrt_spp = strata;
+        self.recip_sqrt_spp = 1.0 / strata as Real;
+        self.samples_per_pixel = strata * strata;
+        self.pixel_samples_scale = 1.0 / self.samples_per_pixel as Real;
+    }
+
+    pub fn samples_per_pixel(&self) -> u32 {
+        self.samples_per_pixel
+    }
+
+    /// Side of the grid of strata the pixel samples are jittered in.
+    pub fn sqrt_spp(&self) -> u32 {
+        self.sqrt_spp
+    }
+
+    /// Weight of each sample in the pixel's average, `1 / samples_per_pixel`.
+    pub fn pixel_samples_scale(&self) -> Real {
+        self.pixel_samples_scale
+    }
+
+    /// Moves the camera back along its current view direction until the sphere at `center`
+    /// with `radius` fits inside the view, keeping the field of view and image size.
+    pub fn frame_scene(&mut self, center: &Vec3, radius: Real) {
+        let (width, height) = self.image_size();
+        let (dx, dy) = (self.pixel_delta_x.length(), self.pixel_delta_y.length());
+        let tan_half_vfov = height as Real * dy / (2.0 * self.focus_dist);
+        let tan_half_hfov = width as Real * dx / (2.0 * self.focus_dist);
+        // The sphere touches the narrower pair of frustum planes
+        let half_fov = Real::atan(Real::min(tan_half_vfov, tan_half_hfov));
+        let distance = radius / half_fov.sin();
+
+        let forward = cross(&self.pixel_delta_x, &self.pixel_delta_y).normalize();
+        let look_from = *center - distance * forward;
+        let vfov = 2.0 * Real::atan(tan_half_vfov).to_degrees();
+        let framed = Camera::new(width, height, vfov, &look_from, center);
+        self.camera_position = framed.camera_position;
+        self.pixel_delta_x = framed.pixel_delta_x;
+        self.pixel_delta_y = framed.pixel_delta_y;
+        self.pixel_origin = framed.pixel_origin;
+        // Keep the lens focused as far in front of it as before
+        let focus_dist = self.focus_dist;
+        self.focus_dist = framed.focus_dist;
+        self.set_focus_distance(focus_dist);
+    }
+
+    /// Step between horizontally neighbouring pixel centers on the focus plane.
+    pub fn pixel_delta_x(&self) -> Vec3 {
+        self.pixel_delta_x
+    }
+
+    pub fn defocus_angle(&self) -> Real {
+        self.defocus_angle
+    }
+
+    /// Lens aperture as the angle of the cone from the focus plane to the lens, in degrees.
+    /// Zero gives a pinhole camera with everything in focus.
+    pub fn set_defocus_angle(&mut self, angle: Real) {
+        self.defocus_angle = angle;
+        self.update_defocus_disk();
+    }
+
+    pub fn focus_distance(&self) -> Real {
+        self.focus_dist
+    }
+
+    /// Moves the plane of perfect focus to `distance` in front of the lens. The viewport moves
+    /// with it and is scaled to keep the field of view.
+    pub fn set_focus_distance(&mut self, distance: Real) {
+        let scale = distance / self.focus_dist;
+        self.pixel_delta_x *= scale;
+        self.pixel_delta_y *= scale;
+        self.pixel_origin =
+            self.camera_position + (self.pixel_origin - self.camera_position) * scale;
+        self.focus_dist = distance;
+        self.update_defocus_disk();
+    }
+
+    /// Focuses on `point`, e.g. one picked in the window. The focus plane faces the camera, so
+    /// the distance is measured along the view direction rather than straight to `point`.
+    /// Points level with or behind the lens are ignored.
+    pub fn focus_on(&mut self, point: Vec3) {
+        let forward = cross(&self.pixel_delta_x, &self.pixel_delta_y).normalize();
+        let distance = dot(&(point - self.camera_position), &forward);
+        if distance > 0.0 {
+            self.set_focus_distance(distance);
         }
     }
 
+    fn update_defocus_disk(&mut self) {
+        let radius = self.focus_dist * Real::tan(degrees_to_radians(self.defocus_angle / 2.0));
+        self.defocus_disk_u = self.pixel_delta_x.normalize() * radius;
+        self.defocus_disk_v = -self.pixel_delta_y.normalize() * radius;
+    }
+
+    /// Image resolution, recovered from where the first pixel sits on the viewport.
+    fn image_size(&self) -> (u32, u32) {
+        let to_first_pixel = self.pixel_origin - self.camera_position;
+        let pixels_from_center =
+            |delta: &Vec3| -dot(&to_first_pixel, delta) / delta.length_squared() * 2.0 + 1.0;
+        (
+            pixels_from_center(&self.pixel_delta_x).round() as u32,
+            pixels_from_center(&self.pixel_delta_y).round() as u32,
+        )
+    }
+
+    /// Bounces after which a path is cut off and contributes nothing more.
+    pub fn set_max_ray_bounces(&mut self, bounces: u32) {
+        self.max_ray_bounces = bounces;
+    }
+
     pub fn set_background_color(&mut self, color: &Vec3) {
         self.background_color = *color;
     }
 
+    /// Replaces the flat background color on ray misses.
+    pub fn set_environment(&mut self, environment: Arc<EnvironmentMap>) {
+        self.environment = Some(environment);
+    }
+
+    /// Emitters to sample directly. Their contribution is combined with the BSDF continuation
+    /// by multiple importance sampling; with no lights, paths only find emitters by chance.
+    pub fn set_lights(&mut self, lights: Vec<Arc<dyn Hittable>>) {
+        self.lights = LightList(lights);
+    }
+
+    /// Caps the luminance of every sample at `max` to suppress fireflies. This biases the
+    /// estimate (bright paths lose energy), so keep it off for reference renders.
+    pub fn set_sample_clamp(&mut self, max: Option<Real>) {
+        self.sample_clamp = max;
+    }
+
+    /// Opens the shutter from time 0 to `close`, clamped to [0, 1], for motion blur of
+    /// entities animated with `AnimatedTransform`.
+    pub fn set_shutter(&mut self, close: Real) {
+        self.shutter = close.clamp(0.0, 1.0);
+    }
+
+    pub fn set_ray_epsilon(&mut self, epsilon: Real) {
+        self.ray_epsilon = epsilon;
+    }
+
+    /// Applied per sample after `ray_color`, never inside the recursion, so indirect light
+    /// is only darkened when the whole path is an outlier.
+    pub fn clamp_sample(&self, color: Vec3) -> Vec3 {
+        match self.sample_clamp {
+            Some(max) => {
+                let luminance = color.luminance();
+                if luminance > max {
+                    color * (max / luminance)
+                } else {
+                    color
+                }
+            }
+            None => color,
+        }
+    }
+
     pub fn ray_color(&self, ray: &Ray, entity_list: &EntityList, bounce_idx: u32) -> Vec3 {
+        let mut rays = 0;
+        let color = self.path_color(ray, entity_list, bounce_idx, Vec3::one(), &mut rays, None);
+        RAYS_TRACED.fetch_add(rays, Ordering::Relaxed);
+        color
+    }
+
+    /// Density of sampling `direction` from `origin` by picking one of the lights uniformly.
+    fn light_pdf(&self, origin: &Vec3, direction: &Vec3) -> Real {
+        HittableListPDF::new(*origin, &self.lights.0).value(direction)
+    }
+
+    /// Next-event estimation: light reaching the hit in `record` along a direction sampled
+    /// towards a random light, MIS-weighted against the continuation, whose density is
+    /// `continuation_pdf`.
+    fn sample_light(
+        &self,
+        ray: &Ray,
+        record: &HitRecord,
+        continuation_pdf: &dyn Fn(&Vec3) -> Real,
+        attenuation: Vec3,
+        entity_list: &EntityList,
+        rays: &mut u64,
+    ) -> Vec3 {
+        let lights_pdf = HittableListPDF::new(record.position, &self.lights.0);
+        let direction = lights_pdf.generate().normalize();
+        let light_pdf = lights_pdf.value(&direction);
+        // Directions the continuation can never take, e.g. below the surface, are skipped too
+        let surface_pdf = continuation_pdf(&direction);
+        if light_pdf <= 0.0 || surface_pdf <= 0.0 {
+            return Vec3::zero();
+        }
+
+        *rays += 1;
+        let shadow_ray = Ray::new(record.position, direction).with_time(ray.time);
+        let mut light_record = HitRecord::new();
+        if !entity_list.hit(
+            &shadow_ray,
+            &shadow_ray.hit_interval(self.ray_epsilon),
+            &mut light_record,
+        ) {
+            return Vec3::zero();
+        }
+        let emitted = light_record.material.unwrap().emitted(
+            &shadow_ray,
+            &light_record,
+            &light_record.uv,
+            &light_record.position,
+        );
+        let material = record.material.expect("Material should never be empty");
+        let scatter_pdf = material.scatter_pdf(ray, record, &shadow_ray);
+        attenuation * scatter_pdf * emitted * power_heuristic(light_pdf, surface_pdf) / light_pdf
+    }
+
+    /// `ray_color` for a path whose earlier bounces already scaled its contribution by
+    /// `throughput`. Adds the number of rays it intersects with the scene to `rays`.
+    /// `surface_pdf` is the density with which the previous bounce sampled `ray`, used to
+    /// MIS-weight emission it hits; camera rays pass `None`.
+    fn path_color(
+        &self,
+        ray: &Ray,
+        entity_list: &EntityList,
+        bounce_idx: u32,
+        throughput: Vec3,
+        rays: &mut u64,
+        surface_pdf: Option<Real>,
+    ) -> Vec3 {
         if bounce_idx == self.max_ray_bounces {
             return Vec3::zero();
         }
 
+        *rays += 1;
         let mut record = HitRecord::new();
-        if entity_list.hit(ray, &Interval::new(0.001, f32::MAX), &mut record) {
+        if entity_list.hit(ray, &ray.hit_interval(self.ray_epsilon), &mut record) {
+            // Set by `scatter`, or replaced by the cosine continuation, before it is traced
             let mut scattered = Ray::default();
             let mut attenuation = Vec3::zero();
-            let Some(material) = record.material.as_ref() else {
+            let Some(material) = record.material else {
                 panic!("Material should never be empty")
             };
+            record.normal = material.shading_normal(&record);
 
-            let emission_color = record.material.as_ref().unwrap().emitted(
+            let mut emission_color = record.material.as_ref().unwrap().emitted(
                 ray,
                 &record,
                 &record.uv,
                 &record.position,
             );
+            if let Some(surface_pdf) = surface_pdf.filter(|_| !self.lights.0.is_empty()) {
+                let light_pdf = self.light_pdf(&ray.origin, &ray.direction());
+                emission_color *= power_heuristic(surface_pdf, light_pdf);
+            }
             let mut pdf_value = 0.0;
             if material.scatter(
                 ray,
@@ -139,7 +510,7 @@ impl Camera {
                     return emission_color;
                 }
                 let light_area = (343.0 - 213.0) * (332.0 - 227.0);
-                let ligh_cos = f32::abs(to_light.y);
+                let ligh_cos = Real::abs(to_light.y);
                 if ligh_cos < 1e-6 {
                     return emission_color;
                 }
@@ -147,53 +518,997 @@ impl Camera {
                 pdf_value = dist_sq / (ligh_cos * light_area);
                 let scatter_pdf = material.scatter_pdf(ray, &record, &scattered);
                 */
-                let surface_pdf = CosinePDF::new(&record.normal);
-                scattered = Ray::new(record.position, surface_pdf.generate());
-                pdf_value = surface_pdf.value(&scattered.direction);
+                // The whole path sees the scene at the moment its camera ray was taken
+                scattered.time = ray.time;
+                if material.is_specular() {
+                    // A delta lobe: no light sample can land in it, so the path just follows it
+                    let throughput = throughput * attenuation;
+                    if Real::max(throughput.x, Real::max(throughput.y, throughput.z))
+                        < MIN_THROUGHPUT
+                    {
+                        return emission_color;
+                    }
+                    return emission_color
+                        + attenuation
+                            * self.path_color(
+                                &scattered,
+                                entity_list,
+                                bounce_idx + 1,
+                                throughput,
+                                rays,
+                                None,
+                            );
+                }
+                // Materials that report a density are continued along their own sample; the rest
+                // get a cosine-distributed continuation, whose basis also serves light sampling
+                let basis = (pdf_value <= 0.0).then(|| CosinePDF::new(&record.normal));
+                if let Some(basis) = &basis {
+                    scattered = Ray::new(record.position, basis.generate());
+                    pdf_value = basis.value(&scattered.direction());
+                    scattered.time = ray.time;
+                }
+                let continuation_pdf = |direction: &Vec3| match &basis {
+                    Some(basis) => basis.value(direction),
+                    None => material.sampling_pdf(&record, direction),
+                };
                 let scatter_pdf = material.scatter_pdf(ray, &record, &scattered);
+                let weight = attenuation * scatter_pdf / pdf_value;
+                debug_assert!(
+                    weight.is_finite(),
+                    "Non-finite path weight {:?} at {:?} (pdf {})",
+                    weight,
+                    record.position,
+                    pdf_value
+                );
+                let throughput = throughput * weight;
+                if Real::max(throughput.x, Real::max(throughput.y, throughput.z)) < MIN_THROUGHPUT {
+                    return emission_color;
+                }
+                let direct_light = if self.lights.0.is_empty() {
+                    Vec3::zero()
+                } else {
+                    self.sample_light(
+                        ray,
+                        &record,
+                        &continuation_pdf,
+                        attenuation,
+                        entity_list,
+                        rays,
+                    )
+                };
                 let bounce_idx = bounce_idx + 1;
-                let scatter_color = (attenuation
-                    * scatter_pdf
-                    * self.ray_color(&scattered, entity_list, bounce_idx))
-                    / pdf_value;
-                emission_color + scatter_color
+                emission_color
+                    + direct_light
+                    + weight
+                        * self.path_color(
+                            &scattered,
+                            entity_list,
+                            bounce_idx,
+                            throughput,
+                            rays,
+                            Some(pdf_value),
+                        )
             } else {
                 emission_color
             }
         } else {
             /*
-            let unit_vec = ray.direction.normalize();
+            let unit_vec = ray.direction().normalize();
             let t = 0.5 * (unit_vec.y + 1.0);
             (1.0 - t) * vec3(1.0, 1.0, 1.0) + t * vec3(0.5, 0.7, 1.0)
             */
-            self.background_color
+            match &self.environment {
+                Some(environment) => environment.sample(&ray.direction()),
+                None => self.background_color,
+            }
         }
     }
 
+    /// Albedo and shading normal at the first hit of `ray`, used as denoiser guides. Misses
+    /// report the background as albedo and a zero normal.
+    pub fn first_hit_aovs(&self, ray: &Ray, entity_list: &EntityList) -> (Vec3, Vec3) {
+        let mut record = HitRecord::new();
+        if !entity_list.hit(ray, &ray.hit_interval(self.ray_epsilon), &mut record) {
+            let background = match &self.environment {
+                Some(environment) => environment.sample(&ray.direction()),
+                None => self.background_color,
+            };
+            return (background, Vec3::zero());
+        }
+        let material = record.material.expect("Material should never be empty");
+        record.normal = material.shading_normal(&record);
+        let mut attenuation = Vec3::zero();
+        let mut scattered = Ray::default();
+        let mut pdf = 0.0;
+        let albedo = if material.scatter(ray, &record, &mut attenuation, &mut scattered, &mut pdf) {
+            attenuation
+        } else {
+            // Guides are expected in [0, 1], so emitters are clamped
+            let emitted = material.emitted(ray, &record, &record.uv, &record.position);
+            Vec3::new(emitted.x.min(1.0), emitted.y.min(1.0), emitted.z.min(1.0))
+        };
+        (albedo, record.normal)
+    }
+
+    /// Distance to and geometric normal of the first surface along `ray`. Misses report an
+    /// infinite distance and a zero normal.
+    pub fn first_hit_geometry(&self, ray: &Ray, entity_list: &EntityList) -> (Real, Vec3) {
+        let mut record = HitRecord::new();
+        if !entity_list.hit(ray, &ray.hit_interval(self.ray_epsilon), &mut record) {
+            return (Real::INFINITY, Vec3::zero());
+        }
+        ((record.position - ray.origin).length(), record.normal)
+    }
+
+    /// Traces a single ray through the centre of pixel (x, y), ignoring depth of field, and
+    /// describes the first surface it hits.
+    pub fn pick(&self, x: u32, y: u32, entity_list: &EntityList) -> Option<PickInfo> {
+        let ray = self.center_ray(x, y);
+        let mut record = HitRecord::new();
+        if !entity_list.hit(&ray, &ray.hit_interval(self.ray_epsilon), &mut record) {
+            return None;
+        }
+        let material = record.material.expect("Material should never be empty");
+        Some(PickInfo {
+            position: record.position,
+            normal: record.normal,
+            distance: (record.position - ray.origin).length(),
+            material: material_name(material.as_ref()),
+        })
+    }
+
+    /// Ray from the centre of the lens through the centre of pixel (x, y).
+    pub fn center_ray(&self, x: u32, y: u32) -> Ray {
+        let pixel_center =
+            self.pixel_origin + (x as Real * self.pixel_delta_x) + (y as Real * self.pixel_delta_y);
+        Ray::new(self.camera_position, pixel_center - self.camera_position)
+    }
+
     fn sample_square_stratified(&self, i: u32, j: u32) -> Vec3 {
         Vec3::new(
-            ((i as f32 + rand_f32()) * self.recip_sqrt_spp) - 0.5,
-            ((j as f32 + rand_f32()) * self.recip_sqrt_spp) - 0.5,
+            ((i as Real + rand_f32()) * self.recip_sqrt_spp) - 0.5,
+            ((j as Real + rand_f32()) * self.recip_sqrt_spp) - 0.5,
             0.0,
         )
     }
 
-    pub fn defocus_disk_sample(&self) -> Vec3 {
-        let p = random_disk_vec3();
+    /// Point on the unit lens disk for pixel stratum (i, j). The lens strata are a
+    /// permutation of the pixel strata so pixel and lens positions stay decorrelated.
+    fn lens_sample_stratified(&self, i: u32, j: u32) -> Vec3 {
+        let strata = self.sqrt_spp.max(1);
+        let count = strata * strata;
+        // Any multiplier coprime with the stratum count gives a permutation
+        let multiplier = (0..)
+            .map(|k| 7 + 2 * k)
+            .find(|m: &u32| gcd(*m, count) == 1)
+            .unwrap();
+        let lens_index = ((i * strata + j) as u64 * multiplier as u64 % count as u64) as u32;
+        let (li, lj) = (lens_index / strata, lens_index % strata);
+        concentric_disk_vec3(
+            (li as Real + rand_f32()) / strata as Real,
+            (lj as Real + rand_f32()) / strata as Real,
+        )
+    }
+
+    pub fn defocus_disk_sample(&self, i: u32, j: u32) -> Vec3 {
+        let p = self.lens_sample_stratified(i, j);
         self.camera_position + (p[0] * self.defocus_disk_u) + (p[1] * self.defocus_disk_v)
     }
 
     pub fn get_ray(&self, x: u32, y: u32, i: u32, j: u32) -> Ray {
         let offset = self.sample_square_stratified(i, j);
         let pixel_pos = self.pixel_origin // pixel _origin is the center of the pixel
-            + ((x as f32 + offset.x) * self.pixel_delta_x)
-            + ((y as f32 + offset.y) * self.pixel_delta_y);
+            + ((x as Real + offset.x) * self.pixel_delta_x)
+            + ((y as Real + offset.y) * self.pixel_delta_y);
         let ray_origin = if self.defocus_angle <= 0.0 {
             self.camera_position
         } else {
-            self.defocus_disk_sample()
+            self.defocus_disk_sample(i, j)
         };
         let ray_direction = pixel_pos - ray_origin;
+        // No draw for a closed shutter, so still images keep their random sequences
+        let time = if self.shutter > 0.0 {
+            self.shutter * rand_f32()
+        } else {
+            0.0
+        };
         Ray::new(ray_origin, ray_direction)
+            .with_differential(RayDifferential {
+                rx_origin: ray_origin,
+                rx_direction: (pixel_pos + self.pixel_delta_x - ray_origin).normalize(),
+                ry_origin: ray_origin,
+                ry_direction: (pixel_pos + self.pixel_delta_y - ray_origin).normalize(),
+            })
+            .with_time(time)
+    }
+
+    /// Ray of sample `sample_index` of pixel (x, y). Indices past the stratum count wrap
+    /// around, so every run of `sqrt_spp * sqrt_spp` consecutive samples covers each stratum
+    /// once.
+    pub fn sample_ray(&self, x: u32, y: u32, sample_index: u32) -> Ray {
+        let strata = self.sqrt_spp.max(1);
+        let stratum = sample_index % (strata * strata);
+        self.get_ray(x, y, stratum % strata, stratum / strata)
+    }
+
+    /// All `sqrt_spp * sqrt_spp` stratified rays of pixel (x, y), paired with their sample
+    /// index. Each ray is drawn from its sample's camera stream, so it is the ray
+    /// `render::trace_sample` traces for that index and pulling it leaves the tracing
+    /// generator untouched.
+    pub fn rays_for_pixel(&self, x: u32, y: u32) -> impl Iterator<Item = (u32, Ray)> + '_ {
+        (0..self.sqrt_spp * self.sqrt_spp).map(move |sample_index| {
+            let ray = with_camera_rng(self.seed, x, y, sample_index, || {
+                self.sample_ray(x, y, sample_index)
+            });
+            (sample_index, ray)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aabb::{HasAABB, AABB};
+    use crate::accumulator::RenderOutput;
+    use crate::assert_vec3_approx;
+    use crate::entities::quad::{create_box, Quad};
+    use crate::entities::sphere::Sphere;
+    use crate::interval::Interval;
+    use crate::material::{Conductor, Dielectric, DiffuseLight, Lambertian, Material, Metal};
+    use crate::math::rand::{rand_in_unit_disk, seed_sample_rng};
+    use crate::render::{render, CancelToken};
+    use crate::texture::Texture;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    /// Sphere that counts how many rays were tested against it.
+    struct CountingSphere {
+        sphere: Sphere,
+        tests: Arc<AtomicU32>,
+    }
+
+    impl HasAABB for CountingSphere {
+        fn get_aabb(&self) -> AABB {
+            self.sphere.get_aabb()
+        }
+
+        fn compute_aabb(&self) -> AABB {
+            self.sphere.compute_aabb()
+        }
+    }
+
+    impl Transformable for CountingSphere {
+        fn translate(&mut self, translation: Vec3) {
+            self.sphere.translate(translation);
+        }
+
+        fn rotate(&mut self, axis: Vec3, angle: Real) {
+            self.sphere.rotate(axis, angle);
+        }
+    }
+
+    impl Hittable for CountingSphere {
+        fn hit<'a>(&'a self, ray: &Ray, t_interval: &Interval, record: &mut HitRecord<'a>) -> bool {
+            self.tests.fetch_add(1, Ordering::Relaxed);
+            self.sphere.hit(ray, t_interval, record)
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_camera_json_round_trip() {
+        let mut camera = Camera::new(
+            320,
+            240,
+            40.0,
+            &Vec3::new(278.0, 278.0, -800.0),
+            &Vec3::new(278.0, 278.0, 0.0),
+        );
+        camera.set_background_color(&Vec3::new(0.7, 0.8, 1.0));
+        camera.set_sample_clamp(Some(20.0));
+
+        let json = serde_json::to_string(&camera).unwrap();
+        let restored: Camera = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, camera);
+    }
+
+    /// Average radiance leaving a grey floor of half-width `scale`, centred far from the origin
+    /// under a white sky. With `ceiling`, a black quad hangs `0.1 * scale` above the floor and
+    /// the camera looks down from between the two.
+    fn floor_radiance(scale: Real, ceiling: bool) -> Real {
+        let center = Vec3::one() * 10.0 * scale;
+        let quad = |height: Real, albedo: Real| {
+            Box::new(Quad::new(
+                center + Vec3::new(-scale, height, -scale),
+                Vec3::new(2.0 * scale, 0.0, 0.0),
+                Vec3::new(0.0, 0.0, 2.0 * scale),
+                Arc::new(Lambertian::new(Box::new(Texture::new(
+                    Vec3::one() * albedo,
+                )))),
+            ))
+        };
+        let mut entities = EntityList::new();
+        entities.add(quad(0.0, 0.5));
+        if ceiling {
+            entities.add(quad(0.1 * scale, 0.0));
+        }
+        let eye = center + Vec3::new(0.0, 0.05 * scale, 0.0);
+        let mut camera = Camera::new(4, 4, 40.0, &eye, &(eye + Vec3::new(0.0, 0.0, 1.0)));
+        camera.set_background_color(&Vec3::one());
+        seed_sample_rng(0, 0, 0, 0);
+        let samples = 4096;
+        (0..samples)
+            .map(|_| {
+                let ray = Ray::new(eye, Vec3::new(0.0, -1.0, 0.0));
+                camera.ray_color(&ray, &entities, 0).x
+            })
+            .sum::<Real>()
+            / samples as Real
+    }
+
+    #[test]
+    fn test_ray_offset_follows_the_scene_scale() {
+        for scale in [1e-3, 1e4] {
+            // Nothing but the sky above the floor: any darkening is self-intersection
+            let open = floor_radiance(scale, false);
+            assert!((open - 0.5).abs() < 0.01, "scale {}: acne, {}", scale, open);
+            // A black ceiling close above: anything but a little grazing sky is a leak
+            let covered = floor_radiance(scale, true);
+            assert!(covered < 0.05, "scale {}: leak, {}", scale, covered);
+        }
+    }
+
+    /// Cameras at a few positions and orientations, none looking along the up axis.
+    fn test_cameras(width: u32, height: u32, vfov: Real) -> Vec<(Camera, Vec3, Vec3)> {
+        [
+            (Vec3::new(0.0, 0.0, 5.0), Vec3::zero()),
+            (
+                Vec3::new(278.0, 278.0, -800.0),
+                Vec3::new(278.0, 278.0, 0.0),
+            ),
+            (Vec3::new(13.0, 2.0, 3.0), Vec3::new(0.0, 0.0, 0.0)),
+            (Vec3::new(-4.0, 6.0, 1.0), Vec3::new(1.0, -2.0, 3.0)),
+        ]
+        .into_iter()
+        .map(|(from, at)| (Camera::new(width, height, vfov, &from, &at), from, at))
+        .collect()
+    }
+
+    #[test]
+    fn test_center_pixel_ray_points_at_look_at() {
+        let (width, height, vfov) = (101, 75, 40.0);
+        let pixel_angle = degrees_to_radians(vfov) / height as Real;
+        for (mut camera, from, at) in test_cameras(width, height, vfov) {
+            camera.set_samples_per_pixel(1);
+            let ray = camera.get_ray(width / 2, height / 2, 0, 0);
+            assert_eq!(ray.origin, from);
+            let forward = (at - from).normalize();
+            let angle = dot(&ray.direction(), &forward).min(1.0).acos();
+            assert!(angle <= pixel_angle, "{} rad off the view axis", angle);
+        }
+    }
+
+    #[test]
+    fn test_corner_pixel_rays_stay_within_the_field_of_view() {
+        let (width, height, vfov) = (64, 36, 50.0);
+        let tan_half_v = Real::tan(degrees_to_radians(vfov) / 2.0);
+        let tan_half_h = tan_half_v * width as Real / height as Real;
+        for (mut camera, from, at) in test_cameras(width, height, vfov) {
+            camera.set_samples_per_pixel(16);
+            let forward = (at - from).normalize();
+            let right = cross(&forward, &UP).normalize();
+            let up = cross(&right, &forward);
+            for (x, y, sign_x, sign_y) in [
+                (0, 0, -1.0, 1.0),
+                (width - 1, 0, 1.0, 1.0),
+                (0, height - 1, -1.0, -1.0),
+                (width - 1, height - 1, 1.0, -1.0),
+            ] {
+                for (i, j) in [(0, 0), (3, 3), (1, 2)] {
+                    let direction = camera.get_ray(x, y, i, j).direction();
+                    let depth = dot(&direction, &forward);
+                    assert!(depth > 0.0);
+                    let (tan_x, tan_y) = (
+                        sign_x * dot(&direction, &right) / depth,
+                        sign_y * dot(&direction, &up) / depth,
+                    );
+                    // Inside the frustum, and within one pixel of its edge
+                    assert!(
+                        tan_x <= tan_half_h * 1.0001
+                            && tan_x >= tan_half_h * (1.0 - 2.0 / width as Real)
+                    );
+                    assert!(
+                        tan_y <= tan_half_v * 1.0001
+                            && tan_y >= tan_half_v * (1.0 - 2.0 / height as Real)
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_sample_counts_round_to_a_full_grid_of_strata() {
+        let mut camera = Camera::new(32, 24, 40.0, &Vec3::new(0.0, 0.0, 5.0), &Vec3::zero());
+        for (requested, taken, strata) in [(1, 1, 1), (4, 4, 2), (16, 16, 4), (1000, 1024, 32)] {
+            camera.set_samples_per_pixel(requested);
+            assert_eq!(camera.samples_per_pixel, taken);
+            assert_eq!(camera.sqrt_spp, strata);
+            assert_eq!(camera.rays_for_pixel(3, 4).count() as u32, taken);
+            assert!((camera.pixel_samples_scale * taken as Real - 1.0).abs() < 1e-6);
+            // Each stratum jitters within its own cell, and together they tile the pixel
+            let cell = 1.0 / strata as Real;
+            for i in 0..strata {
+                for j in 0..strata {
+                    for _ in 0..8 {
+                        let offset = camera.sample_square_stratified(i, j);
+                        let (x0, y0) = (i as Real * cell - 0.5, j as Real * cell - 0.5);
+                        assert!(offset.x >= x0 && offset.x <= x0 + cell, "{:?}", offset);
+                        assert!(offset.y >= y0 && offset.y <= y0 + cell, "{:?}", offset);
+                    }
+                }
+            }
+        }
+
+        // A single sample is spread over the whole pixel, centred on average
+        camera.set_samples_per_pixel(1);
+        let count = 4000;
+        let (mut mean, mut extent) = (Vec3::zero(), 0.0 as Real);
+        for _ in 0..count {
+            let offset = camera.sample_square_stratified(0, 0);
+            mean += offset / count as Real;
+            extent = extent.max(offset.x.abs()).max(offset.y.abs());
+        }
+        assert!(mean.length() < 0.02, "mean offset {:?}", mean);
+        assert!(extent > 0.45 && extent <= 0.5, "extent {}", extent);
+
+        camera.set_samples_per_pixel(0);
+        assert_eq!(camera.samples_per_pixel, 1);
+    }
+
+    #[test]
+    fn test_getters_reflect_settings() {
+        let look_from = Vec3::new(0.0, 1.0, 5.0);
+        let mut camera = Camera::new(16, 8, 40.0, &look_from, &Vec3::zero());
+        camera.set_samples_per_pixel(10);
+        camera.set_max_ray_bounces(7);
+        camera.set_defocus_angle(2.0);
+        camera.set_background_color(&Vec3::new(0.1, 0.2, 0.3));
+        assert_eq!(camera.samples_per_pixel(), 9);
+        assert_eq!(camera.sqrt_spp(), 3);
+        assert_eq!(camera.pixel_samples_scale(), 1.0 / 9.0);
+        assert_eq!(camera.max_ray_bounces, 7);
+        assert_eq!(camera.defocus_angle(), 2.0);
+        assert_eq!(camera.background_color, Vec3::new(0.1, 0.2, 0.3));
+        assert_eq!(camera.camera_position, look_from);
+        // The top-left pixel sits half a pixel in from the viewport's corner
+        let corner = camera.pixel_origin - 0.5 * (camera.pixel_delta_x + camera.pixel_delta_y);
+        let center = corner + 8.0 * camera.pixel_delta_x + 4.0 * camera.pixel_delta_y;
+        assert!((center - look_from)
+            .normalize()
+            .approx_eq(&(-look_from).normalize(), 1e-5));
+    }
+
+    #[test]
+    fn test_rays_for_pixel_yield_one_finite_ray_per_stratum() {
+        for (mut camera, from, _) in test_cameras(32, 24, 40.0) {
+            for samples in [1, 10, 64] {
+                camera.set_samples_per_pixel(samples);
+                let strata = camera.sqrt_spp * camera.sqrt_spp;
+                let rays: Vec<(u32, Ray)> = camera.rays_for_pixel(7, 19).collect();
+                assert_eq!(rays.len() as u32, strata);
+                assert!(strata <= samples && strata > samples / 2);
+                for (sample_index, ray) in &rays {
+                    assert!(ray.origin.is_finite() && ray.direction().is_finite());
+                    assert_eq!(ray.origin, from);
+                    // Each ray is the one `trace_sample` generates for its sample
+                    let expected = with_camera_rng(camera.seed, 7, 19, *sample_index, || {
+                        camera.sample_ray(7, 19, *sample_index)
+                    });
+                    assert_eq!(ray.direction(), expected.direction());
+                }
+                // Pulling rays leaves the tracing stream where it was
+                seed_sample_rng(camera.seed, 0, 0, 0);
+                let expected = rand_f32();
+                seed_sample_rng(camera.seed, 0, 0, 0);
+                assert_eq!(camera.rays_for_pixel(7, 19).count() as u32, strata);
+                assert_eq!(rand_f32(), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_defocus_disk_samples_lie_on_the_lens() {
+        let radius = 0.25;
+        for (mut camera, from, at) in test_cameras(32, 32, 40.0) {
+            camera.set_samples_per_pixel(64);
+            let forward = (at - from).normalize();
+            camera.defocus_angle = 2.0;
+            camera.defocus_disk_u = cross(&forward, &UP).normalize() * radius;
+            camera.defocus_disk_v = cross(&camera.defocus_disk_u, &forward).normalize() * radius;
+            let mut farthest: Real = 0.0;
+            for i in 0..camera.sqrt_spp {
+                for j in 0..camera.sqrt_spp {
+                    // Rays leave from the lens too, each with its own jittered sample
+                    let origins = [
+                        camera.defocus_disk_sample(i, j),
+                        camera.get_ray(5, 7, i, j).origin,
+                    ];
+                    for offset in origins.map(|origin| origin - from) {
+                        assert!(
+                            offset.length() <= radius * 1.0001,
+                            "{:?} is off the lens",
+                            offset
+                        );
+                        assert!(dot(&offset, &forward).abs() < 1e-4 * from.length().max(1.0));
+                        farthest = farthest.max(offset.length());
+                    }
+                }
+            }
+            assert!(farthest > 0.8 * radius, "samples only reach {}", farthest);
+        }
+    }
+
+    #[test]
+    fn test_mirrors_and_glass_show_the_background() {
+        let cases: Vec<(&str, Arc<dyn Material>, Real)> = vec![
+            (
+                "mirror",
+                Arc::new(Metal {
+                    albedo: Vec3::new(0.8, 0.8, 0.8),
+                    fuzz: 0.0,
+                }),
+                0.8,
+            ),
+            (
+                "glass",
+                Arc::new(Dielectric {
+                    refraction_index: 1.5,
+                }),
+                1.0,
+            ),
+            ("gold", Arc::new(Conductor::gold()), 0.5),
+        ];
+        for (name, material, brightness) in cases {
+            let mut entities = EntityList::new();
+            entities.add(Box::new(Sphere::new(Vec3::zero(), 1.0, material)));
+            // Every pixel looks at the sphere, which can only show the white background
+            let mut camera = Camera::new(8, 8, 10.0, &Vec3::new(0.0, 0.0, 4.0), &Vec3::zero());
+            camera.set_background_color(&Vec3::one());
+            camera.set_samples_per_pixel(16);
+            camera.seed = 9;
+            let output = render(2, (8, 8), &entities, &camera, &CancelToken::new(), |_| 16);
+            let mean = output.colors.iter().map(|c| c.luminance()).sum::<Real>() / 64.0;
+            assert!(mean >= 0.95 * brightness, "{} renders at {}", name, mean);
+        }
+    }
+
+    /// Renders `entities` from (0, 0, 4) looking at the origin with a 30 degree field of view,
+    /// so a unit sphere at the origin just fills a `size` x `size` image.
+    fn render_unit_sphere_view(
+        entities: &EntityList,
+        background: Vec3,
+        size: u32,
+        samples_per_pixel: u32,
+    ) -> RenderOutput {
+        let mut camera = Camera::new(size, size, 30.0, &Vec3::new(0.0, 0.0, 4.0), &Vec3::zero());
+        camera.set_background_color(&background);
+        camera.set_samples_per_pixel(samples_per_pixel);
+        camera.seed = 3;
+        let spp = camera.samples_per_pixel();
+        render(
+            2,
+            (size, size),
+            entities,
+            &camera,
+            &CancelToken::new(),
+            |_| spp,
+        )
+    }
+
+    #[test]
+    fn test_gold_brightens_towards_its_rim() {
+        let gold = Conductor::gold();
+        let normal = gold.reflectance(1.0);
+        let mut entities = EntityList::new();
+        entities.add(Box::new(Sphere::new(Vec3::zero(), 1.0, Arc::new(gold))));
+        let output = render_unit_sphere_view(&entities, Vec3::one(), 64, 16);
+
+        // Under a white sky each pixel shows the Fresnel reflectance at its incidence angle
+        let center = output.colors[32 * 64 + 32];
+        assert_vec3_approx!(center, normal, 0.01);
+        // Near the silhouette the light arrives at about 75 degrees, where blue has risen
+        // from a third to about a half while red stays near one
+        let rim = output.colors[32 * 64 + 61];
+        assert!(rim.z > center.z + 0.1, "blue {} at the rim", rim.z);
+        assert!((rim.x - center.x).abs() < 0.02, "red {} at the rim", rim.x);
+    }
+
+    #[test]
+    fn test_glass_slab_reflects_alike_entering_and_leaving() {
+        // A thin glass slab over a white floor light, seen at 70 degrees from its normal
+        // through a narrow lens, so every camera ray arrives at nearly that angle
+        let glass: Arc<dyn Material> = Arc::new(Dielectric {
+            refraction_index: 1.5,
+        });
+        let light: Arc<dyn Material> =
+            Arc::new(DiffuseLight::new(Box::new(Texture::new(Vec3::one()))));
+        let mut entities = EntityList::new();
+        entities.add(Box::new(create_box(
+            Vec3::new(-50.0, -0.5, -50.0),
+            Vec3::new(50.0, 0.0, 50.0),
+            glass,
+        )));
+        entities.add(Box::new(Quad::new(
+            Vec3::new(-50.0, -2.0, 50.0),
+            Vec3::new(100.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, -100.0),
+            light,
+        )));
+        let angle = (70.0 as Real).to_radians();
+        let eye = 3.0 * Vec3::new(0.0, angle.cos(), -angle.sin());
+        let mut camera = Camera::new(16, 16, 1.0, &eye, &Vec3::zero());
+        camera.set_background_color(&Vec3::zero());
+        camera.set_samples_per_pixel(64);
+        camera.seed = 5;
+        let output = render(2, (16, 16), &entities, &camera, &CancelToken::new(), |_| 64);
+        let mean = output.colors.iter().map(|c| c.x).sum::<Real>() / 256.0;
+
+        // Light that gets in leaves the far side parallel to the camera ray, and reaches the
+        // floor unless it is reflected back up. Both faces reflect the same fraction r, so
+        // summing over the bounces inside the slab lets through (1 - r) / (1 + r)
+        let r = Dielectric::reflectance(angle.cos(), 1.0 / 1.5);
+        let expected = (1.0 - r) / (1.0 + r);
+        assert!(
+            (mean - expected).abs() < 0.02,
+            "{} transmitted where {} was expected",
+            mean,
+            expected
+        );
+    }
+
+    #[test]
+    fn test_hollow_glass_shell_does_not_flip_the_view() {
+        // Behind the sphere a light fills the left half of the view and the right stays dark
+        let half_lit = |shell: bool| {
+            let glass: Arc<dyn Material> = Arc::new(Dielectric {
+                refraction_index: 1.5,
+            });
+            let mut entities = EntityList::new();
+            entities.add(Box::new(Sphere::new(Vec3::zero(), 1.0, Arc::clone(&glass))));
+            if shell {
+                entities.add(Box::new(Sphere::new(Vec3::zero(), -0.95, glass)));
+            }
+            entities.add(Box::new(Quad::new(
+                Vec3::new(-10.0, -10.0, -3.0),
+                Vec3::new(10.0, 0.0, 0.0),
+                Vec3::new(0.0, 20.0, 0.0),
+                Arc::new(DiffuseLight::new(Box::new(Texture::new(Vec3::one())))),
+            )));
+            let output = render_unit_sphere_view(&entities, Vec3::zero(), 32, 16);
+            // Patches on either side of the center, well inside the sphere's outline
+            let patch_mean = |columns: std::ops::Range<usize>| {
+                let pixels = (13..19).flat_map(|y| columns.clone().map(move |x| y * 32 + x));
+                let count = pixels.clone().count() as Real;
+                pixels.map(|index| output.colors[index].x).sum::<Real>() / count
+            };
+            (patch_mean(6..12), patch_mean(20..26))
+        };
+
+        // A solid ball is a lens that turns the scene behind it upside down
+        let (left, right) = half_lit(false);
+        assert!(
+            left < 0.2 && right > 0.5,
+            "solid ball: {} and {}",
+            left,
+            right
+        );
+        // Through a thin shell with an air bubble inside, rays go nearly straight
+        let (left, right) = half_lit(true);
+        assert!(
+            left > 0.5 && right < 0.2,
+            "hollow shell: {} and {}",
+            left,
+            right
+        );
+    }
+
+    #[test]
+    fn test_concentric_mapping_preserves_area() {
+        assert_eq!(concentric_disk_vec3(0.5, 0.5), Vec3::zero());
+        // The square's boundary lands on the rim
+        for (u, v) in [(0.0, 0.0), (1.0, 0.3), (0.7, 1.0), (0.0, 0.5)] {
+            assert!((concentric_disk_vec3(u, v).length() - 1.0).abs() < 1e-5);
+        }
+
+        // Cell centers of a fine grid fill equal-area rings and sectors equally
+        let (grid, rings, sectors) = (64, 4, 8);
+        let mut counts = vec![0; rings * sectors];
+        for x in 0..grid {
+            for y in 0..grid {
+                let p = concentric_disk_vec3(
+                    (x as Real + 0.5) / grid as Real,
+                    (y as Real + 0.5) / grid as Real,
+                );
+                assert!(p.length() <= 1.0 && p.z == 0.0);
+                let ring = ((p.length_squared() * rings as Real) as usize).min(rings - 1);
+                let angle = p.y.atan2(p.x).rem_euclid(crate::math::consts::TAU);
+                let sector = ((angle / crate::math::consts::TAU * sectors as Real) as usize)
+                    .min(sectors - 1);
+                counts[ring * sectors + sector] += 1;
+            }
+        }
+        let expected = (grid * grid / (rings * sectors)) as i32;
+        for count in counts {
+            assert!(
+                (count - expected).abs() <= expected / 10,
+                "{} points where {} were expected",
+                count,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_generated_directions_are_unit_and_finite() {
+        for (mut camera, _, _) in test_cameras(16, 9, 70.0) {
+            camera.set_samples_per_pixel(4);
+            for y in 0..9 {
+                for x in 0..16 {
+                    for (i, j) in [(0, 0), (0, 1), (1, 0), (1, 1)] {
+                        let ray = camera.get_ray(x, y, i, j);
+                        assert!(ray.origin.is_finite() && ray.direction().is_finite());
+                        assert!((ray.direction().length() - 1.0).abs() < 1e-5);
+                        let differential =
+                            ray.differential.expect("primary rays carry differentials");
+                        assert!((differential.rx_direction.length() - 1.0).abs() < 1e-5);
+                        assert!((differential.ry_direction.length() - 1.0).abs() < 1e-5);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Chi-square statistic of `points` binned into 16 equal-area cells of the unit disk.
+    fn disk_chi_square(points: &[Vec3]) -> Real {
+        let mut bins = [0u32; 16];
+        for p in points {
+            let ring = ((p.length_squared() * 4.0) as usize).min(3);
+            let angle = p.y.atan2(p.x) + crate::math::consts::PI;
+            let sector = ((angle / (2.0 * crate::math::consts::PI) * 4.0) as usize).min(3);
+            bins[ring * 4 + sector] += 1;
+        }
+        let expected = points.len() as Real / 16.0;
+        bins.iter()
+            .map(|&count| (count as Real - expected).powi(2) / expected)
+            .sum()
+    }
+
+    #[test]
+    fn test_stratified_lens_samples_are_more_even_than_rejection() {
+        let mut camera = Camera::new(4, 4, 90.0, &Vec3::new(0.0, 0.0, 1.0), &Vec3::zero());
+        camera.set_samples_per_pixel(64);
+
+        let (mut stratified, mut rejection) = (0.0, 0.0);
+        for _ in 0..50 {
+            let mut lens = Vec::new();
+            for i in 0..camera.sqrt_spp {
+                for j in 0..camera.sqrt_spp {
+                    lens.push(camera.lens_sample_stratified(i, j));
+                }
+            }
+            stratified += disk_chi_square(&lens);
+
+            let random: Vec<Vec3> = (0..lens.len()).map(|_| rand_in_unit_disk()).collect();
+            rejection += disk_chi_square(&random);
+        }
+        assert!(
+            stratified < 0.5 * rejection,
+            "stratified {} vs rejection {}",
+            stratified,
+            rejection
+        );
+    }
+
+    #[test]
+    fn test_clamp_sample_bounds_luminance() {
+        let mut camera = Camera::new(4, 4, 90.0, &Vec3::new(0.0, 0.0, 1.0), &Vec3::zero());
+        let firefly = Vec3::new(500.0, 200.0, 50.0);
+        assert_eq!(camera.clamp_sample(firefly), firefly);
+
+        camera.set_sample_clamp(Some(10.0));
+        let clamped = camera.clamp_sample(firefly);
+        assert!(clamped.luminance() <= 10.0 + 1e-4);
+        // Hue is preserved, only the magnitude is scaled down
+        assert!((clamped.x / clamped.y - firefly.x / firefly.y).abs() < 1e-4);
+
+        let dim = Vec3::new(0.5, 0.5, 0.5);
+        assert_eq!(camera.clamp_sample(dim), dim);
+    }
+
+    #[test]
+    fn test_absorbing_scene_stops_after_first_bounce() {
+        let tests = Arc::new(AtomicU32::new(0));
+        let mut entities = EntityList::new();
+        entities.add(Box::new(CountingSphere {
+            sphere: Sphere::new(
+                Vec3::zero(),
+                5.0,
+                Arc::new(Lambertian::new(Box::new(Texture::new(Vec3::zero())))),
+            ),
+            tests: Arc::clone(&tests),
+        }));
+        let camera = Camera::new(8, 8, 60.0, &Vec3::zero(), &Vec3::new(0.0, 0.0, -1.0));
+        for y in 0..8 {
+            for x in 0..8 {
+                let color = camera.ray_color(&camera.get_ray(x, y, 0, 0), &entities, 0);
+                assert_eq!(color, Vec3::zero());
+            }
+        }
+        assert_eq!(tests.load(Ordering::Relaxed), 64);
+        // Other tests trace rays concurrently, so the global count can only be bounded below
+        assert!(rays_traced() >= 64);
+    }
+
+    #[test]
+    fn test_focus_on_look_at_brings_it_into_focus() {
+        let (width, height, vfov) = (101, 75, 40.0);
+        for (mut camera, from, at) in test_cameras(width, height, vfov) {
+            camera.set_samples_per_pixel(16);
+            camera.set_defocus_angle(3.0);
+            camera.focus_on(at);
+            let distance = (from - at).length();
+            assert!((camera.focus_distance() - distance).abs() < 1e-5 * distance);
+            // Every lens sample of the centre pixel passes within that pixel of look_at
+            let pixel_size = camera.pixel_delta_x.length();
+            let forward = (at - from).normalize();
+            for i in 0..camera.sqrt_spp {
+                for j in 0..camera.sqrt_spp {
+                    let ray = camera.get_ray(width / 2, height / 2, i, j);
+                    let t = dot(&(at - ray.origin), &forward) / dot(&ray.direction(), &forward);
+                    let on_focus_plane = ray.at(t);
+                    assert!(
+                        (on_focus_plane - at).length() < pixel_size,
+                        "{:?} vs {:?}",
+                        on_focus_plane,
+                        at
+                    );
+                }
+            }
+            // The field of view is unchanged
+            let expected = 2.0 * distance * Real::tan(degrees_to_radians(vfov) / 2.0);
+            let viewport_height = height as Real * camera.pixel_delta_y.length();
+            assert!((viewport_height - expected).abs() < 1e-4 * expected);
+        }
+    }
+
+    #[test]
+    fn test_focus_on_measures_depth_along_the_view_direction() {
+        let from = Vec3::new(0.0, 0.0, 5.0);
+        let mut camera = Camera::new(64, 48, 60.0, &from, &Vec3::zero());
+        camera.set_defocus_angle(3.0);
+        camera.set_focus_distance(2.0);
+        // Near the image's corner, where the straight-line distance of about 5.45 overshoots
+        // the plane through the point
+        camera.focus_on(Vec3::new(1.8, 1.2, 0.0));
+        assert!((camera.focus_distance() - 5.0).abs() < 1e-5);
+
+        // Points beside or behind the lens leave the focus alone
+        camera.focus_on(Vec3::new(3.0, 0.0, 5.0));
+        camera.focus_on(Vec3::new(0.0, 0.0, 9.0));
+        assert!((camera.focus_distance() - 5.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_framed_camera_sees_the_whole_bounding_sphere() {
+        let (center, radius) = (Vec3::new(40.0, -3.0, 12.0), 7.5);
+        for (width, height) in [(160, 90), (90, 160)] {
+            let mut camera = Camera::new(
+                width,
+                height,
+                40.0,
+                &Vec3::new(3.0, 2.0, 10.0),
+                &Vec3::zero(),
+            );
+            assert_eq!(camera.image_size(), (width, height));
+            let view =
+                |camera: &Camera| cross(&camera.pixel_delta_x, &camera.pixel_delta_y).normalize();
+            let before = view(&camera);
+            camera.frame_scene(&center, radius);
+            assert_eq!(camera.image_size(), (width, height));
+            // Still looking the same way, now straight at the sphere
+            assert!(view(&camera).approx_eq(&before, 1e-5));
+            assert!((center - camera.camera_position)
+                .normalize()
+                .approx_eq(&before, 1e-5));
+
+            // Frustum side planes through the camera and the viewport corners
+            let top_left = camera.pixel_origin
+                - 0.5 * (camera.pixel_delta_x + camera.pixel_delta_y)
+                - camera.camera_position;
+            let across = width as Real * camera.pixel_delta_x;
+            let down = height as Real * camera.pixel_delta_y;
+            let corners = [
+                top_left,
+                top_left + across,
+                top_left + across + down,
+                top_left + down,
+            ];
+            let to_center = center - camera.camera_position;
+            let mut closest = Real::MAX;
+            let forward = top_left + 0.5 * (across + down);
+            for i in 0..4 {
+                let mut inward = cross(&corners[i], &corners[(i + 1) % 4]).normalize();
+                if dot(&inward, &forward) < 0.0 {
+                    inward = -inward;
+                }
+                closest = Real::min(closest, dot(&inward, &to_center));
+            }
+            assert!(closest >= radius * 0.999, "sphere crosses a side plane");
+            assert!(closest <= radius * 1.001, "framing is not tight");
+        }
+    }
+
+    /// Mean and variance of the luminance `camera` sees along `ray` over `count` samples.
+    fn luminance_statistics(
+        camera: &Camera,
+        ray: &Ray,
+        entities: &EntityList,
+        count: u32,
+    ) -> (Real, Real) {
+        let samples: Vec<Real> = (0..count)
+            .map(|i| {
+                seed_sample_rng(5, 0, 0, i);
+                camera.ray_color(ray, entities, 0).luminance()
+            })
+            .collect();
+        let mean = samples.iter().sum::<Real>() / count as Real;
+        let variance = samples
+            .iter()
+            .map(|s| (s - mean) * (s - mean))
+            .sum::<Real>()
+            / count as Real;
+        (mean, variance)
+    }
+
+    #[test]
+    fn test_light_sampling_matches_bsdf_sampling_with_less_variance() {
+        let mut entities = EntityList::new();
+        entities.add(Box::new(Quad::new(
+            Vec3::new(-5.0, 0.0, -5.0),
+            Vec3::new(10.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 10.0),
+            Arc::new(Lambertian::new(Box::new(Texture::new(Vec3::new(
+                0.7, 0.7, 0.7,
+            ))))),
+        )));
+        let light: Arc<dyn Hittable> = Arc::new(Quad::new(
+            Vec3::new(-0.5, 2.0, -0.5),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+            Arc::new(DiffuseLight::new(Box::new(Texture::new(
+                Vec3::one() * 15.0,
+            )))),
+        ));
+        entities.add(Box::new(Arc::clone(&light)));
+
+        let mut camera = Camera::new(8, 8, 60.0, &Vec3::new(0.0, 1.0, 4.0), &Vec3::zero());
+        let ray = Ray::new(Vec3::new(0.0, 1.0, 4.0), Vec3::new(0.3, -1.0, -4.0));
+        let (bsdf_mean, bsdf_variance) = luminance_statistics(&camera, &ray, &entities, 20000);
+        camera.set_lights(vec![light]);
+        let (mis_mean, mis_variance) = luminance_statistics(&camera, &ray, &entities, 20000);
+
+        let standard_error = Real::sqrt(bsdf_variance / 20000.0);
+        assert!(
+            (mis_mean - bsdf_mean).abs() < 4.0 * standard_error,
+            "MIS {} vs BSDF {} (standard error {})",
+            mis_mean,
+            bsdf_mean,
+            standard_error
+        );
+        assert!(
+            mis_variance < 0.5 * bsdf_variance,
+            "MIS variance {} vs BSDF {}",
+            mis_variance,
+            bsdf_variance
+        );
     }
 }
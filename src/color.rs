@@ -0,0 +1,141 @@
+use std::sync::OnceLock;
+
+use crate::math::{vec3::Vec3, Real};
+
+/// Transfer function applied to the linear radiance when it is quantized to 8-bit output.
+/// Rendering itself always happens in linear sRGB primaries.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum ColorSpace {
+    /// Piecewise sRGB curve, the inverse of the decoding applied to image textures.
+    #[default]
+    Srgb,
+    /// Square root (gamma 2), the encoding used before textures were decoded.
+    Gamma2,
+    /// No encoding, for tools that apply their own view transform.
+    Linear,
+}
+
+impl ColorSpace {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "srgb" => Some(ColorSpace::Srgb),
+            "gamma2" => Some(ColorSpace::Gamma2),
+            "linear" => Some(ColorSpace::Linear),
+            _ => None,
+        }
+    }
+
+    /// Encodes one linear channel; negative values map to 0.
+    pub fn encode(&self, value: Real) -> Real {
+        let value = value.max(0.0);
+        match self {
+            ColorSpace::Srgb => linear_to_srgb(value),
+            ColorSpace::Gamma2 => value.sqrt(),
+            ColorSpace::Linear => value,
+        }
+    }
+
+    /// Linear value of one channel stored in this encoding, the inverse of `encode`.
+    pub fn decode(&self, value: Real) -> Real {
+        match self {
+            ColorSpace::Srgb => srgb_to_linear(value),
+            ColorSpace::Gamma2 => value * value,
+            ColorSpace::Linear => value,
+        }
+    }
+}
+
+pub fn srgb_to_linear(value: Real) -> Real {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub fn linear_to_srgb(value: Real) -> Real {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Linear color of an 8-bit sRGB pixel, through a table since textures decode one per lookup.
+pub fn decode_srgb8(pixel: &[u8]) -> Vec3 {
+    static TABLE: OnceLock<[Real; 256]> = OnceLock::new();
+    let table = TABLE.get_or_init(|| std::array::from_fn(|i| srgb_to_linear(i as Real / 255.0)));
+    Vec3::new(
+        table[pixel[0] as usize],
+        table[pixel[1] as usize],
+        table[pixel[2] as usize],
+    )
+}
+
+/// Reads a scene-file color given either as linear components or as an sRGB hex string
+/// such as `"#ff8800"`.
+#[cfg(feature = "serde")]
+pub fn deserialize_color<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec3, D::Error> {
+    #[derive(serde::Deserialize)]
+    #[serde(untagged)]
+    enum Color {
+        Hex(String),
+        Linear(Vec3),
+    }
+    match <Color as serde::Deserialize>::deserialize(deserializer)? {
+        Color::Hex(hex) => Vec3::from_hex(&hex).ok_or_else(|| {
+            serde::de::Error::custom(format!("invalid hex color {:?}, expected #rrggbb", hex))
+        }),
+        Color::Linear(color) => Ok(color),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mid_gray_decodes_to_its_linear_value() {
+        // sRGB 128 is the familiar "middle gray" of about 21.6% linear reflectance
+        let gray = decode_srgb8(&[128, 128, 128]);
+        assert!((gray.x - 0.2158605).abs() < 1e-6, "{}", gray.x);
+        assert_eq!(gray.x, gray.y);
+        assert_eq!(decode_srgb8(&[0, 255, 0]), Vec3::new(0.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn test_encoding_inverts_decoding() {
+        for i in 0..=255u8 {
+            let linear = decode_srgb8(&[i, i, i]).x;
+            let encoded = ColorSpace::Srgb.encode(linear);
+            assert_eq!((encoded * 255.0).round() as u8, i);
+        }
+        assert_eq!(ColorSpace::Gamma2.encode(0.25), 0.5);
+        assert_eq!(ColorSpace::Linear.encode(-1.0), 0.0);
+        for space in [ColorSpace::Srgb, ColorSpace::Gamma2, ColorSpace::Linear] {
+            for value in [0.0, 0.02, 0.25, 0.5, 1.0] {
+                assert!((space.decode(space.encode(value)) - value).abs() < 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_scene_colors_accept_hex_strings() {
+        use crate::texture::TextureDesc;
+
+        let parse = |json: &str| serde_json::from_str::<TextureDesc>(json);
+        let hex = parse(r##"{"type": "solid", "color": "#ffffff"}"##).unwrap();
+        let linear = parse(r#"{"type": "solid", "color": {"x": 1.0, "y": 1.0, "z": 1.0}}"#);
+        assert_eq!(hex, linear.unwrap());
+        let error = parse(r##"{"type": "solid", "color": "#fff"}"##).unwrap_err();
+        assert!(error.to_string().contains("#rrggbb"), "{}", error);
+    }
+}
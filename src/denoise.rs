@@ -0,0 +1,208 @@
+use crate::camera::Camera;
+use crate::entities::entity::EntityList;
+use crate::math::{vec3::Vec3, Real};
+
+/// B3 spline taps of the À-Trous filter; each pass spreads them twice as far apart.
+const ATROUS_KERNEL: [Real; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+const ATROUS_PASSES: u32 = 5;
+const COLOR_PHI: Real = 0.5;
+const NORMAL_PHI: Real = 0.1;
+const ALBEDO_PHI: Real = 0.05;
+
+/// Auxiliary per-pixel buffers that guide the denoiser towards keeping real edges.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Aovs {
+    pub albedo: Vec<Vec3>,
+    pub normal: Vec<Vec3>,
+}
+
+/// Traces one primary ray through the centre stratum of every pixel and records the albedo and
+/// normal of what it hits.
+pub fn capture_aovs(camera: &Camera, entities: &EntityList, width: u32, height: u32) -> Aovs {
+    let center = camera.sqrt_spp() / 2;
+    let (albedo, normal) = (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x, y)))
+        .map(|(x, y)| camera.first_hit_aovs(&camera.get_ray(x, y, center, center), entities))
+        .unzip();
+    Aovs { albedo, normal }
+}
+
+/// Denoises the averaged radiance buffer `beauty`. Uses Intel Open Image Denoise when built
+/// with the `oidn` feature, otherwise the built-in À-Trous filter.
+pub fn denoise(beauty: &[Vec3], aovs: &Aovs, width: u32, height: u32) -> Vec<Vec3> {
+    #[cfg(feature = "oidn")]
+    {
+        denoise_oidn(beauty, aovs, width, height)
+    }
+    #[cfg(not(feature = "oidn"))]
+    {
+        denoise_atrous(beauty, aovs, width, height)
+    }
+}
+
+#[cfg(feature = "oidn")]
+fn denoise_oidn(beauty: &[Vec3], aovs: &Aovs, width: u32, height: u32) -> Vec<Vec3> {
+    let flatten = |buffer: &[Vec3]| -> Vec<f32> {
+        buffer
+            .iter()
+            .flat_map(|c| [c.x, c.y, c.z])
+            .map(crate::math::to_f32)
+            .collect()
+    };
+    let color = flatten(&sanitize(beauty));
+    let albedo = flatten(&aovs.albedo);
+    let normal = flatten(&aovs.normal);
+    let mut output = vec![0.0f32; color.len()];
+
+    let device = oidn::Device::new();
+    oidn::RayTracing::new(&device)
+        .srgb(false)
+        .hdr(true)
+        .image_dimensions(width as usize, height as usize)
+        .albedo_normal(&albedo, &normal)
+        .filter(&color, &mut output)
+        .expect("Invalid OIDN filter configuration");
+    if let Err((_, message)) = device.get_error() {
+        panic!("OIDN failed: {}", message);
+    }
+    output
+        .chunks_exact(3)
+        .map(|c| Vec3::new(Real::from(c[0]), Real::from(c[1]), Real::from(c[2])))
+        .collect()
+}
+
+/// Edge-avoiding À-Trous wavelet filter (Dammertz et al. 2010). Each pass widens the tap
+/// spacing and halves the colour tolerance, while normal and albedo differences stop blurring
+/// across geometric and texture edges.
+pub fn denoise_atrous(beauty: &[Vec3], aovs: &Aovs, width: u32, height: u32) -> Vec<Vec3> {
+    let (width, height) = (width as i64, height as i64);
+    let mut current = sanitize(beauty);
+    let mut color_phi = COLOR_PHI;
+    for pass in 0..ATROUS_PASSES {
+        let step = 1i64 << pass;
+        let mut next = vec![Vec3::zero(); current.len()];
+        for y in 0..height {
+            for x in 0..width {
+                let p = (y * width + x) as usize;
+                let mut sum = Vec3::zero();
+                let mut weight_sum = 0.0;
+                for (ky, kernel_y) in ATROUS_KERNEL.iter().enumerate() {
+                    for (kx, kernel_x) in ATROUS_KERNEL.iter().enumerate() {
+                        let qx = (x + (kx as i64 - 2) * step).clamp(0, width - 1);
+                        let qy = (y + (ky as i64 - 2) * step).clamp(0, height - 1);
+                        let q = (qy * width + qx) as usize;
+                        let color_weight =
+                            Real::exp(-(current[p] - current[q]).length_squared() / color_phi);
+                        let normal_weight = Real::exp(
+                            -(aovs.normal[p] - aovs.normal[q]).length_squared() / NORMAL_PHI,
+                        );
+                        let albedo_weight = Real::exp(
+                            -(aovs.albedo[p] - aovs.albedo[q]).length_squared() / ALBEDO_PHI,
+                        );
+                        let weight =
+                            kernel_x * kernel_y * color_weight * normal_weight * albedo_weight;
+                        sum += weight * current[q];
+                        weight_sum += weight;
+                    }
+                }
+                // The centre tap always has weight, so the sum never vanishes
+                next[p] = sum / weight_sum;
+            }
+        }
+        current = next;
+        color_phi *= 0.5;
+    }
+    current
+}
+
+/// Replaces NaN and infinite pixels with black so a single bad sample cannot spread.
+fn sanitize(buffer: &[Vec3]) -> Vec<Vec3> {
+    buffer
+        .iter()
+        .map(|c| {
+            if c.x.is_finite() && c.y.is_finite() && c.z.is_finite() {
+                *c
+            } else {
+                Vec3::zero()
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::accumulator::Accumulator;
+    use crate::entities::quad::Quad;
+    use crate::entities::sphere::Sphere;
+    use crate::material::{DiffuseLight, Lambertian};
+    use crate::render::CancelToken;
+    use crate::texture::Texture;
+    use std::sync::Arc;
+
+    fn render(entities: &EntityList, camera: &Camera, samples: u32) -> Vec<Vec3> {
+        let mut camera = camera.clone();
+        camera.set_samples_per_pixel(samples);
+        let mut accumulator = Accumulator::new(24, 16, 3);
+        accumulator.accumulate(4, entities, &camera, &CancelToken::new(), samples);
+        accumulator.average()
+    }
+
+    fn mean_squared_error(a: &[Vec3], b: &[Vec3]) -> Real {
+        a.iter()
+            .zip(b)
+            .map(|(a, b)| (*a - *b).length_squared())
+            .sum::<Real>()
+            / a.len() as Real
+    }
+
+    #[test]
+    fn test_denoised_simple_scene_is_finite_and_closer_to_reference() {
+        let mut entities = EntityList::new();
+        entities.add(Box::new(Sphere::new(
+            Vec3::zero(),
+            0.5,
+            Arc::new(Lambertian::new(Box::new(Texture::new(Vec3::new(
+                0.8, 0.3, 0.3,
+            ))))),
+        )));
+        entities.add(Box::new(Quad::new(
+            Vec3::new(-0.5, 1.0, -0.5),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+            Arc::new(DiffuseLight::new(Box::new(Texture::new(Vec3::one() * 4.0)))),
+        )));
+        let mut camera = Camera::new(24, 16, 60.0, &Vec3::new(0.0, 0.0, 2.0), &Vec3::zero());
+        camera.set_background_color(&Vec3::new(0.2, 0.2, 0.3));
+        let reference = render(&entities, &camera, 256);
+        let noisy = render(&entities, &camera, 4);
+
+        let aovs = capture_aovs(&camera, &entities, 24, 16);
+        let denoised = denoise_atrous(&noisy, &aovs, 24, 16);
+        assert!(denoised
+            .iter()
+            .all(|c| c.x.is_finite() && c.y.is_finite() && c.z.is_finite()));
+        let noisy_error = mean_squared_error(&noisy, &reference);
+        let denoised_error = mean_squared_error(&denoised, &reference);
+        assert!(
+            denoised_error < noisy_error,
+            "denoised {} vs noisy {}",
+            denoised_error,
+            noisy_error
+        );
+    }
+
+    #[test]
+    fn test_nan_pixels_do_not_spread() {
+        let mut beauty = vec![Vec3::new(0.5, 0.5, 0.5); 16];
+        beauty[5] = Vec3::new(Real::NAN, 0.0, Real::INFINITY);
+        let aovs = Aovs {
+            albedo: vec![Vec3::one(); 16],
+            normal: vec![Vec3::new(0.0, 0.0, 1.0); 16],
+        };
+        let denoised = denoise_atrous(&beauty, &aovs, 4, 4);
+        assert!(denoised
+            .iter()
+            .all(|c| c.x.is_finite() && c.y.is_finite() && c.z.is_finite()));
+    }
+}
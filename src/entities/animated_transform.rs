@@ -0,0 +1,228 @@
+use crate::aabb::{HasAABB, AABB};
+use crate::entities::entity::{HitRecord, Hittable, Transformable};
+use crate::interval::Interval;
+use crate::math::{transform::Transform, vec3::Vec3, Real};
+use crate::ray::Ray;
+
+/// Times at which the motion is sampled to bound it. The rotation can bulge out between two
+/// of them, which the bound is padded for.
+const MOTION_BOUND_STEPS: usize = 32;
+
+/// Entity that moves from `start` at time 0 to `end` at time 1, blurring along its motion when
+/// the camera shutter is open. Unlike moving an entity with `translate`, the path can turn
+/// and scale as well; see `Transform::interpolate` for how the keyframes are blended.
+pub struct AnimatedTransform {
+    pub object: Box<dyn Hittable>,
+    pub start: Transform,
+    pub end: Transform,
+    aabb: AABB,
+}
+
+impl AnimatedTransform {
+    pub fn new(object: Box<dyn Hittable>, start: Transform, end: Transform) -> Self {
+        let mut new = Self {
+            object,
+            start,
+            end,
+            aabb: AABB::default(),
+        };
+        new.aabb = new.compute_aabb();
+        new
+    }
+
+    pub fn transform_at(&self, time: Real) -> Transform {
+        self.start.interpolate(&self.end, time)
+    }
+}
+
+impl HasAABB for AnimatedTransform {
+    fn get_aabb(&self) -> AABB {
+        self.aabb
+    }
+
+    fn compute_aabb(&self) -> AABB {
+        let local = self.object.get_aabb();
+        let corners: Vec<Vec3> = (0..8)
+            .map(|corner| {
+                Vec3::new(
+                    if corner & 1 == 0 {
+                        local.x.min
+                    } else {
+                        local.x.max
+                    },
+                    if corner & 2 == 0 {
+                        local.y.min
+                    } else {
+                        local.y.max
+                    },
+                    if corner & 4 == 0 {
+                        local.z.min
+                    } else {
+                        local.z.max
+                    },
+                )
+            })
+            .collect();
+        let mut aabb = AABB::empty();
+        for step in 0..=MOTION_BOUND_STEPS {
+            let transform = self.transform_at(step as Real / MOTION_BOUND_STEPS as Real);
+            for corner in &corners {
+                let world = transform.point_to_world(corner);
+                aabb = AABB::combine(&aabb, &AABB::construct_exact(world, world));
+            }
+        }
+        // Between two samples a corner strays from the chord of its arc by at most the sagitta
+        let (_, angle) = (self.end.rotation * self.start.rotation.transpose()).axis_angle();
+        let reach = corners.iter().map(|c| c.length()).fold(0.0, Real::max)
+            * Real::max(self.start.scale.abs(), self.end.scale.abs());
+        let sagitta = reach * (1.0 - Real::cos(0.5 * angle / MOTION_BOUND_STEPS as Real));
+        AABB::new(
+            aabb.x.expand(2.0 * sagitta),
+            aabb.y.expand(2.0 * sagitta),
+            aabb.z.expand(2.0 * sagitta),
+        )
+    }
+
+    fn is_bounded(&self) -> bool {
+        self.object.is_bounded()
+    }
+}
+
+impl Hittable for AnimatedTransform {
+    fn hit<'a>(&'a self, ray: &Ray, t_interval: &Interval, record: &mut HitRecord<'a>) -> bool {
+        if !self.aabb.hit(ray, *t_interval) {
+            return false;
+        }
+        let transform = self.transform_at(ray.time);
+        let local_ray = Ray::new(
+            transform.point_to_local(&ray.origin),
+            transform.vector_to_local(&ray.direction()),
+        )
+        .with_time(ray.time);
+        // Directions stay unit length, so distances shrink by the scale
+        let scale = transform.scale;
+        let local_interval = Interval::new(t_interval.min / scale, t_interval.max / scale);
+        if !self.object.hit(&local_ray, &local_interval, record) {
+            return false;
+        }
+        record.t *= scale;
+        record.position = transform.point_to_world(&record.position);
+        record.normal = transform.vector_to_world(&record.normal);
+        record.tangent = transform.vector_to_world(&record.tangent);
+        record.bitangent = transform.vector_to_world(&record.bitangent);
+        record.dpdu = scale * transform.vector_to_world(&record.dpdu);
+        record.dpdv = scale * transform.vector_to_world(&record.dpdv);
+        record.world_footprint *= scale;
+        true
+    }
+
+    fn summary(&self) -> String {
+        format!(
+            "AnimatedTransform from {:.3} to {:.3}, of {}",
+            self.start.translation,
+            self.end.translation,
+            self.object.summary()
+        )
+    }
+
+    fn children(&self) -> Vec<&dyn Hittable> {
+        vec![self.object.as_ref()]
+    }
+}
+
+impl Transformable for AnimatedTransform {
+    fn translate(&mut self, translation: Vec3) {
+        self.start.translate(translation);
+        self.end.translate(translation);
+        self.aabb = self.compute_aabb();
+    }
+
+    fn rotate(&mut self, axis: Vec3, angle: Real) {
+        self.start.rotate(axis, angle);
+        self.end.rotate(axis, angle);
+        self.aabb = self.compute_aabb();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::Camera;
+    use crate::entities::quad::create_box;
+    use crate::material::Normals;
+    use crate::math::rand::{rand_f32, seed_sample_rng};
+    use std::sync::Arc;
+
+    /// Bar along +x from 0.5 to 2, turning 150 degrees about z while the shutter is open.
+    fn spinning_bar() -> AnimatedTransform {
+        let bar = create_box(
+            Vec3::new(0.5, -0.1, -0.1),
+            Vec3::new(2.0, 0.1, 0.1),
+            Arc::new(Normals),
+        );
+        AnimatedTransform::new(
+            Box::new(bar),
+            Transform::default(),
+            Transform::new(Vec3::zero(), Vec3::new(0.0, 0.0, 1.0), 150.0, 1.0),
+        )
+    }
+
+    /// Ray straight down onto the xy-plane at `radius` and `degrees` around the origin.
+    fn ray_onto(radius: Real, degrees: Real, time: Real) -> Ray {
+        let angle = degrees.to_radians();
+        let target = Vec3::new(radius * angle.cos(), radius * angle.sin(), 0.0);
+        Ray::new(target + Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0)).with_time(time)
+    }
+
+    #[test]
+    fn test_motion_blur_streak_follows_the_rotation_arc() {
+        let bar = spinning_bar();
+        let hits = |ray: &Ray| {
+            let mut record = HitRecord::new();
+            bar.hit(ray, &Interval::new(0.001, Real::MAX), &mut record)
+        };
+        // At each moment the bar points along its interpolated angle and nowhere else
+        for time in [0.0, 0.25, 0.5, 0.75, 1.0] {
+            let degrees = 150.0 * time;
+            assert!(hits(&ray_onto(1.5, degrees, time)), "time {}", time);
+            assert!(!hits(&ray_onto(1.5, degrees + 40.0, time)), "time {}", time);
+        }
+
+        // Over the whole shutter the streak covers the arc swept by the bar, and only that
+        seed_sample_rng(3, 0, 0, 0);
+        let coverage = |radius: Real, degrees: Real| {
+            (0..400)
+                .filter(|_| hits(&ray_onto(radius, degrees, rand_f32())))
+                .count()
+        };
+        for degrees in [10.0, 75.0, 140.0] {
+            let count = coverage(1.5, degrees);
+            assert!(count > 0 && count < 100, "{} degrees: {}", degrees, count);
+        }
+        assert_eq!(coverage(1.5, 200.0), 0);
+        assert_eq!(coverage(0.3, 75.0), 0);
+        assert_eq!(coverage(2.5, 75.0), 0);
+
+        // The bounds follow the arc rather than just the two end positions
+        let aabb = bar.get_aabb();
+        for step in 0..=30 {
+            let angle = (5.0 * step as Real).to_radians();
+            let tip = Vec3::new(2.0 * angle.cos(), 2.0 * angle.sin(), 0.0);
+            for axis in [0, 1] {
+                assert!(aabb
+                    .get_axis(crate::aabb::Axis::ALL[axis])
+                    .contains(tip[axis]));
+            }
+        }
+    }
+
+    #[test]
+    fn test_open_shutter_spreads_ray_times() {
+        let mut camera = Camera::new(8, 8, 40.0, &Vec3::new(0.0, 0.0, 5.0), &Vec3::zero());
+        assert!((0..50).all(|_| camera.get_ray(4, 4, 0, 0).time == 0.0));
+        camera.set_shutter(1.0);
+        let times: Vec<Real> = (0..200).map(|_| camera.get_ray(4, 4, 0, 0).time).collect();
+        assert!(times.iter().all(|time| (0.0..1.0).contains(time)));
+        assert!(times.iter().any(|&time| time < 0.1) && times.iter().any(|&time| time > 0.9));
+    }
+}
@@ -1,25 +1,29 @@
+#[cfg(feature = "serde")]
+use crate::entities::entity::EntityDesc;
 use crate::{
-    aabb::{Axis, HasAABB, AABB},
+    aabb::{HasAABB, AABB},
     entities::entity::{HitRecord, Hittable},
     interval::Interval,
-    material::Material,
-    math::{rand, vec3::Vec3},
-    ray::Ray,
+    material::{material_name, Material},
+    math::{rand, vec2::Vec2, vec3::Vec3},
+    ray::{Ray, RAY_EPSILON},
+    texture::TextureSampler,
 };
 use std::sync::Arc;
 
 use super::entity::Transformable;
+use crate::math::Real;
 
 pub struct ConstantMedium {
     pub boundary: Box<dyn Hittable>,
     pub phase_function: Arc<dyn Material>,
-    pub neg_inv_density: f32,
+    pub neg_inv_density: Real,
 }
 
 impl ConstantMedium {
     pub fn new(
         boundary: Box<dyn Hittable>,
-        density: f32,
+        density: Real,
         phase_function: Arc<dyn Material>,
     ) -> Self {
         Self {
@@ -38,6 +42,10 @@ impl HasAABB for ConstantMedium {
     fn compute_aabb(&self) -> AABB {
         self.boundary.compute_aabb()
     }
+
+    fn is_bounded(&self) -> bool {
+        self.boundary.is_bounded()
+    }
 }
 
 impl Transformable for ConstantMedium {
@@ -45,52 +53,311 @@ impl Transformable for ConstantMedium {
         self.boundary.translate(translation);
     }
 
-    fn rotate(&mut self, axis: Vec3, angle: f32) {
+    fn rotate(&mut self, axis: Vec3, angle: Real) {
         self.boundary.rotate(axis, angle);
     }
+
+    fn rotate_about(&mut self, pivot: Vec3, axis: Vec3, angle: Real) {
+        self.boundary.rotate_about(pivot, axis, angle);
+    }
 }
 
 impl Hittable for ConstantMedium {
     fn hit<'a>(&'a self, ray: &Ray, t_interval: &Interval, record: &mut HitRecord<'a>) -> bool {
-        let mut rec1 = HitRecord::new();
-        if !self.boundary.hit(ray, &Interval::universe(), &mut rec1) {
+        let Some((t_enter, t_exit)) = medium_span(self.boundary.as_ref(), ray, t_interval) else {
             return false;
-        }
+        };
+
+        let distance_inside_boundary = (t_exit - t_enter) * ray.direction().length();
+        let hit_distance = self.neg_inv_density * Real::ln(rand::rand_f32());
 
-        let mut rec2 = HitRecord::new();
-        if !self
-            .boundary
-            .hit(ray, &Interval::new(rec1.t + 0.0001, f32::MAX), &mut rec2)
-        {
+        if hit_distance > distance_inside_boundary {
             return false;
         }
 
-        if rec1.t < t_interval.min {
-            rec1.t = t_interval.min;
-        }
-        if rec2.t > t_interval.max {
-            rec2.t = t_interval.max;
+        record_scattering(
+            ray,
+            t_enter + hit_distance / ray.direction().length(),
+            &self.phase_function,
+            record,
+        );
+        true
+    }
+
+    fn summary(&self) -> String {
+        format!(
+            "ConstantMedium of density {}, {}",
+            -1.0 / self.neg_inv_density,
+            material_name(self.phase_function.as_ref())
+        )
+    }
+
+    fn children(&self) -> Vec<&dyn Hittable> {
+        vec![self.boundary.as_ref()]
+    }
+
+    #[cfg(feature = "serde")]
+    fn describe(&self) -> Option<Vec<EntityDesc>> {
+        Some(vec![EntityDesc::ConstantMedium {
+            boundary: self.boundary.describe()?,
+            density: -1.0 / self.neg_inv_density,
+            phase_function: self.phase_function.describe()?,
+        }])
+    }
+}
+
+/// Medium whose density varies through space, e.g. a cloud shaped by a noise texture. The
+/// local density is `max_density` times the first channel of `density`, clamped to [0, 1],
+/// and is sampled by delta tracking: tentative collisions are drawn against the constant
+/// `max_density` and each is kept with probability local / max, which stays unbiased
+/// however quickly the density changes.
+pub struct TexturedMedium {
+    pub boundary: Box<dyn Hittable>,
+    pub phase_function: Arc<dyn Material>,
+    pub max_density: Real,
+    pub density: Box<dyn TextureSampler>,
+}
+
+impl TexturedMedium {
+    pub fn new(
+        boundary: Box<dyn Hittable>,
+        max_density: Real,
+        density: Box<dyn TextureSampler>,
+        phase_function: Arc<dyn Material>,
+    ) -> Self {
+        Self {
+            boundary,
+            phase_function,
+            max_density,
+            density,
         }
+    }
+
+    /// Local density at `position`, never above `max_density`.
+    pub fn density_at(&self, position: &Vec3) -> Real {
+        let fraction = self.density.value(&Vec2::zero(), position).x;
+        self.max_density * fraction.clamp(0.0, 1.0)
+    }
+}
+
+impl HasAABB for TexturedMedium {
+    fn get_aabb(&self) -> AABB {
+        self.boundary.get_aabb()
+    }
+
+    fn compute_aabb(&self) -> AABB {
+        self.boundary.compute_aabb()
+    }
+
+    fn is_bounded(&self) -> bool {
+        self.boundary.is_bounded()
+    }
+}
+
+impl Transformable for TexturedMedium {
+    fn translate(&mut self, translation: Vec3) {
+        self.boundary.translate(translation);
+    }
 
-        if rec1.t >= rec2.t {
+    fn rotate(&mut self, axis: Vec3, angle: Real) {
+        self.boundary.rotate(axis, angle);
+    }
+
+    fn rotate_about(&mut self, pivot: Vec3, axis: Vec3, angle: Real) {
+        self.boundary.rotate_about(pivot, axis, angle);
+    }
+}
+
+impl Hittable for TexturedMedium {
+    fn hit<'a>(&'a self, ray: &Ray, t_interval: &Interval, record: &mut HitRecord<'a>) -> bool {
+        if self.max_density <= 0.0 {
             return false;
         }
-        if rec1.t < 0.0 {
-            rec1.t = 0.0;
+        let Some((t_enter, t_exit)) = medium_span(self.boundary.as_ref(), ray, t_interval) else {
+            return false;
+        };
+
+        let inv_length = 1.0 / ray.direction().length();
+        let mut t = t_enter;
+        loop {
+            t -= Real::ln(rand::rand_f32()) / self.max_density * inv_length;
+            if t >= t_exit {
+                return false;
+            }
+            // Null collisions leave the ray going straight on
+            if rand::rand_f32() * self.max_density < self.density_at(&ray.at(t)) {
+                record_scattering(ray, t, &self.phase_function, record);
+                return true;
+            }
         }
+    }
 
-        let distance_inside_boundary = (rec2.t - rec1.t) * ray.direction.length();
-        let hit_distance = self.neg_inv_density * f32::ln(rand::rand_f32());
+    fn summary(&self) -> String {
+        format!(
+            "TexturedMedium of density up to {}, {}",
+            self.max_density,
+            material_name(self.phase_function.as_ref())
+        )
+    }
 
-        if hit_distance > distance_inside_boundary {
-            return false;
+    fn children(&self) -> Vec<&dyn Hittable> {
+        vec![self.boundary.as_ref()]
+    }
+
+    #[cfg(feature = "serde")]
+    fn describe(&self) -> Option<Vec<EntityDesc>> {
+        Some(vec![EntityDesc::TexturedMedium {
+            boundary: self.boundary.describe()?,
+            max_density: self.max_density,
+            density: self.density.describe()?,
+            phase_function: self.phase_function.describe()?,
+        }])
+    }
+}
+
+/// Ray parameters where `ray` enters and leaves `boundary`, clipped to `t_interval`, or `None`
+/// if it does not pass through the inside within it.
+fn medium_span(boundary: &dyn Hittable, ray: &Ray, t_interval: &Interval) -> Option<(Real, Real)> {
+    let mut rec1 = HitRecord::new();
+    if !boundary.hit(ray, &Interval::universe(), &mut rec1) {
+        return None;
+    }
+
+    // Step off the entry point the same way scattered rays leave a surface
+    let exit_ray = Ray::new(ray.at(rec1.t), ray.direction());
+    let offset = exit_ray.hit_interval(RAY_EPSILON).min;
+    let mut rec2 = HitRecord::new();
+    if !boundary.hit(ray, &Interval::new(rec1.t + offset, Real::MAX), &mut rec2) {
+        return None;
+    }
+
+    // Only the part ahead of the ray's origin can scatter it
+    let (_, ahead) = Interval::new(rec1.t, rec2.t)
+        .intersect(t_interval)
+        .split(0.0);
+    if ahead.size() <= 0.0 {
+        return None;
+    }
+    Some((ahead.min, ahead.max))
+}
+
+/// Fills `record` for a scattering event at `t`, which has no surface and so no real normal
+/// or UVs. Those are reset rather than left over from a surface hit earlier in the traversal.
+fn record_scattering<'a>(
+    ray: &Ray,
+    t: Real,
+    phase_function: &'a Arc<dyn Material>,
+    record: &mut HitRecord<'a>,
+) {
+    record.t = t;
+    record.position = ray.at(t);
+    record.uv = Vec2::zero();
+    record.uv_footprint = 0.0;
+    record.world_footprint = 0.0;
+    record.dpdu = Vec3::zero();
+    record.dpdv = Vec3::zero();
+    record.set_face_normal(ray, &Vec3::new(1.0, 0.0, 0.0));
+    record.set_tangent_frame();
+    record.front_face = true;
+    record.material = Some(phase_function);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entities::quad::create_box;
+    use crate::material::Isotropic;
+    use crate::texture::Texture;
+
+    /// Full density for negative x, a tenth of it elsewhere.
+    #[derive(Debug)]
+    struct HalfDense;
+
+    impl TextureSampler for HalfDense {
+        fn value(&self, _uv: &Vec2, p: &Vec3) -> Vec3 {
+            Vec3::one() * if p.x < 0.0 { 1.0 } else { 0.1 }
         }
+    }
 
-        record.t = rec1.t + hit_distance / ray.direction.length();
-        record.position = ray.at(record.t);
-        record.set_face_normal(ray, &Vec3::new(1.0, 0.0, 0.0));
-        record.front_face = true;
-        record.material = Some(&self.phase_function);
-        true
+    #[test]
+    fn test_scattering_follows_the_density_texture() {
+        let phase: Arc<dyn Material> = Arc::new(Isotropic {
+            albedo: Box::new(Texture::new(Vec3::one())),
+        });
+        let boundary = create_box(
+            Vec3::new(-1.0, -1.0, -1.0),
+            Vec3::new(1.0, 1.0, 1.0),
+            Arc::clone(&phase),
+        );
+        let medium = TexturedMedium::new(Box::new(boundary), 1.0, Box::new(HalfDense), phase);
+        let scattered_fraction = |x: Real| {
+            let ray = Ray::new(Vec3::new(x, 0.0, 5.0), Vec3::new(0.0, 0.0, -2.0));
+            let trials = 4000;
+            let hits = (0..trials)
+                .filter(|_| {
+                    let mut record = HitRecord::new();
+                    medium.hit(&ray, &Interval::new(0.001, Real::MAX), &mut record)
+                })
+                .count();
+            hits as Real / trials as Real
+        };
+        // Two units of medium at densities 1 and 0.1 let 1 - e^(-2 density) of the rays scatter
+        let dense = scattered_fraction(-0.5);
+        let thin = scattered_fraction(0.5);
+        assert!(
+            (dense - (1.0 - Real::exp(-2.0))).abs() < 0.03,
+            "dense {}",
+            dense
+        );
+        assert!(
+            (thin - (1.0 - Real::exp(-0.2))).abs() < 0.03,
+            "thin {}",
+            thin
+        );
+    }
+
+    #[test]
+    fn test_collisions_stay_inside_the_boundary() {
+        let phase: Arc<dyn Material> = Arc::new(Isotropic {
+            albedo: Box::new(Texture::new(Vec3::one())),
+        });
+        let boundary = create_box(Vec3::zero(), Vec3::new(2.0, 1.0, 1.0), Arc::clone(&phase));
+        let medium = TexturedMedium::new(Box::new(boundary), 3.0, Box::new(HalfDense), phase);
+        let ray = Ray::new(Vec3::new(-1.0, 0.5, 0.5), Vec3::new(1.0, 0.0, 0.0));
+        for _ in 0..500 {
+            let mut record = HitRecord::new();
+            if medium.hit(&ray, &Interval::new(0.001, Real::MAX), &mut record) {
+                assert!((1.0..3.0).contains(&record.t), "t {}", record.t);
+                assert!(record.material.is_some());
+            }
+        }
+    }
+
+    #[test]
+    fn test_smoke_albedo_varies_with_position() {
+        let phase: Arc<dyn Material> = Arc::new(Isotropic {
+            albedo: Box::new(HalfDense),
+        });
+        let boundary = create_box(
+            Vec3::new(-1.0, -1.0, -1.0),
+            Vec3::new(1.0, 1.0, 1.0),
+            Arc::clone(&phase),
+        );
+        // Dense enough to scatter right behind the entry face
+        let medium = ConstantMedium::new(Box::new(boundary), 1e4, phase);
+        let albedo_at = |x: Real| {
+            let ray = Ray::new(Vec3::new(x, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+            let mut record = HitRecord::new();
+            // Left over from some surface hit, and the same for both points
+            record.uv = Vec2::new(0.25, 0.75);
+            assert!(medium.hit(&ray, &Interval::new(0.001, Real::MAX), &mut record));
+            assert_eq!(record.uv, Vec2::zero());
+            let (mut attenuation, mut scattered, mut pdf) = (Vec3::zero(), Ray::default(), 0.0);
+            let material = record.material.unwrap();
+            assert!(material.scatter(&ray, &record, &mut attenuation, &mut scattered, &mut pdf));
+            attenuation
+        };
+        assert_eq!(albedo_at(-0.5), Vec3::one());
+        assert_eq!(albedo_at(0.5), Vec3::one() * 0.1);
     }
 }
@@ -0,0 +1,205 @@
+use std::sync::Arc;
+
+use crate::aabb::{HasAABB, AABB};
+use crate::entities::entity::{HitRecord, Hittable, Transformable};
+use crate::entities::sphere::Sphere;
+use crate::interval::Interval;
+use crate::material::{material_name, Material};
+use crate::math::vec3::{dot, Vec3};
+use crate::math::Real;
+use crate::perlin_noise::PerlinNoise;
+use crate::ray::Ray;
+
+/// Step used to take finite differences of the height field, in noise-space units.
+const GRADIENT_DELTA: Real = 0.001;
+const TURBULENCE_DEPTH: i32 = 4;
+
+/// Sphere whose surface is pushed outwards by a turbulence height field, for lumpy
+/// asteroid-like shapes. Rays are still intersected with the base sphere: the hit point is
+/// moved along the normal and the normal is tilted by the height gradient, but the silhouette
+/// stays round. Exact silhouettes would need a displacement solver (e.g. ray marching).
+#[derive(Debug, Clone)]
+pub struct DisplacedSphere {
+    pub sphere: Sphere,
+    /// Largest displacement for a turbulence value of 1, in world units.
+    pub amplitude: Real,
+    /// Noise cycles per unit of radius.
+    pub frequency: Real,
+    noise: PerlinNoise,
+    aabb: AABB,
+}
+
+impl DisplacedSphere {
+    pub fn new(
+        center: Vec3,
+        radius: Real,
+        amplitude: Real,
+        frequency: Real,
+        material: Arc<dyn Material>,
+    ) -> Self {
+        let mut new = Self {
+            sphere: Sphere::new(center, radius, material),
+            amplitude,
+            frequency,
+            noise: PerlinNoise::new(),
+            aabb: AABB::default(),
+        };
+        new.aabb = new.compute_aabb();
+        new
+    }
+
+    /// Displacement at `position`, sampled in the sphere's own frame so it moves with it.
+    fn height(&self, position: &Vec3) -> Real {
+        let local = (*position - self.sphere.center) / self.sphere.radius * self.frequency;
+        self.amplitude * self.noise.turbulence(local, TURBULENCE_DEPTH)
+    }
+
+    /// Tilts `normal` against the tangential part of the height gradient at `position`.
+    fn displaced_normal(&self, position: &Vec3, normal: &Vec3) -> Vec3 {
+        let delta = GRADIENT_DELTA * self.sphere.radius / self.frequency;
+        let h = self.height(position);
+        let gradient = Vec3::new(
+            self.height(&(*position + Vec3::new(delta, 0.0, 0.0))) - h,
+            self.height(&(*position + Vec3::new(0.0, delta, 0.0))) - h,
+            self.height(&(*position + Vec3::new(0.0, 0.0, delta))) - h,
+        ) / delta;
+        let tangential = gradient - dot(&gradient, normal) * *normal;
+        (*normal - tangential).normalize()
+    }
+}
+
+impl HasAABB for DisplacedSphere {
+    fn get_aabb(&self) -> AABB {
+        self.aabb
+    }
+
+    fn compute_aabb(&self) -> AABB {
+        // Turbulence of a few octaves stays well below 2
+        let extent = self.sphere.radius.abs() + 2.0 * self.amplitude;
+        let rvec = Vec3::new(extent, extent, extent);
+        AABB::construct_exact(self.sphere.center - rvec, self.sphere.center + rvec)
+    }
+}
+
+impl Hittable for DisplacedSphere {
+    fn hit<'a>(&'a self, ray: &Ray, t_interval: &Interval, record: &mut HitRecord<'a>) -> bool {
+        if !self.sphere.hit(ray, t_interval, record) {
+            return false;
+        }
+        let outward_normal = (record.position - self.sphere.center).normalize();
+        let displaced = self.displaced_normal(&record.position, &outward_normal);
+        record.position += self.height(&record.position) * outward_normal;
+        // Order the hit by how far along the ray the displaced point lies, so entities
+        // between the base sphere and the raised surface are not drawn in front of it
+        let t = dot(&(record.position - ray.origin), &ray.direction());
+        if !t_interval.surrounds(t) {
+            return false;
+        }
+        record.t = t;
+        record.set_face_normal(ray, &displaced);
+        record.set_tangent_frame();
+        true
+    }
+
+    fn summary(&self) -> String {
+        format!(
+            "DisplacedSphere at {:.3}, radius {}, amplitude {}, {}",
+            self.sphere.center,
+            self.sphere.radius,
+            self.amplitude,
+            material_name(self.sphere.material.as_ref())
+        )
+    }
+}
+
+impl Transformable for DisplacedSphere {
+    fn translate(&mut self, translation: Vec3) {
+        self.sphere.translate(translation);
+        self.aabb = self.compute_aabb();
+    }
+
+    fn rotate(&mut self, axis: Vec3, angle: Real) {
+        self.sphere.rotate(axis, angle);
+    }
+
+    fn rotate_about(&mut self, pivot: Vec3, axis: Vec3, angle: Real) {
+        self.sphere.rotate_about(pivot, axis, angle);
+        self.aabb = self.compute_aabb();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entities::entity::EntityList;
+    use crate::entities::quad::Quad;
+    use crate::material::Lambertian;
+    use crate::texture::Texture;
+
+    #[test]
+    fn test_hits_lie_on_displaced_surface_with_tilted_normals() {
+        let material: Arc<dyn Material> =
+            Arc::new(Lambertian::new(Box::new(Texture::new(Vec3::one()))));
+        let sphere = DisplacedSphere::new(Vec3::zero(), 1.0, 0.1, 3.0, material);
+        let mut tilted = 0;
+        for i in 0..32 {
+            let offset = -0.8 + 0.05 * i as Real;
+            let ray = Ray::new(Vec3::new(offset, 0.3, 5.0), Vec3::new(0.0, 0.0, -1.0));
+            let mut record = HitRecord::new();
+            assert!(sphere.hit(&ray, &Interval::new(0.001, Real::MAX), &mut record));
+
+            let distance = record.position.length();
+            assert!((1.0..1.2).contains(&distance), "distance {}", distance);
+            assert!((record.normal.length() - 1.0).abs() < 1e-4);
+            let geometric = record.position.normalize();
+            assert!(dot(&record.normal, &geometric) > 0.0);
+            if dot(&record.normal, &geometric) < 0.999 {
+                tilted += 1;
+            }
+        }
+        assert!(tilted > 16, "only {} of 32 normals were perturbed", tilted);
+    }
+
+    #[test]
+    fn test_raised_surface_hides_what_lies_beneath_it() {
+        let material: Arc<dyn Material> =
+            Arc::new(Lambertian::new(Box::new(Texture::new(Vec3::one()))));
+        let sphere = DisplacedSphere::new(Vec3::zero(), 1.0, 0.1, 3.0, material);
+        let interval = Interval::new(0.001, Real::MAX);
+        // Of a grid of rays, the one meeting the most raised part of the surface
+        let (ray, base_t, record) = (0..32 * 32)
+            .map(|i| {
+                let ray = Ray::new(
+                    Vec3::new(
+                        -0.6 + 0.04 * (i % 32) as Real,
+                        -0.6 + 0.04 * (i / 32) as Real,
+                        5.0,
+                    ),
+                    Vec3::new(0.0, 0.0, -1.0),
+                );
+                let mut base = HitRecord::new();
+                assert!(sphere.sphere.hit(&ray, &interval, &mut base));
+                let mut record = HitRecord::new();
+                assert!(sphere.hit(&ray, &interval, &mut record));
+                (ray, base.t, record)
+            })
+            .max_by(|a, b| (a.1 - a.2.t).total_cmp(&(b.1 - b.2.t)))
+            .unwrap();
+        // `t` places the displaced point, not the base sphere's, along the ray
+        assert!((record.t - (5.0 - record.position.z)).abs() < 1e-4);
+        assert!(record.t < base_t - 0.02);
+
+        // A card halfway between the base sphere and the raised surface is covered by it
+        let mut entities = EntityList::new();
+        entities.add(Box::new(sphere.clone()));
+        entities.add(Box::new(Quad::new(
+            Vec3::new(-1.0, -1.0, 5.0 - 0.5 * (base_t + record.t)),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 2.0, 0.0),
+            Arc::new(Lambertian::new(Box::new(Texture::new(Vec3::zero())))),
+        )));
+        let mut nearest = HitRecord::new();
+        assert!(entities.hit(&ray, &interval, &mut nearest));
+        assert!((nearest.t - record.t).abs() < 1e-5);
+    }
+}
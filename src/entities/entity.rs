@@ -1,16 +1,43 @@
 use crate::aabb::{HasAABB, AABB};
+use crate::bvh::{IndexedBVH, BVH};
+#[cfg(feature = "serde")]
+use crate::entities::{
+    constant_medium::{ConstantMedium, TexturedMedium},
+    infinite_plane::InfinitePlane,
+    mesh::Mesh,
+    quad::Quad,
+    sphere::Sphere,
+};
 use crate::interval::Interval;
 use crate::material::Material;
-use crate::math::{vec2::*, vec3::*};
+#[cfg(feature = "serde")]
+use crate::material::MaterialDesc;
+use crate::math::{mat3::Mat3, vec2::*, vec3::*, Real};
 use crate::ray::Ray;
-use std::sync::Arc;
+#[cfg(feature = "serde")]
+use crate::texture::TextureDesc;
+#[cfg(feature = "serde")]
+use std::io;
+use std::sync::{Arc, OnceLock};
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct HitRecord<'a> {
-    pub t: f32,
+    pub t: Real,
     pub position: Vec3,
     pub normal: Vec3,
     pub uv: Vec2,
+    /// Width of the pixel footprint in UV units, or 0 when the ray carries no differentials.
+    pub uv_footprint: Real,
+    /// Width of the pixel footprint in world units, or 0 without differentials.
+    pub world_footprint: Real,
+    /// Partial derivatives of the position along the UV parametrization, zero when the
+    /// surface does not provide one.
+    pub dpdu: Vec3,
+    pub dpdv: Vec3,
+    /// Unit tangent frame around the geometric normal, for anisotropic shading and normal
+    /// maps. The tangent follows `dpdu` and `bitangent = normal x tangent`.
+    pub tangent: Vec3,
+    pub bitangent: Vec3,
     pub material: Option<&'a Arc<dyn Material>>,
     pub front_face: bool,
 }
@@ -23,57 +50,377 @@ impl HitRecord<'_> {
             material: None,
             normal: Vec3::zero(),
             uv: Vec2::zero(),
+            uv_footprint: 0.0,
+            world_footprint: 0.0,
+            dpdu: Vec3::zero(),
+            dpdv: Vec3::zero(),
+            tangent: Vec3::zero(),
+            bitangent: Vec3::zero(),
             front_face: false,
         }
     }
 
     pub fn set_face_normal(&mut self, ray: &Ray, out_normal: &Vec3) {
-        self.front_face = dot(&ray.direction, out_normal) < 0.0;
+        self.front_face = dot(&ray.direction(), out_normal) < 0.0;
         self.normal = if self.front_face {
             *out_normal
         } else {
             -*out_normal
         };
     }
+
+    /// Derives `tangent` and `bitangent` from `dpdu` and the face normal. Where `dpdu` vanishes
+    /// or is parallel to the normal, e.g. at the poles of a sphere, any perpendicular tangent
+    /// is used.
+    pub fn set_tangent_frame(&mut self) {
+        let along_u = self.dpdu - dot(&self.dpdu, &self.normal) * self.normal;
+        self.tangent = if along_u.near_zero() {
+            Mat3::get_orthonormal_basis(&self.normal)[0]
+        } else {
+            along_u.normalize()
+        };
+        self.bitangent = cross(&self.normal, &self.tangent);
+    }
 }
 
 pub trait Transformable {
     fn translate(&mut self, translation: Vec3);
-    fn rotate(&mut self, axis: Vec3, angle: f32);
+    fn rotate(&mut self, axis: Vec3, angle: Real);
+    /// Rotates by `angle` degrees about `axis` through `pivot` instead of through the origin,
+    /// so e.g. an object turned about its own center stays in place.
+    fn rotate_about(&mut self, pivot: Vec3, axis: Vec3, angle: Real) {
+        self.translate(-pivot);
+        self.rotate(axis, angle);
+        self.translate(pivot);
+    }
     //fn scale(&mut self, scale: Vec3);
 }
 
 pub trait Hittable: Transformable + HasAABB + Send + Sync {
     fn hit<'a>(&'a self, ray: &Ray, t_interval: &Interval, record: &mut HitRecord<'a>) -> bool;
 
-    fn pdf_value(&self, _origin: &Vec3, _direction: &Vec3) -> f32 {
+    fn pdf_value(&self, _origin: &Vec3, _direction: &Vec3) -> Real {
         0.0
     }
 
     fn random(&self, _origin: &Vec3) -> Vec3 {
         Vec3::new(1.0, 0.0, 0.0)
     }
+
+    /// One-line description for `Scene::describe`: the entity's type, placement and material.
+    fn summary(&self) -> String {
+        let name = std::any::type_name::<Self>();
+        name.rsplit("::").next().unwrap_or(name).to_string()
+    }
+
+    /// Entities nested inside this one, listed below it by `Scene::describe`.
+    fn children(&self) -> Vec<&dyn Hittable> {
+        Vec::new()
+    }
+
+    /// Appends the boxes of the BVH nodes inside this entity down to `max_depth`, each with
+    /// its depth below the BVH root, for the `--show-bounds` overlay.
+    fn node_bounds(&self, max_depth: u32, bounds: &mut Vec<(AABB, u32)>) {
+        for child in self.children() {
+            child.node_bounds(max_depth, bounds);
+        }
+    }
+
+    /// Scene-file description of the entity as a list of primitives, or `None` if it cannot
+    /// be exported.
+    #[cfg(feature = "serde")]
+    fn describe(&self) -> Option<Vec<EntityDesc>> {
+        None
+    }
+}
+
+/// Serializable stand-in for a `Box<dyn Hittable>`. Transforms are baked into the primitives.
+#[cfg(feature = "serde")]
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EntityDesc {
+    Sphere {
+        center: Vec3,
+        radius: Real,
+        material: MaterialDesc,
+    },
+    Quad {
+        q: Vec3,
+        u: Vec3,
+        v: Vec3,
+        uv_scale: Vec2,
+        #[serde(default)]
+        cull_backface: bool,
+        #[serde(default)]
+        flip_back_u: bool,
+        material: MaterialDesc,
+    },
+    InfinitePlane {
+        point: Vec3,
+        normal: Vec3,
+        material: MaterialDesc,
+    },
+    Mesh {
+        vertices: Vec<Vec3>,
+        indices: Vec<[u32; 3]>,
+        material: MaterialDesc,
+    },
+    ConstantMedium {
+        boundary: Vec<EntityDesc>,
+        density: Real,
+        phase_function: MaterialDesc,
+    },
+    TexturedMedium {
+        boundary: Vec<EntityDesc>,
+        max_density: Real,
+        density: TextureDesc,
+        phase_function: MaterialDesc,
+    },
+    /// Primitives that are put behind a BVH again when loaded.
+    Bvh { children: Vec<EntityDesc> },
+}
+
+#[cfg(feature = "serde")]
+impl EntityDesc {
+    /// Builds the entity, failing with `InvalidData` if the description does not form a valid
+    /// shape, such as a quad whose edges span no area.
+    pub fn build(&self) -> io::Result<Box<dyn Hittable>> {
+        let build_list = |descs: &[EntityDesc]| -> io::Result<EntityList> {
+            let mut list = EntityList::new();
+            for desc in descs {
+                list.add(desc.build()?);
+            }
+            Ok(list)
+        };
+        Ok(match self {
+            EntityDesc::Sphere {
+                center,
+                radius,
+                material,
+            } => Box::new(Sphere::new(*center, *radius, material.build())),
+            EntityDesc::Quad {
+                q,
+                u,
+                v,
+                uv_scale,
+                cull_backface,
+                flip_back_u,
+                material,
+            } => {
+                let mut quad = Quad::try_new(*q, *u, *v, material.build()).ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("Degenerate quad: edges {:?} and {:?} span no area", u, v),
+                    )
+                })?;
+                quad.uv_scale = *uv_scale;
+                quad.cull_backface = *cull_backface;
+                quad.flip_back_u = *flip_back_u;
+                Box::new(quad)
+            }
+            EntityDesc::InfinitePlane {
+                point,
+                normal,
+                material,
+            } => Box::new(InfinitePlane::new(*point, *normal, material.build())),
+            EntityDesc::Mesh {
+                vertices,
+                indices,
+                material,
+            } => Box::new(Mesh::new(
+                vertices.clone(),
+                indices.clone(),
+                material.build(),
+            )),
+            EntityDesc::ConstantMedium {
+                boundary,
+                density,
+                phase_function,
+            } => Box::new(ConstantMedium::new(
+                Box::new(build_list(boundary)?),
+                *density,
+                phase_function.build(),
+            )),
+            EntityDesc::TexturedMedium {
+                boundary,
+                max_density,
+                density,
+                phase_function,
+            } => Box::new(TexturedMedium::new(
+                Box::new(build_list(boundary)?),
+                *max_density,
+                density.build(),
+                phase_function.build(),
+            )),
+            EntityDesc::Bvh { children } => Box::new(BVH::new(build_list(children)?)),
+        })
+    }
+
+    pub fn is_emissive(&self) -> bool {
+        match self {
+            EntityDesc::Sphere { material, .. }
+            | EntityDesc::Quad { material, .. }
+            | EntityDesc::InfinitePlane { material, .. }
+            | EntityDesc::Mesh { material, .. } => material.is_emissive(),
+            EntityDesc::ConstantMedium { .. }
+            | EntityDesc::TexturedMedium { .. }
+            | EntityDesc::Bvh { .. } => false,
+        }
+    }
+}
+
+/// Lets an entity be shared, e.g. between the entity list and a scene's light list.
+impl<T: Hittable + ?Sized> Hittable for Arc<T> {
+    fn hit<'a>(&'a self, ray: &Ray, t_interval: &Interval, record: &mut HitRecord<'a>) -> bool {
+        (**self).hit(ray, t_interval, record)
+    }
+
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3) -> Real {
+        (**self).pdf_value(origin, direction)
+    }
+
+    fn random(&self, origin: &Vec3) -> Vec3 {
+        (**self).random(origin)
+    }
+
+    fn summary(&self) -> String {
+        (**self).summary()
+    }
+
+    fn children(&self) -> Vec<&dyn Hittable> {
+        (**self).children()
+    }
+
+    fn node_bounds(&self, max_depth: u32, bounds: &mut Vec<(AABB, u32)>) {
+        (**self).node_bounds(max_depth, bounds)
+    }
+
+    #[cfg(feature = "serde")]
+    fn describe(&self) -> Option<Vec<EntityDesc>> {
+        (**self).describe()
+    }
+}
+
+impl<T: Hittable + ?Sized> HasAABB for Arc<T> {
+    fn get_aabb(&self) -> AABB {
+        (**self).get_aabb()
+    }
+
+    fn compute_aabb(&self) -> AABB {
+        (**self).compute_aabb()
+    }
+
+    fn is_bounded(&self) -> bool {
+        (**self).is_bounded()
+    }
 }
 
+/// Panics if the entity is shared, e.g. a light registered with `Scene::add_light`, since the
+/// other holders would see the change as well.
+impl<T: Hittable + ?Sized> Transformable for Arc<T> {
+    fn translate(&mut self, translation: Vec3) {
+        Arc::get_mut(self)
+            .expect("Shared entities cannot be transformed")
+            .translate(translation);
+    }
+
+    fn rotate(&mut self, axis: Vec3, angle: Real) {
+        Arc::get_mut(self)
+            .expect("Shared entities cannot be transformed")
+            .rotate(axis, angle);
+    }
+
+    fn rotate_about(&mut self, pivot: Vec3, axis: Vec3, angle: Real) {
+        Arc::get_mut(self)
+            .expect("Shared entities cannot be transformed")
+            .rotate_about(pivot, axis, angle);
+    }
+}
+
+/// Lists with at least this many entities are searched through an `IndexedBVH` rather than
+/// one entity at a time.
+const INDEXED_HIT_THRESHOLD: usize = 16;
+
 pub struct EntityList {
-    pub list: Vec<Box<dyn Hittable>>,
-    pub bbox: AABB,
+    list: Vec<Box<dyn Hittable>>,
+    bbox: AABB,
+    /// Built by the first `hit` on a long list and dropped whenever the list changes.
+    index: OnceLock<IndexedBVH>,
 }
 
 impl EntityList {
     pub fn new() -> Self {
         Self {
             list: Vec::new(),
-            bbox: AABB::default(),
+            bbox: AABB::empty(),
+            index: OnceLock::new(),
         }
     }
 
     pub fn add(&mut self, object: Box<dyn Hittable>) {
         self.bbox = AABB::combine(&self.bbox, &object.get_aabb());
         self.list.push(object);
+        self.index = OnceLock::new();
+    }
+
+    pub fn len(&self) -> usize {
+        self.list.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    /// Sphere around the bounding box of the list's bounded entities as `(center, radius)`,
+    /// e.g. to frame the camera. `None` if there are none, such as in an empty list or one
+    /// holding only infinite planes.
+    pub fn bounding_sphere(&self) -> Option<(Vec3, Real)> {
+        let bbox = self
+            .list
+            .iter()
+            .filter(|entity| entity.is_bounded())
+            .fold(AABB::empty(), |bbox, entity| {
+                AABB::combine(&bbox, &entity.get_aabb())
+            });
+        if !bbox.x.is_valid() {
+            return None;
+        }
+        let min = Vec3::new(bbox.x.min, bbox.y.min, bbox.z.min);
+        let max = Vec3::new(bbox.x.max, bbox.y.max, bbox.z.max);
+        Some((0.5 * (min + max), 0.5 * (max - min).length()))
+    }
+
+    /// Puts the bounded entities behind a BVH and keeps the unbounded ones, which no finite
+    /// hierarchy can hold, in a flat list beside it. Hits on both are resolved to the nearest
+    /// by the returned list.
+    pub fn into_bvh(self) -> EntityList {
+        let (bounded, unbounded): (Vec<_>, Vec<_>) = self
+            .list
+            .into_iter()
+            .partition(|entity| entity.is_bounded());
+        let mut list = EntityList::new();
+        if !bounded.is_empty() {
+            let mut bvh_entities = EntityList::new();
+            for entity in bounded {
+                bvh_entities.add(entity);
+            }
+            list.add(Box::new(BVH::new(bvh_entities)));
+        }
+        for entity in unbounded {
+            list.add(entity);
+        }
+        list
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &dyn Hittable> {
+        self.list.iter().map(|entity| entity.as_ref())
     }
 
     pub fn hit<'a>(&'a self, ray: &Ray, t_interval: &Interval, record: &mut HitRecord<'a>) -> bool {
+        if self.list.len() >= INDEXED_HIT_THRESHOLD {
+            let index = self.index.get_or_init(|| IndexedBVH::new(&self.list));
+            return index.hit(&self.list, ray, t_interval, record);
+        }
         let mut tmp_record = HitRecord::new();
         let mut is_hit = false;
         let mut closest_so_far = t_interval.max;
@@ -98,18 +445,48 @@ impl HasAABB for EntityList {
     }
 
     fn compute_aabb(&self) -> AABB {
-        let mut aabb = AABB::default();
+        let mut aabb = AABB::empty();
         for entity in &self.list {
             aabb = AABB::combine(&aabb, &entity.get_aabb());
         }
         aabb
     }
+
+    fn is_bounded(&self) -> bool {
+        self.list.iter().all(|entity| entity.is_bounded())
+    }
 }
 
 impl Hittable for EntityList {
     fn hit<'a>(&'a self, ray: &Ray, t_interval: &Interval, record: &mut HitRecord<'a>) -> bool {
         self.hit(ray, t_interval, record)
     }
+
+    fn summary(&self) -> String {
+        format!("EntityList of {} entities", self.len())
+    }
+
+    fn children(&self) -> Vec<&dyn Hittable> {
+        self.iter().collect()
+    }
+
+    #[cfg(feature = "serde")]
+    fn describe(&self) -> Option<Vec<EntityDesc>> {
+        let mut descs = Vec::new();
+        for entity in &self.list {
+            descs.extend(entity.describe()?);
+        }
+        Some(descs)
+    }
+}
+
+impl IntoIterator for EntityList {
+    type Item = Box<dyn Hittable>;
+    type IntoIter = std::vec::IntoIter<Box<dyn Hittable>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.list.into_iter()
+    }
 }
 
 impl Transformable for EntityList {
@@ -118,12 +495,225 @@ impl Transformable for EntityList {
             entity.translate(translation);
         }
         self.bbox = self.compute_aabb();
+        self.index = OnceLock::new();
     }
 
-    fn rotate(&mut self, axis: Vec3, angle: f32) {
+    fn rotate(&mut self, axis: Vec3, angle: Real) {
         for entity in &mut self.list {
             entity.rotate(axis, angle);
         }
         self.bbox = self.compute_aabb();
+        self.index = OnceLock::new();
+    }
+
+    fn rotate_about(&mut self, pivot: Vec3, axis: Vec3, angle: Real) {
+        for entity in &mut self.list {
+            entity.rotate_about(pivot, axis, angle);
+        }
+        self.bbox = self.compute_aabb();
+        self.index = OnceLock::new();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entities::infinite_plane::InfinitePlane;
+    use crate::entities::material_override::MaterialOverride;
+    use crate::entities::sphere::Sphere;
+    use crate::material::Lambertian;
+    use crate::texture::Texture;
+
+    #[test]
+    fn test_entity_list_len_iter_and_bounds() {
+        let material: Arc<dyn Material> =
+            Arc::new(Lambertian::new(Box::new(Texture::new(Vec3::one()))));
+        let mut entities = EntityList::new();
+        assert!(entities.is_empty());
+        assert_eq!(entities.len(), 0);
+
+        let centers = [
+            Vec3::new(2.0, 3.0, 4.0),
+            Vec3::new(5.0, 3.0, 4.0),
+            Vec3::new(2.0, 6.0, 8.0),
+        ];
+        for center in centers {
+            entities.add(Box::new(Sphere::new(center, 1.0, Arc::clone(&material))));
+        }
+        assert!(!entities.is_empty());
+        assert_eq!(entities.len(), 3);
+        for (entity, center) in entities.iter().zip(centers) {
+            let aabb = entity.get_aabb();
+            assert_eq!(aabb.x.min, center.x - 1.0);
+            assert_eq!(aabb.z.max, center.z + 1.0);
+        }
+
+        // The bounds only cover the spheres, not the origin
+        let aabb = entities.get_aabb();
+        assert_eq!((aabb.x.min, aabb.x.max), (1.0, 6.0));
+        assert_eq!((aabb.y.min, aabb.y.max), (2.0, 7.0));
+        assert_eq!((aabb.z.min, aabb.z.max), (3.0, 9.0));
+        let (center, radius) = entities.bounding_sphere().unwrap();
+        assert_eq!(center, Vec3::new(3.5, 4.5, 6.0));
+        assert!((radius - 0.5 * Real::sqrt(86.0)).abs() < 1e-5);
+        assert_eq!(entities.into_iter().count(), 3);
+    }
+
+    #[test]
+    fn test_rotating_a_list_swings_wrapped_spheres_about_the_pivot() {
+        let material: Arc<dyn Material> =
+            Arc::new(Lambertian::new(Box::new(Texture::new(Vec3::one()))));
+        let sphere = Sphere::new(Vec3::new(2.0, 0.0, 0.0), 1.0, Arc::clone(&material));
+        let mut entities = EntityList::new();
+        entities.add(Box::new(MaterialOverride::new(
+            Box::new(sphere),
+            material,
+            false,
+        )));
+
+        // A quarter turn about y through (1, 0, 0) takes the center from (2, 0, 0) to (1, 0, -1)
+        entities.rotate_about(Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), 90.0);
+        let aabb = entities.get_aabb();
+        assert!((aabb.x.min - 0.0).abs() < 1e-5 && (aabb.x.max - 2.0).abs() < 1e-5);
+        assert!((aabb.z.min + 2.0).abs() < 1e-5 && (aabb.z.max - 0.0).abs() < 1e-5);
+
+        let mut record = HitRecord::new();
+        let ray = Ray::new(Vec3::new(1.0, 5.0, -1.0), Vec3::new(0.0, -1.0, 0.0));
+        assert!(entities.hit(&ray, &Interval::new(0.001, Real::MAX), &mut record));
+        assert!((record.t - 4.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_bvh_and_unbounded_list_report_the_nearest_hit() {
+        let material = |albedo: Real| -> Arc<dyn Material> {
+            Arc::new(Lambertian::new(Box::new(Texture::new(
+                Vec3::one() * albedo,
+            ))))
+        };
+        let mut entities = EntityList::new();
+        entities.add(Box::new(InfinitePlane::new(
+            Vec3::zero(),
+            Vec3::new(0.0, 1.0, 0.0),
+            material(0.1),
+        )));
+        // A lone plane leaves nothing to frame
+        assert!(entities.bounding_sphere().is_none());
+        // One sphere floating above the floor, one sunk halfway into it
+        entities.add(Box::new(Sphere::new(
+            Vec3::new(0.0, 2.0, 0.0),
+            1.0,
+            material(0.2),
+        )));
+        entities.add(Box::new(Sphere::new(
+            Vec3::new(4.0, 0.0, 0.0),
+            1.0,
+            material(0.3),
+        )));
+        assert!(!entities.is_bounded());
+        // Framing ignores the plane
+        let (center, radius) = entities.bounding_sphere().unwrap();
+        assert!(center.is_finite() && radius.is_finite());
+
+        let scene = entities.into_bvh();
+        assert_eq!(scene.len(), 2);
+        assert!(scene.iter().next().unwrap().is_bounded());
+        assert!(!scene.iter().nth(1).unwrap().is_bounded());
+
+        let albedo_at = |origin: Vec3, direction: Vec3| {
+            let mut record = HitRecord::new();
+            let ray = Ray::new(origin, direction);
+            assert!(scene.hit(&ray, &Interval::new(0.001, Real::MAX), &mut record));
+            let material = record.material.unwrap();
+            let lambertian = material.as_any().downcast_ref::<Lambertian>().unwrap();
+            let albedo = lambertian.albedo.value(&record.uv, &record.position).x;
+            (albedo, record.t)
+        };
+        let down = Vec3::new(0.0, -1.0, 0.0);
+        // The sphere is in front of the plane
+        let (albedo, t) = albedo_at(Vec3::new(0.0, 5.0, 0.0), down);
+        assert_eq!(albedo, 0.2);
+        assert!((t - 2.0).abs() < 1e-4);
+        // Beside it, the plane is the only hit
+        let (albedo, t) = albedo_at(Vec3::new(2.0, 5.0, 0.0), down);
+        assert_eq!(albedo, 0.1);
+        assert!((t - 5.0).abs() < 1e-4);
+        // The sunk sphere's cap comes before the plane
+        let (albedo, t) = albedo_at(Vec3::new(4.0, 5.0, 0.0), down);
+        assert_eq!(albedo, 0.3);
+        assert!((t - 4.0).abs() < 1e-4);
+        // From below the floor the plane hides the sphere above it
+        let (albedo, t) = albedo_at(Vec3::new(0.0, -3.0, 0.0), -down);
+        assert_eq!(albedo, 0.1);
+        assert!((t - 3.0).abs() < 1e-4);
+        // Inside the sunk sphere, the plane cuts it off before its upper wall
+        let (albedo, t) = albedo_at(Vec3::new(4.0, -0.5, 0.0), -down);
+        assert_eq!(albedo, 0.1);
+        assert!((t - 0.5).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_long_lists_hit_through_a_cached_bvh() {
+        let material: Arc<dyn Material> =
+            Arc::new(Lambertian::new(Box::new(Texture::new(Vec3::one()))));
+        let mut entities = EntityList::new();
+        entities.add(Box::new(InfinitePlane::new(
+            Vec3::new(0.0, -1.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Arc::clone(&material),
+        )));
+        for i in 0..64 {
+            let center = Vec3::new(
+                (i % 8) as Real * 2.0,
+                (i / 8) as Real * 0.3,
+                (i / 8) as Real,
+            );
+            entities.add(Box::new(Sphere::new(center, 0.7, Arc::clone(&material))));
+        }
+
+        let linear_hit = |ray: &Ray, t_interval: &Interval| {
+            let mut closest: Option<Real> = None;
+            for entity in entities.iter() {
+                let mut record = HitRecord::new();
+                let t_max = closest.unwrap_or(t_interval.max);
+                if entity.hit(ray, &Interval::new(t_interval.min, t_max), &mut record) {
+                    closest = Some(record.t);
+                }
+            }
+            closest
+        };
+        let t_interval = Interval::new(0.001, Real::MAX);
+        let eye = Vec3::new(7.0, 6.0, -6.0);
+        let mut hits = 0;
+        for i in 0..400 {
+            let target = Vec3::new(
+                (i % 20) as Real * 0.8 - 1.0,
+                0.5,
+                (i / 20) as Real * 0.4 - 1.0,
+            );
+            let ray = Ray::new(eye, target - eye);
+            let mut record = HitRecord::new();
+            let hit = entities.hit(&ray, &t_interval, &mut record);
+            let expected = linear_hit(&ray, &t_interval);
+            assert_eq!(hit, expected.is_some());
+            if let Some(t) = expected {
+                assert!((record.t - t).abs() < 1e-5, "{} vs {}", record.t, t);
+                hits += 1;
+            }
+        }
+        assert!(hits > 200, "only {} hits", hits);
+        assert!(entities.index.get().is_some());
+
+        // Changing the list drops the hierarchy, and short lists never build one
+        entities.add(Box::new(Sphere::new(
+            Vec3::zero(),
+            0.5,
+            Arc::clone(&material),
+        )));
+        assert!(entities.index.get().is_none());
+        let mut short = EntityList::new();
+        short.add(Box::new(Sphere::new(Vec3::zero(), 1.0, material)));
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(short.hit(&ray, &t_interval, &mut HitRecord::new()));
+        assert!(short.index.get().is_none());
     }
 }
@@ -0,0 +1,219 @@
+use std::sync::Arc;
+
+use crate::aabb::{Axis, HasAABB, AABB};
+#[cfg(feature = "serde")]
+use crate::entities::entity::EntityDesc;
+use crate::entities::entity::{HitRecord, Hittable, Transformable};
+use crate::interval::Interval;
+use crate::material::{material_name, Material};
+use crate::math::{mat3::Mat3, vec2::Vec2, vec3::*, Real};
+use crate::ray::Ray;
+
+/// Plane through `point` extending forever, e.g. a ground without the curvature of a huge
+/// sphere. Its bounding box is infinite along every axis the plane is not perpendicular to, so
+/// `EntityList::into_bvh` keeps it beside the BVH rather than in it.
+#[derive(Debug, Clone)]
+pub struct InfinitePlane {
+    pub point: Vec3,
+    pub normal: Vec3,
+    pub material: Arc<dyn Material>,
+    /// Tangent frame of the texture coordinates, in world units from `point`.
+    tangent: Vec3,
+    bitangent: Vec3,
+    aabb: AABB,
+}
+
+impl InfinitePlane {
+    pub fn new(point: Vec3, normal: Vec3, material: Arc<dyn Material>) -> Self {
+        let mut new = Self {
+            point,
+            normal: normal.normalize(),
+            material,
+            tangent: Vec3::zero(),
+            bitangent: Vec3::zero(),
+            aabb: AABB::default(),
+        };
+        new.update_frame();
+        new
+    }
+
+    fn update_frame(&mut self) {
+        let onb = Mat3::get_orthonormal_basis(&self.normal);
+        self.tangent = onb[0];
+        self.bitangent = onb[1];
+        self.aabb = self.compute_aabb();
+    }
+}
+
+impl HasAABB for InfinitePlane {
+    fn get_aabb(&self) -> AABB {
+        self.aabb
+    }
+
+    fn compute_aabb(&self) -> AABB {
+        let bounds = |axis: Axis| {
+            let idx = axis as usize;
+            if self.normal[idx].abs() == 1.0 {
+                Interval::new(self.point[idx], self.point[idx])
+            } else {
+                Interval::new(Real::NEG_INFINITY, Real::INFINITY)
+            }
+        };
+        AABB::new(bounds(Axis::X), bounds(Axis::Y), bounds(Axis::Z))
+    }
+
+    fn is_bounded(&self) -> bool {
+        false
+    }
+}
+
+impl Hittable for InfinitePlane {
+    fn hit<'a>(&'a self, ray: &Ray, t_interval: &Interval, record: &mut HitRecord<'a>) -> bool {
+        let denom = dot(&self.normal, &ray.direction());
+        if denom.abs() < 1e-8 {
+            return false;
+        }
+        let t = dot(&self.normal, &(self.point - ray.origin)) / denom;
+        if !t_interval.surrounds(t) {
+            return false;
+        }
+        // Project the hit back into the plane, which is exact for axis-aligned planes, so that
+        // spatial textures do not flicker at the plane's own coordinate
+        let hit_p = ray.at(t);
+        let offset = hit_p - self.point;
+        record.position = hit_p - dot(&offset, &self.normal) * self.normal;
+        record.t = t;
+        record.uv = Vec2::new(dot(&offset, &self.tangent), dot(&offset, &self.bitangent));
+        record.dpdu = self.tangent;
+        record.dpdv = self.bitangent;
+        record.material = Some(&self.material);
+        record.set_face_normal(ray, &self.normal);
+        record.set_tangent_frame();
+        record.world_footprint = ray
+            .footprint_at(&record.position, &self.normal)
+            .map_or(0.0, |(dpdx, dpdy)| Real::max(dpdx.length(), dpdy.length()));
+        // One UV unit per world unit
+        record.uv_footprint = record.world_footprint;
+        true
+    }
+
+    fn summary(&self) -> String {
+        format!(
+            "InfinitePlane through {:.3}, normal {:.3}, {}",
+            self.point,
+            self.normal,
+            material_name(self.material.as_ref())
+        )
+    }
+
+    #[cfg(feature = "serde")]
+    fn describe(&self) -> Option<Vec<EntityDesc>> {
+        Some(vec![EntityDesc::InfinitePlane {
+            point: self.point,
+            normal: self.normal,
+            material: self.material.describe()?,
+        }])
+    }
+}
+
+impl Transformable for InfinitePlane {
+    fn translate(&mut self, translation: Vec3) {
+        self.point += translation;
+        self.aabb = self.compute_aabb();
+    }
+
+    fn rotate(&mut self, axis: Vec3, angle: Real) {
+        let rotation = Mat3::rotation(axis, angle.to_radians());
+        self.point = rotation * self.point;
+        self.normal = (rotation * self.normal).normalize();
+        self.update_frame();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assert_vec3_approx;
+    use crate::material::Lambertian;
+    use crate::texture::{CheckerTexture, Texture};
+
+    fn checker_floor() -> InfinitePlane {
+        let material: Arc<dyn Material> = Arc::new(Lambertian::new(Box::new(CheckerTexture::new(
+            Texture::new(Vec3::zero()),
+            Texture::new(Vec3::one()),
+            1.0,
+        ))));
+        InfinitePlane::new(Vec3::zero(), Vec3::new(0.0, 1.0, 0.0), material)
+    }
+
+    #[test]
+    fn test_floor_is_flat_out_to_the_horizon() {
+        let floor = checker_floor();
+        let eye = Vec3::new(0.0, 2.0, 0.0);
+        // A sphere of radius 1000 would have dropped by about distance^2 / 2000 at the far hits
+        for distance in [1.0, 10.0, 100.0, 1000.0, 10000.0] {
+            let ray = Ray::new(eye, Vec3::new(distance, -2.0, 0.3 * distance));
+            let mut record = HitRecord::new();
+            assert!(floor.hit(&ray, &Interval::new(0.001, Real::MAX), &mut record));
+            assert_eq!(record.position.y, 0.0);
+            assert!(record.front_face);
+            assert_eq!(record.normal, Vec3::new(0.0, 1.0, 0.0));
+            assert_vec3_approx!(
+                record.position,
+                Vec3::new(distance, 0.0, 0.3 * distance),
+                1e-6 * distance.max(1.0) * 10.0
+            );
+        }
+
+        let mut record = HitRecord::new();
+        let parallel = Ray::new(eye, Vec3::new(1.0, 0.0, 0.0));
+        assert!(!floor.hit(&parallel, &Interval::new(0.001, Real::MAX), &mut record));
+        let away = Ray::new(eye, Vec3::new(1.0, 0.5, 0.0));
+        assert!(!floor.hit(&away, &Interval::new(0.001, Real::MAX), &mut record));
+        let below = Ray::new(Vec3::new(0.0, -1.0, 0.0), Vec3::new(0.2, 1.0, 0.0));
+        assert!(floor.hit(&below, &Interval::new(0.001, Real::MAX), &mut record));
+        assert!(!record.front_face);
+    }
+
+    #[test]
+    fn test_checker_tiles_are_unit_squares() {
+        let floor = checker_floor();
+        let material = floor
+            .material
+            .as_any()
+            .downcast_ref::<Lambertian>()
+            .unwrap();
+        let color_at = |x: Real, z: Real| {
+            let target = Vec3::new(x, 0.0, z);
+            let ray = Ray::new(
+                target + Vec3::new(0.3, 5.0, -0.2),
+                Vec3::new(-0.3, -5.0, 0.2),
+            );
+            let mut record = HitRecord::new();
+            assert!(floor.hit(&ray, &Interval::new(0.001, Real::MAX), &mut record));
+            material.albedo.value(&record.uv, &record.position).x
+        };
+        for (x, z) in [(0.5, 0.5), (-3.5, 7.5), (250.5, -120.5)] {
+            let color = color_at(x, z);
+            assert_eq!(color_at(x + 0.4, z - 0.4), color);
+            assert_ne!(color_at(x + 1.0, z), color);
+            assert_ne!(color_at(x, z + 1.0), color);
+            assert_eq!(color_at(x + 1.0, z + 1.0), color);
+        }
+    }
+
+    #[test]
+    fn test_bounds_are_only_finite_across_axis_aligned_planes() {
+        let floor = checker_floor();
+        let aabb = floor.get_aabb();
+        assert!(aabb.y.min <= 0.0 && aabb.y.max >= 0.0 && aabb.y.size() < 1e-3);
+        assert_eq!(aabb.x.max, Real::INFINITY);
+        assert_eq!(aabb.z.min, Real::NEG_INFINITY);
+
+        let mut tilted = floor.clone();
+        tilted.rotate(Vec3::new(1.0, 0.0, 0.0), 30.0);
+        assert_eq!(tilted.get_aabb().y.max, Real::INFINITY);
+        assert!((dot(&tilted.normal, &tilted.tangent)).abs() < 1e-6);
+        assert!((dot(&tilted.normal, &tilted.bitangent)).abs() < 1e-6);
+    }
+}
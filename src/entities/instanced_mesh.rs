@@ -0,0 +1,248 @@
+use std::mem::size_of;
+use std::sync::Arc;
+
+use crate::aabb::{HasAABB, AABB};
+use crate::bvh::BVH;
+use crate::entities::entity::{EntityList, HitRecord, Hittable, Transformable};
+use crate::entities::mesh::Mesh;
+use crate::interval::Interval;
+use crate::math::{transform::Transform, vec3::Vec3, Real};
+use crate::ray::Ray;
+
+/// One placement of a shared mesh. Rays are moved into mesh space instead of copying the
+/// geometry, so an instance costs a transform and a bounding box.
+pub struct MeshInstance {
+    pub mesh: Arc<Mesh>,
+    pub transform: Transform,
+    aabb: AABB,
+}
+
+impl MeshInstance {
+    pub fn new(mesh: Arc<Mesh>, transform: Transform) -> Self {
+        let mut new = Self {
+            mesh,
+            transform,
+            aabb: AABB::default(),
+        };
+        new.aabb = new.compute_aabb();
+        new
+    }
+}
+
+impl HasAABB for MeshInstance {
+    fn get_aabb(&self) -> AABB {
+        self.aabb
+    }
+
+    fn compute_aabb(&self) -> AABB {
+        let local = self.mesh.get_aabb();
+        let mut aabb = AABB::default();
+        for corner in 0..8 {
+            let p = Vec3::new(
+                if corner & 1 == 0 {
+                    local.x.min
+                } else {
+                    local.x.max
+                },
+                if corner & 2 == 0 {
+                    local.y.min
+                } else {
+                    local.y.max
+                },
+                if corner & 4 == 0 {
+                    local.z.min
+                } else {
+                    local.z.max
+                },
+            );
+            let world = self.transform.point_to_world(&p);
+            let corner_box = AABB::construct_exact(world, world);
+            aabb = if corner == 0 {
+                corner_box
+            } else {
+                AABB::combine(&aabb, &corner_box)
+            };
+        }
+        aabb
+    }
+}
+
+impl Hittable for MeshInstance {
+    fn hit<'a>(&'a self, ray: &Ray, t_interval: &Interval, record: &mut HitRecord<'a>) -> bool {
+        if !self.aabb.hit(ray, *t_interval) {
+            return false;
+        }
+        let local_ray = Ray::new(
+            self.transform.point_to_local(&ray.origin),
+            self.transform.vector_to_local(&ray.direction()),
+        );
+        // Directions stay unit length, so distances shrink by the instance scale.
+        let scale = self.transform.scale;
+        let local_interval = Interval::new(t_interval.min / scale, t_interval.max / scale);
+        if !self.mesh.hit(&local_ray, &local_interval, record) {
+            return false;
+        }
+        record.t *= scale;
+        record.position = self.transform.point_to_world(&record.position);
+        record.normal = self.transform.vector_to_world(&record.normal);
+        true
+    }
+
+    fn summary(&self) -> String {
+        format!(
+            "MeshInstance at {:.3}, scale {}, of {}",
+            self.transform.translation,
+            self.transform.scale,
+            self.mesh.summary()
+        )
+    }
+}
+
+impl Transformable for MeshInstance {
+    fn translate(&mut self, translation: Vec3) {
+        self.transform.translate(translation);
+        self.aabb = self.compute_aabb();
+    }
+
+    fn rotate(&mut self, axis: Vec3, angle: Real) {
+        self.transform.rotate(axis, angle);
+        self.aabb = self.compute_aabb();
+    }
+}
+
+/// Many placements of one mesh behind a BVH over the instance bounds.
+pub struct InstancedMesh {
+    pub mesh: Arc<Mesh>,
+    instance_count: usize,
+    bvh: BVH,
+}
+
+impl InstancedMesh {
+    pub fn new(mesh: Arc<Mesh>, transforms: Vec<Transform>) -> Self {
+        let instance_count = transforms.len();
+        let mut instances = EntityList::new();
+        for transform in transforms {
+            instances.add(Box::new(MeshInstance::new(Arc::clone(&mesh), transform)));
+        }
+        Self {
+            mesh,
+            instance_count,
+            bvh: BVH::new(instances),
+        }
+    }
+
+    /// Approximate bytes used: the shared mesh once plus one `MeshInstance` per placement.
+    pub fn memory_footprint(&self) -> usize {
+        self.mesh.memory_footprint() + self.instance_count * size_of::<MeshInstance>()
+    }
+}
+
+impl HasAABB for InstancedMesh {
+    fn get_aabb(&self) -> AABB {
+        self.bvh.get_aabb()
+    }
+
+    fn compute_aabb(&self) -> AABB {
+        self.bvh.compute_aabb()
+    }
+}
+
+impl Hittable for InstancedMesh {
+    fn hit<'a>(&'a self, ray: &Ray, t_interval: &Interval, record: &mut HitRecord<'a>) -> bool {
+        self.bvh.hit(ray, t_interval, record)
+    }
+
+    fn summary(&self) -> String {
+        format!(
+            "InstancedMesh of {} instances of {}",
+            self.instance_count,
+            self.mesh.summary()
+        )
+    }
+
+    fn node_bounds(&self, max_depth: u32, bounds: &mut Vec<(AABB, u32)>) {
+        self.bvh.node_bounds(max_depth, bounds);
+    }
+}
+
+impl Transformable for InstancedMesh {
+    fn translate(&mut self, translation: Vec3) {
+        self.bvh.translate(translation);
+    }
+
+    fn rotate(&mut self, axis: Vec3, angle: Real) {
+        self.bvh.rotate(axis, angle);
+    }
+
+    fn rotate_about(&mut self, pivot: Vec3, axis: Vec3, angle: Real) {
+        self.bvh.rotate_about(pivot, axis, angle);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assert_vec3_approx;
+    use crate::material::Lambertian;
+    use crate::texture::Texture;
+
+    fn unit_quad_mesh() -> Arc<Mesh> {
+        Arc::new(Mesh::new(
+            vec![
+                Vec3::new(-0.5, -0.5, 0.0),
+                Vec3::new(0.5, -0.5, 0.0),
+                Vec3::new(0.5, 0.5, 0.0),
+                Vec3::new(-0.5, 0.5, 0.0),
+            ],
+            vec![[0, 1, 2], [0, 2, 3]],
+            Arc::new(Lambertian::new(Box::new(Texture::new(Vec3::one())))),
+        ))
+    }
+
+    #[test]
+    fn test_instance_hit_in_world_space() {
+        let transforms = vec![
+            Transform::new(
+                Vec3::new(-4.0, 0.0, -5.0),
+                Vec3::new(0.0, 1.0, 0.0),
+                0.0,
+                1.0,
+            ),
+            Transform::new(
+                Vec3::new(4.0, 0.0, -10.0),
+                Vec3::new(0.0, 1.0, 0.0),
+                0.0,
+                2.0,
+            ),
+        ];
+        let instanced = InstancedMesh::new(unit_quad_mesh(), transforms);
+
+        let mut record = HitRecord::new();
+        let ray = Ray::new(Vec3::new(4.0, 0.8, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(instanced.hit(&ray, &Interval::new(0.001, Real::MAX), &mut record));
+        assert!((record.t - 10.0).abs() < 1e-4);
+        assert_vec3_approx!(record.position, Vec3::new(4.0, 0.8, -10.0), 1e-4);
+        assert_vec3_approx!(record.normal, Vec3::new(0.0, 0.0, 1.0), 1e-4);
+
+        // The unscaled instance does not reach y = 0.8
+        let ray = Ray::new(Vec3::new(-4.0, 0.8, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(!instanced.hit(&ray, &Interval::new(0.001, Real::MAX), &mut record));
+    }
+
+    #[test]
+    fn test_instancing_uses_less_memory_than_duplication() {
+        let mesh = unit_quad_mesh();
+        let transforms = (0..1000)
+            .map(|i| {
+                Transform::new(
+                    Vec3::new(i as Real, 0.0, 0.0),
+                    Vec3::new(0.0, 1.0, 0.0),
+                    0.0,
+                    1.0,
+                )
+            })
+            .collect();
+        let instanced = InstancedMesh::new(Arc::clone(&mesh), transforms);
+        assert!(instanced.memory_footprint() < 1000 * mesh.memory_footprint());
+    }
+}
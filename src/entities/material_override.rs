@@ -0,0 +1,169 @@
+use std::sync::Arc;
+
+use crate::math::Real;
+use crate::{
+    aabb::{HasAABB, AABB},
+    entities::entity::{HitRecord, Hittable, Transformable},
+    interval::Interval,
+    material::{material_name, Lambertian, Material, Normals},
+    math::vec3::Vec3,
+    ray::Ray,
+    texture::Texture,
+};
+
+/// Lookdev decorator that shades everything it wraps with a single material, leaving the
+/// wrapped entities untouched. Emissive surfaces keep their own material when
+/// `preserve_lights` is set so the scene stays lit.
+pub struct MaterialOverride {
+    pub inner: Box<dyn Hittable>,
+    pub material: Arc<dyn Material>,
+    pub preserve_lights: bool,
+}
+
+impl MaterialOverride {
+    pub fn new(
+        inner: Box<dyn Hittable>,
+        material: Arc<dyn Material>,
+        preserve_lights: bool,
+    ) -> Self {
+        Self {
+            inner,
+            material,
+            preserve_lights,
+        }
+    }
+
+    /// Looks up one of the named override materials: `clay`, `normals` or `white`.
+    pub fn preset(name: &str) -> Option<Arc<dyn Material>> {
+        match name {
+            "clay" => Some(Arc::new(Lambertian::new(Box::new(Texture::new(
+                Vec3::new(0.5, 0.5, 0.5),
+            ))))),
+            "white" => Some(Arc::new(Lambertian::new(Box::new(Texture::new(
+                Vec3::new(0.8, 0.8, 0.8),
+            ))))),
+            "normals" => Some(Arc::new(Normals)),
+            _ => None,
+        }
+    }
+}
+
+impl HasAABB for MaterialOverride {
+    fn get_aabb(&self) -> AABB {
+        self.inner.get_aabb()
+    }
+
+    fn compute_aabb(&self) -> AABB {
+        self.inner.compute_aabb()
+    }
+
+    fn is_bounded(&self) -> bool {
+        self.inner.is_bounded()
+    }
+}
+
+impl Transformable for MaterialOverride {
+    fn translate(&mut self, translation: Vec3) {
+        self.inner.translate(translation);
+    }
+
+    fn rotate(&mut self, axis: Vec3, angle: Real) {
+        self.inner.rotate(axis, angle);
+    }
+
+    fn rotate_about(&mut self, pivot: Vec3, axis: Vec3, angle: Real) {
+        self.inner.rotate_about(pivot, axis, angle);
+    }
+}
+
+impl Hittable for MaterialOverride {
+    fn hit<'a>(&'a self, ray: &Ray, t_interval: &Interval, record: &mut HitRecord<'a>) -> bool {
+        if !self.inner.hit(ray, t_interval, record) {
+            return false;
+        }
+        let is_light = record
+            .material
+            .is_some_and(|material| material.is_emissive());
+        if !(self.preserve_lights && is_light) {
+            record.material = Some(&self.material);
+        }
+        true
+    }
+
+    fn summary(&self) -> String {
+        format!(
+            "MaterialOverride with {}{}",
+            material_name(self.material.as_ref()),
+            if self.preserve_lights {
+                ", lights preserved"
+            } else {
+                ""
+            }
+        )
+    }
+
+    fn children(&self) -> Vec<&dyn Hittable> {
+        vec![self.inner.as_ref()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entities::{entity::EntityList, quad::Quad, sphere::Sphere};
+    use crate::material::{Dielectric, DiffuseLight, Metal};
+
+    fn albedo_at(entity: &dyn Hittable, target: Vec3) -> Vec3 {
+        let ray = Ray::new(Vec3::zero(), target);
+        let mut record = HitRecord::new();
+        assert!(entity.hit(&ray, &Interval::new(0.001, Real::MAX), &mut record));
+        let mut attenuation = Vec3::zero();
+        let mut scattered = Ray::default();
+        let mut pdf = 0.0;
+        record
+            .material
+            .unwrap()
+            .scatter(&ray, &record, &mut attenuation, &mut scattered, &mut pdf);
+        attenuation
+    }
+
+    #[test]
+    fn test_override_yields_uniform_albedo() {
+        let mut scene = EntityList::new();
+        scene.add(Box::new(Sphere::new(
+            Vec3::new(-3.0, 0.0, -5.0),
+            1.0,
+            Arc::new(Metal {
+                albedo: Vec3::new(0.9, 0.1, 0.1),
+                fuzz: 0.0,
+            }),
+        )));
+        scene.add(Box::new(Sphere::new(
+            Vec3::new(3.0, 0.0, -5.0),
+            1.0,
+            Arc::new(Dielectric {
+                refraction_index: 1.5,
+            }),
+        )));
+        scene.add(Box::new(Quad::new(
+            Vec3::new(-1.0, 5.0, -6.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 2.0),
+            Arc::new(DiffuseLight::new(Box::new(Texture::new(Vec3::new(
+                4.0, 4.0, 4.0,
+            ))))),
+        )));
+
+        let clay = MaterialOverride::preset("clay").unwrap();
+        let overridden = MaterialOverride::new(Box::new(scene), clay, true);
+
+        let expected = Vec3::new(0.5, 0.5, 0.5);
+        assert_eq!(albedo_at(&overridden, Vec3::new(-3.0, 0.0, -5.0)), expected);
+        assert_eq!(albedo_at(&overridden, Vec3::new(3.0, 0.0, -5.0)), expected);
+
+        let mut record = HitRecord::new();
+        let to_light = Ray::new(Vec3::zero(), Vec3::new(0.0, 5.0, -5.0));
+        assert!(overridden.hit(&to_light, &Interval::new(0.001, Real::MAX), &mut record));
+        assert!(record.material.unwrap().is_emissive());
+    }
+}
@@ -0,0 +1,254 @@
+use std::mem::size_of;
+use std::sync::Arc;
+
+use crate::aabb::{HasAABB, AABB};
+#[cfg(feature = "serde")]
+use crate::entities::entity::EntityDesc;
+use crate::entities::entity::{HitRecord, Hittable, Transformable};
+use crate::interval::Interval;
+use crate::material::{material_name, Material};
+use crate::math::Real;
+use crate::math::{
+    mat3::Mat3,
+    vec2::Vec2,
+    vec3::{cross, Vec3},
+};
+use crate::ray::Ray;
+
+/// Indexed triangle mesh sharing one material. Triangles are tested linearly behind the
+/// mesh AABB, which is fine for the low-poly meshes it is meant for.
+#[derive(Debug, Clone)]
+pub struct Mesh {
+    pub vertices: Vec<Vec3>,
+    pub indices: Vec<[u32; 3]>,
+    pub material: Arc<dyn Material>,
+    aabb: AABB,
+}
+
+impl Mesh {
+    pub fn new(vertices: Vec<Vec3>, indices: Vec<[u32; 3]>, material: Arc<dyn Material>) -> Self {
+        let mut new = Self {
+            vertices,
+            indices,
+            material,
+            aabb: AABB::default(),
+        };
+        new.aabb = new.compute_aabb();
+        new
+    }
+
+    /// Low-poly cone standing on the XZ plane with its base centered at the origin.
+    pub fn cone(radius: Real, height: Real, segments: u32, material: Arc<dyn Material>) -> Self {
+        let mut vertices = vec![Vec3::new(0.0, height, 0.0), Vec3::zero()];
+        let mut indices = Vec::new();
+        for i in 0..segments {
+            let phi = 2.0 * crate::math::consts::PI * i as Real / segments as Real;
+            vertices.push(Vec3::new(radius * phi.cos(), 0.0, radius * phi.sin()));
+            let current = 2 + i;
+            let next = 2 + (i + 1) % segments;
+            indices.push([0, next, current]);
+            indices.push([1, current, next]);
+        }
+        Self::new(vertices, indices, material)
+    }
+
+    /// Bytes used by the mesh and its geometry buffers.
+    pub fn memory_footprint(&self) -> usize {
+        size_of::<Self>()
+            + self.vertices.len() * size_of::<Vec3>()
+            + self.indices.len() * size_of::<[u32; 3]>()
+    }
+
+    /// Watertight intersection (Woop, Benthin and Wald 2013) returning `(t, u, v)` for triangle
+    /// `idx`, with `u` and `v` the weights of its second and third vertex. The triangle is
+    /// sheared into the ray's frame so the edge tests of neighbouring triangles evaluate the same
+    /// expressions on the same shared vertices; a ray can then never slip between them.
+    fn hit_triangle(
+        &self,
+        ray: &Ray,
+        idx: usize,
+        t_interval: &Interval,
+    ) -> Option<(Real, Real, Real)> {
+        let direction = ray.direction();
+        let abs_direction = [direction.x.abs(), direction.y.abs(), direction.z.abs()];
+        let kz = if abs_direction[0] > abs_direction[1] {
+            if abs_direction[0] > abs_direction[2] {
+                0
+            } else {
+                2
+            }
+        } else if abs_direction[1] > abs_direction[2] {
+            1
+        } else {
+            2
+        };
+        let (mut kx, mut ky) = ((kz + 1) % 3, (kz + 2) % 3);
+        if direction[kz] < 0.0 {
+            std::mem::swap(&mut kx, &mut ky);
+        }
+        let shear_x = direction[kx] / direction[kz];
+        let shear_y = direction[ky] / direction[kz];
+        let shear_z = 1.0 / direction[kz];
+
+        let [a, b, c] = self.indices[idx].map(|i| self.vertices[i as usize] - ray.origin);
+        let sheared = |p: &Vec3| (p[kx] - shear_x * p[kz], p[ky] - shear_y * p[kz]);
+        let ((ax, ay), (bx, by), (cx, cy)) = (sheared(&a), sheared(&b), sheared(&c));
+
+        let mut edge_a = cx * by - cy * bx;
+        let mut edge_b = ax * cy - ay * cx;
+        let mut edge_c = bx * ay - by * ax;
+        if edge_a == 0.0 || edge_b == 0.0 || edge_c == 0.0 {
+            // Exactly on an edge in single precision: settle the sign in double precision
+            let (ax, ay, bx, by, cx, cy) = (
+                ax as f64, ay as f64, bx as f64, by as f64, cx as f64, cy as f64,
+            );
+            edge_a = (cx * by - cy * bx) as Real;
+            edge_b = (ax * cy - ay * cx) as Real;
+            edge_c = (bx * ay - by * ax) as Real;
+        }
+        if (edge_a < 0.0 || edge_b < 0.0 || edge_c < 0.0)
+            && (edge_a > 0.0 || edge_b > 0.0 || edge_c > 0.0)
+        {
+            return None;
+        }
+        let det = edge_a + edge_b + edge_c;
+        if det == 0.0 {
+            return None;
+        }
+        let scaled_t = shear_z * (edge_a * a[kz] + edge_b * b[kz] + edge_c * c[kz]);
+        let t = scaled_t / det;
+        if !t_interval.surrounds(t) {
+            return None;
+        }
+        Some((t, edge_b / det, edge_c / det))
+    }
+}
+
+impl HasAABB for Mesh {
+    fn get_aabb(&self) -> AABB {
+        self.aabb
+    }
+
+    fn compute_aabb(&self) -> AABB {
+        let axis = |i: usize| {
+            let values: Vec<Real> = self.vertices.iter().map(|p| p[i]).collect();
+            Interval::from_points(&values)
+        };
+        AABB::new(axis(0), axis(1), axis(2))
+    }
+}
+
+impl Hittable for Mesh {
+    fn hit<'a>(&'a self, ray: &Ray, t_interval: &Interval, record: &mut HitRecord<'a>) -> bool {
+        if !self.aabb.hit(ray, *t_interval) {
+            return false;
+        }
+        let mut closest = None;
+        let mut closest_t = t_interval.max;
+        for idx in 0..self.indices.len() {
+            if let Some((t, u, v)) =
+                self.hit_triangle(ray, idx, &Interval::new(t_interval.min, closest_t))
+            {
+                closest_t = t;
+                closest = Some((idx, t, u, v));
+            }
+        }
+        let Some((idx, t, u, v)) = closest else {
+            return false;
+        };
+        let [a, b, c] = self.indices[idx].map(|i| self.vertices[i as usize]);
+        record.t = t;
+        record.position = ray.at(t);
+        record.uv = Vec2::new(u, v);
+        record.material = Some(&self.material);
+        record.set_face_normal(ray, &cross(&(b - a), &(c - a)).normalize());
+        true
+    }
+
+    fn summary(&self) -> String {
+        format!(
+            "Mesh of {} triangles, {}",
+            self.indices.len(),
+            material_name(self.material.as_ref())
+        )
+    }
+
+    #[cfg(feature = "serde")]
+    fn describe(&self) -> Option<Vec<EntityDesc>> {
+        Some(vec![EntityDesc::Mesh {
+            vertices: self.vertices.clone(),
+            indices: self.indices.clone(),
+            material: self.material.describe()?,
+        }])
+    }
+}
+
+impl Transformable for Mesh {
+    fn translate(&mut self, translation: Vec3) {
+        for vertex in &mut self.vertices {
+            *vertex += translation;
+        }
+        self.aabb = self.compute_aabb();
+    }
+
+    fn rotate(&mut self, axis: Vec3, angle: Real) {
+        let rotation_matrix = Mat3::rotation(axis, angle.to_radians());
+        for vertex in &mut self.vertices {
+            *vertex = rotation_matrix * *vertex;
+        }
+        self.aabb = self.compute_aabb();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Normals;
+
+    #[test]
+    fn test_rays_on_a_shared_edge_never_miss_both_triangles() {
+        // Two triangles sharing the diagonal from vertex 1 to vertex 2, tilted off the axes
+        let vertices = vec![
+            Vec3::new(-0.71, 0.13, -1.37),
+            Vec3::new(0.53, 0.29, -1.91),
+            Vec3::new(-0.47, 1.09, -1.23),
+            Vec3::new(0.77, 1.31, -1.79),
+        ];
+        let mesh = Mesh::new(
+            vertices.clone(),
+            vec![[0, 1, 2], [2, 1, 3]],
+            Arc::new(Normals),
+        );
+        let origin = Vec3::new(0.1, 0.4, 2.0);
+        let t_interval = Interval::new(0.001, Real::MAX);
+        // Skip the endpoints, which are outer corners of the pair
+        for i in 1..2000 {
+            let on_edge = vertices[1] + (i as Real / 2000.0) * (vertices[2] - vertices[1]);
+            let ray = Ray::new(origin, on_edge - origin);
+            let hits = (0..2)
+                .filter(|&idx| mesh.hit_triangle(&ray, idx, &t_interval).is_some())
+                .count();
+            assert!(hits > 0, "ray {} slipped through the shared edge", i);
+
+            let mut record = HitRecord::new();
+            assert!(mesh.hit(&ray, &t_interval, &mut record));
+            assert!((record.position - on_edge).length() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_barycentrics_locate_the_hit() {
+        let vertices = vec![
+            Vec3::new(0.0, 0.0, -1.0),
+            Vec3::new(2.0, 0.0, -1.0),
+            Vec3::new(0.0, 2.0, -1.0),
+        ];
+        let mesh = Mesh::new(vertices, vec![[0, 1, 2]], Arc::new(Normals));
+        let ray = Ray::new(Vec3::new(0.5, 0.25, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let (t, u, v) = mesh
+            .hit_triangle(&ray, 0, &Interval::new(0.001, Real::MAX))
+            .unwrap();
+        assert!((t - 2.0).abs() < 1e-6);
+        assert!((u - 0.25).abs() < 1e-6 && (v - 0.125).abs() < 1e-6);
+    }
+}
@@ -1,4 +1,10 @@
+pub mod animated_transform;
 pub mod constant_medium;
+pub mod displaced_sphere;
 pub mod entity;
+pub mod infinite_plane;
+pub mod instanced_mesh;
+pub mod material_override;
+pub mod mesh;
 pub mod quad;
 pub mod sphere;
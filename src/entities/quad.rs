@@ -1,57 +1,223 @@
 use crate::aabb::{HasAABB, AABB};
+#[cfg(feature = "serde")]
+use crate::entities::entity::EntityDesc;
 use crate::entities::entity::{EntityList, HitRecord, Hittable};
 use crate::interval::Interval;
-use crate::material::Material;
-use crate::math::rand::rand_f32;
+use crate::material::{material_name, Material};
 use crate::math::{
-    mat3::{dot_v3, Mat3},
+    mat3::Mat3,
     vec2::Vec2,
     vec3::{cross, dot, Vec3},
 };
-use crate::ray::Ray;
+use crate::math::{rand::rand_f32, Real};
+use crate::ray::{Ray, RAY_EPSILON};
 use std::sync::Arc;
 
 use super::entity::Transformable;
 
+/// Below this solid angle (in steradians) the spherical rectangle math loses too much float
+/// precision, and the light is small enough that area sampling is just as good.
+const MIN_SPHERICAL_SOLID_ANGLE: Real = 3e-4;
+
+/// Slack on the (alpha, beta) bounds of a quad hit. Without it, rays that land exactly on the
+/// edge shared by two quads can round to just outside both and leak through the crack. The
+/// price is that every quad overlaps its neighbours by this fraction of its edge lengths, which
+/// only matters for quads that are meant to show a visible gap.
+const QUAD_EDGE_EPSILON: Real = 1e-5;
+
+/// Quad projected onto the unit sphere around a shading point, in a frame with x along the
+/// quad's `u` edge, y along `v` and the quad in the plane z = z0 < 0. Sampling it is uniform
+/// in solid angle (Ureña, Fajardo and King 2013, "An Area-Preserving Parametrization for
+/// Spherical Rectangles").
+struct SphericalRectangle {
+    origin: Vec3,
+    x: Vec3,
+    y: Vec3,
+    z: Vec3,
+    x0: Real,
+    x1: Real,
+    y0: Real,
+    y1: Real,
+    z0: Real,
+    b0: Real,
+    b1: Real,
+    k: Real,
+    solid_angle: Real,
+}
+
+impl SphericalRectangle {
+    fn new(origin: &Vec3, corner: &Vec3, u: &Vec3, v: &Vec3) -> Self {
+        let (u_length, v_length) = (u.length(), v.length());
+        let x = *u / u_length;
+        let y = *v / v_length;
+        let mut z = cross(&x, &y);
+        let d = *corner - *origin;
+        let mut z0 = dot(&d, &z);
+        if z0 > 0.0 {
+            z0 = -z0;
+            z = -z;
+        }
+        let (x0, y0) = (dot(&d, &x), dot(&d, &y));
+        let (x1, y1) = (x0 + u_length, y0 + v_length);
+
+        let v00 = Vec3::new(x0, y0, z0);
+        let v01 = Vec3::new(x0, y1, z0);
+        let v10 = Vec3::new(x1, y0, z0);
+        let v11 = Vec3::new(x1, y1, z0);
+        let n0 = cross(&v00, &v10).normalize();
+        let n1 = cross(&v10, &v11).normalize();
+        let n2 = cross(&v11, &v01).normalize();
+        let n3 = cross(&v01, &v00).normalize();
+        let angle = |a: &Vec3, b: &Vec3| Real::acos(dot(&-*a, b).clamp(-1.0, 1.0));
+        let (g0, g1, g2, g3) = (
+            angle(&n0, &n1),
+            angle(&n1, &n2),
+            angle(&n2, &n3),
+            angle(&n3, &n0),
+        );
+        let k = 2.0 * crate::math::consts::PI - g2 - g3;
+        Self {
+            origin: *origin,
+            x,
+            y,
+            z,
+            x0,
+            x1,
+            y0,
+            y1,
+            z0,
+            b0: n0.z,
+            b1: n2.z,
+            k,
+            solid_angle: g0 + g1 - k,
+        }
+    }
+
+    /// Point on the quad for the unit square sample (s, t).
+    fn sample(&self, s: Real, t: Real) -> Vec3 {
+        let au = s * self.solid_angle + self.k;
+        let fu = (Real::cos(au) * self.b0 - self.b1) / Real::sin(au);
+        let cu =
+            (Real::signum(fu) / Real::sqrt(fu * fu + self.b0 * self.b0)).clamp(-0.999999, 0.999999);
+        let xu = (-(cu * self.z0) / Real::sqrt(1.0 - cu * cu)).clamp(self.x0, self.x1);
+        let d = Real::sqrt(xu * xu + self.z0 * self.z0);
+        let h0 = self.y0 / Real::sqrt(d * d + self.y0 * self.y0);
+        let h1 = self.y1 / Real::sqrt(d * d + self.y1 * self.y1);
+        let hv = h0 + t * (h1 - h0);
+        let yv = if hv * hv < 1.0 - 1e-6 {
+            hv * d / Real::sqrt(1.0 - hv * hv)
+        } else {
+            self.y1
+        };
+        self.origin + xu * self.x + yv.clamp(self.y0, self.y1) * self.y + self.z0 * self.z
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Quad {
     pub q: Vec3,
     pub u: Vec3,
     pub v: Vec3,
     pub normal: Vec3,
-    pub d: f32,
+    pub d: Real,
     pub w: Vec3,
     pub material: Arc<dyn Material>,
+    /// Multiplies the hit's (alpha, beta) coordinates so textures tile across large quads.
+    pub uv_scale: Vec2,
+    /// Makes the quad single-sided: rays arriving from behind, on the side opposite `normal`,
+    /// pass through it, e.g. for one-way mirrors. Off by default.
+    pub cull_backface: bool,
+    /// Mirrors `u` on hits from behind, so that a texture such as a poster's text reads the
+    /// right way round from both sides instead of mirrored from the back. Off by default.
+    pub flip_back_u: bool,
     aabb: AABB,
-    area: f32,
+    area: Real,
 }
 
 impl Quad {
+    /// Parallelogram with corner `q` and edges `u` and `v`, which must be nonzero and not
+    /// parallel. Panics otherwise, since a zero-area quad has no plane to hit and would feed
+    /// NaNs into the hits and bounds. Use `try_new` for edges that come from user input.
     pub fn new(q: Vec3, u: Vec3, v: Vec3, material: Arc<dyn Material>) -> Self {
+        Self::try_new(q, u, v, material)
+            .unwrap_or_else(|| panic!("Degenerate quad: edges {:?} and {:?} span no area", u, v))
+    }
+
+    /// Like `new`, but returns `None` for edges that span no area.
+    pub fn try_new(q: Vec3, u: Vec3, v: Vec3, material: Arc<dyn Material>) -> Option<Self> {
         let n = cross(&u, &v);
+        let w = n / dot(&n, &n);
+        if !w.is_finite() {
+            return None;
+        }
         let mut new = Self {
             q,
             u,
             v,
             normal: n.normalize(),
             d: 0.0,
-            w: Vec3::zero(),
+            w,
             material,
+            uv_scale: Vec2::one(),
+            cull_backface: false,
+            flip_back_u: false,
             aabb: AABB::default(),
             area: n.length(),
         };
         new.aabb = new.compute_aabb();
         new.d = dot(&new.normal, &new.q);
-        new.w = n / dot(&n, &n);
-        new
+        Some(new)
     }
 
-    pub fn is_interior(alpha: f32, beta: f32, record: &mut HitRecord) -> bool {
+    /// Splits the quad spanned by `u` and `v` from `origin` into `cols` x `rows` tiles. Tile
+    /// (col, row) gets `materials[(col + row) % materials.len()]`, so two materials give a
+    /// checkerboard of physical tiles.
+    pub fn grid(
+        origin: Vec3,
+        u: Vec3,
+        v: Vec3,
+        cols: u32,
+        rows: u32,
+        materials: &[Arc<dyn Material>],
+    ) -> EntityList {
+        assert!(
+            !materials.is_empty(),
+            "A quad grid needs at least one material"
+        );
+        let (tile_u, tile_v) = (u / cols as Real, v / rows as Real);
+        let mut list = EntityList::new();
+        for row in 0..rows {
+            for col in 0..cols {
+                let material = &materials[((col + row) as usize) % materials.len()];
+                list.add(Box::new(Quad::new(
+                    origin + col as Real * tile_u + row as Real * tile_v,
+                    tile_u,
+                    tile_v,
+                    Arc::clone(material),
+                )));
+            }
+        }
+        list
+    }
+
+    /// Solid-angle sampling setup for lights seen from `origin`, or `None` when the quad is
+    /// not a rectangle or covers too little of the sphere, in which case area sampling is used.
+    fn spherical_rectangle(&self, origin: &Vec3) -> Option<SphericalRectangle> {
+        if dot(&self.u, &self.v).abs() > 1e-4 * self.area {
+            return None;
+        }
+        let rectangle = SphericalRectangle::new(origin, &self.q, &self.u, &self.v);
+        (rectangle.solid_angle >= MIN_SPHERICAL_SOLID_ANGLE).then_some(rectangle)
+    }
+
+    pub fn is_interior(alpha: Real, beta: Real, record: &mut HitRecord) -> bool {
         let interval = Interval::new(0.0, 1.0);
-        if !interval.contains(alpha) || !interval.contains(beta) {
+        if !interval.contains_eps(alpha, QUAD_EDGE_EPSILON)
+            || !interval.contains_eps(beta, QUAD_EDGE_EPSILON)
+        {
             return false;
         }
-        record.uv = Vec2::new(alpha, beta);
+        record.uv = Vec2::new(interval.clamp(alpha), interval.clamp(beta));
         true
     }
 }
@@ -70,14 +236,14 @@ impl HasAABB for Quad {
         let (min, max) = [p0, p1, p2, p3].iter().fold((p0, p0), |(min, max), p| {
             (
                 Vec3::new(
-                    f32::min(min.x, p.x),
-                    f32::min(min.y, p.y),
-                    f32::min(min.z, p.z),
+                    Real::min(min.x, p.x),
+                    Real::min(min.y, p.y),
+                    Real::min(min.z, p.z),
                 ),
                 Vec3::new(
-                    f32::max(max.x, p.x),
-                    f32::max(max.y, p.y),
-                    f32::max(max.z, p.z),
+                    Real::max(max.x, p.x),
+                    Real::max(max.y, p.y),
+                    Real::max(max.z, p.z),
                 ),
             )
         });
@@ -92,8 +258,8 @@ impl Hittable for Quad {
         t_interval: &crate::interval::Interval,
         record: &mut HitRecord<'a>,
     ) -> bool {
-        let denom = dot(&self.normal, &ray.direction);
-        if denom.abs() < 1e-6 {
+        let denom = dot(&self.normal, &ray.direction());
+        if denom.abs() < 1e-6 || (self.cull_backface && denom > 0.0) {
             return false;
         }
 
@@ -110,23 +276,45 @@ impl Hittable for Quad {
                 return false;
             }
 
+            record.uv = Vec2::new(record.uv.x * self.uv_scale.x, record.uv.y * self.uv_scale.y);
+            record.dpdu = self.u / self.uv_scale.x;
+            record.dpdv = self.v / self.uv_scale.y;
             record.t = t;
             record.position = hit_p;
             record.material = Some(&self.material);
             record.set_face_normal(ray, &self.normal);
+            if self.flip_back_u && !record.front_face {
+                record.uv.x = self.uv_scale.x - record.uv.x;
+                record.dpdu = -record.dpdu;
+            }
+            record.set_tangent_frame();
+            let footprint = ray.footprint_at(&hit_p, &self.normal);
+            record.world_footprint =
+                footprint.map_or(0.0, |(dpdx, dpdy)| Real::max(dpdx.length(), dpdy.length()));
+            record.uv_footprint = match footprint {
+                Some((dpdx, dpdy)) => [dpdx, dpdy]
+                    .iter()
+                    .map(|dp| {
+                        let du = dot(&self.w, &cross(dp, &self.v)) * self.uv_scale.x;
+                        let dv = dot(&self.w, &cross(&self.u, dp)) * self.uv_scale.y;
+                        Real::max(du.abs(), dv.abs())
+                    })
+                    .fold(0.0, Real::max),
+                None => 0.0,
+            };
             true
         }
     }
 
-    fn pdf_value(&self, origin: &Vec3, direction: &Vec3) -> f32 {
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3) -> Real {
         let mut hit_rec = HitRecord::new();
-        if self.hit(
-            &Ray::new(*origin, *direction),
-            &Interval::new(0.001, f32::MAX),
-            hit_rec,
-        ) {
+        let ray = Ray::new(*origin, *direction);
+        if self.hit(&ray, &ray.hit_interval(RAY_EPSILON), &mut hit_rec) {
+            if let Some(rectangle) = self.spherical_rectangle(origin) {
+                return 1.0 / rectangle.solid_angle;
+            }
             let dist_sq = hit_rec.t * hit_rec.t * direction.length_squared();
-            let cosine = f32::abs(dot(direction, &hit_rec.normal) / direction.length());
+            let cosine = Real::abs(dot(direction, &hit_rec.normal) / direction.length());
             dist_sq / (cosine * self.area)
         } else {
             0.0
@@ -134,13 +322,39 @@ impl Hittable for Quad {
     }
 
     fn random(&self, origin: &Vec3) -> Vec3 {
-        let p = self.q + self.u * rand_f32() + self.v * rand_f32();
+        let p = match self.spherical_rectangle(origin) {
+            Some(rectangle) => rectangle.sample(rand_f32(), rand_f32()),
+            None => self.q + self.u * rand_f32() + self.v * rand_f32(),
+        };
         p - *origin
     }
+
+    fn summary(&self) -> String {
+        format!(
+            "Quad at {:.3}, u {:.3}, v {:.3}, {}",
+            self.q,
+            self.u,
+            self.v,
+            material_name(self.material.as_ref())
+        )
+    }
+
+    #[cfg(feature = "serde")]
+    fn describe(&self) -> Option<Vec<EntityDesc>> {
+        Some(vec![EntityDesc::Quad {
+            q: self.q,
+            u: self.u,
+            v: self.v,
+            uv_scale: self.uv_scale,
+            cull_backface: self.cull_backface,
+            flip_back_u: self.flip_back_u,
+            material: self.material.describe()?,
+        }])
+    }
 }
 
-fn degrees_to_radians(degrees: f32) -> f32 {
-    degrees * (std::f32::consts::PI / 180.0)
+fn degrees_to_radians(degrees: Real) -> Real {
+    degrees * (crate::math::consts::PI / 180.0)
 }
 
 impl Transformable for Quad {
@@ -150,11 +364,11 @@ impl Transformable for Quad {
         self.aabb = self.compute_aabb();
     }
 
-    fn rotate(&mut self, axis: Vec3, angle: f32) {
+    fn rotate(&mut self, axis: Vec3, angle: Real) {
         let rotation_matrix = Mat3::rotation(axis, degrees_to_radians(angle));
-        self.q = dot_v3(&rotation_matrix, &self.q);
-        self.u = dot_v3(&rotation_matrix, &self.u);
-        self.v = dot_v3(&rotation_matrix, &self.v);
+        self.q = rotation_matrix * self.q;
+        self.u = rotation_matrix * self.u;
+        self.v = rotation_matrix * self.v;
 
         let n = cross(&self.u, &self.v);
         self.normal = n.normalize();
@@ -168,15 +382,15 @@ pub fn create_box(p1: Vec3, p2: Vec3, material: Arc<dyn Material>) -> EntityList
     let mut list = EntityList::new();
 
     let min = Vec3::new(
-        f32::min(p1.x, p2.x),
-        f32::min(p1.y, p2.y),
-        f32::min(p1.z, p2.z),
+        Real::min(p1.x, p2.x),
+        Real::min(p1.y, p2.y),
+        Real::min(p1.z, p2.z),
     );
 
     let max = Vec3::new(
-        f32::max(p1.x, p2.x),
-        f32::max(p1.y, p2.y),
-        f32::max(p1.z, p2.z),
+        Real::max(p1.x, p2.x),
+        Real::max(p1.y, p2.y),
+        Real::max(p1.z, p2.z),
     );
 
     let dx = Vec3::new(max.x - min.x, 0.0, 0.0);
@@ -222,3 +436,298 @@ pub fn create_box(p1: Vec3, p2: Vec3, material: Arc<dyn Material>) -> EntityList
 
     list
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assert_vec3_approx;
+    use crate::camera::Camera;
+    use crate::material::{Lambertian, Normals};
+    use crate::math::rand::seed_sample_rng;
+    use crate::texture::{Texture, TextureSampler};
+
+    #[test]
+    fn test_uv_scale_tiles_coordinates() {
+        let material: Arc<dyn Material> =
+            Arc::new(Lambertian::new(Box::new(Texture::new(Vec3::one()))));
+        let mut floor = Quad::new(
+            Vec3::new(-5.0, 0.0, -5.0),
+            Vec3::new(10.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 10.0),
+            material,
+        );
+        floor.uv_scale = Vec2::new(10.0, 10.0);
+
+        let ray = Ray::new(Vec3::new(4.0, 1.0, 4.0), Vec3::new(0.0, -1.0, 0.0));
+        let mut record = HitRecord::new();
+        assert!(floor.hit(&ray, &Interval::new(0.001, Real::MAX), &mut record));
+        assert!((record.uv.x - 9.0).abs() < 1e-4);
+        assert!((record.uv.y - 9.0).abs() < 1e-4);
+        assert_eq!(record.tangent, Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(record.bitangent, Vec3::new(0.0, 0.0, -1.0));
+    }
+
+    #[test]
+    fn test_culled_quads_are_invisible_from_behind() {
+        let material: Arc<dyn Material> = Arc::new(Normals);
+        // Facing +z
+        let mut mirror = Quad::new(
+            Vec3::new(-1.0, -1.0, 0.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 2.0, 0.0),
+            material,
+        );
+        let in_front = Ray::new(Vec3::new(0.2, 0.3, 5.0), Vec3::new(0.0, 0.1, -1.0));
+        let behind = Ray::new(Vec3::new(0.2, 0.3, -5.0), Vec3::new(0.1, 0.0, 1.0));
+        let hits = |quad: &Quad, ray: &Ray| {
+            let mut record = HitRecord::new();
+            quad.hit(ray, &Interval::new(0.001, Real::MAX), &mut record)
+                .then_some(record.front_face)
+        };
+        assert_eq!(hits(&mirror, &in_front), Some(true));
+        assert_eq!(hits(&mirror, &behind), Some(false));
+
+        mirror.cull_backface = true;
+        assert_eq!(hits(&mirror, &in_front), Some(true));
+        assert_eq!(hits(&mirror, &behind), None);
+        // Culled lights cannot be sampled from behind either
+        assert!(mirror.pdf_value(&in_front.origin, &in_front.direction()) > 0.0);
+        assert_eq!(mirror.pdf_value(&behind.origin, &behind.direction()), 0.0);
+    }
+
+    /// Ink on the left third of the texture, like the first letter of a line of text.
+    #[derive(Debug)]
+    struct LeftInk;
+
+    impl TextureSampler for LeftInk {
+        fn value(&self, uv: &Vec2, _p: &Vec3) -> Vec3 {
+            Vec3::one() * if uv.x < 1.0 / 3.0 { 0.0 } else { 1.0 }
+        }
+    }
+
+    #[test]
+    fn test_back_face_u_flip_keeps_text_readable() {
+        let material: Arc<dyn Material> = Arc::new(Lambertian::new(Box::new(LeftInk)));
+        // Facing +z, with u running to the right as seen from the front
+        let mut poster = Quad::new(
+            Vec3::new(-1.0, -1.0, 0.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 2.0, 0.0),
+            material,
+        );
+        // One row of pixels across the poster, inked or not from left to right on screen
+        let render_row = |quad: &Quad, z: Real| -> Vec<bool> {
+            let camera = Camera::new(12, 12, 30.0, &Vec3::new(0.0, 0.0, z), &Vec3::zero());
+            (0..12)
+                .map(|x| {
+                    let ray = camera.center_ray(x, 6);
+                    let mut record = HitRecord::new();
+                    assert!(quad.hit(&ray, &Interval::new(0.001, Real::MAX), &mut record));
+                    let material = record.material.unwrap();
+                    let lambertian = material.as_any().downcast_ref::<Lambertian>().unwrap();
+                    lambertian.albedo.value(&record.uv, &record.position).x == 0.0
+                })
+                .collect()
+        };
+        let front = render_row(&poster, 3.0);
+        assert!(front[0] && !front[11], "{:?}", front);
+        let mut mirrored = render_row(&poster, -3.0);
+        assert!(!mirrored[0] && mirrored[11], "{:?}", mirrored);
+        mirrored.reverse();
+        assert_eq!(mirrored, front);
+
+        poster.flip_back_u = true;
+        assert_eq!(render_row(&poster, 3.0), front);
+        assert_eq!(render_row(&poster, -3.0), front);
+    }
+
+    #[test]
+    fn test_grid_covers_the_full_quad() {
+        let white: Arc<dyn Material> =
+            Arc::new(Lambertian::new(Box::new(Texture::new(Vec3::one()))));
+        let black: Arc<dyn Material> =
+            Arc::new(Lambertian::new(Box::new(Texture::new(Vec3::zero()))));
+        let (origin, u, v) = (
+            Vec3::new(-5.0, 0.0, -3.0),
+            Vec3::new(10.0, 0.0, 0.0),
+            Vec3::new(0.0, 2.0, 6.0),
+        );
+        let grid = Quad::grid(origin, u, v, 5, 3, &[white, black]);
+        assert_eq!(grid.len(), 15);
+
+        let full = Quad::new(origin, u, v, Arc::new(Normals)).get_aabb();
+        let tiles = grid.get_aabb();
+        for (a, b) in [(full.x, tiles.x), (full.y, tiles.y), (full.z, tiles.z)] {
+            assert!((a.min - b.min).abs() < 1e-5 && (a.max - b.max).abs() < 1e-5);
+        }
+    }
+
+    const SAMPLE_COUNT: usize = 4096;
+
+    /// Mean and variance of the irradiance estimate cos(theta) / pdf at `origin` (normal +y)
+    /// from `light`, drawing directions with `sample`.
+    fn irradiance_estimate(
+        light: &Quad,
+        origin: &Vec3,
+        sample: impl Fn() -> (Vec3, Real),
+    ) -> (Real, Real) {
+        seed_sample_rng(11, 0, 0, 0);
+        let estimates: Vec<Real> = (0..SAMPLE_COUNT)
+            .map(|_| {
+                let (direction, pdf) = sample();
+                assert!(pdf > 0.0);
+                let mut record = HitRecord::new();
+                assert!(light.hit(
+                    &Ray::new(*origin, direction),
+                    &Interval::new(0.001, Real::MAX),
+                    &mut record
+                ));
+                direction.normalize().y / pdf
+            })
+            .collect();
+        let mean = estimates.iter().sum::<Real>() / SAMPLE_COUNT as Real;
+        let variance = estimates
+            .iter()
+            .map(|e| (e - mean) * (e - mean))
+            .sum::<Real>()
+            / SAMPLE_COUNT as Real;
+        (mean, variance)
+    }
+
+    #[test]
+    fn test_solid_angle_sampling_reduces_variance() {
+        // A large light close to the shading point and seen at an angle
+        let light = Quad::new(
+            Vec3::new(-2.0, 0.5, 0.5),
+            Vec3::new(4.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 3.0),
+            Arc::new(Normals),
+        );
+        let origin = Vec3::zero();
+        let rectangle = light.spherical_rectangle(&origin).unwrap();
+
+        let (solid_mean, solid_variance) = irradiance_estimate(&light, &origin, || {
+            let direction = light.random(&origin);
+            (direction, light.pdf_value(&origin, &direction))
+        });
+        let (area_mean, area_variance) = irradiance_estimate(&light, &origin, || {
+            let p = light.q + light.u * rand_f32() + light.v * rand_f32();
+            let direction = p - origin;
+            let cosine = dot(&direction.normalize(), &light.normal).abs();
+            (
+                direction,
+                direction.length_squared() / (cosine * light.area),
+            )
+        });
+        // Both estimate the same integral; allow four standard errors of the noisier one
+        assert!(
+            (solid_mean - area_mean).abs() < 4.0 * Real::sqrt(area_variance / SAMPLE_COUNT as Real),
+            "solid angle {} vs area {}",
+            solid_mean,
+            area_mean
+        );
+        assert!(
+            solid_variance < 0.1 * area_variance,
+            "solid angle variance {} vs area {}",
+            solid_variance,
+            area_variance
+        );
+        assert!(
+            rectangle.solid_angle > 0.0 && rectangle.solid_angle < 2.0 * crate::math::consts::PI
+        );
+    }
+
+    #[test]
+    fn test_sheared_or_distant_quads_fall_back_to_area_sampling() {
+        let sheared = Quad::new(
+            Vec3::zero(),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.5, 0.0, 1.0),
+            Arc::new(Normals),
+        );
+        assert!(sheared
+            .spherical_rectangle(&Vec3::new(0.5, 1.0, 0.5))
+            .is_none());
+        let tiny = Quad::new(
+            Vec3::zero(),
+            Vec3::new(0.01, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 0.01),
+            Arc::new(Normals),
+        );
+        assert!(tiny
+            .spherical_rectangle(&Vec3::new(0.0, 10.0, 0.0))
+            .is_none());
+    }
+
+    #[test]
+    fn test_rays_on_a_shared_edge_hit_one_of_the_quads() {
+        // Tilted tiles, so the edge coordinates are not exactly representable
+        let (origin, u, v) = (
+            Vec3::new(-0.31, 0.17, -0.73),
+            Vec3::new(0.43, 0.21, -0.05),
+            Vec3::new(-0.12, 0.35, 0.61),
+        );
+        let tiles = Quad::grid(origin, 2.0 * u, v, 2, 1, &[Arc::new(Normals)]);
+        for i in 0..1000 {
+            let on_edge = origin + u + (i as Real / 1000.0) * v;
+            let eye = Vec3::new(0.37 * (i % 7) as Real - 1.1, 1.9, 0.11 * (i % 13) as Real);
+            let ray = Ray::new(eye, on_edge - eye);
+            let mut record = HitRecord::new();
+            assert!(
+                tiles.hit(&ray, &Interval::new(0.001, Real::MAX), &mut record),
+                "ray {} fell through the seam",
+                i
+            );
+        }
+    }
+
+    #[test]
+    fn test_rotating_about_the_center_keeps_the_quad_in_place() {
+        let mut quad = Quad::new(
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Arc::new(Normals),
+        );
+        let center = quad.q + 0.5 * (quad.u + quad.v);
+        quad.rotate_about(center, Vec3::new(0.0, 1.0, 0.0), 90.0);
+        assert_vec3_approx!(quad.q + 0.5 * (quad.u + quad.v), center);
+        assert_vec3_approx!(quad.u, Vec3::new(0.0, 0.0, -2.0));
+        assert_vec3_approx!(quad.normal, Vec3::new(1.0, 0.0, 0.0));
+        // Plain rotate turns about the origin and carries the quad away
+        quad.rotate(Vec3::new(0.0, 1.0, 0.0), -90.0);
+        assert_vec3_approx!(quad.q + 0.5 * (quad.u + quad.v), Vec3::new(0.0, 0.5, 3.0));
+    }
+
+    #[test]
+    #[should_panic(expected = "Degenerate quad")]
+    fn test_parallel_edges_are_rejected() {
+        Quad::new(
+            Vec3::zero(),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Arc::new(Normals),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Degenerate quad")]
+    fn test_zero_edge_is_rejected() {
+        Quad::new(
+            Vec3::zero(),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::zero(),
+            Arc::new(Normals),
+        );
+    }
+
+    #[test]
+    fn test_try_new_returns_none_for_degenerate_edges() {
+        let x = Vec3::new(1.0, 0.0, 0.0);
+        assert!(Quad::try_new(Vec3::zero(), x, 2.0 * x, Arc::new(Normals)).is_none());
+        assert!(Quad::try_new(Vec3::zero(), x, Vec3::zero(), Arc::new(Normals)).is_none());
+        assert!(
+            Quad::try_new(Vec3::zero(), x, Vec3::new(0.0, 1.0, 0.0), Arc::new(Normals)).is_some()
+        );
+    }
+}
@@ -1,17 +1,23 @@
-use std::f32::consts::PI;
+use crate::math::consts::PI;
 use std::sync::Arc;
 
 use crate::aabb::{HasAABB, AABB};
+#[cfg(feature = "serde")]
+use crate::entities::entity::EntityDesc;
 use crate::entities::entity::{HitRecord, Hittable, Transformable};
 use crate::interval::Interval;
-use crate::material::Material;
-use crate::math::{vec2::*, vec3::*};
+use crate::material::{material_name, Material};
+use crate::math::{mat3::Mat3, vec2::*, vec3::*, Real};
 
 #[derive(Debug, Clone)]
 pub struct Sphere {
     pub center: Vec3,
-    pub radius: f32,
+    /// A negative radius flips the normal inward, e.g. for the inner wall of hollow glass.
+    pub radius: Real,
     pub material: Arc<dyn Material>,
+    /// Cached from `radius` by `new`, since every ray test needs them.
+    radius_squared: Real,
+    inv_radius: Real,
     aabb: AABB,
 }
 
@@ -21,17 +27,20 @@ impl HasAABB for Sphere {
     }
 
     fn compute_aabb(&self) -> AABB {
-        let rvec = Vec3::new(self.radius, self.radius, self.radius);
-        AABB::construct(self.center - rvec, self.center + rvec)
+        let extent = self.radius.abs();
+        let rvec = Vec3::new(extent, extent, extent);
+        AABB::construct_exact(self.center - rvec, self.center + rvec)
     }
 }
 
 impl Sphere {
-    pub fn new(center: Vec3, radius: f32, material: Arc<dyn Material>) -> Self {
+    pub fn new(center: Vec3, radius: Real, material: Arc<dyn Material>) -> Self {
         let mut new = Self {
             center,
             radius,
             material,
+            radius_squared: radius * radius,
+            inv_radius: 1.0 / radius,
             aabb: AABB::default(),
         };
         new.aabb = new.compute_aabb();
@@ -39,12 +48,25 @@ impl Sphere {
     }
 
     pub fn get_uv(p: &Vec3) -> Vec2 {
-        let theta = f32::acos(-p.y);
-        let phi = f32::atan2(-p.z, p.x) + PI;
+        let theta = Real::acos(-p.y);
+        let phi = Real::atan2(-p.z, p.x) + PI;
         let u = phi / (2.0 * PI);
         let v = theta / PI;
         Vec2::new(u, v)
     }
+
+    /// Derivatives of the surface point along `get_uv`'s (u, v) for unit normal `n`.
+    /// The u direction degenerates to zero at the poles.
+    pub fn get_dpduv(n: &Vec3, radius: Real) -> (Vec3, Vec3) {
+        let sin_theta = Real::sqrt(Real::max(0.0, 1.0 - n.y * n.y));
+        let dpdu = 2.0 * PI * radius * Vec3::new(n.z, 0.0, -n.x);
+        if sin_theta < 1e-6 {
+            return (dpdu, Vec3::zero());
+        }
+        let dpdv =
+            PI * radius * Vec3::new(-n.y * n.x / sin_theta, sin_theta, -n.y * n.z / sin_theta);
+        (dpdu, dpdv)
+    }
 }
 
 impl Hittable for Sphere {
@@ -55,10 +77,10 @@ impl Hittable for Sphere {
         record: &mut HitRecord<'a>,
     ) -> bool {
         let ray_sphere_vec = self.center - ray.origin;
-        let a = dot(&ray.direction, &ray.direction);
-        //let b = -2.0 * dot(&ray.direction, &ray_sphere_vec);
-        let h = dot(&ray.direction, &ray_sphere_vec);
-        let c = dot(&ray_sphere_vec, &ray_sphere_vec) - self.radius * self.radius;
+        let a = dot(&ray.direction(), &ray.direction());
+        //let b = -2.0 * dot(&ray.direction(), &ray_sphere_vec);
+        let h = dot(&ray.direction(), &ray_sphere_vec);
+        let c = dot(&ray_sphere_vec, &ray_sphere_vec) - self.radius_squared;
         let discriminant = h * h - a * c;
         if discriminant < 0.0 {
             false
@@ -75,13 +97,43 @@ impl Hittable for Sphere {
 
             record.t = root;
             record.position = ray.at(root);
-            let outward_normal = (record.position - self.center).normalize();
+            let outward_normal = (record.position - self.center) * self.inv_radius;
             record.set_face_normal(ray, &outward_normal);
             record.material = Some(&self.material);
-            record.uv = Sphere::get_uv(&outward_normal);
+            // Texture coordinates follow the geometric direction whatever the radius sign
+            let direction = outward_normal * self.radius.signum();
+            record.uv = Sphere::get_uv(&direction);
+            // The shading frame follows the analytic derivatives of the UV map, so bump and
+            // normal maps line up with the texture's u and v
+            (record.dpdu, record.dpdv) = Sphere::get_dpduv(&direction, self.radius.abs());
+            record.set_tangent_frame();
+            record.world_footprint = ray
+                .footprint_at(&record.position, &direction)
+                .map_or(0.0, |(dpdx, dpdy)| Real::max(dpdx.length(), dpdy.length()));
+            // Latitude spans PI * radius of arc length per unit of v; longitude spans twice that
+            // per unit of u, so this is the larger of the two.
+            record.uv_footprint = record.world_footprint * self.inv_radius.abs() / PI;
             true
         }
     }
+
+    fn summary(&self) -> String {
+        format!(
+            "Sphere at {:.3}, radius {}, {}",
+            self.center,
+            self.radius,
+            material_name(self.material.as_ref())
+        )
+    }
+
+    #[cfg(feature = "serde")]
+    fn describe(&self) -> Option<Vec<EntityDesc>> {
+        Some(vec![EntityDesc::Sphere {
+            center: self.center,
+            radius: self.radius,
+            material: self.material.describe()?,
+        }])
+    }
 }
 
 impl Transformable for Sphere {
@@ -90,7 +142,190 @@ impl Transformable for Sphere {
         self.aabb = self.compute_aabb();
     }
 
-    fn rotate(&mut self, _axis: Vec3, _angle: f32) {
+    fn rotate(&mut self, _axis: Vec3, _angle: Real) {
         // No rotation for sphere
     }
+
+    fn rotate_about(&mut self, pivot: Vec3, axis: Vec3, angle: Real) {
+        // The sphere itself looks the same, but an off-pivot center swings around the pivot
+        let rotation_matrix = Mat3::rotation(axis, angle.to_radians());
+        self.center = pivot + rotation_matrix * (self.center - pivot);
+        self.aabb = self.compute_aabb();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assert_vec3_approx;
+    use crate::material::{Lambertian, Normals};
+    use crate::ray::Ray;
+    use crate::texture::Texture;
+
+    fn point_at(u: Real, v: Real, radius: Real) -> Vec3 {
+        let (theta, phi) = (v * PI, 2.0 * PI * u - PI);
+        radius
+            * Vec3::new(
+                theta.sin() * phi.cos(),
+                -theta.cos(),
+                -theta.sin() * phi.sin(),
+            )
+    }
+
+    #[test]
+    fn test_dpduv_matches_finite_differences() {
+        let radius = 2.0;
+        let n = Vec3::new(0.3, 0.4, -0.5).normalize();
+        let uv = Sphere::get_uv(&n);
+        assert!((point_at(uv.x, uv.y, radius) - radius * n).length() < 1e-4);
+
+        let (dpdu, dpdv) = Sphere::get_dpduv(&n, radius);
+        let h = 1e-3;
+        let numeric_u =
+            (point_at(uv.x + h, uv.y, radius) - point_at(uv.x - h, uv.y, radius)) / (2.0 * h);
+        let numeric_v =
+            (point_at(uv.x, uv.y + h, radius) - point_at(uv.x, uv.y - h, radius)) / (2.0 * h);
+        assert!(
+            (dpdu - numeric_u).length() < 1e-2,
+            "{:?} vs {:?}",
+            dpdu,
+            numeric_u
+        );
+        assert!(
+            (dpdv - numeric_v).length() < 1e-2,
+            "{:?} vs {:?}",
+            dpdv,
+            numeric_v
+        );
+    }
+
+    #[test]
+    fn test_cached_radius_matches_direct_computation() {
+        let material: Arc<dyn Material> =
+            Arc::new(Lambertian::new(Box::new(Texture::new(Vec3::one()))));
+        for radius in [0.37, 2.5, -1.5] {
+            let center = Vec3::new(0.5, -0.25, -3.0);
+            let sphere = Sphere::new(center, radius, Arc::clone(&material));
+            for i in 0..16 {
+                let target = center + Vec3::new(0.09 * i as Real - 0.7, 0.03 * i as Real, 0.0);
+                let ray = Ray::new(Vec3::new(0.0, 0.0, 2.0), target - Vec3::new(0.0, 0.0, 2.0));
+                let mut record = HitRecord::new();
+                if !sphere.hit(&ray, &Interval::new(0.001, Real::MAX), &mut record) {
+                    continue;
+                }
+                let expected = (record.position - center).normalize() * radius.signum();
+                let outward = if record.front_face {
+                    record.normal
+                } else {
+                    -record.normal
+                };
+                assert!((outward - expected).length() < 1e-4);
+                assert!(((record.position - center).length() - radius.abs()).abs() < 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn test_negative_radius_points_normal_inward() {
+        let material: Arc<dyn Material> =
+            Arc::new(Lambertian::new(Box::new(Texture::new(Vec3::one()))));
+        let center = Vec3::new(1.0, 2.0, -3.0);
+        let hollow = Sphere::new(center, -0.5, material);
+        let aabb = hollow.get_aabb();
+        assert_eq!(aabb.x.min, 0.5);
+        assert_eq!(aabb.x.max, 1.5);
+
+        // From inside, the hit is on the far wall and the inward normal faces the ray
+        let ray = Ray::new(center, Vec3::new(0.0, 0.0, 1.0));
+        let mut record = HitRecord::new();
+        assert!(hollow.hit(&ray, &Interval::new(0.001, Real::MAX), &mut record));
+        assert!(record.front_face);
+        assert_vec3_approx!(record.normal, Vec3::new(0.0, 0.0, -1.0));
+        let solid_uv = Sphere::get_uv(&Vec3::new(0.0, 0.0, 1.0));
+        assert!((record.uv.x - solid_uv.x).abs() < 1e-5 && (record.uv.y - solid_uv.y).abs() < 1e-5);
+
+        // From outside, the ray meets the surface from behind its normal
+        let ray = Ray::new(center + Vec3::new(0.0, 0.0, 2.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(hollow.hit(&ray, &Interval::new(0.001, Real::MAX), &mut record));
+        assert!(!record.front_face);
+        assert_vec3_approx!(record.normal, Vec3::new(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    #[cfg(feature = "f64")]
+    fn test_small_distant_sphere_is_hit_at_its_surface() {
+        // In f32 the squared radius vanishes next to the squared distance, and t itself only
+        // has millimetre resolution out here, so the hit lands a whole radius off
+        let material: Arc<dyn Material> =
+            Arc::new(Lambertian::new(Box::new(Texture::new(Vec3::one()))));
+        let sphere = Sphere::new(Vec3::new(0.0, 0.0, -1e4), 0.01, material);
+        for offset in [0.0, 0.005, -0.007] {
+            let ray = Ray::new(Vec3::new(offset, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+            let mut record = HitRecord::new();
+            assert!(sphere.hit(&ray, &Interval::new(0.001, Real::MAX), &mut record));
+            let expected = 1e4 - (0.01 * 0.01 - offset * offset).sqrt();
+            assert!(
+                (record.t - expected).abs() < 1e-6,
+                "t {} vs {}",
+                record.t,
+                expected
+            );
+            let distance = (record.position - sphere.center).length();
+            assert!((distance - 0.01).abs() < 1e-6, "distance {}", distance);
+        }
+    }
+
+    #[test]
+    fn test_tangent_frame_is_orthonormal_everywhere() {
+        let material: Arc<dyn Material> =
+            Arc::new(Lambertian::new(Box::new(Texture::new(Vec3::one()))));
+        let center = Vec3::new(0.5, -1.0, 2.0);
+        let sphere = Sphere::new(center, 1.5, material);
+        // Aim at the surface from outside along a latitude/longitude grid, poles included
+        for i in 0..=12 {
+            for j in 0..24 {
+                let target = center + point_at(j as Real / 24.0, i as Real / 12.0, 1.5);
+                let origin = center + 3.0 * (target - center);
+                let ray = Ray::new(origin, target - origin);
+                let mut record = HitRecord::new();
+                assert!(sphere.hit(&ray, &Interval::new(0.001, Real::MAX), &mut record));
+                let (n, t, b) = (record.normal, record.tangent, record.bitangent);
+                assert!(
+                    (t.length() - 1.0).abs() < 1e-4,
+                    "tangent {:?} at {:?}",
+                    t,
+                    target
+                );
+                assert!(dot(&t, &n).abs() < 1e-4, "tangent {:?} normal {:?}", t, n);
+                assert!(dot(&b, &n).abs() < 1e-4 && dot(&b, &t).abs() < 1e-4);
+                assert_vec3_approx!(cross(&t, &b), n, 1e-4);
+                // Away from the poles the tangent runs along increasing u
+                if record.dpdu.length() > 1e-3 {
+                    assert!(dot(&t, &record.dpdu) > 0.0);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_tangent_runs_along_the_uv_map() {
+        let sphere = Sphere::new(Vec3::new(1.0, 2.0, 3.0), 2.0, Arc::new(Normals));
+        // Straight onto the equator facing +x, where u = 0.5 and v = 0.5
+        let ray = Ray::new(Vec3::new(10.0, 2.0, 3.0), Vec3::new(-1.0, 0.0, 0.0));
+        let mut record = HitRecord::new();
+        assert!(sphere.hit(&ray, &Interval::new(0.001, Real::MAX), &mut record));
+        assert_eq!(record.uv, Vec2::new(0.5, 0.5));
+        assert_vec3_approx!(record.normal, Vec3::new(1.0, 0.0, 0.0));
+        // u grows towards -z there and v towards +y
+        assert_vec3_approx!(record.tangent, Vec3::new(0.0, 0.0, -1.0));
+        assert_vec3_approx!(record.bitangent, Vec3::new(0.0, 1.0, 0.0));
+        assert_eq!(dot(&record.tangent, &record.normal), 0.0);
+
+        // Stepping along the tangent and bitangent moves through the texture along +u and +v
+        let uv_after = |step: Vec3| Sphere::get_uv(&(record.normal + 0.01 * step).normalize());
+        let along_u = uv_after(record.tangent);
+        assert!(along_u.x > 0.5 && (along_u.y - 0.5).abs() < 1e-6);
+        let along_v = uv_after(record.bitangent);
+        assert!(along_v.y > 0.5 && (along_v.x - 0.5).abs() < 1e-6);
+    }
 }
@@ -0,0 +1,139 @@
+use std::fmt;
+
+use image::RgbaImage;
+
+use crate::color::decode_srgb8;
+use crate::math::{vec2::Vec2, vec3::Vec3, Real};
+
+/// Faces of a cube map, in the order they are stored: +X, -X, +Y, -Y, +Z, -Z.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CubeFace {
+    PosX,
+    NegX,
+    PosY,
+    NegY,
+    PosZ,
+    NegZ,
+}
+
+/// Environment background looked up by ray direction on a miss. Cube maps avoid the pole
+/// distortion of an equirectangular image, which shows up in sharp reflections.
+#[derive(Clone, PartialEq)]
+pub struct EnvironmentMap {
+    faces: [RgbaImage; 6],
+}
+
+impl fmt::Debug for EnvironmentMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let face = &self.faces[0];
+        write!(
+            f,
+            "EnvironmentMap::Cube({}x{})",
+            face.width(),
+            face.height()
+        )
+    }
+}
+
+impl EnvironmentMap {
+    /// Faces follow the `CubeFace` order. Image rows run top to bottom as seen from the
+    /// center of the cube (the usual OpenGL cube map layout).
+    pub fn cube(faces: [RgbaImage; 6]) -> Self {
+        Self { faces }
+    }
+
+    pub fn cube_from_files(paths: [&str; 6]) -> Self {
+        Self::cube(paths.map(|path| {
+            image::open(path)
+                .expect("Failed to open cube map face")
+                .into_rgba8()
+        }))
+    }
+
+    /// Splits a horizontal cross (4 faces wide, 3 tall) into its six faces:
+    /// +Y above -X +Z +X -Z, with -Y below.
+    pub fn cross(path: &str) -> Self {
+        let image = image::open(path)
+            .expect("Failed to open cube map cross")
+            .into_rgba8();
+        let size = image.width() / 4;
+        assert_eq!(image.height(), size * 3, "Cube map cross must be 4:3");
+        let face = |col: u32, row: u32| {
+            image::imageops::crop_imm(&image, col * size, row * size, size, size).to_image()
+        };
+        Self::cube([
+            face(2, 1),
+            face(0, 1),
+            face(1, 0),
+            face(1, 2),
+            face(1, 1),
+            face(3, 1),
+        ])
+    }
+
+    /// Picks the face hit by `direction` and the texture coordinate on it, with (0, 0) at the
+    /// top-left of the face image.
+    pub fn face_uv(direction: &Vec3) -> (CubeFace, Vec2) {
+        let (ax, ay, az) = (direction.x.abs(), direction.y.abs(), direction.z.abs());
+        let (face, sc, tc, ma) = if ax >= ay && ax >= az {
+            if direction.x > 0.0 {
+                (CubeFace::PosX, -direction.z, -direction.y, ax)
+            } else {
+                (CubeFace::NegX, direction.z, -direction.y, ax)
+            }
+        } else if ay >= az {
+            if direction.y > 0.0 {
+                (CubeFace::PosY, direction.x, direction.z, ay)
+            } else {
+                (CubeFace::NegY, direction.x, -direction.z, ay)
+            }
+        } else if direction.z > 0.0 {
+            (CubeFace::PosZ, direction.x, -direction.y, az)
+        } else {
+            (CubeFace::NegZ, -direction.x, -direction.y, az)
+        };
+        (
+            face,
+            Vec2::new(0.5 * (sc / ma + 1.0), 0.5 * (tc / ma + 1.0)),
+        )
+    }
+
+    pub fn sample(&self, direction: &Vec3) -> Vec3 {
+        let (face, uv) = Self::face_uv(direction);
+        let image = &self.faces[face as usize];
+        let x = ((uv.x * image.width() as Real) as u32).min(image.width() - 1);
+        let y = ((uv.y * image.height() as Real) as u32).min(image.height() - 1);
+        decode_srgb8(&image.get_pixel(x, y).0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::srgb_to_linear;
+
+    #[test]
+    fn test_axis_directions_map_to_faces() {
+        let cases = [
+            (Vec3::new(1.0, 0.0, 0.0), CubeFace::PosX),
+            (Vec3::new(-1.0, 0.0, 0.0), CubeFace::NegX),
+            (Vec3::new(0.0, 1.0, 0.0), CubeFace::PosY),
+            (Vec3::new(0.0, -1.0, 0.0), CubeFace::NegY),
+            (Vec3::new(0.0, 0.0, 1.0), CubeFace::PosZ),
+            (Vec3::new(0.0, 0.0, -1.0), CubeFace::NegZ),
+        ];
+        let faces = std::array::from_fn(|i| {
+            RgbaImage::from_pixel(2, 2, image::Rgba([i as u8 * 40, 0, 0, 255]))
+        });
+        let environment = EnvironmentMap::cube(faces);
+
+        for (direction, expected) in cases {
+            let (face, uv) = EnvironmentMap::face_uv(&direction);
+            assert_eq!(face, expected);
+            assert_eq!(uv, Vec2::new(0.5, 0.5));
+            let color = environment.sample(&direction);
+            let encoded = (expected as usize * 40) as Real / 255.0;
+            assert!((color.x - srgb_to_linear(encoded)).abs() < 1e-6);
+        }
+    }
+}
@@ -1,12 +1,17 @@
+use std::fmt;
+use std::ops::{Add, Mul};
+
+use crate::math::Real;
 
 #[derive(Debug, Default, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Interval {
-    pub min: f32,
-    pub max: f32,
+    pub min: Real,
+    pub max: Real,
 }
 
 impl Interval {
-    pub fn new(min: f32, max: f32) -> Self {
+    pub fn new(min: Real, max: Real) -> Self {
         Self {
             min,
             max,
@@ -15,43 +20,78 @@ impl Interval {
 
     pub fn combine(a: &Interval, b: &Interval) -> Interval {
         Interval { 
-            min: f32::min(a.min, b.min),
-            max: f32::max(a.max, b.max)
+            min: Real::min(a.min, b.min),
+            max: Real::max(a.max, b.max)
         }
     }
 
+    /// Interval containing nothing. Its bounds are infinite, so `combine` with it returns the
+    /// other interval unchanged even when that one is unbounded.
     pub fn empty() -> Self {
         Self {
-            min: f32::MAX,
-            max: f32::MIN,
+            min: Real::INFINITY,
+            max: Real::NEG_INFINITY,
         }
     }
 
     pub fn universe() -> Self {
         Self {
-            min: f32::MIN,
-            max: f32::MAX,
+            min: Real::MIN,
+            max: Real::MAX,
         }
     }
 
-    pub fn size(&self) -> f32 {
+    /// Tightest interval containing every value; empty when `values` is.
+    pub fn from_points(values: &[Real]) -> Self {
+        values.iter().fold(Self::empty(), |interval, &value| Interval {
+            min: Real::min(interval.min, value),
+            max: Real::max(interval.max, value),
+        })
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.min <= self.max
+    }
+
+    pub fn midpoint(&self) -> Real {
+        0.5 * (self.min + self.max)
+    }
+
+    pub fn size(&self) -> Real {
         self.max - self.min
     }
 
-    pub fn contains(&self, value: f32) -> bool {
+    pub fn contains(&self, value: Real) -> bool {
         self.min <= value && value <= self.max
     }
 
-    pub fn expand(&self, delta: f32) -> Interval {
+    /// `contains` with both bounds widened by `eps`.
+    pub fn contains_eps(&self, value: Real, eps: Real) -> bool {
+        self.min - eps <= value && value <= self.max + eps
+    }
+
+    pub fn expand(&self, delta: Real) -> Interval {
         let padding = delta / 2.0;
         Interval { min: self.min-padding, max: self.max+padding }
     }
 
-    pub fn surrounds(&self, value: f32) -> bool {
+    pub fn surrounds(&self, value: Real) -> bool {
         self.min < value && value < self.max
     }
 
-    pub fn clamp(&self, value: f32) -> f32 {
+    /// Values in both intervals; not valid if they do not overlap.
+    pub fn intersect(&self, other: &Interval) -> Interval {
+        Interval::new(Real::max(self.min, other.min), Real::min(self.max, other.max))
+    }
+
+    /// The parts below and above `value`, which is clamped into the interval first so that
+    /// both halves stay inside it. One half is degenerate if `value` lies outside.
+    pub fn split(&self, value: Real) -> (Interval, Interval) {
+        let value = self.clamp(value);
+        (Interval::new(self.min, value), Interval::new(value, self.max))
+    }
+
+    pub fn clamp(&self, value: Real) -> Real {
         if value < self.min {
             self.min
         } else if value > self.max {
@@ -61,3 +101,130 @@ impl Interval {
         }
     }
 }
+
+/// Shifts both bounds by `offset`.
+impl Add<Real> for Interval {
+    type Output = Interval;
+
+    fn add(self, offset: Real) -> Interval {
+        Interval::new(self.min + offset, self.max + offset)
+    }
+}
+
+/// Scales both bounds by `factor`. A negative factor swaps them, so the result stays valid.
+impl Mul<Real> for Interval {
+    type Output = Interval;
+
+    fn mul(self, factor: Real) -> Interval {
+        let (a, b) = (self.min * factor, self.max * factor);
+        Interval::new(Real::min(a, b), Real::max(a, b))
+    }
+}
+
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}, {}]", self.min, self.max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    /// Valid intervals, including unbounded and degenerate ones.
+    fn interval() -> impl Strategy<Value = Interval> {
+        let bound = prop_oneof![
+            8 => -1e6 as Real..1e6,
+            1 => Just(Real::INFINITY),
+            1 => Just(Real::NEG_INFINITY),
+            1 => Just(0.0 as Real),
+        ];
+        (bound.clone(), bound).prop_map(|(a, b)| Interval::new(a.min(b), a.max(b)))
+    }
+
+    proptest! {
+        #[test]
+        fn prop_combine_is_commutative_and_contains_both(a in interval(), b in interval()) {
+            let ab = Interval::combine(&a, &b);
+            let ba = Interval::combine(&b, &a);
+            prop_assert_eq!((ab.min, ab.max), (ba.min, ba.max));
+            prop_assert!(ab.is_valid());
+            for value in [a.min, a.max, b.min, b.max] {
+                prop_assert!(ab.contains(value), "{} not in {}", value, ab);
+            }
+        }
+
+        #[test]
+        fn prop_empty_is_the_identity_of_combine(a in interval()) {
+            let combined = Interval::combine(&Interval::empty(), &a);
+            prop_assert_eq!((combined.min, combined.max), (a.min, a.max));
+        }
+
+        #[test]
+        fn prop_expand_grows_the_interval(a in interval(), delta in 0.0 as Real..1e3) {
+            let expanded = a.expand(delta);
+            prop_assert!(expanded.min <= a.min && a.max <= expanded.max);
+            prop_assert!(expanded.size() >= a.size() || a.size().is_nan());
+            if a.size().is_finite() && a.min.abs().max(a.max.abs()) < 1e3 {
+                prop_assert!(
+                    (expanded.size() - a.size() - delta).abs() <= 1e-3,
+                    "{} expanded by {} is {}",
+                    a,
+                    delta,
+                    expanded
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_interval_validity() {
+        assert!(Interval::new(-1.0, 2.0).is_valid());
+        assert!(Interval::new(3.0, 3.0).is_valid());
+        assert!(!Interval::empty().is_valid());
+        assert!(Interval::universe().is_valid());
+    }
+
+    #[test]
+    fn test_interval_from_points() {
+        let interval = Interval::from_points(&[2.0, -1.5, 7.0, 0.0]);
+        assert_eq!(interval.min, -1.5);
+        assert_eq!(interval.max, 7.0);
+        assert_eq!(interval.midpoint(), 2.75);
+        assert!(!Interval::from_points(&[]).is_valid());
+    }
+
+    #[test]
+    fn test_interval_shift_and_scale() {
+        let shifted = Interval::new(1.0, 3.0) + 2.5;
+        assert_eq!((shifted.min, shifted.max), (3.5, 5.5));
+        let scaled = Interval::new(1.0, 3.0) * 2.0;
+        assert_eq!((scaled.min, scaled.max), (2.0, 6.0));
+        // A negative scale mirrors the interval, which keeps min below max
+        let flipped = Interval::new(1.0, 3.0) * -2.0;
+        assert_eq!((flipped.min, flipped.max), (-6.0, -2.0));
+        assert!(flipped.is_valid());
+    }
+
+    #[test]
+    fn test_interval_split_and_intersect() {
+        let (below, above) = Interval::new(-1.0, 4.0).split(1.5);
+        assert_eq!((below.min, below.max), (-1.0, 1.5));
+        assert_eq!((above.min, above.max), (1.5, 4.0));
+        let (below, above) = Interval::new(-1.0, 4.0).split(9.0);
+        assert_eq!((below.min, below.max), (-1.0, 4.0));
+        assert_eq!(above.size(), 0.0);
+
+        let overlap = Interval::new(0.0, 5.0).intersect(&Interval::new(2.0, 8.0));
+        assert_eq!((overlap.min, overlap.max), (2.0, 5.0));
+        assert!(!Interval::new(0.0, 1.0)
+            .intersect(&Interval::new(2.0, 3.0))
+            .is_valid());
+    }
+
+    #[test]
+    fn test_interval_display() {
+        assert_eq!(Interval::new(0.5, 2.0).to_string(), "[0.5, 2]");
+    }
+}
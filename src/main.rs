@@ -1,36 +1,70 @@
 mod aabb;
+mod accumulator;
+mod adaptive;
 mod bvh;
 mod camera;
+mod color;
+mod denoise;
 mod entities;
+mod environment;
 mod interval;
+mod logger;
 mod material;
 mod math;
 mod pdf;
 mod perlin_noise;
 mod ray;
+mod render;
+mod scene;
+mod stats;
 mod texture;
+mod validate;
 mod window;
 
 use std::fs::File;
 use std::io::{self, Write};
 use std::ops::Deref;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::path::Path;
 use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::{Duration, Instant};
 
+use accumulator::Accumulator;
 use bvh::BVH;
 use camera::Camera;
-use entities::entity::Transformable;
+use color::ColorSpace;
+use entities::entity::{Hittable, Transformable};
 use entities::quad::create_box;
 use indicatif::ProgressBar;
-
-use entities::{constant_medium::ConstantMedium, entity::EntityList, quad::Quad, sphere::Sphere};
-use interval::Interval;
+use log::{info, LevelFilter};
+
+use entities::{
+    animated_transform::AnimatedTransform,
+    constant_medium::{ConstantMedium, TexturedMedium},
+    displaced_sphere::DisplacedSphere,
+    entity::EntityList,
+    infinite_plane::InfinitePlane,
+    instanced_mesh::InstancedMesh,
+    material_override::MaterialOverride,
+    mesh::Mesh,
+    quad::Quad,
+    sphere::Sphere,
+};
+use environment::EnvironmentMap;
 use material::*;
 use math::rand::{rand_f32, rand_f32_range};
 use math::vec3::*;
+use math::{transform::Transform, vec2::Vec2, Real};
 use ray::Ray;
-use texture::{CheckerTexture, ImageTexture, NoiseTexture, Texture};
+use render::{
+    draw_bounds, expose, render_to_buffer, write_colors, CancelToken, PixelOrder, RenderObserver,
+};
+use scene::{Scene, SceneWatcher};
+use stats::{PhaseTimings, RenderStats};
+use texture::{
+    CheckerTexture, DotsTexture, ImageTexture, NoiseKind, NoiseTexture, Texture, TriplanarTexture,
+    WrapMode,
+};
 use window::Window;
 
 #[derive(Debug, Default)]
@@ -40,13 +74,6 @@ pub struct Bitmap {
     data: Option<Vec<u8>>,
 }
 
-fn linear_to_gamma(value: f32) -> f32 {
-    if value > 0.0 {
-        return value.sqrt();
-    }
-    0.0
-}
-
 pub fn create_bitmap(width: i32, height: i32) -> Bitmap {
     let buffer_size = (width * height * 4) as usize;
     let data = Some(vec![0u8; buffer_size]);
@@ -57,110 +84,544 @@ pub fn create_bitmap(width: i32, height: i32) -> Bitmap {
     }
 }
 
-fn write_ppm(bitmap: &Bitmap) -> io::Result<()> {
-    println!("Writing PPM file...");
+/// Encodes the bitmap as a PPM image, either ASCII P3 or binary P6. P3 rows are formatted on
+/// all available cores, since the number formatting dominates the cost.
+fn encode_ppm(bitmap: &Bitmap, binary: bool) -> Vec<u8> {
+    let data = bitmap.data.as_ref().unwrap();
+    let magic = if binary { "P6" } else { "P3" };
+    let mut output = format!("{}\n{} {}\n255\n", magic, bitmap.width, bitmap.height).into_bytes();
+    if binary {
+        for pixel in data.chunks_exact(4) {
+            output.extend_from_slice(&[pixel[2], pixel[1], pixel[0]]);
+        }
+        return output;
+    }
+
+    let thread_count = thread::available_parallelism().map_or(1, |n| n.get());
+    let rows_per_chunk = (bitmap.height as usize).div_ceil(thread_count).max(1);
+    let chunk_bytes = rows_per_chunk * bitmap.width as usize * 4;
+    let chunks: Vec<Vec<u8>> = thread::scope(|scope| {
+        let handles: Vec<_> = data
+            .chunks(chunk_bytes)
+            .map(|rows| {
+                scope.spawn(move || {
+                    let mut text = String::with_capacity(rows.len() * 3);
+                    for pixel in rows.chunks_exact(4) {
+                        text += &format!("{} {} {}\n", pixel[2], pixel[1], pixel[0]);
+                    }
+                    text.into_bytes()
+                })
+            })
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    });
+    for chunk in chunks {
+        output.extend(chunk);
+    }
+    output
+}
+
+fn write_ppm(bitmap: &Bitmap, binary: bool) -> io::Result<()> {
+    info!("Writing PPM file...");
     let mut file = File::create("render.ppm")?;
-    writeln!(file, "P3")?;
-    writeln!(file, "{} {}", bitmap.width, bitmap.height)?;
-    writeln!(file, "255")?;
-    let pb = ProgressBar::new((bitmap.height * bitmap.width) as u64);
-    for y in 0..bitmap.height {
-        for x in 0..bitmap.width {
-            let offset = (y * bitmap.width + x) * 4;
-            let r = bitmap.data.as_ref().unwrap()[offset as usize + 2];
-            let g = bitmap.data.as_ref().unwrap()[offset as usize + 1];
-            let b = bitmap.data.as_ref().unwrap()[offset as usize];
-            writeln!(file, "{} {} {}", r, g, b)?;
-            pb.inc(1);
-        }
-    }
-    Ok(())
-}
-
-fn render(
-    threads: &mut Vec<thread::JoinHandle<()>>,
-    count: u32,
-    bitmap: &Arc<Mutex<Bitmap>>,
-    image_width: u32,
-    image_height: u32,
-    entities: &Arc<EntityList>,
+    file.write_all(&encode_ppm(bitmap, binary))
+}
+
+fn write_png(bitmap: &Bitmap, path: &str) -> image::ImageResult<()> {
+    let data = bitmap.data.as_ref().unwrap();
+    let width = bitmap.width as u32;
+    image::RgbImage::from_fn(width, bitmap.height as u32, |x, y| {
+        let offset = ((y * width + x) * 4) as usize;
+        image::Rgb([data[offset + 2], data[offset + 1], data[offset]])
+    })
+    .save(path)
+}
+
+/// File of the `--bracket` image at `stops`, e.g. `render_ev-2.png` or `render_ev+0.5.png`.
+fn bracket_path(stops: Real) -> String {
+    if stops == 0.0 {
+        "render_ev0.png".to_string()
+    } else {
+        format!("render_ev{:+}.png", stops)
+    }
+}
+
+/// Samples per pixel traced between two checkpoint writes.
+const CHECKPOINT_INTERVAL: u32 = 16;
+/// How long a watched scene file must stay unchanged before the window reloads it.
+const RELOAD_DEBOUNCE: Duration = Duration::from_millis(250);
+
+/// Progressive render into a float buffer. With a `checkpoint`, the buffer is saved to that file
+/// after every pass. With `resume`, the render continues from the samples stored in that file,
+/// which may differ from the one checkpoints are written to. With `edge_samples`, pixels on
+/// geometric edges found by a one-sample prepass get up to that many samples, while the rest
+/// keep the camera's sample count. With `edge_aa`, pixels on those edges are instead
+/// supersampled as a 2x2 grid of subpixels.
+#[allow(clippy::too_many_arguments)]
+fn render_accumulated(
+    thread_count: u32,
+    (width, height): (u32, u32),
+    entities: &EntityList,
     camera: &Camera,
-    stop: &Arc<AtomicBool>,
-) {
-    let thread_count = count;
-    let pb = ProgressBar::new((image_height * image_width) as u64);
-    let pb = Arc::new(Mutex::new(pb));
-    let chunk_size = ((image_width * image_height) / thread_count) * 4;
-    let stdout = Arc::new(Mutex::new(io::stdout()));
-    for i in 0..thread_count {
-        let pb_clone = Arc::clone(&pb);
-        let buffer = Arc::clone(bitmap);
-        let start = i * chunk_size;
-        let end = if i == thread_count - 1 {
-            image_width * image_height * 4
-        } else {
-            (i + 1) * chunk_size
-        };
-        let entities = Arc::clone(entities);
-        let camera = camera.clone();
-        let stdout = Arc::clone(&stdout);
-        let stop = Arc::clone(stop);
-        let thread = thread::spawn(move || {
-            let data: *mut u8;
-            {
-                let mut buffer = buffer.lock().unwrap();
-                data = buffer.data.as_mut().unwrap().as_mut_ptr();
-                let mut stdout = stdout.lock().unwrap();
-                if let Err(e) = writeln!(
-                    stdout,
-                    "Thread {:?} - Buffer size: {}",
-                    thread::current().id(),
-                    end - start
-                ) {
-                    eprintln!("Error writing to stdout: {}", e);
-                }
-            }
-            for offset in (start..end).step_by(4) {
-                if stop.load(Ordering::Acquire) {
-                    return;
-                }
-                let x = (offset / 4) % image_width;
-                let y = (offset / 4) / image_width;
-
-                let mut color = Vec3::zero();
-                for i in 0..camera.sqrt_spp {
-                    for j in 0..camera.sqrt_spp {
-                        let ray = camera.get_ray(x, y, i, j);
-                        color += camera.ray_color(&ray, &entities, 0);
-                    }
-                }
-                color *= camera.pixel_samples_scale;
-
-                let intensity = Interval::new(0.0, 0.999);
-                let ir = (255.99 * intensity.clamp(linear_to_gamma(color.x))) as u8;
-                let ig = (255.99 * intensity.clamp(linear_to_gamma(color.y))) as u8;
-                let ib = (255.99 * intensity.clamp(linear_to_gamma(color.z))) as u8;
-
-                unsafe {
-                    data.add(offset as usize).write(ib);
-                    data.add((offset + 1) as usize).write(ig);
-                    data.add((offset + 2) as usize).write(ir);
-                    data.add((offset + 3) as usize).write(0xFF);
-                }
-                let pb = pb_clone.lock().unwrap();
-                pb.inc(1);
-            }
-        });
-        threads.push(thread);
+    cancel: &CancelToken,
+    checkpoint: Option<&Path>,
+    resume: Option<&Path>,
+    edge_samples: Option<u32>,
+    edge_aa: bool,
+) -> Accumulator {
+    let mut accumulator = if let Some(resume) = resume {
+        let accumulator = Accumulator::load(resume).expect("Failed to load checkpoint");
+        assert!(
+            accumulator.width == width && accumulator.height == height,
+            "Checkpoint is {}x{}, but the image is {}x{}",
+            accumulator.width,
+            accumulator.height,
+            width,
+            height
+        );
+        accumulator
+    } else {
+        Accumulator::new(width, height, camera.seed)
+    };
+
+    let base_samples = camera.sqrt_spp() * camera.sqrt_spp();
+    let edges = (edge_samples.is_some() || edge_aa).then(|| {
+        let geometry = adaptive::capture_geometry(camera, entities, width, height);
+        adaptive::edge_map(&geometry, width, height)
+    });
+    let supersampling = edges
+        .as_ref()
+        .filter(|_| edge_aa)
+        .map(|edges| adaptive::EdgeSupersampling::new(camera, edges));
+    let targets = match (edges, edge_samples, &supersampling) {
+        (Some(edges), Some(edge_samples), _) => {
+            let targets = adaptive::sample_targets(&edges, base_samples, edge_samples);
+            let budget = targets.iter().map(|&n| n as u64).sum::<u64>();
+            info!(
+                "Edge-aware sampling: {} samples, {:.0}% of {} spp everywhere",
+                budget,
+                100.0 * budget as f64 / (targets.len() as u64 * edge_samples as u64) as f64,
+                edge_samples
+            );
+            targets
+        }
+        (_, _, Some(supersampling)) => {
+            let flagged = supersampling
+                .pixels
+                .iter()
+                .filter(|&&flagged| flagged)
+                .count();
+            info!(
+                "Edge antialiasing: supersampling {} of {} pixels",
+                flagged,
+                supersampling.pixels.len()
+            );
+            supersampling.sample_targets()
+        }
+        _ => vec![base_samples; (width * height) as usize],
+    };
+    let total_samples = targets.iter().copied().max().unwrap_or(0);
+    let mut samples = accumulator.sample_counts.iter().copied().min().unwrap_or(0);
+    while samples < total_samples && !cancel.is_cancelled() {
+        samples = u32::min(samples + CHECKPOINT_INTERVAL, total_samples);
+        let target = |index: usize| targets[index].min(samples);
+        match &supersampling {
+            Some(supersampling) => supersampling.accumulate_by(
+                &mut accumulator,
+                thread_count,
+                entities,
+                camera,
+                cancel,
+                target,
+            ),
+            None => accumulator.accumulate_by(thread_count, entities, camera, cancel, target),
+        }
+        if let Some(checkpoint) = checkpoint {
+            accumulator
+                .save(checkpoint)
+                .expect("Failed to write checkpoint");
+            info!("Checkpoint saved ({}/{} samples)", samples, total_samples);
+        }
+    }
+    accumulator
+}
+
+/// Temporal accumulation for the window: every frame adds one sample per pixel and shows the
+/// running mean, until `cancel` is raised. Sample `n` of a pixel lands in stratum `n` of the
+/// camera's grid, so consecutive frames jitter across the pixel and a still camera converges to
+/// the offline render. `on_frame` runs after each frame is written to `bitmap`.
+fn render_temporal(
+    thread_count: u32,
+    entities: &EntityList,
+    camera: &Camera,
+    bitmap: &Mutex<Bitmap>,
+    cancel: &CancelToken,
+    mut on_frame: impl FnMut(u32),
+) -> Accumulator {
+    let (width, height) = {
+        let bitmap = bitmap.lock().unwrap();
+        (bitmap.width as u32, bitmap.height as u32)
+    };
+    let mut accumulator = Accumulator::new(width, height, camera.seed);
+    let mut frame = 0;
+    while !cancel.is_cancelled() {
+        frame += 1;
+        accumulator.accumulate(thread_count, entities, camera, cancel, frame);
+        write_colors(
+            &accumulator.average(),
+            camera.color_space,
+            &mut bitmap.lock().unwrap(),
+        );
+        on_frame(frame);
+    }
+    accumulator
+}
+
+/// Fixed workload behind `--benchmark`, so timings from different builds are comparable.
+const BENCHMARK_SIZE: u32 = 400;
+const BENCHMARK_SPP: u32 = 64;
+
+/// Renders the Cornell box at a fixed size, seed and sample count and prints the ray rate.
+fn run_benchmark(thread_count: u32) {
+    let mut scene = Scene::new();
+    let mut camera = Camera::default();
+    scene_cornell_box(&mut scene, &mut camera, BENCHMARK_SIZE, BENCHMARK_SIZE);
+    camera.set_samples_per_pixel(BENCHMARK_SPP);
+    let bitmap = Mutex::new(create_bitmap(BENCHMARK_SIZE as i32, BENCHMARK_SIZE as i32));
+
+    let rays_before = camera::rays_traced();
+    let start = Instant::now();
+    render_to_buffer(
+        thread_count,
+        PixelOrder::default(),
+        &bitmap,
+        &scene.entities,
+        &camera,
+        &CancelToken::new(),
+        None,
+    );
+    let seconds = start.elapsed().as_secs_f64();
+    let rays = camera::rays_traced() - rays_before;
+    println!(
+        "Rendered {}x{} at {} spp in {:.2} s",
+        BENCHMARK_SIZE, BENCHMARK_SIZE, BENCHMARK_SPP, seconds
+    );
+    println!(
+        "{} rays, {:.0} rays/s ({:.2} Mrays/s)",
+        rays,
+        rays as f64 / seconds,
+        rays as f64 / seconds / 1e6
+    );
+}
+
+fn arg_value<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+    args.iter().position(|arg| arg == flag).map(|idx| {
+        args.get(idx + 1)
+            .unwrap_or_else(|| panic!("{} expects a value", flag))
+            .as_str()
+    })
+}
+
+const DEFAULT_WIDTH: u32 = 800;
+const DEFAULT_HEIGHT: u32 = 600;
+/// Settings of `--preview`, each used only where the matching flag is not given.
+const PREVIEW_WIDTH: u32 = 400;
+const PREVIEW_HEIGHT: u32 = 300;
+const PREVIEW_SPP: u32 = 16;
+const PREVIEW_MAX_DEPTH: u32 = 8;
+
+/// Parses an aspect ratio written as `16:9` or as a decimal such as `1.7778`.
+fn parse_aspect(text: &str) -> Option<Real> {
+    let ratio = match text.split_once(':') {
+        Some((width, height)) => {
+            width.trim().parse::<Real>().ok()? / height.trim().parse::<Real>().ok()?
+        }
+        None => text.trim().parse::<Real>().ok()?,
+    };
+    (ratio.is_finite() && ratio > 0.0).then_some(ratio)
+}
+
+/// Parses a resolution written as `1920x1080`.
+fn parse_resolution(text: &str) -> Option<(u32, u32)> {
+    let (width, height) = text.split_once('x')?;
+    Some((width.trim().parse().ok()?, height.trim().parse().ok()?))
+}
+
+/// Image size from `--resolution <W>x<H>`, or from `--width` and `--aspect` with the height
+/// derived from the ratio. Either of the latter falls back to the 800x600 default, or to
+/// 400x300 with `--preview`.
+fn image_size(args: &[String]) -> (u32, u32) {
+    let resolution = arg_value(args, "--resolution").map(|value| {
+        parse_resolution(value).expect("--resolution expects <width>x<height>, e.g. 1920x1080")
+    });
+    let width = arg_value(args, "--width")
+        .map(|value| value.parse::<u32>().expect("--width expects a pixel count"));
+    let aspect = arg_value(args, "--aspect")
+        .map(|value| parse_aspect(value).expect("--aspect expects a ratio such as 16:9 or 1.7778"));
+    let (width, height) = match (resolution, width, aspect) {
+        (Some(resolution), None, None) => resolution,
+        (Some(_), _, _) => panic!("--resolution cannot be combined with --width or --aspect"),
+        (None, width, aspect) => {
+            let (default_width, default_height) = if args.iter().any(|arg| arg == "--preview") {
+                (PREVIEW_WIDTH, PREVIEW_HEIGHT)
+            } else {
+                (DEFAULT_WIDTH, DEFAULT_HEIGHT)
+            };
+            let width = width.unwrap_or(default_width);
+            let aspect = aspect.unwrap_or(default_width as Real / default_height as Real);
+            (width, (width as Real / aspect).round() as u32)
+        }
+    };
+    assert!(
+        width > 0 && height > 1,
+        "Image must be at least 1x2 pixels, got {}x{}",
+        width,
+        height
+    );
+    (width, height)
+}
+
+#[cfg(feature = "serde")]
+fn load_scene(path: &str) -> (Scene, Camera) {
+    Scene::load(Path::new(path)).expect("Failed to load scene")
+}
+
+#[cfg(not(feature = "serde"))]
+fn load_scene(_path: &str) -> (Scene, Camera) {
+    panic!("--scene requires building with the serde feature")
+}
+
+/// Loads a scene changed while the window is open. Errors are logged rather than fatal, since
+/// the file may be mid-edit.
+#[cfg(feature = "serde")]
+fn reload_scene(path: &Path) -> Option<(Scene, Camera)> {
+    Scene::load(path)
+        .map_err(|error| log::error!("Failed to reload {}: {}", path.display(), error))
+        .ok()
+}
+
+#[cfg(not(feature = "serde"))]
+fn reload_scene(_path: &Path) -> Option<(Scene, Camera)> {
+    None
+}
+
+#[cfg(feature = "serde")]
+fn export_scene(scene: &Scene, camera: &Camera, path: &str) {
+    scene
+        .save(camera, Path::new(path))
+        .expect("Failed to export scene");
+    info!("Scene exported to {}", path);
+}
+
+#[cfg(not(feature = "serde"))]
+fn export_scene(_scene: &Scene, _camera: &Camera, _path: &str) {
+    panic!("--export-scene requires building with the serde feature")
+}
+
+struct ProgressObserver {
+    pb: ProgressBar,
+}
+
+impl ProgressObserver {
+    fn new(pixel_count: u32) -> Self {
+        Self {
+            pb: ProgressBar::new(pixel_count as u64),
+        }
+    }
+}
+
+impl RenderObserver for ProgressObserver {
+    fn on_pixel(&self, _n: u32, _total: u32) {
+        self.pb.inc(1);
+    }
+}
+
+/// A scene compiled into the binary, selectable with `--scene <name>`.
+struct BuiltinScene {
+    name: &'static str,
+    description: &'static str,
+    /// Samples per pixel for a mostly noise-free image, used unless `--spp` is given. `None`
+    /// takes the default for the scene's lighting.
+    samples_per_pixel: Option<u32>,
+    builder: fn(&mut Scene, &mut Camera, u32, u32),
+}
+
+impl BuiltinScene {
+    /// Builds the scene and its camera for a `width` x `height` image.
+    fn build(&self, width: u32, height: u32) -> (Scene, Camera) {
+        let mut scene = Scene::new();
+        let mut camera = Camera::default();
+        (self.builder)(&mut scene, &mut camera, width, height);
+        let samples_per_pixel = self
+            .samples_per_pixel
+            .unwrap_or_else(|| scene.lighting().default_spp());
+        camera.set_samples_per_pixel(samples_per_pixel);
+        (scene, camera)
     }
 }
 
-fn scene_scattered_balls(
-    entities_out: &mut EntityList,
-    camera: &mut Camera,
-    width: u32,
-    height: u32,
-) {
+/// Rendered when `--scene` is not given.
+const DEFAULT_SCENE: &str = "cornell-box";
+
+const BUILTIN_SCENES: &[BuiltinScene] = &[
+    BuiltinScene {
+        name: "scattered-balls",
+        description: "Three large spheres among hundreds of small random ones under a sky",
+        samples_per_pixel: Some(500),
+        builder: scene_scattered_balls,
+    },
+    BuiltinScene {
+        name: "checker-spheres",
+        description: "Two large checker-textured spheres touching at the origin",
+        samples_per_pixel: None,
+        builder: checker_spheres,
+    },
+    BuiltinScene {
+        name: "ground-plane",
+        description: "Three diffuse spheres on an infinite checkered floor",
+        samples_per_pixel: Some(200),
+        builder: scene_ground_plane,
+    },
+    BuiltinScene {
+        name: "metal-spheres",
+        description: "Copper, aluminum and gold spheres on an infinite checkered floor",
+        samples_per_pixel: Some(200),
+        builder: scene_metal_spheres,
+    },
+    BuiltinScene {
+        name: "hollow-glass",
+        description: "A solid glass sphere beside a hollow glass shell",
+        samples_per_pixel: Some(500),
+        builder: scene_hollow_glass,
+    },
+    BuiltinScene {
+        name: "smooth-checker",
+        description: "A box-filtered checkered floor receding to the horizon",
+        samples_per_pixel: None,
+        builder: scene_smooth_checker,
+    },
+    BuiltinScene {
+        name: "earth",
+        description: "A globe textured with assets/earth.jpg",
+        samples_per_pixel: None,
+        builder: scene_earth,
+    },
+    BuiltinScene {
+        name: "perlin-spheres",
+        description: "Gradient and value noise textures side by side",
+        samples_per_pixel: None,
+        builder: scene_perlin_spheres,
+    },
+    BuiltinScene {
+        name: "quads",
+        description: "Five colored quads facing the camera from different sides",
+        samples_per_pixel: None,
+        builder: scene_quads,
+    },
+    BuiltinScene {
+        name: "tiled-floor",
+        description: "A glass sphere on a floor of alternating tile quads",
+        samples_per_pixel: None,
+        builder: scene_tiled_floor,
+    },
+    BuiltinScene {
+        name: "repeating-map",
+        description: "A globe on a floor that repeats the earth map three times each way",
+        samples_per_pixel: None,
+        builder: scene_repeating_map,
+    },
+    BuiltinScene {
+        name: "simple-light",
+        description: "Noise-textured spheres lit only by a quad light",
+        samples_per_pixel: Some(1000),
+        builder: scene_simple_light,
+    },
+    BuiltinScene {
+        name: "lava-sphere",
+        description: "A sphere with noise mapped through a lava color ramp, under a quad light",
+        samples_per_pixel: Some(1000),
+        builder: scene_lava_sphere,
+    },
+    BuiltinScene {
+        name: "bump-quad",
+        description: "A noise bump-mapped quad under a grazing light",
+        samples_per_pixel: Some(1000),
+        builder: scene_bump_quad,
+    },
+    BuiltinScene {
+        name: "lumpy-sphere",
+        description: "A displaced sphere lit from the side",
+        samples_per_pixel: Some(1000),
+        builder: scene_lumpy_sphere,
+    },
+    BuiltinScene {
+        name: "cornell-box",
+        description: "The Cornell box with two rotated boxes and a ceiling light",
+        samples_per_pixel: None,
+        builder: scene_cornell_box,
+    },
+    BuiltinScene {
+        name: "cornell-smoke",
+        description: "The Cornell box with its boxes turned into smoke",
+        samples_per_pixel: None,
+        builder: scene_cornell_smoke,
+    },
+    BuiltinScene {
+        name: "noisy-cloud",
+        description: "A box of noise-textured fog over the ground",
+        samples_per_pixel: Some(500),
+        builder: scene_noisy_cloud,
+    },
+    BuiltinScene {
+        name: "instanced-forest",
+        description: "A forest of instances sharing one cone-shaped tree mesh",
+        samples_per_pixel: Some(200),
+        builder: scene_instanced_forest,
+    },
+    BuiltinScene {
+        name: "spinning-box",
+        description: "A box turning while the shutter is open, for motion blur",
+        samples_per_pixel: Some(200),
+        builder: scene_spinning_box,
+    },
+];
+
+fn builtin_scene(name: &str) -> Option<&'static BuiltinScene> {
+    BUILTIN_SCENES.iter().find(|scene| scene.name == name)
+}
+
+/// One line per built-in scene for `--list-scenes`: name, samples per pixel (`auto` when it
+/// comes from the scene's lighting) and description.
+fn list_scenes() -> String {
+    let width = BUILTIN_SCENES
+        .iter()
+        .map(|scene| scene.name.len())
+        .max()
+        .unwrap_or(0);
+    BUILTIN_SCENES
+        .iter()
+        .map(|scene| {
+            let marker = if scene.name == DEFAULT_SCENE {
+                " (default)"
+            } else {
+                ""
+            };
+            let samples_per_pixel = scene
+                .samples_per_pixel
+                .map_or_else(|| "auto".to_string(), |spp| spp.to_string());
+            format!(
+                "{:width$}  {:>5} spp  {}{}\n",
+                scene.name,
+                samples_per_pixel,
+                scene.description,
+                marker,
+                width = width
+            )
+        })
+        .collect()
+}
+
+fn scene_scattered_balls(scene: &mut Scene, camera: &mut Camera, width: u32, height: u32) {
     let mut entities = EntityList::new();
 
     let new_camera = Camera::new(
@@ -173,19 +634,18 @@ fn scene_scattered_balls(
 
     *camera = new_camera;
 
-    let material_ground: Arc<dyn Material> = Arc::new(Lambertian {
-        albedo: Box::new(CheckerTexture::new(
+    let material_ground: Arc<dyn Material> =
+        Arc::new(Lambertian::new(Box::new(CheckerTexture::new(
             Texture::new(Vec3::new(0.2, 0.3, 0.1)),
             Texture::new(Vec3::new(0.9, 0.9, 0.9)),
             0.32,
-        )),
-    });
+        ))));
     let material_1: Arc<dyn Material> = Arc::new(Dielectric {
         refraction_index: 1.5,
     });
-    let material_2: Arc<dyn Material> = Arc::new(Lambertian {
-        albedo: Box::new(Texture::new(Vec3::new(0.4, 0.2, 0.1))),
-    });
+    let material_2: Arc<dyn Material> = Arc::new(Lambertian::new(Box::new(Texture::new(
+        Vec3::new(0.4, 0.2, 0.1),
+    ))));
     let material_3: Arc<dyn Material> = Arc::new(Metal {
         albedo: Vec3::new(0.7, 0.6, 0.5),
         fuzz: 0.0,
@@ -203,16 +663,15 @@ fn scene_scattered_balls(
         for b in -11..11 {
             let choose_mat = rand_f32();
             let center = Vec3::new(
-                (a as f32) + 0.9 * rand_f32(),
+                (a as Real) + 0.9 * rand_f32(),
                 0.2,
-                (b as f32) * 0.9 * rand_f32(),
+                (b as Real) * 0.9 * rand_f32(),
             );
             if (center - Vec3::new(4.0, 0.2, 0.0)).length() > 0.9 {
                 if choose_mat < 0.8 {
                     let albedo = Vec3::random() * Vec3::random();
-                    let material: Arc<dyn Material> = Arc::new(Lambertian {
-                        albedo: Box::new(Texture::new(albedo)),
-                    });
+                    let material: Arc<dyn Material> =
+                        Arc::new(Lambertian::new(Box::new(Texture::new(albedo))));
                     entities.add(Box::new(Sphere::new(center, 0.2, material)));
                 } else if choose_mat < 0.95 {
                     let albedo = Vec3::random_range(0.5, 1.0);
@@ -254,10 +713,10 @@ fn scene_scattered_balls(
     )));
 
     let bvh = BVH::new(entities);
-    entities_out.add(Box::new(bvh));
+    scene.add(Box::new(bvh));
 }
 
-fn checker_spheres(entities_out: &mut EntityList, camera: &mut Camera, width: u32, height: u32) {
+fn checker_spheres(scene: &mut Scene, camera: &mut Camera, width: u32, height: u32) {
     let mut entities = EntityList::new();
 
     let new_camera = Camera::new(
@@ -270,13 +729,12 @@ fn checker_spheres(entities_out: &mut EntityList, camera: &mut Camera, width: u3
 
     *camera = new_camera;
 
-    let material_ground: Arc<dyn Material> = Arc::new(Lambertian {
-        albedo: Box::new(CheckerTexture::new(
+    let material_ground: Arc<dyn Material> =
+        Arc::new(Lambertian::new(Box::new(CheckerTexture::new(
             Texture::new(Vec3::new(0.2, 0.3, 0.1)),
             Texture::new(Vec3::new(0.9, 0.9, 0.9)),
             0.32,
-        )),
-    });
+        ))));
 
     entities.add(Box::new(Sphere::new(
         Vec3::new(0.0, -10.0, 0.0),
@@ -290,10 +748,180 @@ fn checker_spheres(entities_out: &mut EntityList, camera: &mut Camera, width: u3
         Arc::clone(&material_ground),
     )));
 
-    *entities_out = entities;
+    scene.entities = entities;
+}
+
+fn scene_ground_plane(scene: &mut Scene, camera: &mut Camera, width: u32, height: u32) {
+    let mut new_camera = Camera::new(
+        width,
+        height,
+        30.0,
+        &Vec3::new(0.0, 1.5, 6.0),
+        &Vec3::new(0.0, 0.5, 0.0),
+    );
+    new_camera.set_background_color(&Vec3::new(0.70, 0.80, 1.00));
+    *camera = new_camera;
+
+    // Not smoothed: the floor lies on a cell boundary in y, where the box filter averages the
+    // pattern away
+    let material_ground: Arc<dyn Material> =
+        Arc::new(Lambertian::new(Box::new(CheckerTexture::new(
+            Texture::new(Vec3::new(0.2, 0.3, 0.1)),
+            Texture::new(Vec3::new(0.9, 0.9, 0.9)),
+            1.0,
+        ))));
+    // Straight tile rows all the way to the horizon, where a ground sphere would bend them
+    scene.add(Box::new(InfinitePlane::new(
+        Vec3::zero(),
+        Vec3::new(0.0, 1.0, 0.0),
+        material_ground,
+    )));
+
+    let mut entities = EntityList::new();
+    entities.add(Box::new(Sphere::new(
+        Vec3::new(-1.2, 0.5, 0.0),
+        0.5,
+        Arc::new(Lambertian::new(Box::new(Texture::new(Vec3::new(
+            0.7, 0.3, 0.2,
+        ))))),
+    )));
+    entities.add(Box::new(Sphere::new(
+        Vec3::new(0.0, 0.5, -1.0),
+        0.5,
+        Arc::new(Lambertian::new(Box::new(Texture::new(Vec3::new(
+            0.2, 0.4, 0.7,
+        ))))),
+    )));
+    entities.add(Box::new(Sphere::new(
+        Vec3::new(1.2, 0.5, 0.0),
+        0.5,
+        Arc::new(Lambertian::new(Box::new(Texture::new(Vec3::new(
+            0.8, 0.7, 0.3,
+        ))))),
+    )));
+    // The plane stays out of the BVH, whose bounds would otherwise become infinite
+    scene.add(Box::new(BVH::new(entities)));
+}
+
+fn scene_metal_spheres(scene: &mut Scene, camera: &mut Camera, width: u32, height: u32) {
+    let mut new_camera = Camera::new(
+        width,
+        height,
+        30.0,
+        &Vec3::new(0.0, 1.5, 6.0),
+        &Vec3::new(0.0, 0.5, 0.0),
+    );
+    new_camera.set_background_color(&Vec3::new(0.70, 0.80, 1.00));
+    *camera = new_camera;
+
+    let material_ground: Arc<dyn Material> =
+        Arc::new(Lambertian::new(Box::new(CheckerTexture::new(
+            Texture::new(Vec3::new(0.2, 0.3, 0.1)),
+            Texture::new(Vec3::new(0.9, 0.9, 0.9)),
+            1.0,
+        ))));
+    scene.add(Box::new(InfinitePlane::new(
+        Vec3::zero(),
+        Vec3::new(0.0, 1.0, 0.0),
+        material_ground,
+    )));
+
+    // Polished metals, so the tiles show up reflected in each metal's own tint
+    let mut entities = EntityList::new();
+    entities.add(Box::new(Sphere::new(
+        Vec3::new(-1.2, 0.5, 0.0),
+        0.5,
+        Arc::new(Conductor::copper()),
+    )));
+    entities.add(Box::new(Sphere::new(
+        Vec3::new(0.0, 0.5, -1.0),
+        0.5,
+        Arc::new(Conductor::aluminum()),
+    )));
+    entities.add(Box::new(Sphere::new(
+        Vec3::new(1.2, 0.5, 0.0),
+        0.5,
+        Arc::new(Conductor::gold()),
+    )));
+    scene.add(Box::new(BVH::new(entities)));
+}
+
+fn scene_hollow_glass(scene: &mut Scene, camera: &mut Camera, width: u32, height: u32) {
+    let mut new_camera = Camera::new(
+        width,
+        height,
+        30.0,
+        &Vec3::new(0.0, 1.5, 7.0),
+        &Vec3::new(0.0, 0.8, 0.0),
+    );
+    new_camera.set_background_color(&Vec3::new(0.70, 0.80, 1.00));
+    *camera = new_camera;
+
+    let material_ground: Arc<dyn Material> =
+        Arc::new(Lambertian::new(Box::new(CheckerTexture::new(
+            Texture::new(Vec3::new(0.2, 0.3, 0.1)),
+            Texture::new(Vec3::new(0.9, 0.9, 0.9)),
+            0.5,
+        ))));
+    let glass: Arc<dyn Material> = Arc::new(Dielectric {
+        refraction_index: 1.5,
+    });
+
+    scene.add(Box::new(Sphere::new(
+        Vec3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        material_ground,
+    )));
+    scene.add(Box::new(Sphere::new(
+        Vec3::new(-1.1, 1.0, 0.0),
+        1.0,
+        Arc::clone(&glass),
+    )));
+    // Negative radius turns the inner surface into an air bubble: a hollow glass shell
+    let center = Vec3::new(1.1, 1.0, 0.0);
+    scene.add(Box::new(Sphere::new(center, 1.0, Arc::clone(&glass))));
+    scene.add(Box::new(Sphere::new(center, -0.9, Arc::clone(&glass))));
+}
+
+fn scene_smooth_checker(scene: &mut Scene, camera: &mut Camera, width: u32, height: u32) {
+    let mut new_camera = Camera::new(
+        width,
+        height,
+        40.0,
+        &Vec3::new(0.0, 1.0, 6.0),
+        &Vec3::new(0.0, 0.0, -10.0),
+    );
+    new_camera.set_background_color(&Vec3::new(0.70, 0.80, 1.00));
+    *camera = new_camera;
+
+    // Box-filtered, so the rows fade to their average toward the horizon instead of aliasing
+    let material_ground: Arc<dyn Material> = Arc::new(Lambertian::new(Box::new(
+        CheckerTexture::new(
+            Texture::new(Vec3::new(0.2, 0.3, 0.1)),
+            Texture::new(Vec3::new(0.9, 0.9, 0.9)),
+            1.0,
+        )
+        .with_smooth(true),
+    )));
+    let material_sphere: Arc<dyn Material> = Arc::new(Lambertian::new(Box::new(Texture::new(
+        Vec3::new(0.7, 0.3, 0.2),
+    ))));
+
+    // Half a cell below the origin, since the filter averages the pattern away on a cell
+    // boundary in y
+    scene.add(Box::new(InfinitePlane::new(
+        Vec3::new(0.0, -0.5, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        material_ground,
+    )));
+    scene.add(Box::new(Sphere::new(
+        Vec3::new(0.0, 0.5, 0.0),
+        1.0,
+        material_sphere,
+    )));
 }
 
-fn scene_earth(entities_out: &mut EntityList, camera: &mut Camera, width: u32, height: u32) {
+fn scene_earth(scene: &mut Scene, camera: &mut Camera, width: u32, height: u32) {
     let new_camera = Camera::new(
         width,
         height,
@@ -304,23 +932,19 @@ fn scene_earth(entities_out: &mut EntityList, camera: &mut Camera, width: u32, h
 
     *camera = new_camera;
 
-    let earth_material: Arc<dyn Material> = Arc::new(Lambertian {
-        albedo: Box::new(ImageTexture::new("assets/earth.jpg")),
-    });
+    let earth_material: Arc<dyn Material> = Arc::new(Lambertian::new(Box::new(ImageTexture::new(
+        "assets/earth.jpg",
+        ColorSpace::Srgb,
+    ))));
 
-    entities_out.add(Box::new(Sphere::new(
+    scene.add(Box::new(Sphere::new(
         Vec3::zero(),
         2.0,
         Arc::clone(&earth_material),
     )));
 }
 
-fn scene_perlin_spheres(
-    entities_out: &mut EntityList,
-    camera: &mut Camera,
-    width: u32,
-    height: u32,
-) {
+fn scene_perlin_spheres(scene: &mut Scene, camera: &mut Camera, width: u32, height: u32) {
     let new_camera = Camera::new(
         width,
         height,
@@ -331,24 +955,27 @@ fn scene_perlin_spheres(
 
     *camera = new_camera;
 
-    let perlin_material: Arc<dyn Material> = Arc::new(Lambertian {
-        albedo: Box::new(NoiseTexture::new(4.0)),
-    });
+    let perlin_material: Arc<dyn Material> =
+        Arc::new(Lambertian::new(Box::new(NoiseTexture::new(4.0))));
 
-    entities_out.add(Box::new(Sphere::new(
+    scene.add(Box::new(Sphere::new(
         Vec3::new(0.0, 2.0, 0.0),
         2.0,
         Arc::clone(&perlin_material),
     )));
 
-    entities_out.add(Box::new(Sphere::new(
+    // Value noise on the ground, for comparison with the gradient turbulence above
+    let value_noise_material: Arc<dyn Material> = Arc::new(Lambertian::new(Box::new(
+        NoiseTexture::new(4.0).with_kind(NoiseKind::Value),
+    )));
+    scene.add(Box::new(Sphere::new(
         Vec3::new(0.0, -1000.0, 0.0),
         1000.0,
-        Arc::clone(&perlin_material),
+        value_noise_material,
     )));
 }
 
-fn scene_quads(entities_out: &mut EntityList, camera: &mut Camera, width: u32, height: u32) {
+fn scene_quads(scene: &mut Scene, camera: &mut Camera, width: u32, height: u32) {
     let mut new_camera = Camera::new(
         width,
         height,
@@ -361,47 +988,47 @@ fn scene_quads(entities_out: &mut EntityList, camera: &mut Camera, width: u32, h
 
     *camera = new_camera;
 
-    let left_red: Arc<dyn Material> = Arc::new(Lambertian {
-        albedo: Box::new(Texture::new(Vec3::new(1.0, 0.2, 0.2))),
-    });
-    let back_green: Arc<dyn Material> = Arc::new(Lambertian {
-        albedo: Box::new(Texture::new(Vec3::new(0.2, 1.0, 0.2))),
-    });
-    let right_blue: Arc<dyn Material> = Arc::new(Lambertian {
-        albedo: Box::new(Texture::new(Vec3::new(0.2, 0.2, 1.0))),
-    });
-    let upper_orange: Arc<dyn Material> = Arc::new(Lambertian {
-        albedo: Box::new(Texture::new(Vec3::new(1.0, 0.5, 0.0))),
-    });
-    let lower_teal: Arc<dyn Material> = Arc::new(Lambertian {
-        albedo: Box::new(Texture::new(Vec3::new(0.2, 0.8, 0.8))),
-    });
-
-    entities_out.add(Box::new(Quad::new(
+    let left_red: Arc<dyn Material> = Arc::new(Lambertian::new(Box::new(Texture::new(Vec3::new(
+        1.0, 0.2, 0.2,
+    )))));
+    let back_green: Arc<dyn Material> = Arc::new(Lambertian::new(Box::new(Texture::new(
+        Vec3::new(0.2, 1.0, 0.2),
+    ))));
+    let right_blue: Arc<dyn Material> = Arc::new(Lambertian::new(Box::new(Texture::new(
+        Vec3::new(0.2, 0.2, 1.0),
+    ))));
+    let upper_orange: Arc<dyn Material> = Arc::new(Lambertian::new(Box::new(Texture::new(
+        Vec3::new(1.0, 0.5, 0.0),
+    ))));
+    let lower_teal: Arc<dyn Material> = Arc::new(Lambertian::new(Box::new(Texture::new(
+        Vec3::new(0.2, 0.8, 0.8),
+    ))));
+
+    scene.add(Box::new(Quad::new(
         Vec3::new(-3.0, -2.0, 5.0),
         Vec3::new(0.0, 0.0, -4.0),
         Vec3::new(0.0, 4.0, 0.0),
         Arc::clone(&left_red),
     )));
-    entities_out.add(Box::new(Quad::new(
+    scene.add(Box::new(Quad::new(
         Vec3::new(-2.0, -2.0, 0.0),
         Vec3::new(4.0, 0.0, -0.0),
         Vec3::new(0.0, 4.0, 0.0),
         Arc::clone(&back_green),
     )));
-    entities_out.add(Box::new(Quad::new(
+    scene.add(Box::new(Quad::new(
         Vec3::new(3.0, -2.0, 1.0),
         Vec3::new(0.0, 0.0, 4.0),
         Vec3::new(0.0, 4.0, 0.0),
         Arc::clone(&right_blue),
     )));
-    entities_out.add(Box::new(Quad::new(
+    scene.add(Box::new(Quad::new(
         Vec3::new(-2.0, 3.0, 1.0),
         Vec3::new(4.0, 0.0, 0.0),
         Vec3::new(0.0, 0.0, 4.0),
         Arc::clone(&upper_orange),
     )));
-    entities_out.add(Box::new(Quad::new(
+    scene.add(Box::new(Quad::new(
         Vec3::new(-2.0, -3.0, 5.0),
         Vec3::new(4.0, 0.0, 0.0),
         Vec3::new(0.0, 0.0, -4.0),
@@ -409,7 +1036,79 @@ fn scene_quads(entities_out: &mut EntityList, camera: &mut Camera, width: u32, h
     )));
 }
 
-fn scene_simple_light(entities_out: &mut EntityList, camera: &mut Camera, width: u32, height: u32) {
+fn scene_tiled_floor(scene: &mut Scene, camera: &mut Camera, width: u32, height: u32) {
+    let mut new_camera = Camera::new(
+        width,
+        height,
+        40.0,
+        &Vec3::new(0.0, 4.0, 9.0),
+        &Vec3::new(0.0, 0.5, 0.0),
+    );
+    new_camera.set_background_color(&Vec3::new(0.70, 0.80, 1.00));
+    *camera = new_camera;
+
+    let light_tile: Arc<dyn Material> = Arc::new(Lambertian::new(Box::new(Texture::new(
+        Vec3::new(0.8, 0.8, 0.75),
+    ))));
+    let dark_tile: Arc<dyn Material> = Arc::new(Lambertian::new(Box::new(Texture::new(
+        Vec3::new(0.15, 0.15, 0.2),
+    ))));
+    let glass: Arc<dyn Material> = Arc::new(Dielectric {
+        refraction_index: 1.5,
+    });
+
+    // Separate tile quads rather than a checker texture, so the glass refracts real edges
+    scene.add(Box::new(Quad::grid(
+        Vec3::new(-4.0, 0.0, 4.0),
+        Vec3::new(8.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, -8.0),
+        8,
+        8,
+        &[light_tile, dark_tile],
+    )));
+    scene.add(Box::new(Sphere::new(
+        Vec3::new(0.0, 1.0, 0.0),
+        1.0,
+        Arc::clone(&glass),
+    )));
+}
+
+fn scene_repeating_map(scene: &mut Scene, camera: &mut Camera, width: u32, height: u32) {
+    let mut new_camera = Camera::new(
+        width,
+        height,
+        40.0,
+        &Vec3::new(0.0, 5.0, 9.0),
+        &Vec3::new(0.0, 0.5, 0.0),
+    );
+    new_camera.set_background_color(&Vec3::new(0.70, 0.80, 1.00));
+    *camera = new_camera;
+
+    let map_material: Arc<dyn Material> = Arc::new(Lambertian::new(Box::new(
+        ImageTexture::new("assets/earth.jpg", ColorSpace::Srgb).with_wrap_mode(WrapMode::Repeat),
+    )));
+    let globe_material: Arc<dyn Material> = Arc::new(Lambertian::new(Box::new(ImageTexture::new(
+        "assets/earth.jpg",
+        ColorSpace::Srgb,
+    ))));
+
+    // The floor repeats its map three times in each direction
+    let mut floor = Quad::new(
+        Vec3::new(-6.0, 0.0, 6.0),
+        Vec3::new(12.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, -12.0),
+        Arc::clone(&map_material),
+    );
+    floor.uv_scale = Vec2::new(3.0, 3.0);
+    scene.add(Box::new(floor));
+    scene.add(Box::new(Sphere::new(
+        Vec3::new(0.0, 1.0, 0.0),
+        1.0,
+        Arc::clone(&globe_material),
+    )));
+}
+
+fn scene_simple_light(scene: &mut Scene, camera: &mut Camera, width: u32, height: u32) {
     let new_camera = Camera::new(
         width,
         height,
@@ -420,27 +1119,68 @@ fn scene_simple_light(entities_out: &mut EntityList, camera: &mut Camera, width:
 
     *camera = new_camera;
 
-    let perlin_material: Arc<dyn Material> = Arc::new(Lambertian {
-        albedo: Box::new(NoiseTexture::new(4.0)),
-    });
+    let perlin_material: Arc<dyn Material> =
+        Arc::new(Lambertian::new(Box::new(NoiseTexture::new(4.0))));
 
-    entities_out.add(Box::new(Sphere::new(
+    scene.add(Box::new(Sphere::new(
         Vec3::new(0.0, 2.0, 0.0),
         2.0,
         Arc::clone(&perlin_material),
     )));
 
-    entities_out.add(Box::new(Sphere::new(
+    scene.add(Box::new(Sphere::new(
         Vec3::new(0.0, -1000.0, 0.0),
         1000.0,
         Arc::clone(&perlin_material),
     )));
 
-    let light_material: Arc<dyn Material> = Arc::new(DiffuseLight {
-        emit: Box::new(Texture::new(Vec3::new(4.0, 4.0, 4.0))),
-    });
+    let light_material: Arc<dyn Material> =
+        Arc::new(DiffuseLight::new(Box::new(Texture::new(Vec3::one()))).with_intensity(4.0));
+
+    scene.add_light(Arc::new(Quad::new(
+        Vec3::new(3.0, 1.0, -2.0),
+        Vec3::new(2.0, 0.0, 0.0),
+        Vec3::new(0.0, 2.0, 0.0),
+        Arc::clone(&light_material),
+    )));
+}
+
+fn scene_lava_sphere(scene: &mut Scene, camera: &mut Camera, width: u32, height: u32) {
+    let new_camera = Camera::new(
+        width,
+        height,
+        20.0,
+        &Vec3::new(26.0, 3.0, 6.0),
+        &Vec3::new(0.0, 2.0, 0.0),
+    );
+
+    *camera = new_camera;
+
+    // Dark crust below 0.2, glowing red through to yellow in the hottest veins
+    let lava_material: Arc<dyn Material> = Arc::new(Lambertian::new(Box::new(
+        NoiseTexture::new(4.0).with_color_ramp(vec![
+            (0.2, Vec3::zero()),
+            (0.5, Vec3::new(0.8, 0.1, 0.0)),
+            (0.8, Vec3::new(1.0, 0.8, 0.1)),
+        ]),
+    )));
+    let ground_material: Arc<dyn Material> = Arc::new(Lambertian::new(Box::new(Texture::new(
+        Vec3::new(0.3, 0.3, 0.3),
+    ))));
+    let light_material: Arc<dyn Material> =
+        Arc::new(DiffuseLight::new(Box::new(Texture::new(Vec3::one()))).with_intensity(4.0));
 
-    entities_out.add(Box::new(Quad::new(
+    scene.add(Box::new(Sphere::new(
+        Vec3::new(0.0, 2.0, 0.0),
+        2.0,
+        Arc::clone(&lava_material),
+    )));
+    scene.add(Box::new(Sphere::new(
+        Vec3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Arc::clone(&ground_material),
+    )));
+    scene.add_light(Arc::new(Quad::new(
         Vec3::new(3.0, 1.0, -2.0),
         Vec3::new(2.0, 0.0, 0.0),
         Vec3::new(0.0, 2.0, 0.0),
@@ -448,7 +1188,94 @@ fn scene_simple_light(entities_out: &mut EntityList, camera: &mut Camera, width:
     )));
 }
 
-fn scene_cornell_box(entities_out: &mut EntityList, camera: &mut Camera, width: u32, height: u32) {
+fn scene_bump_quad(scene: &mut Scene, camera: &mut Camera, width: u32, height: u32) {
+    let new_camera = Camera::new(
+        width,
+        height,
+        40.0,
+        &Vec3::new(0.0, 6.0, 10.0),
+        &Vec3::new(0.0, 0.0, 0.0),
+    );
+
+    *camera = new_camera;
+
+    let bumped_material: Arc<dyn Material> = Arc::new(
+        Lambertian::new(Box::new(Texture::new(Vec3::new(0.73, 0.73, 0.73))))
+            .with_bump_map(Box::new(NoiseTexture::new(4.0))),
+    );
+    let light_material: Arc<dyn Material> =
+        Arc::new(DiffuseLight::new(Box::new(Texture::new(Vec3::one()))).with_intensity(8.0));
+
+    scene.add(Box::new(Quad::new(
+        Vec3::new(-4.0, 0.0, 4.0),
+        Vec3::new(8.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, -8.0),
+        Arc::clone(&bumped_material),
+    )));
+    // Grazing light from the side makes the relief cast visible shading. The panel is tipped
+    // slightly toward the floor about its own center so it stays at the floor's edge.
+    let mut light = Quad::new(
+        Vec3::new(-6.0, 0.0, -2.0),
+        Vec3::new(0.0, 2.0, 0.0),
+        Vec3::new(0.0, 0.0, 4.0),
+        Arc::clone(&light_material),
+    );
+    light.rotate_about(Vec3::new(-6.0, 1.0, 0.0), Vec3::new(0.0, 0.0, 1.0), -15.0);
+    scene.add_light(Arc::new(light));
+}
+
+fn scene_lumpy_sphere(scene: &mut Scene, camera: &mut Camera, width: u32, height: u32) {
+    let new_camera = Camera::new(
+        width,
+        height,
+        30.0,
+        &Vec3::new(0.0, 3.0, 12.0),
+        &Vec3::new(0.0, 1.5, 0.0),
+    );
+
+    *camera = new_camera;
+
+    // The displaced surface has no useful UVs, so the speckles are projected along each axis
+    let speckles = || {
+        Box::new(DotsTexture::new(
+            Texture::new(Vec3::new(0.55, 0.5, 0.45)),
+            Texture::new(Vec3::new(0.3, 0.27, 0.24)),
+            1.0,
+            0.2,
+        ))
+    };
+    let rock_material: Arc<dyn Material> = Arc::new(Lambertian::new(Box::new(
+        TriplanarTexture::new(speckles(), speckles(), speckles(), 0.25).with_blend_sharpness(4.0),
+    )));
+    let ground_material: Arc<dyn Material> = Arc::new(Lambertian::new(Box::new(Texture::new(
+        Vec3::new(0.3, 0.3, 0.3),
+    ))));
+    let light_material: Arc<dyn Material> =
+        Arc::new(DiffuseLight::new(Box::new(Texture::new(Vec3::one()))).with_intensity(10.0));
+
+    scene.add(Box::new(DisplacedSphere::new(
+        Vec3::new(0.0, 1.5, 0.0),
+        1.5,
+        0.25,
+        2.0,
+        Arc::clone(&rock_material),
+    )));
+    scene.add(Box::new(Quad::new(
+        Vec3::new(-10.0, 0.0, 10.0),
+        Vec3::new(20.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, -20.0),
+        Arc::clone(&ground_material),
+    )));
+    // Light from the side so the lumps cast visible shading across the sphere
+    scene.add_light(Arc::new(Quad::new(
+        Vec3::new(-5.0, 0.5, -2.0),
+        Vec3::new(0.0, 4.0, 0.0),
+        Vec3::new(0.0, 0.0, 4.0),
+        Arc::clone(&light_material),
+    )));
+}
+
+fn scene_cornell_box(scene: &mut Scene, camera: &mut Camera, width: u32, height: u32) {
     let new_camera = Camera::new(
         width,
         height,
@@ -459,50 +1286,49 @@ fn scene_cornell_box(entities_out: &mut EntityList, camera: &mut Camera, width:
 
     *camera = new_camera;
 
-    let red_material: Arc<dyn Material> = Arc::new(Lambertian {
-        albedo: Box::new(Texture::new(Vec3::new(0.65, 0.05, 0.05))),
-    });
-    let white_material: Arc<dyn Material> = Arc::new(Lambertian {
-        albedo: Box::new(Texture::new(Vec3::new(0.73, 0.73, 0.73))),
-    });
-    let green_material: Arc<dyn Material> = Arc::new(Lambertian {
-        albedo: Box::new(Texture::new(Vec3::new(0.12, 0.45, 0.15))),
-    });
-    let light_material: Arc<dyn Material> = Arc::new(DiffuseLight {
-        emit: Box::new(Texture::new(Vec3::new(15.0, 15.0, 15.0))),
-    });
-
-    entities_out.add(Box::new(Quad::new(
+    let red_material: Arc<dyn Material> = Arc::new(Lambertian::new(Box::new(Texture::new(
+        Vec3::new(0.65, 0.05, 0.05),
+    ))));
+    let white_material: Arc<dyn Material> = Arc::new(Lambertian::new(Box::new(Texture::new(
+        Vec3::new(0.73, 0.73, 0.73),
+    ))));
+    let green_material: Arc<dyn Material> = Arc::new(Lambertian::new(Box::new(Texture::new(
+        Vec3::new(0.12, 0.45, 0.15),
+    ))));
+    let light_material: Arc<dyn Material> =
+        Arc::new(DiffuseLight::new(Box::new(Texture::new(Vec3::one()))).with_intensity(15.0));
+
+    scene.add(Box::new(Quad::new(
         Vec3::new(555.0, 0.0, 0.0),
         Vec3::new(0.0, 555.0, 0.0),
         Vec3::new(0.0, 0.0, 555.0),
         Arc::clone(&green_material),
     )));
-    entities_out.add(Box::new(Quad::new(
+    scene.add(Box::new(Quad::new(
         Vec3::new(0.0, 0.0, 0.0),
         Vec3::new(0.0, 555.0, 0.0),
         Vec3::new(0.0, 0.0, 555.0),
         Arc::clone(&red_material),
     )));
-    entities_out.add(Box::new(Quad::new(
+    scene.add_light(Arc::new(Quad::new(
         Vec3::new(343.0, 554.0, 332.0),
         Vec3::new(-130.0, 0.0, 0.0),
         Vec3::new(0.0, 0.0, -105.0),
         Arc::clone(&light_material),
     )));
-    entities_out.add(Box::new(Quad::new(
+    scene.add(Box::new(Quad::new(
         Vec3::new(0.0, 0.0, 555.0),
         Vec3::new(555.0, 0.0, 0.0),
         Vec3::new(0.0, 555.0, 0.0),
         Arc::clone(&white_material),
     )));
-    entities_out.add(Box::new(Quad::new(
+    scene.add(Box::new(Quad::new(
         Vec3::new(555.0, 555.0, 555.0),
         Vec3::new(-555.0, 0.0, 0.0),
         Vec3::new(0.0, 0.0, -555.0),
         Arc::clone(&white_material),
     )));
-    entities_out.add(Box::new(Quad::new(
+    scene.add(Box::new(Quad::new(
         Vec3::new(0.0, 0.0, 0.0),
         Vec3::new(555.0, 0.0, 0.0),
         Vec3::new(0.0, 0.0, 555.0),
@@ -516,7 +1342,7 @@ fn scene_cornell_box(entities_out: &mut EntityList, camera: &mut Camera, width:
     );
     box1.rotate(Vec3::new(0.0, 1.0, 0.0), 15.0);
     box1.translate(Vec3::new(265.0, 0.0, 295.0));
-    entities_out.add(Box::from(box1));
+    scene.add(Box::from(box1));
 
     let mut box2 = create_box(
         Vec3::new(0.0, 0.0, 0.0),
@@ -525,15 +1351,10 @@ fn scene_cornell_box(entities_out: &mut EntityList, camera: &mut Camera, width:
     );
     box2.rotate(Vec3::new(0.0, 1.0, 0.0), -18.0);
     box2.translate(Vec3::new(130.0, 0.0, 65.0));
-    entities_out.add(Box::from(box2));
+    scene.add(Box::from(box2));
 }
 
-fn scene_cornell_smoke(
-    entities_out: &mut EntityList,
-    camera: &mut Camera,
-    width: u32,
-    height: u32,
-) {
+fn scene_cornell_smoke(scene: &mut Scene, camera: &mut Camera, width: u32, height: u32) {
     let new_camera = Camera::new(
         width,
         height,
@@ -544,50 +1365,49 @@ fn scene_cornell_smoke(
 
     *camera = new_camera;
 
-    let red_material: Arc<dyn Material> = Arc::new(Lambertian {
-        albedo: Box::new(Texture::new(Vec3::new(0.65, 0.05, 0.05))),
-    });
-    let white_material: Arc<dyn Material> = Arc::new(Lambertian {
-        albedo: Box::new(Texture::new(Vec3::new(0.73, 0.73, 0.73))),
-    });
-    let green_material: Arc<dyn Material> = Arc::new(Lambertian {
-        albedo: Box::new(Texture::new(Vec3::new(0.12, 0.45, 0.15))),
-    });
-    let light_material: Arc<dyn Material> = Arc::new(DiffuseLight {
-        emit: Box::new(Texture::new(Vec3::new(15.0, 15.0, 15.0))),
-    });
-
-    entities_out.add(Box::new(Quad::new(
+    let red_material: Arc<dyn Material> = Arc::new(Lambertian::new(Box::new(Texture::new(
+        Vec3::new(0.65, 0.05, 0.05),
+    ))));
+    let white_material: Arc<dyn Material> = Arc::new(Lambertian::new(Box::new(Texture::new(
+        Vec3::new(0.73, 0.73, 0.73),
+    ))));
+    let green_material: Arc<dyn Material> = Arc::new(Lambertian::new(Box::new(Texture::new(
+        Vec3::new(0.12, 0.45, 0.15),
+    ))));
+    let light_material: Arc<dyn Material> =
+        Arc::new(DiffuseLight::new(Box::new(Texture::new(Vec3::one()))).with_intensity(15.0));
+
+    scene.add(Box::new(Quad::new(
         Vec3::new(555.0, 0.0, 0.0),
         Vec3::new(0.0, 555.0, 0.0),
         Vec3::new(0.0, 0.0, 555.0),
         Arc::clone(&green_material),
     )));
-    entities_out.add(Box::new(Quad::new(
+    scene.add(Box::new(Quad::new(
         Vec3::new(0.0, 0.0, 0.0),
         Vec3::new(0.0, 555.0, 0.0),
         Vec3::new(0.0, 0.0, 555.0),
         Arc::clone(&red_material),
     )));
-    entities_out.add(Box::new(Quad::new(
+    scene.add_light(Arc::new(Quad::new(
         Vec3::new(343.0, 554.0, 332.0),
         Vec3::new(-130.0, 0.0, 0.0),
         Vec3::new(0.0, 0.0, -105.0),
         Arc::clone(&light_material),
     )));
-    entities_out.add(Box::new(Quad::new(
+    scene.add(Box::new(Quad::new(
         Vec3::new(0.0, 0.0, 555.0),
         Vec3::new(555.0, 0.0, 0.0),
         Vec3::new(0.0, 555.0, 0.0),
         Arc::clone(&white_material),
     )));
-    entities_out.add(Box::new(Quad::new(
+    scene.add(Box::new(Quad::new(
         Vec3::new(555.0, 555.0, 555.0),
         Vec3::new(-555.0, 0.0, 0.0),
         Vec3::new(0.0, 0.0, -555.0),
         Arc::clone(&white_material),
     )));
-    entities_out.add(Box::new(Quad::new(
+    scene.add(Box::new(Quad::new(
         Vec3::new(0.0, 0.0, 0.0),
         Vec3::new(555.0, 0.0, 0.0),
         Vec3::new(0.0, 0.0, 555.0),
@@ -617,91 +1437,1011 @@ fn scene_cornell_smoke(
     box2.rotate(Vec3::new(0.0, 1.0, 0.0), -18.0);
     box2.translate(Vec3::new(130.0, 0.0, 65.0));
 
-    entities_out.add(Box::new(ConstantMedium::new(
+    scene.add(Box::new(ConstantMedium::new(
         Box::from(box1),
         0.01,
         Arc::clone(&black_smoke_material),
     )));
-    entities_out.add(Box::new(ConstantMedium::new(
+    scene.add(Box::new(ConstantMedium::new(
         Box::from(box2),
         0.01,
         Arc::clone(&white_smoke_material),
     )));
 }
 
+fn scene_noisy_cloud(scene: &mut Scene, camera: &mut Camera, width: u32, height: u32) {
+    let mut new_camera = Camera::new(
+        width,
+        height,
+        35.0,
+        &Vec3::new(0.0, 2.0, 10.0),
+        &Vec3::new(0.0, 2.0, 0.0),
+    );
+    new_camera.set_background_color(&Vec3::new(0.70, 0.80, 1.00));
+
+    *camera = new_camera;
+
+    let ground_material: Arc<dyn Material> = Arc::new(Lambertian::new(Box::new(Texture::new(
+        Vec3::new(0.4, 0.45, 0.35),
+    ))));
+    let cloud_material: Arc<dyn Material> = Arc::new(Isotropic {
+        albedo: Box::new(Texture::new(Vec3::new(0.9, 0.9, 0.9))),
+    });
+
+    scene.add(Box::new(Quad::new(
+        Vec3::new(-20.0, 0.0, 20.0),
+        Vec3::new(40.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, -40.0),
+        Arc::clone(&ground_material),
+    )));
+    // The box only bounds the cloud; the noise decides where inside it there is any
+    let boundary = create_box(
+        Vec3::new(-3.0, 1.0, -1.5),
+        Vec3::new(3.0, 3.5, 1.5),
+        Arc::clone(&cloud_material),
+    );
+    scene.add(Box::new(TexturedMedium::new(
+        Box::from(boundary),
+        8.0,
+        Box::new(NoiseTexture::new(1.0)),
+        Arc::clone(&cloud_material),
+    )));
+}
+
+fn scene_spinning_box(scene: &mut Scene, camera: &mut Camera, width: u32, height: u32) {
+    let mut new_camera = Camera::new(
+        width,
+        height,
+        40.0,
+        &Vec3::new(0.0, 3.0, 8.0),
+        &Vec3::new(0.0, 1.0, 0.0),
+    );
+    new_camera.set_background_color(&Vec3::new(0.70, 0.80, 1.00));
+    new_camera.set_shutter(1.0);
+    *camera = new_camera;
+
+    let ground_material: Arc<dyn Material> =
+        Arc::new(Lambertian::new(Box::new(CheckerTexture::new(
+            Texture::new(Vec3::new(0.2, 0.3, 0.1)),
+            Texture::new(Vec3::new(0.9, 0.9, 0.9)),
+            1.0,
+        ))));
+    let box_material: Arc<dyn Material> = Arc::new(Lambertian::new(Box::new(Texture::new(
+        Vec3::new(0.8, 0.2, 0.1),
+    ))));
+
+    scene.add(Box::new(Quad::new(
+        Vec3::new(-20.0, 0.0, 20.0),
+        Vec3::new(40.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, -40.0),
+        Arc::clone(&ground_material),
+    )));
+    // A long box turning a quarter turn about the vertical while the shutter is open, so its
+    // ends smear along arcs while the middle stays sharp
+    let bar = create_box(
+        Vec3::new(-2.0, -0.3, -0.3),
+        Vec3::new(2.0, 0.3, 0.3),
+        Arc::clone(&box_material),
+    );
+    let up = Vec3::new(0.0, 1.0, 0.0);
+    scene.add(Box::new(AnimatedTransform::new(
+        Box::new(bar),
+        Transform::new(Vec3::new(0.0, 1.0, 0.0), up, 0.0, 1.0),
+        Transform::new(Vec3::new(0.0, 1.0, 0.0), up, 90.0, 1.0),
+    )));
+}
+
+fn scene_instanced_forest(scene: &mut Scene, camera: &mut Camera, width: u32, height: u32) {
+    let mut new_camera = Camera::new(
+        width,
+        height,
+        35.0,
+        &Vec3::new(0.0, 12.0, 45.0),
+        &Vec3::new(0.0, 0.0, 0.0),
+    );
+    new_camera.set_background_color(&Vec3::new(0.70, 0.80, 1.00));
+    *camera = new_camera;
+
+    let ground_material: Arc<dyn Material> = Arc::new(Lambertian::new(Box::new(Texture::new(
+        Vec3::new(0.35, 0.3, 0.2),
+    ))));
+    let tree_material: Arc<dyn Material> = Arc::new(Lambertian::new(Box::new(Texture::new(
+        Vec3::new(0.1, 0.45, 0.15),
+    ))));
+
+    scene.add(Box::new(Sphere::new(
+        Vec3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Arc::clone(&ground_material),
+    )));
+
+    let tree = Arc::new(Mesh::cone(0.6, 2.0, 7, tree_material));
+    let transforms: Vec<Transform> = (0..1000)
+        .map(|i| {
+            let (row, col) = (i / 40, i % 40);
+            Transform::new(
+                Vec3::new(
+                    (col as Real - 20.0) + rand_f32_range(-0.3, 0.3),
+                    0.0,
+                    (row as Real - 12.5) * 1.5 + rand_f32_range(-0.3, 0.3),
+                ),
+                Vec3::new(0.0, 1.0, 0.0),
+                rand_f32_range(0.0, 360.0),
+                rand_f32_range(0.6, 1.4),
+            )
+        })
+        .collect();
+    let instance_count = transforms.len();
+    let forest = InstancedMesh::new(Arc::clone(&tree), transforms);
+    info!(
+        "Instanced {} trees in {} KiB ({} KiB if duplicated)",
+        instance_count,
+        forest.memory_footprint() / 1024,
+        instance_count * tree.memory_footprint() / 1024
+    );
+    scene.add(Box::new(forest));
+}
+
 fn main() {
     let mut use_ppm = true;
     let args: Vec<String> = std::env::args().collect();
+    logger::init(if args.iter().any(|arg| arg == "--quiet") {
+        LevelFilter::Error
+    } else if args.iter().any(|arg| arg == "--verbose") {
+        LevelFilter::Debug
+    } else {
+        LevelFilter::Info
+    });
+    if args.iter().any(|arg| arg == "--validate") {
+        let passed = validate::run(24);
+        std::process::exit(if passed { 0 } else { 1 });
+    }
+    if args.iter().any(|arg| arg == "--list-scenes") {
+        print!("{}", list_scenes());
+        return;
+    }
+    if args.iter().any(|arg| arg == "--benchmark") {
+        run_benchmark(24);
+        return;
+    }
     if args.iter().any(|arg| arg == "--window") {
         use_ppm = false;
     }
+    let temporal = args.iter().any(|arg| arg == "--taa");
+    let ppm_binary = args.iter().any(|arg| arg == "--ppm-binary");
+    let sample_clamp = arg_value(&args, "--clamp").map(|value| {
+        value
+            .parse::<Real>()
+            .expect("--clamp expects a maximum sample luminance")
+    });
+    let ray_epsilon = arg_value(&args, "--ray-epsilon").map(|value| {
+        value
+            .parse::<Real>()
+            .expect("--ray-epsilon expects a relative offset")
+    });
+    let resume_path = arg_value(&args, "--resume");
+    // A resumed render keeps checkpointing to the file it came from unless told otherwise
+    let checkpoint_path = arg_value(&args, "--checkpoint").or(resume_path);
+    let override_material = arg_value(&args, "--override-material").map(|name| {
+        MaterialOverride::preset(name)
+            .expect("--override-material expects one of: clay, normals, white")
+    });
+    let override_lights = args.iter().any(|arg| arg == "--override-lights");
+    // Either one horizontal cross or six face images in `CubeFace` order
+    let environment = arg_value(&args, "--cubemap").map(|paths| {
+        let faces: Vec<&str> = paths.split(',').collect();
+        Arc::new(match <[&str; 6]>::try_from(faces.as_slice()) {
+            Ok(faces) => EnvironmentMap::cube_from_files(faces),
+            Err(_) if faces.len() == 1 => EnvironmentMap::cross(paths),
+            Err(_) => panic!("--cubemap expects one cross image or six comma-separated faces"),
+        })
+    });
+    let order = arg_value(&args, "--order").map_or(PixelOrder::default(), |name| {
+        PixelOrder::from_name(name).expect("--order expects one of: scanline, morton, tiled")
+    });
+    let seed = arg_value(&args, "--seed").map(|value| {
+        value
+            .parse::<u64>()
+            .expect("--seed expects an unsigned integer")
+    });
+    let denoise_output = args.iter().any(|arg| arg == "--denoise-oidn");
+    let edge_samples = arg_value(&args, "--edge-samples").map(|value| {
+        value
+            .parse::<u32>()
+            .expect("--edge-samples expects a sample count")
+    });
+    let color_space = arg_value(&args, "--color-space").map(|name| {
+        ColorSpace::from_name(name).expect("--color-space expects one of: srgb, gamma2, linear")
+    });
+    let edge_aa = args.iter().any(|arg| arg == "--edge-aa");
+    if edge_aa && edge_samples.is_some() {
+        panic!("--edge-aa cannot be combined with --edge-samples");
+    }
+    let preview = args.iter().any(|arg| arg == "--preview");
+    let spp = arg_value(&args, "--spp")
+        .map(|value| value.parse::<u32>().expect("--spp expects a sample count"))
+        .or(preview.then_some(PREVIEW_SPP));
+    let max_depth = arg_value(&args, "--max-depth")
+        .map(|value| {
+            value
+                .parse::<u32>()
+                .expect("--max-depth expects a bounce count")
+        })
+        .or(preview.then_some(PREVIEW_MAX_DEPTH));
+    // A preview renders through a pinhole, even if the scene file sets an aperture
+    let defocus_angle = arg_value(&args, "--defocus-angle")
+        .map(|value| {
+            value
+                .parse::<Real>()
+                .expect("--defocus-angle expects an angle in degrees")
+        })
+        .or(preview.then_some(0.0));
+    let autofocus = defocus_angle.is_some_and(|angle| angle > 0.0);
+    let auto_frame = args.iter().any(|arg| arg == "--auto-frame");
+    let show_stats = args.iter().any(|arg| arg == "--stats");
+    let show_timing = args.iter().any(|arg| arg == "--timing");
+    let save_on_interrupt = args.iter().any(|arg| arg == "--save-on-interrupt");
+    let bracket = arg_value(&args, "--bracket").map(|list| {
+        list.split(',')
+            .map(|stop| {
+                stop.trim()
+                    .parse::<Real>()
+                    .expect("--bracket expects comma-separated stops, e.g. -2,0,+2")
+            })
+            .collect::<Vec<_>>()
+    });
+    if bracket.is_some() && !use_ppm {
+        log::warn!("--bracket only applies to file output and is ignored with --window");
+    }
+    // The depth is optional, so a following flag means "all levels"
+    let show_bounds = args
+        .iter()
+        .position(|arg| arg == "--show-bounds")
+        .map(|idx| {
+            args.get(idx + 1)
+                .and_then(|value| value.parse::<u32>().ok())
+                .unwrap_or(u32::MAX)
+        });
+    if show_bounds.is_some() && !use_ppm {
+        log::warn!("--show-bounds only applies to file output and is ignored with --window");
+    }
     //let aspect_ratio = window.dim.width as f32 / window.dim.height as f32; //16f32/9f32;
-    const DEFAULT_WIDTH: u32 = 800;
-    const DEFAULT_HEIGHT: u32 = 600;
-    let image_width = DEFAULT_WIDTH;
-    let image_height = DEFAULT_HEIGHT;
-    assert!(image_height > 1);
+    let (image_width, image_height) = image_size(&args);
     let bitmap = create_bitmap(image_width as i32, image_height as i32);
 
-    let mut entities = EntityList::new();
-    let mut camera = Camera::default();
-    //scene_scattered_balls(&mut entities, &mut camera, image_width, image_height);
-    //checker_spheres(&mut entities, &mut camera, image_width, image_height);
-    //scene_earth(&mut entities, &mut camera, image_width, image_height);
-    //scene_perlin_spheres(&mut entities, &mut camera, image_width, image_height);
-    //scene_quads(&mut entities, &mut camera, image_width, image_height);
-    //scene_simple_light(&mut entities, &mut camera, image_width, image_height);
-    scene_cornell_box(&mut entities, &mut camera, image_width, image_height);
-    //scene_cornell_smoke(&mut entities, &mut camera, image_width, image_height);
-    let entities = Arc::from(entities);
+    // A built-in scene's name, or else the path of a scene file
+    let scene_arg = arg_value(&args, "--scene");
+    let scene_start = Instant::now();
+    let (scene, camera) = match builtin_scene(scene_arg.unwrap_or(DEFAULT_SCENE)) {
+        Some(builtin) => builtin.build(image_width, image_height),
+        None => load_scene(scene_arg.expect("the default scene is built in")),
+    };
+    let scene_build = scene_start.elapsed();
+    if let Some(path) = arg_value(&args, "--export-scene") {
+        export_scene(&scene, &camera, path);
+    }
+    if args.iter().any(|arg| arg == "--dump-scene") {
+        print!("{}", scene.describe());
+        return;
+    }
+    // Applied again to every scene the window reloads
+    let prepare = |mut scene: Scene, mut camera: Camera| {
+        if scene.entities.is_empty() {
+            log::warn!("The scene has no entities, so only the background will show");
+        }
+        if auto_frame {
+            match scene.entities.bounding_sphere() {
+                Some((center, radius)) => camera.frame_scene(&center, radius),
+                None => log::warn!("--auto-frame found nothing bounded to frame"),
+            }
+        }
+        camera.set_lights(scene.lights.clone());
+        camera.set_sample_clamp(sample_clamp);
+        if let Some(spp) = spp {
+            camera.set_samples_per_pixel(spp);
+        }
+        if let Some(max_depth) = max_depth {
+            camera.set_max_ray_bounces(max_depth);
+        }
+        if let Some(epsilon) = ray_epsilon {
+            camera.set_ray_epsilon(epsilon);
+        }
+        if let Some(seed) = seed {
+            camera.seed = seed;
+        }
+        if let Some(color_space) = color_space {
+            camera.color_space = color_space;
+        }
+        if let Some(environment) = &environment {
+            camera.set_environment(Arc::clone(environment));
+        }
+        let counts = (scene.entities.len(), scene.lights.len(), scene.lighting());
+        let build_start = Instant::now();
+        scene.entities = scene.entities.into_bvh();
+        let bvh_build = build_start.elapsed();
+        if let Some(angle) = defocus_angle {
+            camera.set_defocus_angle(angle);
+        }
+        if autofocus {
+            // Start focused on whatever is in the middle of the image
+            if let Some(pick) = camera.pick(image_width / 2, image_height / 2, &scene.entities) {
+                camera.focus_on(pick.position);
+            }
+        }
+        if let Some(material) = &override_material {
+            let mut overridden = EntityList::new();
+            overridden.add(Box::new(MaterialOverride::new(
+                Box::new(scene.entities),
+                Arc::clone(material),
+                !override_lights,
+            )));
+            scene.entities = overridden;
+        }
+        (Arc::from(scene.entities), camera, counts, bvh_build)
+    };
+    let (entities, camera, (entity_count, light_count, lighting), bvh_build) =
+        prepare(scene, camera);
+    let samples_per_pixel = camera.samples_per_pixel();
     let thread_count = 24;
-    let mut threads = Vec::with_capacity(thread_count as usize);
-    let stop = Arc::new(AtomicBool::new(false));
+    let cancel = CancelToken::new();
+    {
+        let cancel = cancel.clone();
+        ctrlc::set_handler(move || cancel.cancel()).expect("Failed to install Ctrl-C handler");
+    }
+    let observer = ProgressObserver::new(image_width * image_height);
+    let rays_before = camera::rays_traced();
+    let start = Instant::now();
+    let mut trace_time = None;
+    let mut write_time = None;
 
     if use_ppm {
-        let bitmap = Arc::new(Mutex::new(bitmap));
-        render(
-            &mut threads,
-            thread_count,
-            &bitmap,
-            image_width,
-            image_height,
-            &entities,
-            &camera,
-            &stop,
-        );
-        for thread in threads {
-            thread.join().unwrap();
+        let bitmap = Mutex::new(bitmap);
+        let completed = if checkpoint_path.is_some()
+            || denoise_output
+            || edge_samples.is_some()
+            || edge_aa
+            || bracket.is_some()
+            || save_on_interrupt
+        {
+            let accumulator = render_accumulated(
+                thread_count,
+                (image_width, image_height),
+                &entities,
+                &camera,
+                &cancel,
+                checkpoint_path.map(Path::new),
+                resume_path.map(Path::new),
+                edge_samples,
+                edge_aa,
+            );
+            let output = accumulator.output();
+            let (fewest, most) = output.sample_range();
+            if fewest != most {
+                info!("Pixels averaged {} to {} samples", fewest, most);
+            }
+            let mut colors = output.colors;
+            if denoise_output && !cancel.is_cancelled() {
+                info!("Denoising...");
+                let aovs = denoise::capture_aovs(&camera, &entities, image_width, image_height);
+                colors = denoise::denoise(&colors, &aovs, image_width, image_height);
+            }
+            write_colors(&colors, camera.color_space, &mut bitmap.lock().unwrap());
+            // Each stop only re-runs the encoding of the same float buffer
+            for &stops in bracket.iter().flatten() {
+                if cancel.is_cancelled() {
+                    break;
+                }
+                let mut bracketed = create_bitmap(image_width as i32, image_height as i32);
+                write_colors(&expose(&colors, stops), camera.color_space, &mut bracketed);
+                let path = bracket_path(stops);
+                match write_png(&bracketed, &path) {
+                    Ok(()) => info!("Wrote {}", path),
+                    Err(error) => log::error!("Failed to write {}: {}", path, error),
+                }
+            }
+            !cancel.is_cancelled()
+        } else {
+            render_to_buffer(
+                thread_count,
+                order,
+                &bitmap,
+                &entities,
+                &camera,
+                &cancel,
+                Some(&observer),
+            )
+        };
+        trace_time = Some(start.elapsed());
+        // The accumulated path averages each pixel over the samples it actually got, so an
+        // interrupted render still gives a correctly exposed, if noisy, image
+        let save_partial = !completed && save_on_interrupt;
+        if completed || save_partial {
+            if completed {
+                info!("Rendering completed.");
+            } else {
+                info!("Rendering cancelled, writing the samples taken so far.");
+            }
+            if let Some(max_depth) = show_bounds {
+                let mut bounds = Vec::new();
+                entities.node_bounds(max_depth, &mut bounds);
+                draw_bounds(&mut bitmap.lock().unwrap(), &camera, &bounds);
+            }
+            let write_start = Instant::now();
+            write_ppm(bitmap.lock().unwrap().deref(), ppm_binary).unwrap();
+            write_time = Some(write_start.elapsed());
+            info!("PPM file written successfully.");
+        } else {
+            info!("Rendering cancelled.");
         }
-        println!("Rendering completed.");
-        write_ppm(bitmap.lock().unwrap().deref()).unwrap();
-        println!("PPM file written successfully.");
     } else {
-        let window = Window::new("Raytracer", image_width as i32, image_height as i32, bitmap);
-        let mut first = true;
+        let mut window = Window::new("Raytracer", image_width as i32, image_height as i32, bitmap);
+        let window_bitmap = Arc::clone(&window.buffer.bitmap);
+        let start_render = |entities: Arc<EntityList>, camera: Camera| {
+            let bitmap = Arc::clone(&window_bitmap);
+            let cancel = CancelToken::new();
+            let render_cancel = cancel.clone();
+            let observer = ProgressObserver::new(image_width * image_height);
+            let render_thread = thread::spawn(move || {
+                if temporal {
+                    render_temporal(thread_count, &entities, &camera, &bitmap, &cancel, |_| {});
+                } else {
+                    render_to_buffer(
+                        thread_count,
+                        order,
+                        &bitmap,
+                        &entities,
+                        &camera,
+                        &cancel,
+                        Some(&observer),
+                    );
+                }
+            });
+            (render_thread, render_cancel)
+        };
+        let mut watcher = scene_arg
+            .filter(|name| builtin_scene(name).is_none())
+            .map(|path| {
+                info!("Watching {} for changes", path);
+                SceneWatcher::new(Path::new(path), RELOAD_DEBOUNCE)
+            });
+        let (mut pick_camera, mut pick_entities) = (camera.clone(), Arc::clone(&entities));
+        let mut render = start_render(entities, camera);
         loop {
             window.process_messages();
             window.display();
-            if first {
-                render(
-                    &mut threads,
-                    thread_count,
-                    &window.buffer.bitmap,
-                    image_width,
-                    image_height,
-                    &entities,
-                    &camera,
-                    &stop,
-                );
-                first = false;
-            }
-            if window.shutdown_requested {
-                stop.store(true, Ordering::Release);
-                for thread in threads {
-                    thread.join().unwrap();
+            if let Some((x, y)) = window.clicked.take() {
+                match pick_camera.pick(x, y, &pick_entities) {
+                    // Any lens with an aperture refocuses, including one from a scene file
+                    Some(pick) if pick_camera.defocus_angle() > 0.0 => {
+                        render.1.cancel();
+                        render.0.join().unwrap();
+                        pick_camera.focus_on(pick.position);
+                        info!(
+                            "Pixel ({}, {}): {}, now in focus at {:.3}",
+                            x,
+                            y,
+                            pick,
+                            pick_camera.focus_distance()
+                        );
+                        render = start_render(Arc::clone(&pick_entities), pick_camera.clone());
+                    }
+                    Some(pick) => info!("Pixel ({}, {}): {}", x, y, pick),
+                    None => info!("Pixel ({}, {}): background", x, y),
                 }
+            }
+            // The old render is stopped and joined before the new scene is swapped in, so no
+            // frame ever mixes the two
+            let reloaded = watcher
+                .as_mut()
+                .and_then(|watcher| watcher.poll().then(|| reload_scene(&watcher.path))?);
+            if let Some((scene, camera)) = reloaded {
+                info!("Scene file changed, restarting the render");
+                render.1.cancel();
+                render.0.join().unwrap();
+                let (entities, camera, _, _) = prepare(scene, camera);
+                (pick_camera, pick_entities) = (camera.clone(), Arc::clone(&entities));
+                render = start_render(entities, camera);
+            }
+            if window.shutdown_requested || cancel.is_cancelled() {
+                cancel.cancel();
+                render.1.cancel();
+                render.0.join().unwrap();
                 break;
             }
         }
     }
+
+    if show_stats {
+        let stats = RenderStats {
+            width: image_width,
+            height: image_height,
+            samples_per_pixel,
+            lighting,
+            rays: camera::rays_traced() - rays_before,
+            elapsed: start.elapsed(),
+            peak_memory: stats::peak_memory(),
+            bvh_nodes: bvh::nodes_built(),
+            entities: entity_count,
+            lights: light_count,
+        };
+        info!("{}", stats);
+    }
+    if show_timing {
+        let timings = PhaseTimings {
+            scene_build,
+            bvh_build,
+            trace: trace_time.unwrap_or_else(|| start.elapsed()),
+            write: write_time,
+        };
+        info!("{}", timings);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use entities::entity::HitRecord;
+    use interval::Interval;
+    use scene::Lighting;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[test]
+    fn test_pick_finds_the_cornell_floor() {
+        let mut scene = Scene::new();
+        let mut camera = Camera::default();
+        scene_cornell_box(&mut scene, &mut camera, 40, 40);
+
+        // Near the bottom left, clear of the boxes
+        let (x, y) = (8, 33);
+        let pick = camera
+            .pick(x, y, &scene.entities)
+            .expect("pixel should hit the floor");
+        assert_eq!(pick.material, "Lambertian");
+        assert!(pick.position.y.abs() < 1e-3);
+        assert_vec3_approx!(pick.normal, Vec3::new(0.0, 1.0, 0.0), 1e-4);
+        // The camera looks down +z from (278, 278, -800) with a 40 degree field of view
+        let half_height = Real::tan(Real::to_radians(20.0));
+        let direction = Vec3::new(
+            half_height * (x as Real + 0.5 - 20.0) / 20.0,
+            -half_height * (y as Real + 0.5 - 20.0) / 20.0,
+            1.0,
+        );
+        let expected = 278.0 / -direction.y * direction.length();
+        assert!(
+            (pick.distance - expected).abs() < 1e-2 * expected,
+            "distance {} vs {}",
+            pick.distance,
+            expected
+        );
+    }
+
+    #[test]
+    fn test_cornell_dump_lists_walls_light_and_boxes() {
+        let mut scene = Scene::new();
+        let mut camera = Camera::default();
+        scene_cornell_box(&mut scene, &mut camera, 40, 40);
+
+        let dump = scene.describe();
+        let lines: Vec<&str> = dump.lines().collect();
+        assert_eq!(lines[0], "8 entities, 1 lights");
+        let top_level: Vec<&str> = lines[1..]
+            .iter()
+            .copied()
+            .filter(|line| !line.starts_with(' '))
+            .collect();
+        let quads: Vec<&str> = top_level
+            .iter()
+            .copied()
+            .filter(|line| line.starts_with("Quad at "))
+            .collect();
+        assert_eq!(quads.len(), 6, "{}", dump);
+        assert_eq!(
+            quads
+                .iter()
+                .filter(|line| line.contains(", Lambertian, "))
+                .count(),
+            5
+        );
+        assert_eq!(
+            quads
+                .iter()
+                .filter(|line| line.contains(", DiffuseLight, "))
+                .count(),
+            1
+        );
+        assert!(quads[0].starts_with("Quad at (555.000, 0.000, 0.000), u (0.000, 555.000, 0.000)"));
+        assert!(quads[0].ends_with("bounds (555.000, 0.000, 0.000) to (555.000, 555.000, 555.000)"));
+        // Each box is a list of its six faces, indented below it
+        assert_eq!(
+            top_level
+                .iter()
+                .filter(|line| line.starts_with("EntityList of 6 entities"))
+                .count(),
+            2
+        );
+        assert_eq!(
+            lines
+                .iter()
+                .filter(|line| line.starts_with("  Quad at "))
+                .count(),
+            12
+        );
+    }
+
+    #[test]
+    fn test_cornell_box_registers_only_the_light() {
+        let mut scene = Scene::new();
+        let mut camera = Camera::default();
+        scene_cornell_box(&mut scene, &mut camera, 40, 40);
+
+        assert_eq!(scene.lights.len(), 1);
+        assert!(scene.entities.len() > scene.lights.len());
+        let light = &scene.lights[0];
+        let mut record = HitRecord::new();
+        let up = Ray::new(Vec3::new(278.0, 300.0, 278.0), Vec3::new(0.0, 1.0, 0.0));
+        assert!(light.hit(&up, &Interval::new(0.001, Real::MAX), &mut record));
+        assert!(record.material.unwrap().is_emissive());
+        // The ceiling wall sits just above the light and must not be in the list
+        assert!((record.t - 254.0).abs() < 1e-3);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_exported_cornell_box_renders_identically() {
+        let (width, height) = (24, 24);
+        let render = |scene: &Scene, camera: &Camera| {
+            let bitmap = Mutex::new(create_bitmap(width as i32, height as i32));
+            render_to_buffer(
+                2,
+                PixelOrder::Tiled,
+                &bitmap,
+                &scene.entities,
+                camera,
+                &CancelToken::new(),
+                None,
+            );
+            bitmap.into_inner().unwrap().data.unwrap()
+        };
+        let mut scene = Scene::new();
+        let mut camera = Camera::default();
+        scene_cornell_box(&mut scene, &mut camera, width, height);
+        camera.set_samples_per_pixel(4);
+
+        let path = std::env::temp_dir().join("rustrt_cornell_export.json");
+        scene.save(&camera, &path).unwrap();
+        let (loaded, loaded_camera) = Scene::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded_camera, camera);
+        assert_eq!(loaded.lights.len(), scene.lights.len());
+        assert_eq!(render(&loaded, &loaded_camera), render(&scene, &camera));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_bvh_exports_its_primitives() {
+        let mut scene = Scene::new();
+        let mut camera = Camera::default();
+        scene_scattered_balls(&mut scene, &mut camera, 40, 40);
+        let descs = entities::entity::Hittable::describe(&scene.entities).unwrap();
+        let [entities::entity::EntityDesc::Bvh { children }] = descs.as_slice() else {
+            panic!("Expected a single BVH, got {:?}", descs);
+        };
+        assert!(children
+            .iter()
+            .all(|desc| matches!(desc, entities::entity::EntityDesc::Sphere { .. })));
+    }
+
+    fn gradient_bitmap(width: i32, height: i32) -> Bitmap {
+        let mut bitmap = create_bitmap(width, height);
+        for (idx, pixel) in bitmap
+            .data
+            .as_mut()
+            .unwrap()
+            .chunks_exact_mut(4)
+            .enumerate()
+        {
+            let idx = idx as u8;
+            pixel.copy_from_slice(&[idx, idx.wrapping_mul(3), 255 - idx, 0xFF]);
+        }
+        bitmap
+    }
+
+    #[test]
+    fn test_every_builtin_scene_builds() {
+        assert!(builtin_scene(DEFAULT_SCENE).is_some());
+        for (index, builtin) in BUILTIN_SCENES.iter().enumerate() {
+            assert!(
+                BUILTIN_SCENES[..index]
+                    .iter()
+                    .all(|other| other.name != builtin.name),
+                "{} is registered twice",
+                builtin.name
+            );
+            let (scene, camera) = builtin.build(4, 3);
+            assert!(!scene.entities.is_empty(), "{} is empty", builtin.name);
+            let samples_per_pixel = builtin
+                .samples_per_pixel
+                .unwrap_or_else(|| scene.lighting().default_spp());
+            assert_eq!(camera.samples_per_pixel(), {
+                let strata = (samples_per_pixel as Real).sqrt().round() as u32;
+                strata * strata
+            });
+        }
+        let listing = list_scenes();
+        assert_eq!(listing.lines().count(), BUILTIN_SCENES.len());
+        assert!(listing.contains("cornell-box") && listing.contains("(default)"));
+        assert!(listing.contains("auto spp"));
+    }
+
+    #[test]
+    fn test_lighting_tells_enclosed_from_open_scenes() {
+        let lighting = |name: &str| builtin_scene(name).unwrap().build(4, 3).0.lighting();
+        assert_eq!(lighting("cornell-box"), Lighting::EnclosedEmitters);
+        assert_eq!(lighting("simple-light"), Lighting::OpenEmitters);
+        assert_eq!(lighting("checker-spheres"), Lighting::Sky);
+        assert!(Lighting::EnclosedEmitters.default_spp() > Lighting::Sky.default_spp());
+    }
+
+    #[test]
+    fn test_binary_ppm_decodes_like_ascii() {
+        let bitmap = gradient_bitmap(13, 7);
+        let ascii = image::load_from_memory(&encode_ppm(&bitmap, false))
+            .unwrap()
+            .into_rgb8();
+        let binary = image::load_from_memory(&encode_ppm(&bitmap, true))
+            .unwrap()
+            .into_rgb8();
+        assert_eq!(ascii.dimensions(), (13, 7));
+        assert_eq!(ascii, binary);
+    }
+
+    #[test]
+    fn test_bracket_paths_carry_signed_stops() {
+        assert_eq!(bracket_path(-2.0), "render_ev-2.png");
+        assert_eq!(bracket_path(0.0), "render_ev0.png");
+        assert_eq!(bracket_path(2.0), "render_ev+2.png");
+        assert_eq!(bracket_path(0.5), "render_ev+0.5.png");
+    }
+
+    /// Sphere out of view that raises `cancel` once it has been tested against `limit` rays,
+    /// to interrupt a render at a reproducible point.
+    struct CancelAfterRays {
+        sphere: Sphere,
+        tests: AtomicU32,
+        limit: u32,
+        cancel: CancelToken,
+    }
+
+    impl aabb::HasAABB for CancelAfterRays {
+        fn get_aabb(&self) -> aabb::AABB {
+            self.sphere.get_aabb()
+        }
+
+        fn compute_aabb(&self) -> aabb::AABB {
+            self.sphere.compute_aabb()
+        }
+    }
+
+    impl Transformable for CancelAfterRays {
+        fn translate(&mut self, translation: Vec3) {
+            self.sphere.translate(translation);
+        }
+
+        fn rotate(&mut self, axis: Vec3, angle: Real) {
+            self.sphere.rotate(axis, angle);
+        }
+    }
+
+    impl Hittable for CancelAfterRays {
+        fn hit<'a>(&'a self, ray: &Ray, t_interval: &Interval, record: &mut HitRecord<'a>) -> bool {
+            let tests = self.tests.fetch_add(1, Ordering::Relaxed) + 1;
+            if tests == self.limit {
+                self.cancel.cancel();
+            }
+            self.sphere.hit(ray, t_interval, record)
+        }
+    }
+
+    #[test]
+    fn test_interrupted_render_averages_the_samples_taken() {
+        let background = Vec3::new(0.25, 0.5, 0.75);
+        let mut camera = Camera::new(8, 8, 40.0, &Vec3::zero(), &Vec3::new(0.0, 0.0, -1.0));
+        camera.set_samples_per_pixel(64);
+        camera.set_background_color(&background);
+        // One pass of 16 samples over all 64 pixels, then three and a bit rows of the next
+        let cancel = CancelToken::new();
+        let mut entities = EntityList::new();
+        entities.add(Box::new(CancelAfterRays {
+            sphere: Sphere::new(Vec3::new(0.0, 0.0, 10.0), 1.0, Arc::new(Normals)),
+            tests: Default::default(),
+            limit: 64 * 16 + 3 * 8 * 16 + 1,
+            cancel: cancel.clone(),
+        }));
+
+        let accumulator = render_accumulated(
+            1,
+            (8, 8),
+            &entities,
+            &camera,
+            &cancel,
+            None,
+            None,
+            None,
+            false,
+        );
+        assert!(cancel.is_cancelled());
+        // Whole rows got the second pass before the workers noticed
+        for (index, &count) in accumulator.sample_counts.iter().enumerate() {
+            assert_eq!(
+                count,
+                if index < 4 * 8 { 32 } else { 16 },
+                "pixel {}",
+                index
+            );
+        }
+        // Every pixel sees only the background, however many samples it got
+        for color in accumulator.average() {
+            assert_vec3_approx!(color, background);
+        }
+        let mut bitmap = create_bitmap(8, 8);
+        write_colors(&accumulator.average(), camera.color_space, &mut bitmap);
+        let data = bitmap.data.unwrap();
+        assert!(data.chunks(4).all(|pixel| pixel == &data[..4]));
+    }
+
+    #[test]
+    fn test_resume_reads_one_file_and_checkpoints_to_another() {
+        let mut camera = Camera::new(6, 4, 40.0, &Vec3::zero(), &Vec3::new(0.0, 0.0, -1.0));
+        camera.set_samples_per_pixel(64);
+        let entities = EntityList::new();
+        let cancel = CancelToken::new();
+        let directory = std::env::temp_dir();
+        let (resume, checkpoint) = (
+            directory.join("rustrt_resume_from.ckpt"),
+            directory.join("rustrt_resume_to.ckpt"),
+        );
+        let mut started = Accumulator::new(6, 4, camera.seed);
+        started.accumulate(2, &entities, &camera, &cancel, 16);
+        started.save(&resume).unwrap();
+
+        let finished = render_accumulated(
+            2,
+            (6, 4),
+            &entities,
+            &camera,
+            &cancel,
+            Some(&checkpoint),
+            Some(&resume),
+            None,
+            false,
+        );
+        assert!(finished.sample_counts.iter().all(|&count| count == 64));
+        assert_eq!(Accumulator::load(&checkpoint).unwrap(), finished);
+        // The file resumed from is left as it was, and no partial save is left behind
+        assert_eq!(Accumulator::load(&resume).unwrap(), started);
+        assert!(!directory.join("rustrt_resume_to.ckpt.tmp").exists());
+        std::fs::remove_file(&resume).unwrap();
+        std::fs::remove_file(&checkpoint).unwrap();
+    }
+
+    #[test]
+    fn test_binary_ppm_reads_back_original_pixels() {
+        let bitmap = gradient_bitmap(13, 7);
+        let encoded = encode_ppm(&bitmap, true);
+        let header = b"P6\n13 7\n255\n";
+        assert_eq!(&encoded[..header.len()], header);
+
+        let pixels = &encoded[header.len()..];
+        assert_eq!(pixels.len(), 13 * 7 * 3);
+        for (rgb, bgra) in pixels
+            .chunks_exact(3)
+            .zip(bitmap.data.as_ref().unwrap().chunks_exact(4))
+        {
+            assert_eq!(rgb, [bgra[2], bgra[1], bgra[0]]);
+        }
+        assert!(encoded.len() * 3 < encode_ppm(&bitmap, false).len());
+    }
+
+    #[test]
+    fn test_still_temporal_accumulation_converges_to_offline_render() {
+        let mut scene = Scene::new();
+        let mut camera = Camera::default();
+        scene_cornell_box(&mut scene, &mut camera, 16, 16);
+        camera.set_samples_per_pixel(16);
+
+        let offline = Mutex::new(create_bitmap(16, 16));
+        render_to_buffer(
+            2,
+            PixelOrder::Tiled,
+            &offline,
+            &scene.entities,
+            &camera,
+            &CancelToken::new(),
+            None,
+        );
+
+        let temporal = Mutex::new(create_bitmap(16, 16));
+        let cancel = CancelToken::new();
+        let mut frames = 0;
+        let accumulator =
+            render_temporal(2, &scene.entities, &camera, &temporal, &cancel, |frame| {
+                frames = frame;
+                if frame == 16 {
+                    cancel.cancel();
+                }
+            });
+        assert_eq!(frames, 16);
+        assert!(accumulator.sample_counts.iter().all(|&count| count == 16));
+
+        // Same samples, only summed in a different order
+        let offline = offline.lock().unwrap();
+        let temporal = temporal.lock().unwrap();
+        for (a, b) in offline
+            .data
+            .as_ref()
+            .unwrap()
+            .iter()
+            .zip(temporal.data.as_ref().unwrap())
+        {
+            assert!(a.abs_diff(*b) <= 1, "{} vs {}", a, b);
+        }
+    }
+
+    fn args(list: &[&str]) -> Vec<String> {
+        std::iter::once("RustRT")
+            .chain(list.iter().copied())
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn test_aspect_parses_ratios_and_decimals() {
+        assert!((parse_aspect("16:9").unwrap() - 16.0 / 9.0).abs() < 1e-6);
+        assert!((parse_aspect("4:3").unwrap() - 4.0 / 3.0).abs() < 1e-6);
+        assert!((parse_aspect("1.7778").unwrap() - 1.7778).abs() < 1e-6);
+        assert!((parse_aspect(" 21 : 9 ").unwrap() - 21.0 / 9.0).abs() < 1e-6);
+        for invalid in ["", "wide", "16:", "16:0", "0", "-1.5", "16:9:1"] {
+            assert_eq!(parse_aspect(invalid), None, "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_image_size_derives_height_from_width_and_aspect() {
+        assert_eq!(image_size(&args(&[])), (800, 600));
+        assert_eq!(
+            image_size(&args(&["--width", "1920", "--aspect", "16:9"])),
+            (1920, 1080)
+        );
+        assert_eq!(
+            image_size(&args(&["--width", "1920", "--aspect", "1.7778"])),
+            (1920, 1080)
+        );
+        assert_eq!(image_size(&args(&["--aspect", "2:1"])), (800, 400));
+        assert_eq!(image_size(&args(&["--width", "400"])), (400, 300));
+        assert_eq!(image_size(&args(&["--resolution", "640x360"])), (640, 360));
+    }
+
+    #[test]
+    fn test_preview_size_yields_to_explicit_flags() {
+        assert_eq!(image_size(&args(&["--preview"])), (400, 300));
+        assert_eq!(
+            image_size(&args(&["--preview", "--aspect", "2:1"])),
+            (400, 200)
+        );
+        assert_eq!(
+            image_size(&args(&["--preview", "--width", "640"])),
+            (640, 480)
+        );
+        assert_eq!(
+            image_size(&args(&["--preview", "--resolution", "1280x720"])),
+            (1280, 720)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot be combined")]
+    fn test_resolution_conflicts_with_aspect() {
+        image_size(&args(&["--resolution", "640x360", "--aspect", "16:9"]));
+    }
 }
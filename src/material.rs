@@ -1,34 +1,68 @@
-use crate::math::mat3::dot_v3;
-use crate::math::rand::rand_f32;
 use crate::math::{
     mat3::Mat3,
     vec2::Vec2,
-    vec3::{dot, reflect, Vec3},
+    vec3::{cross, dot, reflect, Vec3},
 };
+use crate::math::{rand::rand_f32, Real};
+#[cfg(feature = "serde")]
+use crate::texture::TextureDesc;
 use crate::texture::TextureSampler;
 use crate::{entities::entity::HitRecord, Ray};
-use core::f32;
 use std::any::Any;
 use std::fmt::Debug;
+#[cfg(feature = "serde")]
+use std::sync::Arc;
 pub trait Material: Debug + Any + Sync + Send {
-    fn scatter_pdf(&self, _ray_in: &Ray, _record: &HitRecord, _ray_scattered: &Ray) -> f32 {
+    fn scatter_pdf(&self, _ray_in: &Ray, _record: &HitRecord, _ray_scattered: &Ray) -> Real {
         0.0
     }
 
+    /// Samples a scattered ray. Materials that set `pdf` to the density of the chosen
+    /// direction are continued along it, and so are specular ones; see `is_specular`. For
+    /// other materials that leave `pdf` at zero the integrator samples the continuation from
+    /// a cosine distribution instead.
     fn scatter(
         &self,
         _ray: &Ray,
         _hit_record: &HitRecord,
         _attenuation: &mut Vec3,
         _scattered: &mut Ray,
-        _pdf: &mut f32,
+        _pdf: &mut Real,
     ) -> bool {
         false
     }
 
+    /// True for mirror-like materials whose scattered direction is the only one they send light
+    /// along, such as smooth metals and glass. Their `scatter` ray is followed as it is, with
+    /// the attenuation as its weight, and no light is sampled at their hits.
+    fn is_specular(&self) -> bool {
+        false
+    }
+
+    /// Density with which `scatter` picks `direction`, for materials that report a `pdf`.
+    fn sampling_pdf(&self, _record: &HitRecord, _direction: &Vec3) -> Real {
+        0.0
+    }
+
     fn emitted(&self, _ray_in: &Ray, _record: &HitRecord, _uv: &Vec2, _position: &Vec3) -> Vec3 {
         Vec3::zero()
     }
+
+    fn is_emissive(&self) -> bool {
+        false
+    }
+
+    /// Normal used for shading the hit, e.g. after bump mapping. Defaults to the geometric one.
+    fn shading_normal(&self, record: &HitRecord) -> Vec3 {
+        record.normal
+    }
+
+    /// Scene-file description of the material, or `None` if it cannot be exported.
+    #[cfg(feature = "serde")]
+    fn describe(&self) -> Option<MaterialDesc> {
+        None
+    }
+
     fn as_any(&self) -> &dyn Any;
 }
 
@@ -38,58 +72,381 @@ impl PartialEq for dyn Material {
     }
 }
 
+/// Name of the concrete type behind `material`, for debugging output.
+pub fn material_name(material: &dyn Material) -> &'static str {
+    let any = material.as_any();
+    if any.is::<Lambertian>() {
+        "Lambertian"
+    } else if any.is::<Metal>() {
+        "Metal"
+    } else if any.is::<Conductor>() {
+        "Conductor"
+    } else if any.is::<Dielectric>() {
+        "Dielectric"
+    } else if any.is::<DiffuseLight>() {
+        "DiffuseLight"
+    } else if any.is::<Spotlight>() {
+        "Spotlight"
+    } else if any.is::<Isotropic>() {
+        "Isotropic"
+    } else if any.is::<Normals>() {
+        "Normals"
+    } else {
+        "unknown"
+    }
+}
+
+/// How `Lambertian` picks scattered directions. Both are unbiased; cosine sampling matches
+/// the `cos θ / π` integrand exactly, so it has less variance.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum DiffuseSampling {
+    /// pdf `cos θ / π`.
+    #[default]
+    Cosine,
+    /// pdf `1 / (2π)`.
+    Uniform,
+}
+
+impl DiffuseSampling {
+    /// Density of `direction` around the unit `normal`.
+    pub fn pdf(&self, normal: &Vec3, direction: &Vec3) -> Real {
+        let cosine = dot(normal, &direction.normalize());
+        if cosine <= 0.0 {
+            return 0.0;
+        }
+        match self {
+            DiffuseSampling::Cosine => cosine / crate::math::consts::PI,
+            DiffuseSampling::Uniform => 1.0 / (2.0 * crate::math::consts::PI),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Lambertian {
     pub albedo: Box<dyn TextureSampler>,
+    /// Grayscale height field (red channel, world units) that perturbs the shading normal.
+    pub bump_map: Option<Box<dyn TextureSampler>>,
+    pub sampling: DiffuseSampling,
+}
+
+impl Lambertian {
+    /// Unbumped diffuse surface with cosine-weighted sampling.
+    pub fn new(albedo: Box<dyn TextureSampler>) -> Self {
+        Self {
+            albedo,
+            bump_map: None,
+            sampling: DiffuseSampling::default(),
+        }
+    }
+
+    pub fn with_bump_map(mut self, bump_map: Box<dyn TextureSampler>) -> Self {
+        self.bump_map = Some(bump_map);
+        self
+    }
+}
+
+/// UV step used to take finite differences of a bump map.
+const BUMP_DELTA: Real = 0.0005;
+
+/// Perturbs the hit normal by the gradient of `height` along the surface parametrization.
+/// Surfaces that do not provide `dpdu`/`dpdv` are left unchanged.
+pub fn bump_normal(height: &dyn TextureSampler, record: &HitRecord) -> Vec3 {
+    if record.dpdu.near_zero() || record.dpdv.near_zero() {
+        return record.normal;
+    }
+    let sample = |du: Real, dv: Real| {
+        let uv = Vec2::new(record.uv.x + du, record.uv.y + dv);
+        let p = record.position + du * record.dpdu + dv * record.dpdv;
+        height.value(&uv, &p).x
+    };
+    let h = sample(0.0, 0.0);
+    let dhdu = (sample(BUMP_DELTA, 0.0) - h) / BUMP_DELTA;
+    let dhdv = (sample(0.0, BUMP_DELTA) - h) / BUMP_DELTA;
+    let dpdu = record.dpdu + dhdu * record.normal;
+    let dpdv = record.dpdv + dhdv * record.normal;
+    let bumped = cross(&dpdu, &dpdv).normalize();
+    if dot(&bumped, &record.normal) < 0.0 {
+        -bumped
+    } else {
+        bumped
+    }
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Metal {
     pub albedo: Vec3,
-    pub fuzz: f32,
+    pub fuzz: Real,
 }
 
+/// Mirror-like metal whose tint follows the conductor Fresnel equations, so it brightens
+/// towards white at grazing angles. `eta` and `k` are the real and imaginary parts of the
+/// complex index of refraction per RGB channel.
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Conductor {
+    pub eta: Vec3,
+    pub k: Vec3,
+}
+
+impl Conductor {
+    pub fn gold() -> Self {
+        Self {
+            eta: Vec3::new(0.143, 0.374, 1.442),
+            k: Vec3::new(3.983, 2.385, 1.603),
+        }
+    }
+
+    pub fn copper() -> Self {
+        Self {
+            eta: Vec3::new(0.200, 0.924, 1.102),
+            k: Vec3::new(3.912, 2.452, 2.142),
+        }
+    }
+
+    pub fn aluminum() -> Self {
+        Self {
+            eta: Vec3::new(1.657, 0.880, 0.521),
+            k: Vec3::new(9.224, 6.270, 4.837),
+        }
+    }
+
+    /// Unpolarized reflectance per channel for light arriving at `cos_theta` to the normal.
+    pub fn reflectance(&self, cos_theta: Real) -> Vec3 {
+        Vec3::new(
+            fresnel_conductor(cos_theta, self.eta.x, self.k.x),
+            fresnel_conductor(cos_theta, self.eta.y, self.k.y),
+            fresnel_conductor(cos_theta, self.eta.z, self.k.z),
+        )
+    }
+}
+
+/// Exact Fresnel reflectance of a conductor with complex index `eta + ik`, averaging the s and
+/// p polarizations (see PBR, 3rd edition, section 8.2.1).
+fn fresnel_conductor(cos_theta: Real, eta: Real, k: Real) -> Real {
+    let cos2 = cos_theta.clamp(0.0, 1.0).powi(2);
+    let sin2 = 1.0 - cos2;
+    let (eta2, k2) = (eta * eta, k * k);
+    let t0 = eta2 - k2 - sin2;
+    let a2_plus_b2 = Real::sqrt(t0 * t0 + 4.0 * eta2 * k2);
+    let a = Real::sqrt(0.5 * (a2_plus_b2 + t0));
+    let t1 = a2_plus_b2 + cos2;
+    let t2 = 2.0 * cos2.sqrt() * a;
+    let rs = (t1 - t2) / (t1 + t2);
+    let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let rp = rs * (t3 - t4) / (t3 + t4);
+    0.5 * (rs + rp)
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Dielectric {
-    pub refraction_index: f32,
+    pub refraction_index: Real,
 }
 
 impl Dielectric {
-    pub fn refract(uv: &Vec3, normal: &Vec3, etai_over_etat: f32) -> Vec3 {
-        let cos_theta = f32::min(dot(&-uv, normal), 1.0);
+    pub fn refract(uv: &Vec3, normal: &Vec3, etai_over_etat: Real) -> Vec3 {
+        let cos_theta = Real::min(dot(&-uv, normal), 1.0);
         let r_out_perp = etai_over_etat * (*uv + (cos_theta * normal));
-        let r_out_parallel = -f32::sqrt(f32::abs(1.0 - r_out_perp.length_squared())) * *normal;
+        let r_out_parallel =
+            -Real::sqrt(Real::max(0.0, 1.0 - r_out_perp.length_squared())) * *normal;
         r_out_perp + r_out_parallel
     }
 
-    pub fn reflectance(cosine: f32, ri: f32) -> f32 {
+    /// Schlick's approximation of the reflected fraction for incidence `cosine` and index
+    /// ratio `ri`. Schlick needs the angle on the optically thinner side, so rays leaving the
+    /// denser medium use the transmitted angle; that makes the reflectance reach 1 at the
+    /// critical angle instead of jumping there, and it is 1 beyond it.
+    pub fn reflectance(cosine: Real, ri: Real) -> Real {
+        let cosine = if ri > 1.0 {
+            let cos_transmitted_sq = 1.0 - ri * ri * (1.0 - cosine * cosine);
+            if cos_transmitted_sq <= 0.0 {
+                return 1.0;
+            }
+            cos_transmitted_sq.sqrt()
+        } else {
+            cosine
+        };
         let mut r0 = (1.0 - ri) / (1.0 + ri);
         r0 = r0 * r0;
-        r0 + (1.0 - r0) * f32::powi(1.0 - cosine, 5)
+        r0 + (1.0 - r0) * Real::powi(1.0 - cosine, 5)
     }
 }
 
 #[derive(Debug)]
 pub struct DiffuseLight {
     pub emit: Box<dyn TextureSampler>,
+    /// Scales `emit`, so brightness can be tuned without touching the color.
+    pub intensity: Real,
 }
 
+impl DiffuseLight {
+    pub fn new(emit: Box<dyn TextureSampler>) -> Self {
+        Self {
+            emit,
+            intensity: 1.0,
+        }
+    }
+
+    pub fn with_intensity(mut self, intensity: Real) -> Self {
+        self.intensity = intensity;
+        self
+    }
+}
+
+/// Area light that only shines within a cone around `direction`. Emission is full inside
+/// `inner_angle` and fades smoothly to zero at `outer_angle`, both in degrees from the axis.
+#[derive(Debug)]
+pub struct Spotlight {
+    pub emit: Box<dyn TextureSampler>,
+    pub direction: Vec3,
+    pub inner_angle: Real,
+    pub outer_angle: Real,
+}
+
+impl Spotlight {
+    /// Fraction of the emission sent along `outgoing`, the direction from light to receiver.
+    pub fn falloff(&self, outgoing: &Vec3) -> Real {
+        let cos_angle = dot(&outgoing.normalize(), &self.direction.normalize());
+        let cos_inner = self.inner_angle.to_radians().cos();
+        let cos_outer = self.outer_angle.to_radians().cos();
+        if cos_angle >= cos_inner {
+            return 1.0;
+        }
+        if cos_angle <= cos_outer {
+            return 0.0;
+        }
+        let t = (cos_angle - cos_outer) / (cos_inner - cos_outer);
+        t * t * (3.0 - 2.0 * t)
+    }
+}
+
+/// Phase function of participating media. Volume hits have no surface parametrization, so
+/// `albedo` is looked up at the scattering point, e.g. a noise texture to tint smoke.
 #[derive(Debug)]
 pub struct Isotropic {
     pub albedo: Box<dyn TextureSampler>,
 }
 
+/// Debug material that shows the shading normal as a color and never scatters.
+#[derive(Debug)]
+pub struct Normals;
+
+/// Serializable stand-in for an `Arc<dyn Material>`.
+#[cfg(feature = "serde")]
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MaterialDesc {
+    Lambertian {
+        albedo: TextureDesc,
+        #[serde(default)]
+        bump_map: Option<TextureDesc>,
+        #[serde(default)]
+        sampling: DiffuseSampling,
+    },
+    Metal {
+        #[serde(deserialize_with = "crate::color::deserialize_color")]
+        albedo: Vec3,
+        fuzz: Real,
+    },
+    Conductor {
+        eta: Vec3,
+        k: Vec3,
+    },
+    Dielectric {
+        refraction_index: Real,
+    },
+    DiffuseLight {
+        emit: TextureDesc,
+        #[serde(default = "default_intensity")]
+        intensity: Real,
+    },
+    Spotlight {
+        emit: TextureDesc,
+        direction: Vec3,
+        inner_angle: Real,
+        outer_angle: Real,
+    },
+    Isotropic {
+        albedo: TextureDesc,
+    },
+}
+
+#[cfg(feature = "serde")]
+fn default_intensity() -> Real {
+    1.0
+}
+
+#[cfg(feature = "serde")]
+impl MaterialDesc {
+    pub fn is_emissive(&self) -> bool {
+        matches!(
+            self,
+            MaterialDesc::DiffuseLight { .. } | MaterialDesc::Spotlight { .. }
+        )
+    }
+
+    pub fn build(&self) -> Arc<dyn Material> {
+        match self {
+            MaterialDesc::Lambertian {
+                albedo,
+                bump_map,
+                sampling,
+            } => Arc::new(Lambertian {
+                albedo: albedo.build(),
+                bump_map: bump_map.as_ref().map(TextureDesc::build_data),
+                sampling: *sampling,
+            }),
+            MaterialDesc::Metal { albedo, fuzz } => Arc::new(Metal {
+                albedo: *albedo,
+                fuzz: *fuzz,
+            }),
+            MaterialDesc::Conductor { eta, k } => Arc::new(Conductor { eta: *eta, k: *k }),
+            MaterialDesc::Dielectric { refraction_index } => Arc::new(Dielectric {
+                refraction_index: *refraction_index,
+            }),
+            MaterialDesc::DiffuseLight { emit, intensity } => {
+                Arc::new(DiffuseLight::new(emit.build()).with_intensity(*intensity))
+            }
+            MaterialDesc::Spotlight {
+                emit,
+                direction,
+                inner_angle,
+                outer_angle,
+            } => Arc::new(Spotlight {
+                emit: emit.build(),
+                direction: *direction,
+                inner_angle: *inner_angle,
+                outer_angle: *outer_angle,
+            }),
+            MaterialDesc::Isotropic { albedo } => Arc::new(Isotropic {
+                albedo: albedo.build(),
+            }),
+        }
+    }
+}
+
 impl Material for Lambertian {
-    fn scatter_pdf(&self, _ray_in: &Ray, _record: &HitRecord, _ray_scattered: &Ray) -> f32 {
-        /*
-        let cos_theta = dot(&record.normal, &ray_scattered.direction);
-        if cos_theta < 0.0 {
-            0.0
-        } else {
-            cos_theta / f32::consts::PI
+    /// The `cos θ / π` of the Lambertian BRDF times the cosine, whatever the sampling.
+    fn scatter_pdf(&self, _ray_in: &Ray, record: &HitRecord, ray_scattered: &Ray) -> Real {
+        DiffuseSampling::Cosine.pdf(&record.normal, &ray_scattered.direction())
+    }
+
+    fn sampling_pdf(&self, record: &HitRecord, direction: &Vec3) -> Real {
+        self.sampling.pdf(&record.normal, direction)
+    }
+
+    fn shading_normal(&self, record: &HitRecord) -> Vec3 {
+        match &self.bump_map {
+            Some(bump_map) => bump_normal(bump_map.as_ref(), record),
+            None => record.normal,
         }
-         */
-        1.0 / (2.0 * std::f32::consts::PI)
     }
 
     fn scatter(
@@ -98,41 +455,100 @@ impl Material for Lambertian {
         hit_record: &HitRecord,
         attenuation: &mut Vec3,
         scattered: &mut Ray,
-        pdf: &mut f32,
+        pdf: &mut Real,
     ) -> bool {
-        let uwv = Mat3::get_orthonormal_basis(&hit_record.normal);
-        let scatter_direction = dot_v3(
-            &uwv.transpose(),
-            &Vec3::random_cosine_hemisphere_direction(),
-        );
+        let scatter_direction = match self.sampling {
+            DiffuseSampling::Cosine => {
+                let uvw = Mat3::get_orthonormal_basis(&hit_record.normal);
+                uvw.transpose_mul(&Vec3::random_cosine_direction())
+            }
+            DiffuseSampling::Uniform => Vec3::random_on_hemisphere(&hit_record.normal),
+        };
         *scattered = Ray::new(hit_record.position, scatter_direction.normalize());
-        *attenuation = self
-            .albedo
-            .as_ref()
-            .value(&hit_record.uv, &hit_record.position);
-        *pdf = dot(&uwv[2], &scattered.direction) / f32::consts::PI;
+        *attenuation = self.albedo.value_ctx(hit_record);
+        *pdf = self
+            .sampling
+            .pdf(&hit_record.normal, &scattered.direction());
         true
     }
 
+    #[cfg(feature = "serde")]
+    fn describe(&self) -> Option<MaterialDesc> {
+        let bump_map = match &self.bump_map {
+            Some(bump_map) => Some(bump_map.describe()?),
+            None => None,
+        };
+        Some(MaterialDesc::Lambertian {
+            albedo: self.albedo.describe()?,
+            bump_map,
+            sampling: self.sampling,
+        })
+    }
+
     fn as_any(&self) -> &dyn Any {
         self
     }
 }
 
 impl Material for Metal {
+    fn is_specular(&self) -> bool {
+        true
+    }
+
     fn scatter(
         &self,
         ray: &Ray,
         hit_record: &HitRecord,
         attenuation: &mut Vec3,
         scattered: &mut Ray,
-        _pdf: &mut f32,
+        _pdf: &mut Real,
     ) -> bool {
-        let reflected = reflect(&ray.direction, &hit_record.normal).normalize()
+        let reflected = reflect(&ray.direction(), &hit_record.normal).normalize()
             + (self.fuzz * Vec3::random_unit());
         *scattered = Ray::new(hit_record.position, reflected);
         *attenuation = self.albedo;
-        dot(&scattered.direction, &hit_record.normal) > 0.0
+        dot(&scattered.direction(), &hit_record.normal) > 0.0
+    }
+
+    #[cfg(feature = "serde")]
+    fn describe(&self) -> Option<MaterialDesc> {
+        Some(MaterialDesc::Metal {
+            albedo: self.albedo,
+            fuzz: self.fuzz,
+        })
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl Material for Conductor {
+    fn is_specular(&self) -> bool {
+        true
+    }
+
+    fn scatter(
+        &self,
+        ray: &Ray,
+        hit_record: &HitRecord,
+        attenuation: &mut Vec3,
+        scattered: &mut Ray,
+        _pdf: &mut Real,
+    ) -> bool {
+        let direction = ray.direction().normalize();
+        let cos_theta = dot(&-direction, &hit_record.normal);
+        *scattered = Ray::new(hit_record.position, reflect(&direction, &hit_record.normal));
+        *attenuation = self.reflectance(cos_theta);
+        true
+    }
+
+    #[cfg(feature = "serde")]
+    fn describe(&self) -> Option<MaterialDesc> {
+        Some(MaterialDesc::Conductor {
+            eta: self.eta,
+            k: self.k,
+        })
     }
 
     fn as_any(&self) -> &dyn Any {
@@ -141,13 +557,17 @@ impl Material for Metal {
 }
 
 impl Material for Dielectric {
+    fn is_specular(&self) -> bool {
+        true
+    }
+
     fn scatter(
         &self,
         ray: &Ray,
         hit_record: &HitRecord,
         attenuation: &mut Vec3,
         scattered: &mut Ray,
-        _pdf: &mut f32,
+        _pdf: &mut Real,
     ) -> bool {
         *attenuation = Vec3::one();
         let ri = if hit_record.front_face {
@@ -155,32 +575,80 @@ impl Material for Dielectric {
         } else {
             self.refraction_index
         };
-        let direction = ray.direction.normalize();
-        let cos_theta = f32::min(dot(&-direction, &hit_record.normal), 1.0);
-        let sin_theta = f32::sqrt(1.0 - cos_theta * cos_theta);
-        let cannot_refract = (ri * sin_theta) > 1.0;
-        let ref_direction =
-            if cannot_refract || (Dielectric::reflectance(cos_theta, ri) > rand_f32()) {
-                reflect(&direction, &hit_record.normal)
-            } else {
-                Dielectric::refract(&direction, &hit_record.normal, ri)
-            };
+        let direction = ray.direction().normalize();
+        // `normal` faces the incoming ray on both sides, so `ri` and the angle always agree
+        let cos_theta = dot(&-direction, &hit_record.normal).clamp(0.0, 1.0);
+        let ref_direction = if Dielectric::reflectance(cos_theta, ri) > rand_f32() {
+            reflect(&direction, &hit_record.normal)
+        } else {
+            Dielectric::refract(&direction, &hit_record.normal, ri)
+        };
 
         *scattered = Ray::new(hit_record.position, ref_direction);
         true
     }
 
+    #[cfg(feature = "serde")]
+    fn describe(&self) -> Option<MaterialDesc> {
+        Some(MaterialDesc::Dielectric {
+            refraction_index: self.refraction_index,
+        })
+    }
+
     fn as_any(&self) -> &dyn Any {
         self
     }
 }
 
 impl Material for DiffuseLight {
-    fn emitted(&self, _ray_in: &Ray, record: &HitRecord, uv: &Vec2, position: &Vec3) -> Vec3 {
+    fn emitted(&self, _ray_in: &Ray, record: &HitRecord, _uv: &Vec2, _position: &Vec3) -> Vec3 {
         if !record.front_face {
             return Vec3::zero();
         }
-        self.emit.as_ref().value(uv, position)
+        self.intensity * self.emit.value_ctx(record)
+    }
+
+    fn is_emissive(&self) -> bool {
+        true
+    }
+
+    #[cfg(feature = "serde")]
+    fn describe(&self) -> Option<MaterialDesc> {
+        Some(MaterialDesc::DiffuseLight {
+            emit: self.emit.describe()?,
+            intensity: self.intensity,
+        })
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl Material for Spotlight {
+    fn emitted(&self, ray_in: &Ray, record: &HitRecord, _uv: &Vec2, _position: &Vec3) -> Vec3 {
+        if !record.front_face {
+            return Vec3::zero();
+        }
+        let falloff = self.falloff(&-ray_in.direction());
+        if falloff == 0.0 {
+            return Vec3::zero();
+        }
+        falloff * self.emit.value_ctx(record)
+    }
+
+    fn is_emissive(&self) -> bool {
+        true
+    }
+
+    #[cfg(feature = "serde")]
+    fn describe(&self) -> Option<MaterialDesc> {
+        Some(MaterialDesc::Spotlight {
+            emit: self.emit.describe()?,
+            direction: self.direction,
+            inner_angle: self.inner_angle,
+            outer_angle: self.outer_angle,
+        })
     }
 
     fn as_any(&self) -> &dyn Any {
@@ -189,8 +657,8 @@ impl Material for DiffuseLight {
 }
 
 impl Material for Isotropic {
-    fn scatter_pdf(&self, _ray_in: &Ray, _record: &HitRecord, _ray_scattered: &Ray) -> f32 {
-        1.0 / (4.0 * f32::consts::PI)
+    fn scatter_pdf(&self, _ray_in: &Ray, _record: &HitRecord, _ray_scattered: &Ray) -> Real {
+        1.0 / (4.0 * crate::math::consts::PI)
     }
 
     fn scatter(
@@ -199,18 +667,309 @@ impl Material for Isotropic {
         hit_record: &HitRecord,
         attenuation: &mut Vec3,
         scattered: &mut Ray,
-        pdf: &mut f32,
+        pdf: &mut Real,
     ) -> bool {
         *scattered = Ray::new(hit_record.position, Vec3::random_unit());
-        *attenuation = self
-            .albedo
-            .as_ref()
-            .value(&hit_record.uv, &hit_record.position);
-        *pdf = 1.0 / (4.0 * f32::consts::PI);
+        *attenuation = self.albedo.value_ctx(hit_record);
+        *pdf = 1.0 / (4.0 * crate::math::consts::PI);
         true
     }
 
+    fn sampling_pdf(&self, _record: &HitRecord, _direction: &Vec3) -> Real {
+        1.0 / (4.0 * crate::math::consts::PI)
+    }
+
+    #[cfg(feature = "serde")]
+    fn describe(&self) -> Option<MaterialDesc> {
+        Some(MaterialDesc::Isotropic {
+            albedo: self.albedo.describe()?,
+        })
+    }
+
     fn as_any(&self) -> &dyn Any {
         self
     }
 }
+
+impl Material for Normals {
+    fn emitted(&self, _ray_in: &Ray, record: &HitRecord, _uv: &Vec2, _position: &Vec3) -> Vec3 {
+        0.5 * (record.normal + Vec3::one())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::rand::seed_sample_rng;
+    use crate::texture::{Texture, TriplanarTexture};
+
+    #[test]
+    fn test_conductor_reflectance_approaches_one_at_grazing_incidence() {
+        for conductor in [
+            Conductor::gold(),
+            Conductor::copper(),
+            Conductor::aluminum(),
+        ] {
+            let normal = conductor.reflectance(1.0);
+            let grazing = conductor.reflectance(0.001);
+            for (normal, grazing) in [
+                (normal.x, grazing.x),
+                (normal.y, grazing.y),
+                (normal.z, grazing.z),
+            ] {
+                assert!((0.0..1.0).contains(&normal));
+                assert!(grazing > normal && grazing > 0.99, "{}", grazing);
+            }
+        }
+        // Gold and copper reflect red more strongly than blue head-on
+        let gold = Conductor::gold().reflectance(1.0);
+        assert!(gold.x > gold.z);
+        let copper = Conductor::copper().reflectance(1.0);
+        assert!(copper.x > copper.z);
+    }
+
+    #[test]
+    fn test_conductor_normal_incidence_matches_closed_form() {
+        let (eta, k): (Real, Real) = (0.2, 3.9);
+        let expected = ((eta - 1.0).powi(2) + k * k) / ((eta + 1.0).powi(2) + k * k);
+        assert!((fresnel_conductor(1.0, eta, k) - expected).abs() < 1e-5);
+    }
+
+    /// Scatters a ray arriving at `degrees` from the normal of a horizontal glass surface,
+    /// either from above (`entering`) or from inside the glass below it.
+    fn scatter_glass(glass: &Dielectric, degrees: Real, entering: bool, draws: u32) -> Vec<Vec3> {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let direction = if entering {
+            Vec3::new(sin, -cos, 0.0)
+        } else {
+            Vec3::new(sin, cos, 0.0)
+        };
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 0.0) - direction, direction);
+        let mut record = HitRecord::new();
+        record.set_face_normal(&ray, &Vec3::new(0.0, 1.0, 0.0));
+        assert_eq!(record.front_face, entering);
+        (0..draws)
+            .map(|i| {
+                seed_sample_rng(3, degrees as u32, entering as u32, i);
+                let mut attenuation = Vec3::zero();
+                let mut scattered = Ray::default();
+                assert!(glass.scatter(&ray, &record, &mut attenuation, &mut scattered, &mut 0.0));
+                scattered.direction()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_dielectric_obeys_snell_and_total_internal_reflection() {
+        let glass = Dielectric {
+            refraction_index: 1.5,
+        };
+        let critical = Real::asin(1.0 / 1.5).to_degrees();
+        for entering in [true, false] {
+            let (n_in, n_out) = if entering { (1.0, 1.5) } else { (1.5, 1.0) };
+            for degrees in (1..90).step_by(4).map(|d| d as Real) {
+                let sin_in = degrees.to_radians().sin();
+                let mut refracted = 0;
+                for out in scatter_glass(&glass, degrees, entering, 64) {
+                    assert!((out.length() - 1.0).abs() < 1e-4);
+                    // Both branches keep the tangential direction
+                    assert!(out.x > 0.0 && out.z.abs() < 1e-6);
+                    let crosses = if entering { out.y < 0.0 } else { out.y > 0.0 };
+                    if crosses {
+                        refracted += 1;
+                        assert!((n_out * out.x - n_in * sin_in).abs() < 1e-4);
+                    } else {
+                        assert!((out.x - sin_in).abs() < 1e-4);
+                    }
+                }
+                if !entering && degrees > critical {
+                    assert_eq!(refracted, 0, "refraction past the critical angle");
+                } else if degrees < 60.0 {
+                    assert!(
+                        refracted > 32,
+                        "{} of 64 refracted at {}",
+                        refracted,
+                        degrees
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_dielectric_reflectance_is_continuous_at_the_critical_angle() {
+        let critical = Real::asin(1.0 / 1.5);
+        let just_below = Dielectric::reflectance(Real::cos(critical - 1e-5), 1.5);
+        assert!(
+            just_below > 0.9,
+            "reflectance {} just below the critical angle",
+            just_below
+        );
+        assert_eq!(
+            Dielectric::reflectance(Real::cos(critical + 1e-3), 1.5),
+            1.0
+        );
+        // Entering glass head-on reflects the usual 4%
+        assert!((Dielectric::reflectance(1.0, 1.0 / 1.5) - 0.04).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_diffuse_light_intensity_scales_emission() {
+        let color = Vec3::new(1.0, 0.5, 0.25);
+        let light = DiffuseLight::new(Box::new(Texture::new(color)));
+        let brighter = DiffuseLight::new(Box::new(Texture::new(color))).with_intensity(2.0);
+        let mut record = HitRecord::new();
+        record.front_face = true;
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let uv = Vec2::new(0.5, 0.5);
+
+        let base = light.emitted(&ray, &record, &uv, &Vec3::zero());
+        assert_eq!(base, color);
+        assert_eq!(
+            brighter.emitted(&ray, &record, &uv, &Vec3::zero()),
+            2.0 * base
+        );
+    }
+
+    #[test]
+    fn test_emitters_and_media_sample_textures_with_the_hit_normal() {
+        let red = Vec3::new(1.0, 0.0, 0.0);
+        let triplanar = || -> Box<dyn TextureSampler> {
+            Box::new(TriplanarTexture::new(
+                Box::new(Texture::new(red)),
+                Box::new(Texture::new(Vec3::new(0.0, 1.0, 0.0))),
+                Box::new(Texture::new(Vec3::new(0.0, 0.0, 1.0))),
+                1.0,
+            ))
+        };
+        let mut record = HitRecord::new();
+        record.front_face = true;
+        record.normal = Vec3::new(1.0, 0.0, 0.0);
+        let ray = Ray::new(Vec3::new(1.0, 0.0, 0.0), Vec3::new(-1.0, 0.0, 0.0));
+        let uv = Vec2::zero();
+
+        let light = DiffuseLight::new(triplanar());
+        assert_eq!(light.emitted(&ray, &record, &uv, &Vec3::zero()), red);
+        let spotlight = Spotlight {
+            emit: triplanar(),
+            direction: Vec3::new(1.0, 0.0, 0.0),
+            inner_angle: 20.0,
+            outer_angle: 40.0,
+        };
+        assert_eq!(spotlight.emitted(&ray, &record, &uv, &Vec3::zero()), red);
+        let smoke = Isotropic {
+            albedo: triplanar(),
+        };
+        let mut attenuation = Vec3::zero();
+        let mut scattered = Ray::new(Vec3::zero(), Vec3::new(0.0, 1.0, 0.0));
+        let mut pdf = 0.0;
+        assert!(smoke.scatter(&ray, &record, &mut attenuation, &mut scattered, &mut pdf));
+        assert_eq!(attenuation, red);
+    }
+
+    #[test]
+    fn test_spotlight_falls_off_between_inner_and_outer_cones() {
+        let spotlight = Spotlight {
+            emit: Box::new(Texture::new(Vec3::one() * 4.0)),
+            direction: Vec3::new(0.0, -1.0, 0.0),
+            inner_angle: 20.0,
+            outer_angle: 40.0,
+        };
+        let mut record = HitRecord::new();
+        record.front_face = true;
+        let uv = Vec2::new(0.5, 0.5);
+        // Rays are traced from the receiver, so they travel against the emitted direction
+        let emitted_at = |degrees: Real| {
+            let angle = degrees.to_radians();
+            let outgoing = Vec3::new(angle.sin(), -angle.cos(), 0.0);
+            let ray = Ray::new(outgoing, -outgoing);
+            spotlight.emitted(&ray, &record, &uv, &Vec3::zero()).x
+        };
+
+        assert_eq!(emitted_at(0.0), 4.0);
+        assert_eq!(emitted_at(19.0), 4.0);
+        assert_eq!(emitted_at(41.0), 0.0);
+        assert_eq!(emitted_at(90.0), 0.0);
+        let mut previous = 4.0;
+        for degrees in [22.0, 26.0, 30.0, 34.0, 38.0] {
+            let emitted = emitted_at(degrees);
+            assert!(
+                emitted > 0.0 && emitted < previous,
+                "{} at {} degrees",
+                emitted,
+                degrees
+            );
+            previous = emitted;
+        }
+    }
+
+    /// Mean and variance of the one-bounce furnace estimate `attenuation * scatter_pdf / pdf`
+    /// of a Lambertian under a white sky.
+    fn diffuse_furnace(sampling: DiffuseSampling) -> (Real, Real) {
+        const SAMPLE_COUNT: u32 = 20000;
+        let lambertian = Lambertian {
+            sampling,
+            ..Lambertian::new(Box::new(Texture::new(Vec3::one() * 0.5)))
+        };
+        let mut record = HitRecord::new();
+        record.normal = Vec3::new(0.3, 0.9, -0.2).normalize();
+        let ray = Ray::new(Vec3::new(0.0, 1.0, 0.0), -record.normal);
+        let estimates: Vec<Real> = (0..SAMPLE_COUNT)
+            .map(|i| {
+                seed_sample_rng(21, i, 0, 0);
+                let (mut attenuation, mut scattered, mut pdf) = (Vec3::zero(), Ray::default(), 0.0);
+                assert!(lambertian.scatter(
+                    &ray,
+                    &record,
+                    &mut attenuation,
+                    &mut scattered,
+                    &mut pdf
+                ));
+                assert!(dot(&scattered.direction(), &record.normal) >= 0.0);
+                assert!(
+                    (pdf - lambertian.sampling_pdf(&record, &scattered.direction())).abs() < 1e-6
+                );
+                attenuation.x * lambertian.scatter_pdf(&ray, &record, &scattered) / pdf
+            })
+            .collect();
+        let mean = estimates.iter().sum::<Real>() / SAMPLE_COUNT as Real;
+        let variance = estimates
+            .iter()
+            .map(|e| (e - mean) * (e - mean))
+            .sum::<Real>()
+            / SAMPLE_COUNT as Real;
+        (mean, variance)
+    }
+
+    #[test]
+    fn test_diffuse_sampling_modes_both_converge_to_the_albedo() {
+        let (cosine_mean, cosine_variance) = diffuse_furnace(DiffuseSampling::Cosine);
+        let (uniform_mean, uniform_variance) = diffuse_furnace(DiffuseSampling::Uniform);
+        assert!(
+            (cosine_mean - 0.5).abs() < 1e-4,
+            "cosine mean {}",
+            cosine_mean
+        );
+        // Uniform sampling has variance albedo² / 3, a standard error of about 0.002 here
+        assert!(
+            (uniform_mean - 0.5).abs() < 0.01,
+            "uniform mean {}",
+            uniform_mean
+        );
+        // The cosine pdf matches the integrand exactly, so its estimate never varies
+        assert!(
+            cosine_variance < 1e-6,
+            "cosine variance {}",
+            cosine_variance
+        );
+        assert!(
+            uniform_variance > 0.01,
+            "uniform variance {}",
+            uniform_variance
+        );
+    }
+}
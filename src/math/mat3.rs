@@ -1,16 +1,19 @@
-use std::ops::{Index, IndexMut};
+use std::ops::{Index, IndexMut, Mul};
 
-use crate::math::vec3::{cross, Vec3};
+use crate::math::vec3::{cross, dot as dot_vec, Vec3};
+use crate::math::Real;
 
+/// Row-major 3x3 matrix acting on column vectors: `m * v` dots each row with `v`, and
+/// `a * b` applies `b` first. `m[i]` is row `i`.
 #[derive(Debug, Clone, Copy)]
 pub struct Mat3 {
-    pub columns: [Vec3; 3],
+    pub rows: [Vec3; 3],
 }
 
 impl Mat3 {
     pub fn identity() -> Self {
         Self {
-            columns: [
+            rows: [
                 Vec3::new(1.0, 0.0, 0.0),
                 Vec3::new(0.0, 1.0, 0.0),
                 Vec3::new(0.0, 0.0, 1.0),
@@ -20,7 +23,7 @@ impl Mat3 {
 
     pub fn zero() -> Self {
         Self {
-            columns: [
+            rows: [
                 Vec3::new(0.0, 0.0, 0.0),
                 Vec3::new(0.0, 0.0, 0.0),
                 Vec3::new(0.0, 0.0, 0.0),
@@ -28,7 +31,9 @@ impl Mat3 {
         }
     }
 
-    pub fn rotation(axis: Vec3, angle: f32) -> Self {
+    /// Counter-clockwise rotation by `angle` radians about `axis`, looking down the axis
+    /// (right-hand rule).
+    pub fn rotation(axis: Vec3, angle: Real) -> Self {
         let [x, y, z] = axis.into();
         let norm = (x * x + y * y + z * z).sqrt();
         let (x, y, z) = (x / norm, y / norm, z / norm);
@@ -38,7 +43,7 @@ impl Mat3 {
         let one_minus_cos = 1.0 - cos;
 
         Self {
-            columns: [
+            rows: [
                 Vec3::new(
                     cos + x * x * one_minus_cos,
                     x * y * one_minus_cos - z * sin,
@@ -58,6 +63,41 @@ impl Mat3 {
         }
     }
 
+    /// Axis and angle in radians, between 0 and π, of a rotation matrix: the inverse of
+    /// `rotation`. The identity reports the x axis and a zero angle.
+    pub fn axis_angle(&self) -> (Vec3, Real) {
+        let r = &self.rows;
+        let cos = ((r[0].x + r[1].y + r[2].z - 1.0) / 2.0).clamp(-1.0, 1.0);
+        let angle = cos.acos();
+        // The antisymmetric part is the axis scaled by 2 sin(angle)
+        let skew = Vec3::new(r[2].y - r[1].z, r[0].z - r[2].x, r[1].x - r[0].y);
+        if skew.length() > 1e-3 {
+            return (skew.normalize(), angle);
+        }
+        if cos > 0.0 {
+            return (Vec3::new(1.0, 0.0, 0.0), 0.0);
+        }
+        // Near a half turn the matrix plus the identity is twice the axis times its transpose,
+        // so its row with the largest diagonal entry lies along the axis
+        let i = (0..3)
+            .max_by(|&a, &b| r[a][a].partial_cmp(&r[b][b]).unwrap())
+            .unwrap();
+        let unit = [
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+        ];
+        let axis = (r[i] + unit[i]).normalize();
+        let sign = if dot_vec(&axis, &skew) < 0.0 {
+            -1.0
+        } else {
+            1.0
+        };
+        (sign * axis, angle)
+    }
+
+    /// Right-handed basis (u, v, w) with w along `vec`, stored as rows. The matrix maps world
+    /// directions into the basis; its transpose maps local directions back to world space.
     pub fn get_orthonormal_basis(vec: &Vec3) -> Self {
         let mut onb = Self::zero();
         onb[2] = vec.normalize();
@@ -67,16 +107,22 @@ impl Mat3 {
             Vec3::new(1.0, 0.0, 0.0)
         };
         onb[1] = cross(&onb[2], &a).normalize();
-        onb[0] = cross(&onb[2], &onb[1]).normalize();
+        onb[0] = cross(&onb[1], &onb[2]);
         onb
     }
 
+    /// `self.transpose() * vec` without building the transpose: the rows weighted by `vec`.
+    /// Maps local coordinates in an orthonormal basis back to world space.
+    pub fn transpose_mul(&self, vec: &Vec3) -> Vec3 {
+        self.rows[0] * vec.x + self.rows[1] * vec.y + self.rows[2] * vec.z
+    }
+
     pub fn transpose(&self) -> Self {
         Self {
-            columns: [
-                Vec3::new(self.columns[0].x, self.columns[1].x, self.columns[2].x),
-                Vec3::new(self.columns[0].y, self.columns[1].y, self.columns[2].y),
-                Vec3::new(self.columns[0].z, self.columns[1].z, self.columns[2].z),
+            rows: [
+                Vec3::new(self.rows[0].x, self.rows[1].x, self.rows[2].x),
+                Vec3::new(self.rows[0].y, self.rows[1].y, self.rows[2].y),
+                Vec3::new(self.rows[0].z, self.rows[1].z, self.rows[2].z),
             ],
         }
     }
@@ -86,32 +132,143 @@ impl Index<usize> for Mat3 {
     type Output = Vec3;
 
     fn index(&self, index: usize) -> &Self::Output {
-        &self.columns[index]
+        &self.rows[index]
     }
 }
 
 impl IndexMut<usize> for Mat3 {
     fn index_mut(&mut self, index: usize) -> &mut Self::Output {
-        &mut self.columns[index]
+        &mut self.rows[index]
     }
 }
 
+/// Matrix product `a * b`.
 pub fn dot(a: &Mat3, b: &Mat3) -> Mat3 {
     let mut result = Mat3::identity();
     for i in 0..3 {
         for j in 0..3 {
-            result.columns[i][j] = a.columns[i][0] * b.columns[0][j]
-                + a.columns[i][1] * b.columns[1][j]
-                + a.columns[i][2] * b.columns[2][j];
+            result.rows[i][j] = a.rows[i][0] * b.rows[0][j]
+                + a.rows[i][1] * b.rows[1][j]
+                + a.rows[i][2] * b.rows[2][j];
         }
     }
     result
 }
 
+/// Transforms `vec` by `mat`, i.e. `mat * vec`.
 pub fn dot_v3(mat: &Mat3, vec: &Vec3) -> Vec3 {
     Vec3::new(
-        mat.columns[0][0] * vec.x + mat.columns[0][1] * vec.y + mat.columns[0][2] * vec.z,
-        mat.columns[1][0] * vec.x + mat.columns[1][1] * vec.y + mat.columns[1][2] * vec.z,
-        mat.columns[2][0] * vec.x + mat.columns[2][1] * vec.y + mat.columns[2][2] * vec.z,
+        mat.rows[0][0] * vec.x + mat.rows[0][1] * vec.y + mat.rows[0][2] * vec.z,
+        mat.rows[1][0] * vec.x + mat.rows[1][1] * vec.y + mat.rows[1][2] * vec.z,
+        mat.rows[2][0] * vec.x + mat.rows[2][1] * vec.y + mat.rows[2][2] * vec.z,
     )
 }
+
+impl Mul<Vec3> for Mat3 {
+    type Output = Vec3;
+    fn mul(self, rhs: Vec3) -> Self::Output {
+        dot_v3(&self, &rhs)
+    }
+}
+
+impl Mul<Vec3> for &Mat3 {
+    type Output = Vec3;
+    fn mul(self, rhs: Vec3) -> Self::Output {
+        dot_v3(self, &rhs)
+    }
+}
+
+impl Mul<Mat3> for Mat3 {
+    type Output = Mat3;
+    fn mul(self, rhs: Mat3) -> Self::Output {
+        dot(&self, &rhs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assert_vec3_approx;
+
+    #[test]
+    fn test_operators_match_free_functions() {
+        let a = Mat3::rotation(Vec3::new(1.0, 2.0, -0.5), 0.7);
+        let b = Mat3::rotation(Vec3::new(-0.3, 0.1, 1.0), -1.9);
+        let v = Vec3::new(0.25, -4.0, 1.5);
+
+        assert_vec3_approx!(a * v, dot_v3(&a, &v), 1e-6);
+        let borrowed = &a;
+        assert_vec3_approx!(borrowed * v, dot_v3(&a, &v), 1e-6);
+        let product = a * b;
+        let expected = dot(&a, &b);
+        for i in 0..3 {
+            assert_vec3_approx!(product[i], expected[i], 1e-6);
+        }
+        assert_vec3_approx!(a * b * v, a * (b * v), 1e-6);
+    }
+
+    #[test]
+    fn test_rotation_follows_right_hand_rule() {
+        let quarter = Mat3::rotation(Vec3::new(0.0, 0.0, 1.0), crate::math::consts::FRAC_PI_2);
+        assert_vec3_approx!(
+            quarter * Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            1e-6
+        );
+        assert_vec3_approx!(
+            quarter * Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(-1.0, 0.0, 0.0),
+            1e-6
+        );
+        assert_vec3_approx!(
+            quarter * Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(0.0, 0.0, 1.0),
+            1e-6
+        );
+        // Rotations are orthogonal, so the transpose undoes them
+        assert_vec3_approx!(
+            quarter.transpose() * Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            1e-6
+        );
+    }
+
+    #[test]
+    fn test_orthonormal_basis_is_right_handed_around_the_normal() {
+        for normal in [
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(-0.3, 0.8, 0.2),
+        ] {
+            let onb = Mat3::get_orthonormal_basis(&normal);
+            let w = normal.normalize();
+            assert_vec3_approx!(cross(&onb[0], &onb[1]), onb[2], 1e-6);
+            assert_vec3_approx!(onb.transpose() * Vec3::new(0.0, 0.0, 1.0), w, 1e-6);
+            assert_vec3_approx!(onb * w, Vec3::new(0.0, 0.0, 1.0), 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_axis_angle_inverts_rotation() {
+        for (axis, angle) in [
+            (Vec3::new(0.0, 0.0, 1.0), 0.3),
+            (Vec3::new(1.0, -2.0, 0.5), 2.0),
+            (Vec3::new(-0.3, 0.1, 1.0), 3.1),
+            (Vec3::new(0.0, 1.0, 0.0), crate::math::consts::PI),
+        ] {
+            let rotation = Mat3::rotation(axis, angle);
+            let (found_axis, found_angle) = rotation.axis_angle();
+            assert!(
+                (found_angle - angle).abs() < 1e-3,
+                "{} vs {}",
+                found_angle,
+                angle
+            );
+            let rebuilt = Mat3::rotation(found_axis, found_angle);
+            for i in 0..3 {
+                assert_vec3_approx!(rebuilt[i], rotation[i], 1e-3);
+            }
+        }
+        assert_eq!(Mat3::identity().axis_angle().1, 0.0);
+    }
+}
@@ -1,4 +1,23 @@
 pub mod mat3;
 pub mod rand;
+pub mod transform;
 pub mod vec2;
 pub mod vec3;
+
+/// Floating-point type of the geometry and shading math. The `f64` feature switches it to
+/// double precision for scenes whose coordinates and epsilons are too far apart for `f32`.
+#[cfg(not(feature = "f64"))]
+pub type Real = f32;
+#[cfg(feature = "f64")]
+pub type Real = f64;
+
+#[cfg(not(feature = "f64"))]
+pub use std::f32::consts;
+#[cfg(feature = "f64")]
+pub use std::f64::consts;
+
+/// Narrows to the `f32` that image buffers store.
+#[allow(clippy::unnecessary_cast)]
+pub fn to_f32(value: Real) -> f32 {
+    value as f32
+}
@@ -1,14 +1,148 @@
+use std::cell::RefCell;
 
-use rand::Rng;
+use crate::math::{vec3::Vec3, Real};
 
-pub fn rand_f32() -> f32 {
-    rand::thread_rng().gen()
+/// PCG32 (XSH-RR variant). Unlike a cryptographic generator it is cheap enough to reseed for
+/// every sample, which lets each sample draw from its own stream.
+#[derive(Debug, Clone)]
+struct Pcg32 {
+    state: u64,
+    increment: u64,
 }
 
-pub fn rand_f32_range(min: f32, max: f32) -> f32 {
+impl Pcg32 {
+    const MULTIPLIER: u64 = 6364136223846793005;
+
+    fn new(seed: u64, stream: u64) -> Self {
+        let mut rng = Pcg32 {
+            state: 0,
+            increment: (stream << 1) | 1,
+        };
+        rng.next_u32();
+        rng.state = rng.state.wrapping_add(seed);
+        rng.next_u32();
+        rng
+    }
+
+    fn next_u32(&mut self) -> u32 {
+        let old = self.state;
+        self.state = old.wrapping_mul(Self::MULTIPLIER).wrapping_add(self.increment);
+        let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+        xorshifted.rotate_right((old >> 59) as u32)
+    }
+
+    fn next_f32(&mut self) -> Real {
+        // 24 random bits fill the mantissa exactly, so the result stays below 1
+        (self.next_u32() >> 8) as Real / (1u32 << 24) as Real
+    }
+}
+
+/// SplitMix64 finalizer, used to spread nearby seeds over the whole state space.
+fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+thread_local! {
+    static RNG: RefCell<Pcg32> = RefCell::new(Pcg32::new(rand::random(), rand::random()));
+}
+
+pub fn rand_f32() -> Real {
+    RNG.with(|rng| rng.borrow_mut().next_f32())
+}
+
+pub fn rand_f32_range(min: Real, max: Real) -> Real {
     min + (max-min)*rand_f32()
 }
 
 pub fn rand_i32_range(min: i32, max: i32) -> i32 {
-    rand_f32_range(min as f32, (max+1) as f32) as i32
+    rand_f32_range(min as Real, (max+1) as Real) as i32
+}
+
+/// Uniform point inside the unit disk in the xy-plane, by rejection sampling. The lens samples
+/// stratified points instead, so this only serves as their unstratified reference in tests.
+#[cfg(test)]
+pub fn rand_in_unit_disk() -> Vec3 {
+    loop {
+        let p = Vec3::new(rand_f32_range(-1.0, 1.0), rand_f32_range(-1.0, 1.0), 0.0);
+        if p.length_squared() < 1.0 {
+            return p;
+        }
+    }
+}
+
+/// Uniform point inside the unit sphere, by rejection sampling. Points too close to the origin
+/// to be normalized are rejected as well.
+pub fn rand_in_unit_sphere() -> Vec3 {
+    loop {
+        let p = Vec3::random_range(-1.0, 1.0);
+        let len_sq = p.length_squared();
+        if len_sq > Real::EPSILON && len_sq <= 1.0 {
+            return p;
+        }
+    }
+}
+
+/// Switches the calling thread's generator to the stream of one pixel sample. The draws depend
+/// only on the arguments, not on which thread traces the sample or what it traced before.
+pub fn seed_sample_rng(seed: u64, x: u32, y: u32, sample_index: u32) {
+    let (state, stream) = sample_stream(seed, x, y, sample_index);
+    RNG.with(|rng| *rng.borrow_mut() = Pcg32::new(state, stream));
+}
+
+/// Runs `f` on the camera stream of one pixel sample, the draws that place the sample's ray,
+/// then puts the calling thread's generator back where it was. The camera stream is separate
+/// from the one `seed_sample_rng` selects for tracing, so rays can be drawn ahead of time.
+pub fn with_camera_rng<T>(
+    seed: u64,
+    x: u32,
+    y: u32,
+    sample_index: u32,
+    f: impl FnOnce() -> T,
+) -> T {
+    let (state, stream) = sample_stream(seed, x, y, sample_index);
+    let camera = Pcg32::new(mix(state ^ CAMERA_STREAM), stream);
+    let saved = RNG.with(|rng| std::mem::replace(&mut *rng.borrow_mut(), camera));
+    let result = f();
+    RNG.with(|rng| *rng.borrow_mut() = saved);
+    result
+}
+
+/// Salt that sets the camera stream of a sample apart from its tracing stream.
+const CAMERA_STREAM: u64 = 0x4341_4D45_5241;
+
+fn sample_stream(seed: u64, x: u32, y: u32, sample_index: u32) -> (u64, u64) {
+    let pixel = ((y as u64) << 32) | x as u64;
+    let stream = mix(pixel) ^ sample_index as u64;
+    (mix(seed ^ mix(stream)), stream)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check_ball(sample: fn() -> Vec3) {
+        let count = 20000;
+        let mut sum = Vec3::zero();
+        for _ in 0..count {
+            let p = sample();
+            assert!(p.length_squared() <= 1.0, "{} lies outside", p);
+            sum += p;
+        }
+        let mean = sum / count as Real;
+        assert!(mean.length() < 0.02, "mean {}", mean);
+    }
+
+    #[test]
+    fn test_disk_samples_lie_in_the_disk_around_the_origin() {
+        check_ball(rand_in_unit_disk);
+        assert_eq!(rand_in_unit_disk().z, 0.0);
+    }
+
+    #[test]
+    fn test_sphere_samples_lie_in_the_ball_around_the_origin() {
+        check_ball(rand_in_unit_sphere);
+        assert!(rand_in_unit_sphere().length_squared() > 0.0);
+    }
 }
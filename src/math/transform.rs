@@ -0,0 +1,69 @@
+use crate::math::{mat3::Mat3, vec3::Vec3, Real};
+
+/// Rigid transform with uniform scale, applied as scale, then rotation, then translation.
+#[derive(Debug, Clone, Copy)]
+pub struct Transform {
+    pub rotation: Mat3,
+    pub translation: Vec3,
+    pub scale: Real,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self {
+            rotation: Mat3::identity(),
+            translation: Vec3::zero(),
+            scale: 1.0,
+        }
+    }
+}
+
+impl Transform {
+    /// `angle` is in degrees, matching `Transformable::rotate`.
+    pub fn new(translation: Vec3, axis: Vec3, angle: Real, scale: Real) -> Self {
+        Self {
+            rotation: Mat3::rotation(axis, angle.to_radians()),
+            translation,
+            scale,
+        }
+    }
+
+    /// Transform a fraction `t` of the way from `self` to `end`. The rotation turns at a
+    /// steady rate about a single axis, taking the shorter way round, so keyframes more than
+    /// half a turn apart need an intermediate one.
+    pub fn interpolate(&self, end: &Transform, t: Real) -> Transform {
+        let (axis, angle) = (end.rotation * self.rotation.transpose()).axis_angle();
+        Transform {
+            rotation: Mat3::rotation(axis, t * angle) * self.rotation,
+            translation: self.translation + t * (end.translation - self.translation),
+            scale: self.scale + t * (end.scale - self.scale),
+        }
+    }
+
+    pub fn point_to_world(&self, p: &Vec3) -> Vec3 {
+        self.rotation * (self.scale * p) + self.translation
+    }
+
+    pub fn point_to_local(&self, p: &Vec3) -> Vec3 {
+        self.rotation.transpose() * (p - self.translation) / self.scale
+    }
+
+    /// Rotates a direction into world space; scale is left out so unit vectors stay unit.
+    pub fn vector_to_world(&self, v: &Vec3) -> Vec3 {
+        self.rotation * *v
+    }
+
+    pub fn vector_to_local(&self, v: &Vec3) -> Vec3 {
+        self.rotation.transpose() * *v
+    }
+
+    pub fn translate(&mut self, translation: Vec3) {
+        self.translation += translation;
+    }
+
+    pub fn rotate(&mut self, axis: Vec3, angle: Real) {
+        let rotation = Mat3::rotation(axis, angle.to_radians());
+        self.rotation = rotation * self.rotation;
+        self.translation = rotation * self.translation;
+    }
+}
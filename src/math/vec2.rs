@@ -1,13 +1,16 @@
 use std::ops::{Add, Sub, Mul, Div};
 
+use crate::math::Real;
+
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Vec2 {
-    pub x: f32,
-    pub y: f32,
+    pub x: Real,
+    pub y: Real,
 }
 
 impl Vec2 {
-    pub fn new(x: f32, y: f32) -> Self {
+    pub fn new(x: Real, y: Real) -> Self {
         Self { x, y }
     }
 
@@ -19,7 +22,7 @@ impl Vec2 {
         Self { x: 1.0, y: 1.0 }
     }
 
-    pub fn length(&self) -> f32 {
+    pub fn length(&self) -> Real {
         (self.x * self.x + self.y * self.y).sqrt()
     }
 
@@ -35,7 +38,7 @@ impl Vec2 {
         }
     }
 
-    pub fn dot(&self, other: &Self) -> f32 {
+    pub fn dot(&self, other: &Self) -> Real {
         self.x * other.x + self.y * other.y
     }
 }
@@ -64,10 +67,10 @@ impl Sub for Vec2 {
     }
 }
 
-impl Mul<f32> for Vec2 {
+impl Mul<Real> for Vec2 {
     type Output = Self;
 
-    fn mul(self, scalar: f32) -> Self {
+    fn mul(self, scalar: Real) -> Self {
         Self {
             x: self.x * scalar,
             y: self.y * scalar,
@@ -75,10 +78,10 @@ impl Mul<f32> for Vec2 {
     }
 }
 
-impl Div<f32> for Vec2 {
+impl Div<Real> for Vec2 {
     type Output = Self;
 
-    fn div(self, scalar: f32) -> Self {
+    fn div(self, scalar: Real) -> Self {
         Self {
             x: self.x / scalar,
             y: self.y / scalar,
@@ -1,15 +1,18 @@
-use crate::math::rand;
+use crate::color::{decode_srgb8, ColorSpace};
+use crate::math::{rand, Real};
+use std::fmt;
 use std::ops;
 
 #[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Vec3 {
-    pub x: f32,
-    pub y: f32,
-    pub z: f32,
+    pub x: Real,
+    pub y: Real,
+    pub z: Real,
 }
 
 impl Vec3 {
-    pub fn new(x: f32, y: f32, z: f32) -> Self {
+    pub fn new(x: Real, y: Real, z: Real) -> Self {
         Self { x, y, z }
     }
 
@@ -48,16 +51,54 @@ impl Vec3 {
         }
     }
 
-    pub fn length_squared(&self) -> f32 {
+    pub fn length_squared(&self) -> Real {
         dot(self, self)
     }
 
-    pub fn length(&self) -> f32 {
+    pub fn length(&self) -> Real {
         dot(self, self).sqrt()
     }
 
+    pub fn luminance(&self) -> Real {
+        0.2126 * self.x + 0.7152 * self.y + 0.0722 * self.z
+    }
+
+    /// Linear color of an sRGB hex code such as `#ff8800`, with or without the `#`, as color
+    /// pickers show it. `None` unless it is exactly six hex digits.
+    pub fn from_hex(hex: &str) -> Option<Vec3> {
+        let digits = hex.strip_prefix('#').unwrap_or(hex);
+        if digits.len() != 6 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        let channel = |i: usize| u8::from_str_radix(&digits[2 * i..2 * i + 2], 16).ok();
+        Some(decode_srgb8(&[channel(0)?, channel(1)?, channel(2)?]))
+    }
+
+    /// sRGB hex code of this linear color, clamped to [0, 1]; the inverse of `from_hex`.
+    pub fn to_hex(self) -> String {
+        let channel = |value: Real| (ColorSpace::Srgb.encode(value).min(1.0) * 255.0).round() as u8;
+        format!(
+            "#{:02x}{:02x}{:02x}",
+            channel(self.x),
+            channel(self.y),
+            channel(self.z)
+        )
+    }
+
+    /// True if no component is NaN or infinite.
+    pub fn is_finite(&self) -> bool {
+        self.x.is_finite() && self.y.is_finite() && self.z.is_finite()
+    }
+
+    /// True if every component differs from `other`'s by at most `eps`.
+    pub fn approx_eq(&self, other: &Vec3, eps: Real) -> bool {
+        (self.x - other.x).abs() <= eps
+            && (self.y - other.y).abs() <= eps
+            && (self.z - other.z).abs() <= eps
+    }
+
     pub fn near_zero(&self) -> bool {
-        let eps: f32 = 1e-8;
+        let eps: Real = 1e-8;
         self.x.abs() < eps && self.y.abs() < eps && self.z.abs() < eps
     }
 
@@ -69,7 +110,7 @@ impl Vec3 {
         Vec3::new(rand::rand_f32(), rand::rand_f32(), rand::rand_f32())
     }
 
-    pub fn random_range(min: f32, max: f32) -> Vec3 {
+    pub fn random_range(min: Real, max: Real) -> Vec3 {
         Vec3::new(
             rand::rand_f32_range(min, max),
             rand::rand_f32_range(min, max),
@@ -78,169 +119,141 @@ impl Vec3 {
     }
 
     pub fn random_unit() -> Vec3 {
-        loop {
-            let v = Vec3::random_range(-1.0, 1.0);
-            let len_sq = v.length_squared();
-            if len_sq > f32::EPSILON && len_sq <= 1.0 {
-                return v;
-            }
+        rand::rand_in_unit_sphere()
+    }
+
+    /// Uniformly distributed unit direction in the hemisphere around `normal` (pdf `1/(2π)`).
+    pub fn random_on_hemisphere(normal: &Vec3) -> Vec3 {
+        let on_sphere = Vec3::random_unit().normalize();
+        if dot(&on_sphere, normal) > 0.0 {
+            on_sphere
+        } else {
+            -on_sphere
         }
     }
 
-    pub fn random_cosine_hemisphere_direction() -> Vec3 {
+    /// Cosine-weighted unit direction around +z (pdf `cos θ / π`). Rotate it into a
+    /// surface frame with an orthonormal basis.
+    pub fn random_cosine_direction() -> Vec3 {
         let r1 = rand::rand_f32();
         let r2 = rand::rand_f32();
-        let phi = 2.0 * std::f32::consts::PI * r1;
-        let x = f32::cos(phi) * f32::sqrt(r2);
-        let y = f32::sin(phi) * f32::sqrt(r2);
-        let z = f32::sqrt(1.0 - r2);
+        let phi = 2.0 * crate::math::consts::PI * r1;
+        let x = Real::cos(phi) * Real::sqrt(r2);
+        let y = Real::sin(phi) * Real::sqrt(r2);
+        let z = Real::sqrt(1.0 - r2);
         Vec3 { x, y, z }
     }
 }
 
+/// Writes `(x, y, z)`, applying any precision to each component.
+impl fmt::Display for Vec3 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match f.precision() {
+            Some(p) => write!(f, "({:.*}, {:.*}, {:.*})", p, self.x, p, self.y, p, self.z),
+            None => write!(f, "({}, {}, {})", self.x, self.y, self.z),
+        }
+    }
+}
+
 impl ops::Deref for Vec3 {
-    type Target = [f32; 3];
+    type Target = [Real; 3];
 
     fn deref(&self) -> &Self::Target {
-        unsafe { &*(self as *const Vec3 as *const [f32; 3]) }
+        unsafe { &*(self as *const Vec3 as *const [Real; 3]) }
     }
 }
 
 impl ops::DerefMut for Vec3 {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        unsafe { &mut *(self as *mut Vec3 as *mut [f32; 3]) }
+        unsafe { &mut *(self as *mut Vec3 as *mut [Real; 3]) }
     }
 }
 
 impl ops::Add for Vec3 {
     type Output = Self;
     fn add(self, rhs: Self) -> Self::Output {
-        Self {
-            x: self.x + rhs.x,
-            y: self.y + rhs.y,
-            z: self.z + rhs.z,
-        }
+        lanes::add(&self, &rhs)
     }
 }
 
 impl ops::Add for &Vec3 {
     type Output = Vec3;
     fn add(self, rhs: Self) -> Self::Output {
-        Vec3 {
-            x: self.x + rhs.x,
-            y: self.y + rhs.y,
-            z: self.z + rhs.z,
-        }
+        lanes::add(self, rhs)
     }
 }
 
 impl ops::AddAssign for Vec3 {
     fn add_assign(&mut self, rhs: Self) {
-        self.x += rhs.x;
-        self.y += rhs.y;
-        self.z += rhs.z;
+        *self = lanes::add(self, &rhs);
     }
 }
 
 impl ops::Sub for Vec3 {
     type Output = Self;
     fn sub(self, rhs: Self) -> Self::Output {
-        Self {
-            x: self.x - rhs.x,
-            y: self.y - rhs.y,
-            z: self.z - rhs.z,
-        }
+        lanes::sub(&self, &rhs)
     }
 }
 
 impl ops::Sub<&Vec3> for Vec3 {
     type Output = Self;
     fn sub(self, rhs: &Self) -> Self::Output {
-        Self {
-            x: self.x - rhs.x,
-            y: self.y - rhs.y,
-            z: self.z - rhs.z,
-        }
+        lanes::sub(&self, rhs)
     }
 }
 
 impl ops::Sub for &Vec3 {
     type Output = Vec3;
     fn sub(self, rhs: Self) -> Self::Output {
-        Vec3 {
-            x: self.x - rhs.x,
-            y: self.y - rhs.y,
-            z: self.z - rhs.z,
-        }
+        lanes::sub(self, rhs)
     }
 }
 
 impl ops::Sub<Vec3> for &Vec3 {
     type Output = Vec3;
     fn sub(self, rhs: Vec3) -> Self::Output {
-        Vec3 {
-            x: self.x - rhs.x,
-            y: self.y - rhs.y,
-            z: self.z - rhs.z,
-        }
+        lanes::sub(self, &rhs)
     }
 }
 
 impl ops::Mul<Vec3> for Vec3 {
     type Output = Self;
     fn mul(self, rhs: Vec3) -> Self::Output {
-        Self {
-            x: rhs.x * self.x,
-            y: rhs.y * self.y,
-            z: rhs.z * self.z,
-        }
+        lanes::mul(&self, &rhs)
     }
 }
 
-impl ops::Mul<f32> for Vec3 {
+impl ops::Mul<Real> for Vec3 {
     type Output = Self;
-    fn mul(self, rhs: f32) -> Self::Output {
-        Self {
-            x: rhs * self.x,
-            y: rhs * self.y,
-            z: rhs * self.z,
-        }
+    fn mul(self, rhs: Real) -> Self::Output {
+        lanes::scale(&self, rhs)
     }
 }
 
-impl ops::Mul<Vec3> for f32 {
+impl ops::Mul<Vec3> for Real {
     type Output = Vec3;
     fn mul(self, rhs: Vec3) -> Self::Output {
-        Vec3 {
-            x: self * rhs.x,
-            y: self * rhs.y,
-            z: self * rhs.z,
-        }
+        lanes::scale(&rhs, self)
     }
 }
 
-impl ops::Mul<&Vec3> for f32 {
+impl ops::Mul<&Vec3> for Real {
     type Output = Vec3;
     fn mul(self, rhs: &Vec3) -> Self::Output {
-        Vec3 {
-            x: self * rhs.x,
-            y: self * rhs.y,
-            z: self * rhs.z,
-        }
+        lanes::scale(rhs, self)
     }
 }
 
-impl ops::MulAssign<f32> for Vec3 {
-    fn mul_assign(&mut self, rhs: f32) {
-        self.x *= rhs;
-        self.y *= rhs;
-        self.z *= rhs;
+impl ops::MulAssign<Real> for Vec3 {
+    fn mul_assign(&mut self, rhs: Real) {
+        *self = lanes::scale(self, rhs);
     }
 }
 
-impl ops::Div<f32> for Vec3 {
+impl ops::Div<Real> for Vec3 {
     type Output = Self;
-    fn div(self, rhs: f32) -> Self::Output {
+    fn div(self, rhs: Real) -> Self::Output {
         Self {
             x: self.x / rhs,
             y: self.y / rhs,
@@ -271,28 +284,184 @@ impl ops::Neg for &Vec3 {
     }
 }
 
-impl From<Vec3> for [f32; 3] {
+impl From<Vec3> for [Real; 3] {
     fn from(v: Vec3) -> Self {
         [v.x, v.y, v.z]
     }
 }
 
-pub fn dot(a: &Vec3, b: &Vec3) -> f32 {
-    a.x * b.x + a.y * b.y + a.z * b.z
+impl From<[Real; 3]> for Vec3 {
+    fn from([x, y, z]: [Real; 3]) -> Self {
+        Vec3 { x, y, z }
+    }
+}
+
+impl From<Vec3> for (Real, Real, Real) {
+    fn from(v: Vec3) -> Self {
+        (v.x, v.y, v.z)
+    }
+}
+
+impl From<(Real, Real, Real)> for Vec3 {
+    fn from((x, y, z): (Real, Real, Real)) -> Self {
+        Vec3 { x, y, z }
+    }
+}
+
+pub fn dot(a: &Vec3, b: &Vec3) -> Real {
+    lanes::dot(a, b)
 }
 
 pub fn cross(a: &Vec3, b: &Vec3) -> Vec3 {
-    Vec3 {
-        x: a.y * b.z - a.z * b.y,
-        y: a.z * b.x - a.x * b.z,
-        z: a.x * b.y - a.y * b.x,
+    lanes::cross(a, b)
+}
+
+/// The per-component arithmetic behind the operators, `dot` and `cross`, one component at a
+/// time. This is the default; the `simd` feature swaps in `simd` on x86_64.
+#[cfg_attr(
+    all(feature = "simd", target_arch = "x86_64", not(feature = "f64")),
+    allow(dead_code)
+)]
+mod scalar {
+    use super::{Real, Vec3};
+
+    #[inline(always)]
+    pub fn add(a: &Vec3, b: &Vec3) -> Vec3 {
+        Vec3::new(a.x + b.x, a.y + b.y, a.z + b.z)
+    }
+
+    #[inline(always)]
+    pub fn sub(a: &Vec3, b: &Vec3) -> Vec3 {
+        Vec3::new(a.x - b.x, a.y - b.y, a.z - b.z)
+    }
+
+    #[inline(always)]
+    pub fn mul(a: &Vec3, b: &Vec3) -> Vec3 {
+        Vec3::new(a.x * b.x, a.y * b.y, a.z * b.z)
+    }
+
+    #[inline(always)]
+    pub fn scale(a: &Vec3, s: Real) -> Vec3 {
+        Vec3::new(s * a.x, s * a.y, s * a.z)
+    }
+
+    #[inline(always)]
+    pub fn dot(a: &Vec3, b: &Vec3) -> Real {
+        a.x * b.x + a.y * b.y + a.z * b.z
+    }
+
+    #[inline(always)]
+    pub fn cross(a: &Vec3, b: &Vec3) -> Vec3 {
+        Vec3::new(
+            a.y * b.z - a.z * b.y,
+            a.z * b.x - a.x * b.z,
+            a.x * b.y - a.y * b.x,
+        )
+    }
+}
+
+/// SSE versions of the `scalar` kernels. Each operand is loaded into a 4-wide register with
+/// the fourth lane zeroed and stored back into the plain `x`, `y`, `z` fields, so `Vec3`'s
+/// layout and API are the same with or without the feature.
+#[cfg(all(feature = "simd", target_arch = "x86_64", not(feature = "f64")))]
+mod simd {
+    use super::{Real, Vec3};
+    use std::arch::x86_64::*;
+
+    // SAFETY (for every intrinsic below): SSE and SSE2 are part of the x86_64 baseline, so
+    // they are always available on this target.
+
+    /// Lane order (y, z, x, w) for `_mm_shuffle_ps`.
+    const YZXW: i32 = 0b11_00_10_01;
+
+    #[inline(always)]
+    fn load(v: &Vec3) -> __m128 {
+        unsafe { _mm_set_ps(0.0, v.z, v.y, v.x) }
+    }
+
+    #[inline(always)]
+    fn store(r: __m128) -> Vec3 {
+        let mut lanes = [0.0; 4];
+        // `lanes` has room for the four floats written
+        unsafe { _mm_storeu_ps(lanes.as_mut_ptr(), r) };
+        Vec3::new(lanes[0], lanes[1], lanes[2])
+    }
+
+    #[inline(always)]
+    pub fn add(a: &Vec3, b: &Vec3) -> Vec3 {
+        store(unsafe { _mm_add_ps(load(a), load(b)) })
+    }
+
+    #[inline(always)]
+    pub fn sub(a: &Vec3, b: &Vec3) -> Vec3 {
+        store(unsafe { _mm_sub_ps(load(a), load(b)) })
+    }
+
+    #[inline(always)]
+    pub fn mul(a: &Vec3, b: &Vec3) -> Vec3 {
+        store(unsafe { _mm_mul_ps(load(a), load(b)) })
+    }
+
+    #[inline(always)]
+    pub fn scale(a: &Vec3, s: Real) -> Vec3 {
+        store(unsafe { _mm_mul_ps(load(a), _mm_set1_ps(s)) })
+    }
+
+    #[inline(always)]
+    pub fn dot(a: &Vec3, b: &Vec3) -> Real {
+        unsafe {
+            let products = _mm_mul_ps(load(a), load(b));
+            // (x + z, y + w) in the low lanes, then the two of them summed
+            let sums = _mm_add_ps(products, _mm_movehl_ps(products, products));
+            _mm_cvtss_f32(_mm_add_ss(sums, _mm_shuffle_ps::<0b01>(sums, sums)))
+        }
+    }
+
+    #[inline(always)]
+    pub fn cross(a: &Vec3, b: &Vec3) -> Vec3 {
+        let (a, b) = (load(a), load(b));
+        store(unsafe {
+            let a_yzx = _mm_shuffle_ps::<YZXW>(a, a);
+            let b_yzx = _mm_shuffle_ps::<YZXW>(b, b);
+            // Comes out in (z, x, y) order
+            let zxy = _mm_sub_ps(_mm_mul_ps(a, b_yzx), _mm_mul_ps(a_yzx, b));
+            _mm_shuffle_ps::<YZXW>(zxy, zxy)
+        })
     }
 }
 
+#[cfg(not(all(feature = "simd", target_arch = "x86_64", not(feature = "f64"))))]
+use scalar as lanes;
+#[cfg(all(feature = "simd", target_arch = "x86_64", not(feature = "f64")))]
+use simd as lanes;
+
 pub fn reflect(v: &Vec3, n: &Vec3) -> Vec3 {
     *v - 2.0 * dot(v, n) * n
 }
 
+/// Asserts that two `Vec3`s agree per component to within `eps` (default `1e-5`).
+#[cfg(test)]
+#[macro_export]
+macro_rules! assert_vec3_approx {
+    ($left:expr, $right:expr $(,)?) => {
+        $crate::assert_vec3_approx!($left, $right, 1e-5)
+    };
+    ($left:expr, $right:expr, $eps:expr $(,)?) => {{
+        let (left, right, eps): (
+            $crate::math::vec3::Vec3,
+            $crate::math::vec3::Vec3,
+            $crate::math::Real,
+        ) = ($left, $right, $eps);
+        assert!(
+            left.approx_eq(&right, eps),
+            "assertion failed: {:?} != {:?} (eps {})",
+            left,
+            right,
+            eps
+        );
+    }};
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -446,7 +615,7 @@ mod tests {
         // Create a Vec3 with arbitrary values
         let vec = Vec3::new(6.0, 9.0, 12.0);
 
-        // Divide by a scalar (f32)
+        // Divide by a scalar (Real)
         let result = vec / 3.0;
 
         // Check if the result is as expected (each component divided by 3)
@@ -454,4 +623,156 @@ mod tests {
         assert_eq!(result.y, 3.0); // 9.0 / 3.0 = 3.0
         assert_eq!(result.z, 4.0); // 12.0 / 3.0 = 4.0
     }
+
+    #[test]
+    fn test_vec3_array_conversion() {
+        let v = Vec3::new(1.0, 2.0, 3.0);
+        let arr: [Real; 3] = v.into();
+        assert_eq!(arr, [1.0, 2.0, 3.0]);
+        assert_eq!(Vec3::from(arr), v);
+    }
+
+    #[test]
+    fn test_vec3_tuple_conversion() {
+        let v = Vec3::new(-1.0, 0.5, 4.0);
+        let tuple: (Real, Real, Real) = v.into();
+        assert_eq!(tuple, (-1.0, 0.5, 4.0));
+        assert_eq!(Vec3::from(tuple), v);
+    }
+
+    #[test]
+    fn test_approx_eq() {
+        let v = Vec3::new(1.0, -2.0, 3.0);
+        assert!(v.approx_eq(&Vec3::new(1.0005, -2.0, 2.9995), 1e-3));
+        assert!(!v.approx_eq(&Vec3::new(1.0, -2.01, 3.0), 1e-3));
+        assert!(!v.approx_eq(&Vec3::new(Real::NAN, -2.0, 3.0), 1e-3));
+    }
+
+    #[test]
+    fn test_normalize() {
+        assert_vec3_approx!(
+            Vec3::new(3.0, 0.0, -4.0).normalize(),
+            Vec3::new(0.6, 0.0, -0.8)
+        );
+        let v = Vec3::new(0.1, 0.2, 0.3).normalize();
+        assert!((v.length() - 1.0).abs() < 1e-6);
+        assert_vec3_approx!(v, Vec3::new(1.0, 2.0, 3.0) / Real::sqrt(14.0));
+        assert_eq!(Vec3::zero().normalize(), Vec3::zero());
+    }
+
+    #[test]
+    fn test_cross() {
+        let (x, y, z) = (
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+        );
+        assert_eq!(cross(&x, &y), z);
+        assert_eq!(cross(&y, &z), x);
+        assert_eq!(cross(&z, &x), y);
+
+        let a = Vec3::new(0.3, -1.2, 2.5).normalize();
+        let b = Vec3::new(-0.7, 0.4, 0.9).normalize();
+        let c = cross(&a, &b);
+        assert!(dot(&c, &a).abs() < 1e-6 && dot(&c, &b).abs() < 1e-6);
+        assert_vec3_approx!(cross(&b, &a), -c);
+        // |a x b| = sin of the angle between unit vectors
+        let sin = (1.0 - dot(&a, &b).powi(2)).sqrt();
+        assert!((c.length() - sin).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_reflect() {
+        let normal = Vec3::new(0.0, 1.0, 0.0);
+        assert_vec3_approx!(
+            reflect(&Vec3::new(1.0, -1.0, 0.5), &normal),
+            Vec3::new(1.0, 1.0, 0.5)
+        );
+
+        let normal = Vec3::new(1.0, 1.0, 0.0).normalize();
+        let incoming = Vec3::new(0.2, -0.9, 0.4).normalize();
+        let reflected = reflect(&incoming, &normal);
+        assert!((reflected.length() - 1.0).abs() < 1e-6);
+        assert!((dot(&reflected, &normal) + dot(&incoming, &normal)).abs() < 1e-6);
+        assert_vec3_approx!(reflect(&reflected, &normal), incoming);
+    }
+
+    #[test]
+    fn test_random_on_hemisphere_mean() {
+        let normal = Vec3::new(0.0, 1.0, 0.0);
+        let n = 100_000;
+        let mut mean_cos = 0.0;
+        for _ in 0..n {
+            let d = Vec3::random_on_hemisphere(&normal);
+            assert!((d.length() - 1.0).abs() < 1e-4);
+            assert!(dot(&d, &normal) >= 0.0);
+            mean_cos += dot(&d, &normal) / n as Real;
+        }
+        // E[cos θ] over the uniform hemisphere is 1/2
+        assert!((mean_cos - 0.5).abs() < 0.01, "mean cos {}", mean_cos);
+    }
+
+    #[test]
+    fn test_random_cosine_direction_mean() {
+        let n = 100_000;
+        let mut mean = Vec3::zero();
+        for _ in 0..n {
+            let d = Vec3::random_cosine_direction();
+            assert!((d.length() - 1.0).abs() < 1e-4);
+            mean += d / n as Real;
+        }
+        // E[cos θ] under the cosine-weighted pdf is 2/3, and the lateral components cancel
+        assert!((mean.z - 2.0 / 3.0).abs() < 0.01, "mean {:?}", mean);
+        assert!(
+            mean.x.abs() < 0.01 && mean.y.abs() < 0.01,
+            "mean {:?}",
+            mean
+        );
+    }
+
+    #[test]
+    fn test_hex_colors_decode_to_linear_and_back() {
+        let white = Vec3::from_hex("#ffffff").unwrap();
+        assert!((white - Vec3::one()).length() < 1e-6, "{}", white);
+        assert_eq!(Vec3::from_hex("000000"), Some(Vec3::zero()));
+        // sRGB 0x80 is middle gray, about 21.6% linear
+        let orange = Vec3::from_hex("#FF8000").unwrap();
+        assert!(
+            (orange.y - 0.2158605).abs() < 1e-6 && orange.z == 0.0,
+            "{}",
+            orange
+        );
+
+        for hex in [
+            "#ff8800", "#1e90ff", "#808080", "#c0ffee", "#010203", "#fefefe",
+        ] {
+            assert_eq!(Vec3::from_hex(hex).unwrap().to_hex(), hex);
+        }
+        assert_eq!(Vec3::new(2.0, -1.0, 0.5).to_hex(), "#ff00bc");
+
+        for bad in ["", "#fff", "#ff880", "#ff88000", "#gg8800", "ff 880"] {
+            assert_eq!(Vec3::from_hex(bad), None, "{:?}", bad);
+        }
+    }
+
+    #[test]
+    #[cfg(all(feature = "simd", target_arch = "x86_64", not(feature = "f64")))]
+    fn test_simd_kernels_match_scalar() {
+        rand::seed_sample_rng(11, 0, 0, 0);
+        for _ in 0..1000 {
+            let (a, b) = (
+                Vec3::random_range(-50.0, 50.0),
+                Vec3::random_range(-50.0, 50.0),
+            );
+            let s = rand::rand_f32_range(-10.0, 10.0);
+            assert_eq!(simd::add(&a, &b), scalar::add(&a, &b));
+            assert_eq!(simd::sub(&a, &b), scalar::sub(&a, &b));
+            assert_eq!(simd::mul(&a, &b), scalar::mul(&a, &b));
+            assert_eq!(simd::scale(&a, s), scalar::scale(&a, s));
+            // dot sums in a different order, and cross may cancel, so allow the rounding
+            let eps = 1e-5 * a.length() * b.length();
+            assert!((simd::dot(&a, &b) - scalar::dot(&a, &b)).abs() <= eps);
+            assert!(simd::cross(&a, &b).approx_eq(&scalar::cross(&a, &b), eps));
+        }
+    }
 }
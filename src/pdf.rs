@@ -1,11 +1,13 @@
 use crate::entities::entity::Hittable;
 use crate::math::{
-    mat3::{dot_v3, Mat3},
+    mat3::Mat3,
     vec3::{dot, Vec3},
 };
+use crate::math::{rand::rand_f32, Real};
+use std::sync::Arc;
 
 pub trait PDF {
-    fn value(&self, direction: &Vec3) -> f32;
+    fn value(&self, direction: &Vec3) -> Real;
     fn generate(&self) -> Vec3;
 }
 
@@ -34,9 +36,22 @@ impl HittablePDF {
     }
 }
 
+/// Samples towards one of several lights, picked uniformly, so the density of a direction is
+/// the average of the lights' densities. `generate` needs at least one light.
+pub struct HittableListPDF<'a> {
+    pub origin: Vec3,
+    pub lights: &'a [Arc<dyn Hittable>],
+}
+
+impl<'a> HittableListPDF<'a> {
+    pub fn new(origin: Vec3, lights: &'a [Arc<dyn Hittable>]) -> Self {
+        Self { origin, lights }
+    }
+}
+
 impl PDF for SpherePDF {
-    fn value(&self, _direction: &Vec3) -> f32 {
-        1.0 / (4.0 * std::f32::consts::PI)
+    fn value(&self, _direction: &Vec3) -> Real {
+        1.0 / (4.0 * crate::math::consts::PI)
     }
 
     fn generate(&self) -> Vec3 {
@@ -45,20 +60,17 @@ impl PDF for SpherePDF {
 }
 
 impl PDF for CosinePDF {
-    fn value(&self, direction: &Vec3) -> f32 {
+    fn value(&self, direction: &Vec3) -> Real {
         let cosine = dot(&direction.normalize(), &self.uvw[2]);
-        f32::max(0.0, cosine / std::f32::consts::PI)
+        Real::max(0.0, cosine / crate::math::consts::PI)
     }
     fn generate(&self) -> Vec3 {
-        dot_v3(
-            &self.uvw.transpose(),
-            &Vec3::random_cosine_hemisphere_direction(),
-        )
+        self.uvw.transpose_mul(&Vec3::random_cosine_direction())
     }
 }
 
 impl PDF for HittablePDF {
-    fn value(&self, direction: &Vec3) -> f32 {
+    fn value(&self, direction: &Vec3) -> Real {
         self.hittable.pdf_value(&self.origin, direction)
     }
 
@@ -66,3 +78,65 @@ impl PDF for HittablePDF {
         self.hittable.random(&self.origin)
     }
 }
+
+impl PDF for HittableListPDF<'_> {
+    fn value(&self, direction: &Vec3) -> Real {
+        if self.lights.is_empty() {
+            return 0.0;
+        }
+        self.lights
+            .iter()
+            .map(|light| light.pdf_value(&self.origin, direction))
+            .sum::<Real>()
+            / self.lights.len() as Real
+    }
+
+    fn generate(&self) -> Vec3 {
+        let count = self.lights.len();
+        let light = &self.lights[((rand_f32() * count as Real) as usize).min(count - 1)];
+        light.random(&self.origin)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entities::quad::Quad;
+    use crate::material::DiffuseLight;
+    use crate::math::rand::seed_sample_rng;
+    use crate::texture::Texture;
+
+    #[test]
+    fn test_two_light_pdf_integrates_to_one() {
+        let emit = Arc::new(DiffuseLight::new(Box::new(Texture::new(Vec3::one()))));
+        let light = |q: Vec3, u: Vec3, v: Vec3| -> Arc<dyn Hittable> {
+            Arc::new(Quad::new(q, u, v, emit.clone()))
+        };
+        let lights = [
+            light(
+                Vec3::new(-1.0, 1.0, -1.0),
+                Vec3::new(2.0, 0.0, 0.0),
+                Vec3::new(0.0, 0.0, 2.0),
+            ),
+            light(
+                Vec3::new(2.0, -0.5, -0.5),
+                Vec3::new(0.0, 1.0, 0.0),
+                Vec3::new(0.0, 0.0, 1.0),
+            ),
+        ];
+        let pdf = HittableListPDF::new(Vec3::zero(), &lights);
+        seed_sample_rng(5, 0, 0, 0);
+        const SAMPLES: u32 = 200_000;
+        let sum: f64 = (0..SAMPLES)
+            .map(|_| pdf.value(&Vec3::random_unit().normalize()) as f64)
+            .sum();
+        let integral = 4.0 * crate::math::consts::PI as f64 * sum / SAMPLES as f64;
+        assert!((integral - 1.0).abs() < 0.03, "integral {}", integral);
+
+        // Generated directions head for one of the two lights, each about half the time
+        let towards_second = (0..1000)
+            .filter(|_| pdf.generate().normalize().y < 0.5)
+            .count();
+        assert!((400..600).contains(&towards_second), "{}", towards_second);
+    }
+}
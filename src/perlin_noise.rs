@@ -1,5 +1,6 @@
 use rand::seq::SliceRandom;
 
+use crate::math::Real;
 use crate::math::{
     rand::rand_f32,
     vec3::{dot, Vec3},
@@ -8,6 +9,8 @@ use crate::math::{
 #[derive(Debug, Clone)]
 pub struct PerlinNoise {
     rand_vec: [Vec3; Self::POINT_COUNT],
+    /// Lattice values of the value-noise variant.
+    rand_float: [Real; Self::POINT_COUNT],
     perm_x: [u8; Self::POINT_COUNT],
     perm_y: [u8; Self::POINT_COUNT],
     perm_z: [u8; Self::POINT_COUNT],
@@ -17,15 +20,17 @@ impl PerlinNoise {
     const POINT_COUNT: usize = 256;
     pub fn new() -> Self {
         let rand_vec = std::array::from_fn(|_| Vec3::random_unit());
+        let rand_float = std::array::from_fn(|_| rand_f32());
         Self {
             rand_vec,
+            rand_float,
             perm_x: PerlinNoise::generate_permutation(),
             perm_y: PerlinNoise::generate_permutation(),
             perm_z: PerlinNoise::generate_permutation(),
         }
     }
 
-    pub fn noise(&self, p: Vec3) -> f32 {
+    pub fn noise(&self, p: Vec3) -> Real {
         let u = p.x - p.x.floor();
         let v = p.y - p.y.floor();
         let w = p.z - p.z.floor();
@@ -48,7 +53,32 @@ impl PerlinNoise {
         perlin_interpolate(interpolation_points, u, v, w)
     }
 
-    pub fn turbulence(&self, p: Vec3, depth: i32) -> f32 {
+    /// Value noise in [0, 1]: random lattice values blended with smoothed trilinear
+    /// interpolation. Blockier than the gradient `noise`, with features on the lattice.
+    pub fn value_noise(&self, p: Vec3) -> Real {
+        let u = hermite_fade(p.x - p.x.floor());
+        let v = hermite_fade(p.y - p.y.floor());
+        let w = hermite_fade(p.z - p.z.floor());
+
+        let i = p.x.floor() as i32;
+        let j = p.y.floor() as i32;
+        let k = p.z.floor() as i32;
+
+        let interpolation_points = std::array::from_fn(|x| {
+            std::array::from_fn(|y| {
+                std::array::from_fn(|z| {
+                    self.rand_float[(self.perm_x[((x as i32 + i) & 255) as usize]
+                        ^ self.perm_y[((y as i32 + j) & 255) as usize]
+                        ^ self.perm_z[((z as i32 + k) & 255) as usize])
+                        as usize]
+                })
+            })
+        });
+
+        trilinear_interpolate(interpolation_points, u, v, w)
+    }
+
+    pub fn turbulence(&self, p: Vec3, depth: i32) -> Real {
         let mut accum = 0.0;
         let mut temp_p = p;
         let mut weight = 1.0;
@@ -68,7 +98,7 @@ impl PerlinNoise {
     }
 }
 
-fn perlin_interpolate(points: [[[Vec3; 2]; 2]; 2], u: f32, v: f32, w: f32) -> f32 {
+fn perlin_interpolate(points: [[[Vec3; 2]; 2]; 2], u: Real, v: Real, w: Real) -> Real {
     let uu = hermite_fade(u);
     let vv = hermite_fade(v);
     let ww = hermite_fade(w);
@@ -76,10 +106,10 @@ fn perlin_interpolate(points: [[[Vec3; 2]; 2]; 2], u: f32, v: f32, w: f32) -> f3
     points.iter().enumerate().for_each(|(i, x)| {
         x.iter().enumerate().for_each(|(j, y)| {
             y.iter().enumerate().for_each(|(k, z)| {
-                let weight = Vec3::new(u - i as f32, v - j as f32, w - k as f32);
-                accum += (i as f32 * uu + (1 - i) as f32 * (1.0 - uu))
-                    * (j as f32 * vv + (1 - j) as f32 * (1.0 - vv))
-                    * (k as f32 * ww + (1 - k) as f32 * (1.0 - ww))
+                let weight = Vec3::new(u - i as Real, v - j as Real, w - k as Real);
+                accum += (i as Real * uu + (1 - i) as Real * (1.0 - uu))
+                    * (j as Real * vv + (1 - j) as Real * (1.0 - vv))
+                    * (k as Real * ww + (1 - k) as Real * (1.0 - ww))
                     * dot(z, &weight);
             });
         });
@@ -87,14 +117,14 @@ fn perlin_interpolate(points: [[[Vec3; 2]; 2]; 2], u: f32, v: f32, w: f32) -> f3
     accum
 }
 
-fn trilinear_interpolate(points: [[[f32; 2]; 2]; 2], u: f32, v: f32, w: f32) -> f32 {
+fn trilinear_interpolate(points: [[[Real; 2]; 2]; 2], u: Real, v: Real, w: Real) -> Real {
     let mut accum = 0.0;
     points.iter().enumerate().for_each(|(i, x)| {
         x.iter().enumerate().for_each(|(j, y)| {
             y.iter().enumerate().for_each(|(k, z)| {
-                accum += (i as f32 * u + (1 - i) as f32 * (1.0 - u))
-                    * (j as f32 * v + (1 - j) as f32 * (1.0 - v))
-                    * (k as f32 * w + (1 - k) as f32 * (1.0 - w))
+                accum += (i as Real * u + (1 - i) as Real * (1.0 - u))
+                    * (j as Real * v + (1 - j) as Real * (1.0 - v))
+                    * (k as Real * w + (1 - k) as Real * (1.0 - w))
                     * z;
             });
         });
@@ -102,6 +132,31 @@ fn trilinear_interpolate(points: [[[f32; 2]; 2]; 2], u: f32, v: f32, w: f32) ->
     accum
 }
 
-fn hermite_fade(t: f32) -> f32 {
+fn hermite_fade(t: Real) -> Real {
     t * t * (3.0 - 2.0 * t)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_value_noise_interpolates_lattice_values() {
+        let noise = PerlinNoise::new();
+        for i in 0..16 {
+            let lattice = Vec3::new(i as Real, (3 * i) as Real, -(i as Real));
+            let index = noise.perm_x[(lattice.x as i32 & 255) as usize]
+                ^ noise.perm_y[(lattice.y as i32 & 255) as usize]
+                ^ noise.perm_z[(lattice.z as i32 & 255) as usize];
+            assert_eq!(noise.value_noise(lattice), noise.rand_float[index as usize]);
+        }
+        let mut previous = noise.value_noise(Vec3::new(0.0, 0.5, 0.5));
+        for step in 1..=400 {
+            let value = noise.value_noise(Vec3::new(step as Real * 0.01, 0.5, 0.5));
+            assert!((0.0..=1.0).contains(&value));
+            // Smooth: small steps never jump
+            assert!((value - previous).abs() < 0.05, "jump at step {}", step);
+            previous = value;
+        }
+    }
+}
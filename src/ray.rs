@@ -1,26 +1,128 @@
-use crate::math::vec3::*;
+use crate::interval::Interval;
+use crate::math::{vec3::*, Real};
+
+/// Default self-intersection offset, relative to the magnitude of a ray's origin. Rounding
+/// errors in a hit position grow with its coordinates, so a fixed distance would be too short
+/// in large scenes and too long, leaking light through thin gaps, in tiny ones.
+pub const RAY_EPSILON: Real = 1e-4;
+/// Floor on the coordinate magnitude the offset scales with, for origins near the world origin.
+const MIN_OFFSET_SCALE: Real = 1e-2;
+
+/// Offset rays through the neighbouring pixels, used to estimate a hit's screen footprint.
+#[derive(Debug, Clone, Copy)]
+pub struct RayDifferential {
+    pub rx_origin: Vec3,
+    pub rx_direction: Vec3,
+    pub ry_origin: Vec3,
+    pub ry_direction: Vec3,
+}
 
 pub struct Ray {
     pub origin: Vec3,
-    pub direction: Vec3,
+    /// Unit direction, kept private so it cannot drift from `inv_direction`.
+    direction: Vec3,
+    pub differential: Option<RayDifferential>,
+    /// Moment within the shutter interval, from 0 at opening to 1 at closing, at which the
+    /// ray samples animated entities.
+    pub time: Real,
+    /// Componentwise reciprocal of `direction`, computed once for the slab tests of every box
+    /// the ray visits. Zero components give infinities, which the slab test handles.
+    inv_direction: Vec3,
 }
 
 impl Ray {
     pub fn new(origin: Vec3, direction: Vec3) -> Self {
+        let direction = direction.normalize();
+        debug_assert!(
+            origin.is_finite() && direction.is_finite(),
+            "Non-finite ray: origin {:?}, direction {:?}",
+            origin,
+            direction
+        );
         Self {
             origin,
-            direction: direction.normalize(),
+            direction,
+            differential: None,
+            time: 0.0,
+            inv_direction: Vec3::new(1.0 / direction.x, 1.0 / direction.y, 1.0 / direction.z),
         }
     }
 
-    pub fn at(&self, t: f32) -> Vec3 {
+    pub fn with_time(mut self, time: Real) -> Self {
+        self.time = time;
+        self
+    }
+
+    pub fn with_differential(mut self, differential: RayDifferential) -> Self {
+        self.differential = Some(differential);
+        self
+    }
+
+    /// Intersects the differential rays with the tangent plane at a hit and returns the
+    /// world-space offsets `(dpdx, dpdy)` of the pixel footprint.
+    pub fn footprint_at(&self, position: &Vec3, normal: &Vec3) -> Option<(Vec3, Vec3)> {
+        let differential = self.differential?;
+        let d = dot(normal, position);
+        let offset = |origin: &Vec3, direction: &Vec3| {
+            let denom = dot(normal, direction);
+            if denom.abs() < 1e-8 {
+                return None;
+            }
+            let t = (d - dot(normal, origin)) / denom;
+            Some(*origin + t * *direction - position)
+        };
+        Some((
+            offset(&differential.rx_origin, &differential.rx_direction)?,
+            offset(&differential.ry_origin, &differential.ry_direction)?,
+        ))
+    }
+
+    /// Distances along the ray that count as hits: everything beyond `epsilon` times the
+    /// largest coordinate of the origin, so the surface the ray leaves is not hit again.
+    /// Every traced ray asks for its interval, so this is also where rays that cannot be traced
+    /// are caught in debug builds.
+    pub fn hit_interval(&self, epsilon: Real) -> Interval {
+        debug_assert!(
+            self.has_direction(),
+            "Tracing a ray without a direction from {:?}; was a Ray::default() never overwritten?",
+            self.origin
+        );
+        let scale = self
+            .origin
+            .x
+            .abs()
+            .max(self.origin.y.abs())
+            .max(self.origin.z.abs());
+        Interval::new(epsilon * scale.max(MIN_OFFSET_SCALE), Real::MAX)
+    }
+
+    pub fn direction(&self) -> Vec3 {
+        self.direction
+    }
+
+    pub fn inv_direction(&self) -> Vec3 {
+        self.inv_direction
+    }
+
+    pub fn at(&self, t: Real) -> Vec3 {
         self.origin + t * self.direction
     }
 
+    /// False for the zero direction of `Ray::default` or of `Ray::new` given a zero vector,
+    /// along which a ray would only ever see its own origin.
+    pub fn has_direction(&self) -> bool {
+        self.direction.length_squared() > 0.0
+    }
+
+    /// Placeholder with a zero direction, for out-parameters such as `Material::scatter`'s
+    /// that are overwritten before use. It cannot be traced; see `has_direction`.
     pub fn default() -> Self {
         Self {
             origin: Vec3::origin(),
             direction: Vec3::origin(),
+            differential: None,
+            time: 0.0,
+            inv_direction: Vec3::new(Real::INFINITY, Real::INFINITY, Real::INFINITY),
         }
     }
 }
@@ -39,7 +141,7 @@ mod tests {
         // Check that the ray's origin is correctly set
         assert_eq!(ray.origin, origin);
         // Check that the direction is normalized
-        assert_eq!(ray.direction, Vec3::new(0.0, 1.0, 0.0)); // already normalized
+        assert_eq!(ray.direction(), Vec3::new(0.0, 1.0, 0.0)); // already normalized
 
         // Test the `at` function at t = 0, which should return the origin
         let point_at_t_0 = ray.at(0.0);
@@ -57,7 +159,7 @@ mod tests {
         let direction = Vec3::zero(); // zero direction vector
         let ray = Ray::new(origin, direction);
 
-        assert_eq!(ray.direction, Vec3::zero());
+        assert_eq!(ray.direction(), Vec3::zero());
     }
 
     #[test]
@@ -74,4 +176,30 @@ mod tests {
         let point_at_t_2 = ray.at(2.0);
         assert_eq!(point_at_t_2, Vec3::new(3.0, 2.0, 3.0)); // origin + 2 * direction
     }
+
+    #[test]
+    fn test_inverse_direction_is_precomputed() {
+        let ray = Ray::new(Vec3::zero(), Vec3::new(3.0, -4.0, 0.0));
+        let inverse = ray.inv_direction();
+        assert_eq!(inverse.x, 1.0 / ray.direction().x);
+        assert!((inverse.y + 1.25).abs() < 1e-5);
+        // Parallel to the z slabs, which the slab test treats as inside or missing them
+        assert_eq!(inverse.z, Real::INFINITY);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "Tracing a ray without a direction")]
+    fn test_tracing_a_default_ray_trips_debug_assertion() {
+        assert!(!Ray::default().has_direction());
+        Ray::default().hit_interval(RAY_EPSILON);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "Non-finite ray")]
+    fn test_infinite_direction_trips_debug_assertion() {
+        // inf / inf while normalizing turns the direction into NaN
+        Ray::new(Vec3::zero(), Vec3::new(Real::INFINITY, 1.0, 0.0));
+    }
 }
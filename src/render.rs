@@ -0,0 +1,684 @@
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::aabb::{Axis, AABB};
+use crate::accumulator::{Accumulator, RenderOutput};
+use crate::camera::Camera;
+use crate::color::ColorSpace;
+use crate::entities::entity::EntityList;
+use crate::interval::Interval;
+use crate::math::{
+    rand::{seed_sample_rng, with_camera_rng},
+    vec3::Vec3,
+    Real,
+};
+use crate::ray::Ray;
+use crate::Bitmap;
+
+pub const TILE_SIZE: u32 = 16;
+
+/// Hooks for embedders that want to follow render progress. Callbacks are invoked from the
+/// worker threads, so implementations must be cheap and thread-safe.
+pub trait RenderObserver: Sync {
+    fn on_tile_complete(&self, _tile_index: u32, _total: u32) {}
+    fn on_pixel(&self, _n: u32, _total: u32) {}
+}
+
+/// Shared flag used to stop a render early. Clones observe the same flag, so the token can be
+/// handed to a signal handler or UI thread while the render holds another copy.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Release);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+}
+
+/// Order in which pixels are handed to the worker threads. Work is split into units (a row or
+/// a `TILE_SIZE` square); workers pull whole units off a shared counter.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum PixelOrder {
+    /// One row at a time, left to right.
+    Scanline,
+    /// Square tiles in row-major order.
+    #[default]
+    Tiled,
+    /// Square tiles visited along a Z-order curve, with the pixels inside each tile in Z-order
+    /// too, so consecutive rays stay spatially close and reuse the same BVH nodes.
+    Morton,
+}
+
+impl PixelOrder {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "scanline" => Some(PixelOrder::Scanline),
+            "tiled" => Some(PixelOrder::Tiled),
+            "morton" => Some(PixelOrder::Morton),
+            _ => None,
+        }
+    }
+
+    /// Splits a `width` x `height` image into work units, each listing its pixels in the order
+    /// they are traced.
+    pub fn work_units(&self, width: u32, height: u32) -> Vec<Vec<(u32, u32)>> {
+        let tiles_x = width.div_ceil(TILE_SIZE);
+        let tiles_y = height.div_ceil(TILE_SIZE);
+        let tile = |x0: u32, y0: u32, morton: bool| -> Vec<(u32, u32)> {
+            let x1 = u32::min(x0 + TILE_SIZE, width);
+            let y1 = u32::min(y0 + TILE_SIZE, height);
+            if morton {
+                (0..TILE_SIZE * TILE_SIZE)
+                    .map(morton_decode)
+                    .map(|(dx, dy)| (x0 + dx, y0 + dy))
+                    .filter(|&(x, y)| x < x1 && y < y1)
+                    .collect()
+            } else {
+                (y0..y1)
+                    .flat_map(|y| (x0..x1).map(move |x| (x, y)))
+                    .collect()
+            }
+        };
+        match self {
+            PixelOrder::Scanline => (0..height)
+                .map(|y| (0..width).map(|x| (x, y)).collect())
+                .collect(),
+            PixelOrder::Tiled => (0..tiles_x * tiles_y)
+                .map(|t| tile((t % tiles_x) * TILE_SIZE, (t / tiles_x) * TILE_SIZE, false))
+                .collect(),
+            PixelOrder::Morton => {
+                let side = u32::max(tiles_x, tiles_y).next_power_of_two();
+                (0..side * side)
+                    .map(morton_decode)
+                    .filter(|&(tx, ty)| tx < tiles_x && ty < tiles_y)
+                    .map(|(tx, ty)| tile(tx * TILE_SIZE, ty * TILE_SIZE, true))
+                    .collect()
+            }
+        }
+    }
+}
+
+/// Splits a Z-order index into its (x, y) coordinates by de-interleaving the bits.
+fn morton_decode(index: u32) -> (u32, u32) {
+    let compact = |mut v: u32| {
+        v &= 0x5555_5555;
+        v = (v | (v >> 1)) & 0x3333_3333;
+        v = (v | (v >> 2)) & 0x0F0F_0F0F;
+        v = (v | (v >> 4)) & 0x00FF_00FF;
+        v = (v | (v >> 8)) & 0x0000_FFFF;
+        v
+    };
+    (compact(index), compact(index >> 1))
+}
+
+/// Renders `entities` into `bitmap` using `thread_count` workers pulling work units of `order`
+/// off a shared counter. Blocks until every unit is done or `cancel` is raised; the token is
+/// checked between units, so a cancelled render leaves whole units either finished or
+/// untouched. Returns `false` if the render was cancelled before completing.
+pub fn render_to_buffer(
+    thread_count: u32,
+    order: PixelOrder,
+    bitmap: &Mutex<Bitmap>,
+    entities: &EntityList,
+    camera: &Camera,
+    cancel: &CancelToken,
+    observer: Option<&dyn RenderObserver>,
+) -> bool {
+    let (image_width, image_height) = {
+        let bitmap = bitmap.lock().unwrap();
+        (bitmap.width as u32, bitmap.height as u32)
+    };
+    let units = order.work_units(image_width, image_height);
+    let tile_count = units.len() as u32;
+    let pixel_count = image_width * image_height;
+    let next_tile = AtomicU32::new(0);
+    let pixels_done = AtomicU32::new(0);
+
+    thread::scope(|scope| {
+        for worker in 0..thread_count {
+            let (next_tile, pixels_done) = (&next_tile, &pixels_done);
+            let units = &units;
+            scope.spawn(move || {
+                let data: *mut u8 = bitmap.lock().unwrap().data.as_mut().unwrap().as_mut_ptr();
+                let mut units_done = 0;
+                loop {
+                    if cancel.is_cancelled() {
+                        break;
+                    }
+                    let tile = next_tile.fetch_add(1, Ordering::Relaxed);
+                    if tile >= tile_count {
+                        break;
+                    }
+                    units_done += 1;
+                    for &(x, y) in &units[tile as usize] {
+                        let offset = ((y * image_width + x) * 4) as usize;
+                        let [ib, ig, ir] = shade_pixel(camera, entities, x, y);
+                        unsafe {
+                            data.add(offset).write(ib);
+                            data.add(offset + 1).write(ig);
+                            data.add(offset + 2).write(ir);
+                            data.add(offset + 3).write(0xFF);
+                        }
+                        let done = pixels_done.fetch_add(1, Ordering::Relaxed) + 1;
+                        if let Some(observer) = observer {
+                            observer.on_pixel(done, pixel_count);
+                        }
+                    }
+                    if let Some(observer) = observer {
+                        observer.on_tile_complete(tile, tile_count);
+                    }
+                }
+                log::debug!("Worker {} rendered {} work units", worker, units_done);
+            });
+        }
+    });
+    !cancel.is_cancelled()
+}
+
+/// Headless render into floats. Pixel `index` (row-major) is traced up to `target(index)`
+/// samples, seeded from the camera's seed: the camera's own count everywhere for uniform
+/// sampling, or per-pixel counts such as `adaptive::sample_targets` for adaptive sampling.
+pub fn render(
+    thread_count: u32,
+    (width, height): (u32, u32),
+    entities: &EntityList,
+    camera: &Camera,
+    cancel: &CancelToken,
+    target: impl Fn(usize) -> u32 + Sync,
+) -> RenderOutput {
+    let mut accumulator = Accumulator::new(width, height, camera.seed);
+    accumulator.accumulate_by(thread_count, entities, camera, cancel, target);
+    accumulator.output()
+}
+
+/// Traces every sample of pixel (x, y), each on its own stream, so the pixel comes out the
+/// same as the accumulating renderer's `trace_sample` calls would make it.
+fn shade_pixel(camera: &Camera, entities: &EntityList, x: u32, y: u32) -> [u8; 3] {
+    let mut color = Vec3::zero();
+    for (sample_index, ray) in camera.rays_for_pixel(x, y) {
+        seed_sample_rng(camera.seed, x, y, sample_index);
+        color += sample_radiance(camera, entities, &ray, x, y);
+    }
+    color *= camera.pixel_samples_scale();
+    color_to_bgr(color, camera.color_space)
+}
+
+/// Traces sample `sample_index` of pixel (x, y). Its ray is drawn from the sample's camera
+/// stream and the tracing from its own stream, so the result does not depend on which thread
+/// or pass traces it.
+pub fn trace_sample(
+    camera: &Camera,
+    entities: &EntityList,
+    seed: u64,
+    x: u32,
+    y: u32,
+    sample_index: u32,
+) -> Vec3 {
+    let ray = with_camera_rng(seed, x, y, sample_index, || {
+        camera.sample_ray(x, y, sample_index)
+    });
+    seed_sample_rng(seed, x, y, sample_index);
+    sample_radiance(camera, entities, &ray, x, y)
+}
+
+fn sample_radiance(camera: &Camera, entities: &EntityList, ray: &Ray, x: u32, y: u32) -> Vec3 {
+    let color = camera.clamp_sample(camera.ray_color(ray, entities, 0));
+    debug_assert!(
+        color.is_finite(),
+        "Sample of pixel ({}, {}) is {:?}",
+        x,
+        y,
+        color
+    );
+    color
+}
+
+/// Returns the color encoded in `color_space`, in the bitmap's BGR byte order.
+pub fn color_to_bgr(color: Vec3, color_space: ColorSpace) -> [u8; 3] {
+    let intensity = Interval::new(0.0, 0.999);
+    let ir = (255.99 * intensity.clamp(color_space.encode(color.x))) as u8;
+    let ig = (255.99 * intensity.clamp(color_space.encode(color.y))) as u8;
+    let ib = (255.99 * intensity.clamp(color_space.encode(color.z))) as u8;
+    [ib, ig, ir]
+}
+
+/// Scales linear radiance by `2^stops`, an exposure change of `stops` photographic stops. Only
+/// the encoding clamps, so brightened highlights clip there rather than here.
+pub fn expose(colors: &[Vec3], stops: Real) -> Vec<Vec3> {
+    let scale = Real::powf(2.0, stops);
+    colors.iter().map(|color| *color * scale).collect()
+}
+
+/// Writes one linear color per pixel into `bitmap`, row by row.
+pub fn write_colors(colors: &[Vec3], color_space: ColorSpace, bitmap: &mut Bitmap) {
+    let data = bitmap.data.as_mut().unwrap();
+    for (idx, color) in colors.iter().enumerate() {
+        let offset = idx * 4;
+        data[offset..offset + 3].copy_from_slice(&color_to_bgr(*color, color_space));
+        data[offset + 3] = 0xFF;
+    }
+}
+
+/// Wireframe colors of `draw_bounds`, cycled by node depth.
+const BOUNDS_COLORS: [[u8; 3]; 6] = [
+    [255, 64, 64],
+    [64, 255, 64],
+    [64, 128, 255],
+    [255, 255, 64],
+    [255, 64, 255],
+    [64, 255, 255],
+];
+
+/// Draws the edges of `bounds` over the image in `bitmap`, as seen through the centre of each
+/// pixel, in a color per depth. Edges hidden behind geometry are drawn too, since the point
+/// is to see where the boxes are. Where boxes overlap the shallowest one wins.
+pub fn draw_bounds(bitmap: &mut Bitmap, camera: &Camera, bounds: &[(AABB, u32)]) {
+    let width = bitmap.width as u32;
+    let height = bitmap.height as u32;
+    // Width of a pixel at unit distance from the camera
+    let pixel_angle = camera.pixel_delta_x().length() / camera.focus_distance();
+    let data = bitmap.data.as_mut().unwrap();
+    for y in 0..height {
+        for x in 0..width {
+            let ray = camera.center_ray(x, y);
+            let shallowest = bounds
+                .iter()
+                .filter(|(bbox, _)| on_box_edge(bbox, &ray, pixel_angle))
+                .map(|(_, depth)| *depth)
+                .min();
+            if let Some(depth) = shallowest {
+                let [r, g, b] = BOUNDS_COLORS[depth as usize % BOUNDS_COLORS.len()];
+                let offset = ((y * width + x) * 4) as usize;
+                data[offset..offset + 4].copy_from_slice(&[b, g, r, 0xFF]);
+            }
+        }
+    }
+}
+
+/// True if `ray` passes within about half a pixel of one of the twelve edges of `bbox`.
+fn on_box_edge(bbox: &AABB, ray: &Ray, pixel_angle: Real) -> bool {
+    Axis::ALL.iter().any(|&axis| {
+        // Edges along `axis` sit at the corners of the box's cross-section in the other two
+        let (k, i, j) = (
+            axis as usize,
+            (axis as usize + 1) % 3,
+            (axis as usize + 2) % 3,
+        );
+        let (di, dj) = (ray.direction()[i], ray.direction()[j]);
+        let across = di * di + dj * dj;
+        if across < 1e-12 {
+            return false;
+        }
+        let (slab_i, slab_j) = (bbox.get_axis(Axis::ALL[i]), bbox.get_axis(Axis::ALL[j]));
+        [slab_i.min, slab_i.max].into_iter().any(|a| {
+            [slab_j.min, slab_j.max].into_iter().any(|b| {
+                // Closest approach of the ray to the infinite line through the edge
+                let (oi, oj) = (a - ray.origin[i], b - ray.origin[j]);
+                let t = (oi * di + oj * dj) / across;
+                if t <= 0.0 {
+                    return false;
+                }
+                let (ei, ej) = (oi - t * di, oj - t * dj);
+                let tolerance = 0.5 * t * pixel_angle;
+                ei * ei + ej * ej < tolerance * tolerance
+                    && bbox.get_axis(axis).contains_eps(ray.at(t)[k], tolerance)
+            })
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::create_bitmap;
+    use crate::entities::entity::Hittable;
+    use crate::entities::quad::create_box;
+    use crate::entities::sphere::Sphere;
+    use crate::material::{Lambertian, Normals};
+    use crate::math::vec3::dot;
+    use crate::texture::Texture;
+
+    #[derive(Default)]
+    struct CountingObserver {
+        tiles: AtomicU32,
+        pixels: AtomicU32,
+    }
+
+    impl RenderObserver for CountingObserver {
+        fn on_tile_complete(&self, _tile_index: u32, _total: u32) {
+            self.tiles.fetch_add(1, Ordering::Relaxed);
+        }
+
+        fn on_pixel(&self, _n: u32, _total: u32) {
+            self.pixels.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn test_observer_receives_every_tile_and_pixel() {
+        let (width, height) = (40, 20);
+        let bitmap = Mutex::new(create_bitmap(width as i32, height as i32));
+        let mut camera = Camera::new(
+            width,
+            height,
+            90.0,
+            &Vec3::new(0.0, 0.0, 1.0),
+            &Vec3::zero(),
+        );
+        camera.set_samples_per_pixel(1);
+        let observer = CountingObserver::default();
+
+        render_to_buffer(
+            4,
+            PixelOrder::Tiled,
+            &bitmap,
+            &EntityList::new(),
+            &camera,
+            &CancelToken::new(),
+            Some(&observer),
+        );
+
+        assert_eq!(observer.pixels.load(Ordering::Relaxed), width * height);
+        assert_eq!(observer.tiles.load(Ordering::Relaxed), 3 * 2);
+    }
+
+    struct CancelAfterFirstTile(CancelToken);
+
+    impl RenderObserver for CancelAfterFirstTile {
+        fn on_tile_complete(&self, _tile_index: u32, _total: u32) {
+            self.0.cancel();
+        }
+    }
+
+    #[test]
+    fn test_cancel_mid_render_leaves_partial_buffer() {
+        let (width, height) = (64, 64);
+        let bitmap = Mutex::new(create_bitmap(width as i32, height as i32));
+        let mut camera = Camera::new(
+            width,
+            height,
+            90.0,
+            &Vec3::new(0.0, 0.0, 1.0),
+            &Vec3::zero(),
+        );
+        camera.set_samples_per_pixel(1);
+        let cancel = CancelToken::new();
+        let observer = CancelAfterFirstTile(cancel.clone());
+
+        let completed = render_to_buffer(
+            1,
+            PixelOrder::Tiled,
+            &bitmap,
+            &EntityList::new(),
+            &camera,
+            &cancel,
+            Some(&observer),
+        );
+
+        assert!(!completed);
+        let bitmap = bitmap.lock().unwrap();
+        let alpha: Vec<u8> = bitmap
+            .data
+            .as_ref()
+            .unwrap()
+            .iter()
+            .skip(3)
+            .step_by(4)
+            .copied()
+            .collect();
+        let written = alpha.iter().filter(|&&a| a == 0xFF).count() as u32;
+        assert_eq!(written, TILE_SIZE * TILE_SIZE);
+    }
+
+    #[test]
+    fn test_morton_decode_follows_z_curve() {
+        let points: Vec<(u32, u32)> = (0..8).map(morton_decode).collect();
+        assert_eq!(
+            points,
+            [
+                (0, 0),
+                (1, 0),
+                (0, 1),
+                (1, 1),
+                (2, 0),
+                (3, 0),
+                (2, 1),
+                (3, 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_every_order_covers_each_pixel_once() {
+        let (width, height) = (37, 50);
+        for order in [PixelOrder::Scanline, PixelOrder::Tiled, PixelOrder::Morton] {
+            let mut seen = vec![0u32; (width * height) as usize];
+            for (x, y) in order.work_units(width, height).into_iter().flatten() {
+                seen[(y * width + x) as usize] += 1;
+            }
+            assert!(seen.iter().all(|&count| count == 1), "{:?}", order);
+        }
+    }
+
+    #[test]
+    fn test_output_independent_of_thread_count() {
+        let (width, height) = (32, 24);
+        let mut entities = EntityList::new();
+        entities.add(Box::new(Sphere::new(
+            Vec3::zero(),
+            0.5,
+            Arc::new(Lambertian::new(Box::new(Texture::new(Vec3::new(
+                0.8, 0.3, 0.3,
+            ))))),
+        )));
+        let mut camera = Camera::new(
+            width,
+            height,
+            60.0,
+            &Vec3::new(0.0, 0.0, 2.0),
+            &Vec3::zero(),
+        );
+        camera.set_background_color(&Vec3::new(0.7, 0.8, 1.0));
+        camera.set_samples_per_pixel(4);
+        camera.seed = 17;
+
+        let render = |thread_count: u32| {
+            let bitmap = Mutex::new(create_bitmap(width as i32, height as i32));
+            render_to_buffer(
+                thread_count,
+                PixelOrder::Morton,
+                &bitmap,
+                &entities,
+                &camera,
+                &CancelToken::new(),
+                None,
+            );
+            bitmap.into_inner().unwrap().data.unwrap()
+        };
+        assert_eq!(render(1), render(8));
+    }
+
+    #[test]
+    fn test_tile_and_float_renders_match_for_the_same_seed() {
+        let (width, height) = (16, 12);
+        let mut entities = EntityList::new();
+        entities.add(Box::new(Sphere::new(
+            Vec3::zero(),
+            0.5,
+            Arc::new(Lambertian::new(Box::new(Texture::new(Vec3::new(
+                0.8, 0.3, 0.3,
+            ))))),
+        )));
+        let mut camera = Camera::new(
+            width,
+            height,
+            60.0,
+            &Vec3::new(0.0, 0.0, 2.0),
+            &Vec3::zero(),
+        );
+        camera.set_background_color(&Vec3::new(0.7, 0.8, 1.0));
+        camera.set_samples_per_pixel(16);
+        camera.seed = 29;
+
+        let tiled = Mutex::new(create_bitmap(width as i32, height as i32));
+        render_to_buffer(
+            2,
+            PixelOrder::Tiled,
+            &tiled,
+            &entities,
+            &camera,
+            &CancelToken::new(),
+            None,
+        );
+        let float = render(
+            2,
+            (width, height),
+            &entities,
+            &camera,
+            &CancelToken::new(),
+            |_| 16,
+        );
+        let mut float_bitmap = create_bitmap(width as i32, height as i32);
+        write_colors(&float.colors, camera.color_space, &mut float_bitmap);
+
+        // Both trace every sample on its own stream, so only the summing code differs
+        assert_eq!(tiled.into_inner().unwrap().data, float_bitmap.data);
+    }
+
+    #[test]
+    fn test_float_render_reports_samples_per_pixel() {
+        use crate::adaptive::{capture_geometry, edge_map, sample_targets};
+
+        let (width, height) = (24, 16);
+        let mut entities = EntityList::new();
+        entities.add(Box::new(Sphere::new(Vec3::zero(), 0.6, Arc::new(Normals))));
+        let mut camera = Camera::new(
+            width,
+            height,
+            60.0,
+            &Vec3::new(0.0, 0.0, 2.0),
+            &Vec3::zero(),
+        );
+        camera.set_samples_per_pixel(4);
+        let cancel = CancelToken::new();
+
+        let uniform = render(4, (width, height), &entities, &camera, &cancel, |_| 4);
+        assert_eq!(uniform.colors.len(), (width * height) as usize);
+        assert_eq!(uniform.sample_range(), (4, 4));
+
+        // Pixels on the silhouette get more samples than the flat interior and background
+        let geometry = capture_geometry(&camera, &entities, width, height);
+        let targets = sample_targets(&edge_map(&geometry, width, height), 4, 16);
+        let adaptive = render(4, (width, height), &entities, &camera, &cancel, |index| {
+            targets[index]
+        });
+        assert_eq!(adaptive.sample_counts, targets);
+        assert_eq!(adaptive.sample_range(), (4, 16));
+    }
+
+    #[test]
+    fn test_one_stop_up_doubles_mid_gray() {
+        let gray = Vec3::new(0.18, 0.18, 0.18);
+        let colors = vec![gray, Vec3::new(0.9, 0.9, 0.9)];
+        let ev0 = expose(&colors, 0.0);
+        let ev1 = expose(&colors, 1.0);
+        assert_eq!(ev0[0], gray);
+        assert!((ev1[0].x - 2.0 * ev0[0].x).abs() < 1e-6, "{}", ev1[0]);
+        assert!((expose(&colors, -2.0)[0].x - 0.045).abs() < 1e-6);
+        // Highlights go past 1 before the encoding clips them
+        assert!(ev1[1].x > 1.0);
+
+        let encoded = |colors: &[Vec3]| {
+            let mut bitmap = create_bitmap(2, 1);
+            write_colors(colors, ColorSpace::Srgb, &mut bitmap);
+            bitmap.data.unwrap()
+        };
+        let (dark, bright) = (encoded(&ev0), encoded(&ev1));
+        assert!(bright[0] > dark[0]);
+        assert_eq!(bright[4], 255);
+    }
+
+    #[test]
+    fn test_box_bounds_draw_the_projected_outline() {
+        let (width, height) = (64, 64);
+        let entities = create_box(
+            Vec3::new(-1.0, -1.0, -1.0),
+            Vec3::new(1.0, 1.0, 1.0),
+            Arc::new(Normals),
+        )
+        .into_bvh();
+        let mut bounds = Vec::new();
+        entities.node_bounds(0, &mut bounds);
+        assert_eq!(bounds.len(), 1);
+        // From above and to the side, so the hidden back edges project inside the outline
+        let camera = Camera::new(
+            width,
+            height,
+            50.0,
+            &Vec3::new(1.5, 2.0, 5.0),
+            &Vec3::zero(),
+        );
+        let mut bitmap = create_bitmap(width as i32, height as i32);
+        draw_bounds(&mut bitmap, &camera, &bounds);
+        let data = bitmap.data.as_ref().unwrap();
+        let is_drawn = |x: i64, y: i64| {
+            let offset = ((y * width as i64 + x) * 4) as usize;
+            data[offset..offset + 3] != [0, 0, 0]
+        };
+
+        // Pixel whose center ray points most directly at a world point
+        let project = |p: Vec3| {
+            let towards = |&(x, y): &(u32, u32)| {
+                let ray = camera.center_ray(x, y);
+                dot(&ray.direction(), &(p - ray.origin).normalize())
+            };
+            let (x, y) = (0..height)
+                .flat_map(|y| (0..width).map(move |x| (x, y)))
+                .max_by(|a, b| towards(a).total_cmp(&towards(b)))
+                .unwrap();
+            (x as i64, y as i64)
+        };
+        let corner = |i: usize| {
+            let pick = |bit: usize| if i & bit == 0 { -1.0 } else { 1.0 };
+            Vec3::new(pick(1), pick(2), pick(4))
+        };
+        for a in 0..8 {
+            for bit in [1, 2, 4] {
+                let b = a | bit;
+                if b == a {
+                    continue;
+                }
+                for fraction in [0.25, 0.5, 0.75] {
+                    let (x, y) = project(corner(a) + fraction * (corner(b) - corner(a)));
+                    let near = (-1..=1).any(|dy| (-1..=1).any(|dx| is_drawn(x + dx, y + dy)));
+                    assert!(near, "edge {}-{} not drawn near ({}, {})", a, b, x, y);
+                }
+            }
+        }
+
+        // Face interiors and the background stay untouched
+        let (x, y) = project(Vec3::new(0.0, 0.0, 1.0));
+        assert!(!is_drawn(x, y));
+        let (x, y) = project(Vec3::new(0.0, 1.0, 0.0));
+        assert!(!is_drawn(x, y));
+        assert!(!is_drawn(0, 0) && !is_drawn(63, 63));
+        let drawn = (0..height as i64)
+            .flat_map(|y| (0..width as i64).map(move |x| (x, y)))
+            .filter(|&(x, y)| is_drawn(x, y))
+            .count();
+        assert!(
+            drawn < (width * height / 4) as usize,
+            "{} pixels drawn",
+            drawn
+        );
+    }
+}
@@ -0,0 +1,339 @@
+use std::fmt;
+#[cfg(feature = "serde")]
+use std::fs::File;
+#[cfg(feature = "serde")]
+use std::io::{self, BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+#[cfg(feature = "serde")]
+use crate::camera::Camera;
+#[cfg(feature = "serde")]
+use crate::entities::entity::EntityDesc;
+use crate::entities::entity::{EntityList, HitRecord, Hittable};
+use crate::interval::Interval;
+use crate::math::{consts::PI, vec3::Vec3, Real};
+use crate::ray::Ray;
+
+/// Directions probed around each light by `Scene::lighting`.
+const ENCLOSURE_PROBES: usize = 256;
+/// Lights from which fewer than this fraction of probe rays escape the scene count as walled
+/// in. A Cornell box, open towards the camera, lets out about a sixth.
+pub const ENCLOSED_ESCAPE_FRACTION: Real = 0.3;
+
+/// How a scene is lit, as far as `Scene::lighting` can tell from its lights and geometry.
+/// Picks the sample count used when none is given.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lighting {
+    /// No emitters: light only comes from the background, which most paths reach directly.
+    Sky,
+    /// Emitters that shine out into the open, so many paths still escape to the background.
+    OpenEmitters,
+    /// Emitters surrounded by walls, like a Cornell box. Nearly every path has to find a
+    /// small light after several bounces, which takes many samples to converge.
+    EnclosedEmitters,
+}
+
+impl Lighting {
+    /// Heuristic samples per pixel: 100 for sky-lit scenes, 400 for open ones with emitters
+    /// and 1000 for enclosed ones. `--spp` always takes precedence.
+    pub fn default_spp(self) -> u32 {
+        match self {
+            Lighting::Sky => 100,
+            Lighting::OpenEmitters => 400,
+            Lighting::EnclosedEmitters => 1000,
+        }
+    }
+}
+
+impl fmt::Display for Lighting {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Lighting::Sky => "sky-lit",
+            Lighting::OpenEmitters => "open, lit by emitters",
+            Lighting::EnclosedEmitters => "enclosed, lit by emitters",
+        })
+    }
+}
+
+/// Entities to render together with the emitters among them. Lights are kept in their own list
+/// so that light sampling does not have to search the whole scene for them. Because each light
+/// is shared between both lists, `entities` cannot be transformed once a light has been added.
+pub struct Scene {
+    pub entities: EntityList,
+    pub lights: Vec<Arc<dyn Hittable>>,
+}
+
+/// Layout of a scene file.
+#[cfg(feature = "serde")]
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct SceneDesc {
+    camera: Camera,
+    entities: Vec<EntityDesc>,
+}
+
+impl Scene {
+    pub fn new() -> Self {
+        Self {
+            entities: EntityList::new(),
+            lights: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, object: Box<dyn Hittable>) {
+        self.entities.add(object);
+    }
+
+    /// Adds an emitter to the scene and registers it as a light. The light is shared with the
+    /// entity list, so it has to be positioned before it is added: from then on, translating
+    /// or rotating the light or `entities` panics, since a shared `Arc` cannot be mutated.
+    pub fn add_light(&mut self, light: Arc<dyn Hittable>) {
+        self.entities.add(Box::new(Arc::clone(&light)));
+        self.lights.push(light);
+    }
+
+    /// Human-readable listing of the scene: one line per entity with its type, placement,
+    /// material and bounds, and nested entities indented below their parent.
+    pub fn describe(&self) -> String {
+        fn describe_entity(entity: &dyn Hittable, depth: usize, out: &mut String) {
+            out.push_str(&format!(
+                "{:indent$}{}, bounds {:.3}\n",
+                "",
+                entity.summary(),
+                entity.get_aabb(),
+                indent = 2 * depth
+            ));
+            for child in entity.children() {
+                describe_entity(child, depth + 1, out);
+            }
+        }
+        let mut out = format!(
+            "{} entities, {} lights\n",
+            self.entities.len(),
+            self.lights.len()
+        );
+        for entity in self.entities.iter() {
+            describe_entity(entity, 0, &mut out);
+        }
+        out
+    }
+
+    /// Classifies the lighting by casting rays in evenly spread directions from the center of
+    /// each light and counting those that leave the scene without hitting anything. Probes
+    /// start where they leave the light's bounds, so the light does not block itself.
+    pub fn lighting(&self) -> Lighting {
+        if self.lights.is_empty() {
+            return Lighting::Sky;
+        }
+        // Offsets scale with the scene, like the camera's ray epsilon
+        let radius = self
+            .entities
+            .bounding_sphere()
+            .map_or(0.0, |(_, radius)| radius);
+        let epsilon = 1e-4 * radius.max(1.0);
+        let mut escaped = 0;
+        for light in &self.lights {
+            let aabb = light.get_aabb();
+            let center = Vec3::new(aabb.x.midpoint(), aabb.y.midpoint(), aabb.z.midpoint());
+            for direction in probe_directions() {
+                let ray = Ray::new(center, direction);
+                let Some(inside) = aabb.hit_interval(&ray, Interval::new(0.0, Real::MAX)) else {
+                    continue;
+                };
+                let mut record = HitRecord::new();
+                let outside = Interval::new(inside.max + epsilon, Real::MAX);
+                if !self.entities.hit(&ray, &outside, &mut record) {
+                    escaped += 1;
+                }
+            }
+        }
+        let probes = self.lights.len() * ENCLOSURE_PROBES;
+        if (escaped as Real) < ENCLOSED_ESCAPE_FRACTION * probes as Real {
+            Lighting::EnclosedEmitters
+        } else {
+            Lighting::OpenEmitters
+        }
+    }
+
+    /// Writes the scene and `camera` to `path` as JSON. BVHs are stored as their primitives.
+    /// Fails with `InvalidInput` if an entity, material or texture has no scene-file form.
+    #[cfg(feature = "serde")]
+    pub fn save(&self, camera: &Camera, path: &Path) -> io::Result<()> {
+        let entities = self.entities.describe().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Scene contains an entity, material or texture that cannot be exported",
+            )
+        })?;
+        let desc = SceneDesc {
+            camera: camera.clone(),
+            entities,
+        };
+        serde_json::to_writer_pretty(BufWriter::new(File::create(path)?), &desc)?;
+        Ok(())
+    }
+
+    /// Reads a scene written by `save`. Top-level emissive entities are registered as lights.
+    /// A camera without a sample count gets the default for the scene's `lighting`. Fails with
+    /// `InvalidData` if an entity does not form a valid shape.
+    #[cfg(feature = "serde")]
+    pub fn load(path: &Path) -> io::Result<(Scene, Camera)> {
+        let mut desc: SceneDesc = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+        let mut scene = Scene::new();
+        for entity in &desc.entities {
+            if entity.is_emissive() {
+                scene.add_light(Arc::from(entity.build()?));
+            } else {
+                scene.add(entity.build()?);
+            }
+        }
+        // Always set, since the file only carries the sample count and not the strata and
+        // scales derived from it
+        let samples_per_pixel = match desc.camera.samples_per_pixel() {
+            0 => scene.lighting().default_spp(),
+            samples_per_pixel => samples_per_pixel,
+        };
+        desc.camera.set_samples_per_pixel(samples_per_pixel);
+        Ok((scene, desc.camera))
+    }
+}
+
+/// Unit vectors spread evenly over the sphere along a Fibonacci spiral.
+fn probe_directions() -> impl Iterator<Item = Vec3> {
+    let golden_angle = PI * (3.0 - Real::sqrt(5.0));
+    (0..ENCLOSURE_PROBES).map(move |i| {
+        let z = 1.0 - (2 * i + 1) as Real / ENCLOSURE_PROBES as Real;
+        let r = Real::sqrt(1.0 - z * z);
+        let phi = golden_angle * i as Real;
+        Vec3::new(r * phi.cos(), r * phi.sin(), z)
+    })
+}
+
+/// Polls a scene file for modifications. A change is reported once the file has stayed
+/// untouched for `debounce`, so an editor that saves in several writes triggers one reload.
+#[derive(Debug, Clone)]
+pub struct SceneWatcher {
+    pub path: PathBuf,
+    debounce: Duration,
+    modified: Option<SystemTime>,
+    changed_at: Option<Instant>,
+}
+
+impl SceneWatcher {
+    pub fn new(path: &Path, debounce: Duration) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            debounce,
+            modified: Self::modified(path),
+            changed_at: None,
+        }
+    }
+
+    fn modified(path: &Path) -> Option<SystemTime> {
+        std::fs::metadata(path)
+            .and_then(|meta| meta.modified())
+            .ok()
+    }
+
+    /// Returns `true` once per settled change. A missing file counts as unchanged, since
+    /// editors often replace the file by deleting and renaming.
+    pub fn poll(&mut self) -> bool {
+        let modified = Self::modified(&self.path);
+        if modified.is_some() && modified != self.modified {
+            self.modified = modified;
+            self.changed_at = Some(Instant::now());
+            return false;
+        }
+        match self.changed_at {
+            Some(changed_at) if changed_at.elapsed() >= self.debounce => {
+                self.changed_at = None;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+
+    #[test]
+    fn test_watcher_reports_a_burst_of_saves_once() {
+        let path = std::env::temp_dir().join("rustrt_watched_scene.json");
+        let file = File::create(&path).unwrap();
+        let start = SystemTime::now();
+        file.set_modified(start).unwrap();
+        let mut watcher = SceneWatcher::new(&path, Duration::from_millis(50));
+        assert!(!watcher.poll());
+
+        // Three saves in quick succession only settle after the last one
+        for i in 1..=3 {
+            file.set_modified(start + Duration::from_secs(i)).unwrap();
+            assert!(!watcher.poll());
+        }
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(watcher.poll());
+        assert!(!watcher.poll());
+
+        file.set_modified(start + Duration::from_secs(10)).unwrap();
+        assert!(!watcher.poll());
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(watcher.poll());
+        std::fs::remove_file(&path).unwrap();
+        assert!(!watcher.poll());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_loaded_sample_count_sets_up_its_strata() {
+        // A hand-written camera gives the sample count but none of the fields derived from it
+        let mut camera = serde_json::to_value(Camera::default()).unwrap();
+        let fields = camera.as_object_mut().unwrap();
+        for derived in ["sqrt_spp", "recip_sqrt_spp", "pixel_samples_scale"] {
+            fields.remove(derived);
+        }
+        fields.insert("samples_per_pixel".to_string(), 64.into());
+        let desc = serde_json::json!({ "camera": camera, "entities": [] });
+
+        let path = std::env::temp_dir().join("rustrt_loaded_spp_scene.json");
+        std::fs::write(&path, desc.to_string()).unwrap();
+        let (_, camera) = Scene::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(camera.samples_per_pixel(), 64);
+        assert_eq!(camera.sqrt_spp(), 8);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_degenerate_quad_fails_to_load() {
+        use crate::material::MaterialDesc;
+        use crate::math::vec2::Vec2;
+
+        let quad = EntityDesc::Quad {
+            q: Vec3::zero(),
+            u: Vec3::new(1.0, 0.0, 0.0),
+            v: Vec3::zero(),
+            uv_scale: Vec2::one(),
+            cull_backface: false,
+            flip_back_u: false,
+            material: MaterialDesc::Metal {
+                albedo: Vec3::one(),
+                fuzz: 0.0,
+            },
+        };
+        let desc = serde_json::json!({
+            "camera": serde_json::to_value(Camera::default()).unwrap(),
+            "entities": [serde_json::to_value(quad).unwrap()],
+        });
+
+        let path = std::env::temp_dir().join("rustrt_degenerate_quad_scene.json");
+        std::fs::write(&path, desc.to_string()).unwrap();
+        let error = Scene::load(&path).err().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error.to_string().contains("Degenerate quad"), "{}", error);
+    }
+}
@@ -0,0 +1,216 @@
+use std::fmt;
+use std::time::Duration;
+
+use crate::scene::Lighting;
+
+/// Summary printed after a render with `--stats`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderStats {
+    pub width: u32,
+    pub height: u32,
+    pub samples_per_pixel: u32,
+    /// Classification behind the heuristic sample count, shown with the count it suggests.
+    pub lighting: Lighting,
+    pub rays: u64,
+    pub elapsed: Duration,
+    pub peak_memory: Option<u64>,
+    pub bvh_nodes: usize,
+    pub entities: usize,
+    pub lights: usize,
+}
+
+impl RenderStats {
+    pub fn total_samples(&self) -> u64 {
+        self.width as u64 * self.height as u64 * self.samples_per_pixel as u64
+    }
+}
+
+impl fmt::Display for RenderStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let seconds = self.elapsed.as_secs_f64();
+        writeln!(f, "Render statistics")?;
+        writeln!(f, "  Resolution:     {}x{}", self.width, self.height)?;
+        writeln!(f, "  Samples/pixel:  {}", self.samples_per_pixel)?;
+        writeln!(
+            f,
+            "  Lighting:       {} (suggests {} spp)",
+            self.lighting,
+            self.lighting.default_spp()
+        )?;
+        writeln!(f, "  Total samples:  {}", self.total_samples())?;
+        writeln!(
+            f,
+            "  Rays traced:    {} ({:.2} Mrays/s)",
+            self.rays,
+            self.rays as f64 / seconds.max(f64::EPSILON) / 1e6
+        )?;
+        writeln!(f, "  Wall time:      {:.2} s", seconds)?;
+        match self.peak_memory {
+            Some(bytes) => writeln!(
+                f,
+                "  Peak memory:    {:.1} MiB",
+                bytes as f64 / (1024.0 * 1024.0)
+            )?,
+            None => writeln!(f, "  Peak memory:    unavailable")?,
+        }
+        writeln!(f, "  BVH nodes:      {}", self.bvh_nodes)?;
+        writeln!(f, "  Entities:       {}", self.entities)?;
+        write!(f, "  Lights:         {}", self.lights)
+    }
+}
+
+/// Wall time of each phase of a run, printed with `--timing`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PhaseTimings {
+    /// Building or loading the scene, including any BVHs its builder makes, e.g. for meshes.
+    pub scene_build: Duration,
+    /// The BVH over the scene's top-level entities. Lists that build their search index on
+    /// first use do so while tracing.
+    pub bvh_build: Duration,
+    /// Rendering, including the edge prepass and denoising when enabled.
+    pub trace: Duration,
+    /// Writing the image file, or `None` when nothing was written.
+    pub write: Option<Duration>,
+}
+
+impl fmt::Display for PhaseTimings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let write = self.write.unwrap_or_default();
+        let total = (self.scene_build + self.bvh_build + self.trace + write).as_secs_f64();
+        let share = |phase: Duration| 100.0 * phase.as_secs_f64() / total.max(f64::EPSILON);
+        writeln!(f, "Timing breakdown")?;
+        writeln!(
+            f,
+            "  Scene build:    {:.3} s ({:.1}%)",
+            self.scene_build.as_secs_f64(),
+            share(self.scene_build)
+        )?;
+        writeln!(
+            f,
+            "  Top-level BVH:  {:.3} s ({:.1}%)",
+            self.bvh_build.as_secs_f64(),
+            share(self.bvh_build)
+        )?;
+        writeln!(
+            f,
+            "  Trace:          {:.3} s ({:.1}%)",
+            self.trace.as_secs_f64(),
+            share(self.trace)
+        )?;
+        match self.write {
+            Some(write) => write!(
+                f,
+                "  Write:          {:.3} s ({:.1}%)",
+                write.as_secs_f64(),
+                share(write)
+            ),
+            None => write!(f, "  Write:          not written"),
+        }
+    }
+}
+
+/// Peak resident memory of the process in bytes, where the platform reports it.
+#[cfg(windows)]
+pub fn peak_memory() -> Option<u64> {
+    use windows::Win32::System::ProcessStatus::{GetProcessMemoryInfo, PROCESS_MEMORY_COUNTERS};
+    use windows::Win32::System::Threading::GetCurrentProcess;
+
+    let mut counters = PROCESS_MEMORY_COUNTERS::default();
+    let size = std::mem::size_of::<PROCESS_MEMORY_COUNTERS>() as u32;
+    unsafe { GetProcessMemoryInfo(GetCurrentProcess(), &mut counters, size) }.ok()?;
+    Some(counters.PeakWorkingSetSize as u64)
+}
+
+/// Peak resident memory of the process in bytes, where the platform reports it.
+#[cfg(target_os = "linux")]
+pub fn peak_memory() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    let kilobytes = line.split_whitespace().nth(1)?.parse::<u64>().ok()?;
+    Some(kilobytes * 1024)
+}
+
+/// Peak resident memory of the process in bytes, where the platform reports it.
+#[cfg(not(any(windows, target_os = "linux")))]
+pub fn peak_memory() -> Option<u64> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary_lists_every_value() {
+        let stats = RenderStats {
+            width: 800,
+            height: 600,
+            samples_per_pixel: 16,
+            lighting: Lighting::EnclosedEmitters,
+            rays: 30_000_000,
+            elapsed: Duration::from_secs(3),
+            peak_memory: Some(64 * 1024 * 1024),
+            bvh_nodes: 41,
+            entities: 8,
+            lights: 1,
+        };
+        assert_eq!(stats.total_samples(), 7_680_000);
+        let summary = stats.to_string();
+        for expected in [
+            "800x600",
+            "Lighting:       enclosed, lit by emitters (suggests 1000 spp)",
+            "7680000",
+            "10.00 Mrays/s",
+            "3.00 s",
+            "64.0 MiB",
+            "BVH nodes:      41",
+            "Lights:         1",
+        ] {
+            assert!(
+                summary.contains(expected),
+                "missing {:?} in\n{}",
+                expected,
+                summary
+            );
+        }
+
+        let summary = RenderStats {
+            peak_memory: None,
+            ..stats
+        }
+        .to_string();
+        assert!(summary.contains("Peak memory:    unavailable"));
+    }
+
+    #[test]
+    fn test_timing_breakdown_shows_each_phase_and_its_share() {
+        let timings = PhaseTimings {
+            scene_build: Duration::from_millis(500),
+            bvh_build: Duration::from_millis(250),
+            trace: Duration::from_millis(1000),
+            write: Some(Duration::from_millis(250)),
+        };
+        let summary = timings.to_string();
+        for expected in [
+            "Scene build:    0.500 s (25.0%)",
+            "Top-level BVH:  0.250 s (12.5%)",
+            "Trace:          1.000 s (50.0%)",
+            "Write:          0.250 s (12.5%)",
+        ] {
+            assert!(
+                summary.contains(expected),
+                "missing {:?} in\n{}",
+                expected,
+                summary
+            );
+        }
+
+        let summary = PhaseTimings {
+            write: None,
+            ..timings
+        }
+        .to_string();
+        assert!(summary.contains("Trace:          1.000 s (57.1%)"));
+        assert!(summary.contains("Write:          not written"));
+    }
+}
@@ -1,12 +1,46 @@
-use crate::math::{vec2::Vec2, vec3::Vec3};
+use crate::color::{decode_srgb8, ColorSpace};
+use crate::entities::entity::HitRecord;
+use crate::math::{to_f32, vec2::Vec2, vec3::Vec3, Real};
 use crate::perlin_noise::PerlinNoise;
 use std::fmt::Debug;
 
 pub trait TextureSampler: Debug + Send + Sync {
     fn value(&self, uv: &Vec2, p: &Vec3) -> Vec3;
+
+    /// Samples the texture averaged over a pixel footprint that is `uv_footprint` wide in UV
+    /// units and `world_footprint` wide in world units. Textures without prefiltered data fall
+    /// back to point sampling.
+    fn value_filtered(
+        &self,
+        uv: &Vec2,
+        p: &Vec3,
+        _uv_footprint: Real,
+        _world_footprint: Real,
+    ) -> Vec3 {
+        self.value(uv, p)
+    }
+
+    /// Samples the texture with the whole hit at hand, for textures that depend on more than
+    /// the position, such as the normal or the side that was hit. Defaults to `value_filtered`
+    /// over the hit's footprint.
+    fn value_ctx(&self, record: &HitRecord) -> Vec3 {
+        self.value_filtered(
+            &record.uv,
+            &record.position,
+            record.uv_footprint,
+            record.world_footprint,
+        )
+    }
+
+    /// Scene-file description of the texture, or `None` if it cannot be exported.
+    #[cfg(feature = "serde")]
+    fn describe(&self) -> Option<TextureDesc> {
+        None
+    }
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Texture {
     color: Vec3,
 }
@@ -18,56 +52,429 @@ impl Texture {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct CheckerTexture {
-    inv_scale: f32,
+    inv_scale: Real,
     odd: Texture,
     even: Texture,
+    /// Box-filter the pattern over the pixel footprint instead of point sampling it.
+    #[cfg_attr(feature = "serde", serde(default))]
+    smooth: bool,
 }
 
 impl CheckerTexture {
-    pub fn new(odd: Texture, even: Texture, scale: f32) -> Self {
+    pub fn new(odd: Texture, even: Texture, scale: Real) -> Self {
         Self {
             inv_scale: 1.0 / scale,
             odd,
             even,
+            smooth: false,
+        }
+    }
+
+    pub fn with_smooth(mut self, smooth: bool) -> Self {
+        self.smooth = smooth;
+        self
+    }
+}
+
+/// Grid of circular dots in UV space. `scale` is the number of cells per UV unit and `radius`
+/// the dot radius as a fraction of a cell, so 0.5 makes neighbouring dots touch.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DotsTexture {
+    background: Texture,
+    dot_color: Texture,
+    scale: Real,
+    radius: Real,
+}
+
+impl DotsTexture {
+    pub fn new(background: Texture, dot_color: Texture, scale: Real, radius: Real) -> Self {
+        Self {
+            background,
+            dot_color,
+            scale,
+            radius,
+        }
+    }
+}
+
+/// Average of the square wave `(-1)^floor(x)` over a box `width` wide centered on `x`,
+/// computed from its integral, a triangle wave.
+fn filtered_square_wave(x: Real, width: Real) -> Real {
+    let integral = |t: Real| 1.0 - (1.0 - t.rem_euclid(2.0)).abs();
+    (integral(x + 0.5 * width) - integral(x - 0.5 * width)) / width
+}
+
+/// How UV coordinates outside `[0, 1]` are mapped back onto an image.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum WrapMode {
+    #[default]
+    Clamp,
+    Repeat,
+}
+
+impl WrapMode {
+    fn apply(&self, value: Real) -> Real {
+        match self {
+            WrapMode::Clamp => value.clamp(0.0, 1.0),
+            WrapMode::Repeat => value - value.floor(),
+        }
+    }
+
+    fn texel(&self, index: i64, size: u32) -> u32 {
+        match self {
+            WrapMode::Clamp => index.clamp(0, size as i64 - 1) as u32,
+            WrapMode::Repeat => index.rem_euclid(size as i64) as u32,
         }
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct ImageTexture {
-    image: image::RgbaImage,
+    /// File the image was loaded from, kept so the texture can be exported.
+    #[cfg(feature = "serde")]
+    path: Option<String>,
+    /// Encoding the file was decoded from, kept so the texture can be exported.
+    #[cfg(feature = "serde")]
+    color_space: ColorSpace,
+    /// Texels decoded to linear on load, so filtering averages actual reflectances.
+    image: image::Rgb32FImage,
+    /// Successively halved copies of `image`, down to 1x1.
+    mips: Vec<image::Rgb32FImage>,
+    wrap_mode: WrapMode,
 }
 
 impl ImageTexture {
-    pub fn new(path: &str) -> Self {
+    /// Loads the image at `path`, decoding it from `color_space`. Photographs and painted
+    /// albedos are usually sRGB, while data such as bump maps is stored linearly.
+    pub fn new(path: &str, color_space: ColorSpace) -> Self {
         let img = image::open(path)
             .expect("Failed to open image")
             .flipv()
             .into_rgba8();
-        Self { image: img }
+        let texture = Self::from_image(img, color_space);
+        #[cfg(feature = "serde")]
+        let texture = Self {
+            path: Some(path.to_string()),
+            ..texture
+        };
+        texture
+    }
+
+    /// Texture from an 8-bit image encoded in `color_space`.
+    pub fn from_image(image: image::RgbaImage, color_space: ColorSpace) -> Self {
+        let image = image::Rgb32FImage::from_fn(image.width(), image.height(), |x, y| {
+            let pixel = image.get_pixel(x, y).0;
+            let color = match color_space {
+                ColorSpace::Srgb => decode_srgb8(&pixel),
+                _ => {
+                    let channel = |i: usize| color_space.decode(pixel[i] as Real / 255.0);
+                    Vec3::new(channel(0), channel(1), channel(2))
+                }
+            };
+            image::Rgb([to_f32(color.x), to_f32(color.y), to_f32(color.z)])
+        });
+        let mut mips: Vec<image::Rgb32FImage> = Vec::new();
+        let (mut width, mut height) = image.dimensions();
+        while width > 1 || height > 1 {
+            width = u32::max(width / 2, 1);
+            height = u32::max(height / 2, 1);
+            let previous = mips.last().unwrap_or(&image);
+            mips.push(image::imageops::resize(
+                previous,
+                width,
+                height,
+                image::imageops::FilterType::Triangle,
+            ));
+        }
+        Self {
+            #[cfg(feature = "serde")]
+            path: None,
+            #[cfg(feature = "serde")]
+            color_space,
+            image,
+            mips,
+            wrap_mode: WrapMode::default(),
+        }
+    }
+
+    fn level(&self, level: usize) -> &image::Rgb32FImage {
+        if level == 0 {
+            &self.image
+        } else {
+            &self.mips[level - 1]
+        }
+    }
+
+    fn bilinear(&self, level: usize, uv: &Vec2) -> Vec3 {
+        let image = self.level(level);
+        let x = self.wrap_mode.apply(uv.x) * image.width() as Real - 0.5;
+        let y = self.wrap_mode.apply(uv.y) * image.height() as Real - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (x - x0, y - y0);
+        let texel = |dx: i64, dy: i64| {
+            let px = self.wrap_mode.texel(x0 as i64 + dx, image.width());
+            let py = self.wrap_mode.texel(y0 as i64 + dy, image.height());
+            let [r, g, b] = image.get_pixel(px, py).0;
+            Vec3::new(Real::from(r), Real::from(g), Real::from(b))
+        };
+        (1.0 - ty) * ((1.0 - tx) * texel(0, 0) + tx * texel(1, 0))
+            + ty * ((1.0 - tx) * texel(0, 1) + tx * texel(1, 1))
     }
+
+    pub fn with_wrap_mode(mut self, wrap_mode: WrapMode) -> Self {
+        self.wrap_mode = wrap_mode;
+        self
+    }
+}
+
+/// Which lattice noise a `NoiseTexture` shows.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum NoiseKind {
+    /// Turbulence of gradient (Perlin) noise.
+    #[default]
+    Gradient,
+    /// Smoothed value noise at `scale` cells per world unit.
+    Value,
 }
 
 #[derive(Debug, Clone)]
 pub struct NoiseTexture {
-    scale: f32,
+    scale: Real,
+    kind: NoiseKind,
+    /// Stops `(value, color)` the noise value is mapped through, sorted by value. Empty keeps
+    /// the noise grayscale.
+    color_ramp: Vec<(Real, Vec3)>,
     noise: PerlinNoise,
 }
 
 impl NoiseTexture {
-    pub fn new(scale: f32) -> Self {
+    pub fn new(scale: Real) -> Self {
         Self {
             scale,
+            kind: NoiseKind::default(),
+            color_ramp: Vec::new(),
             noise: PerlinNoise::new(),
         }
     }
+
+    pub fn with_kind(mut self, kind: NoiseKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    /// Colors the noise through a piecewise-linear gradient, e.g. black to red to yellow for
+    /// lava. Values outside the first and last stop take their color.
+    pub fn with_color_ramp(mut self, mut color_ramp: Vec<(Real, Vec3)>) -> Self {
+        color_ramp.sort_by(|a, b| a.0.total_cmp(&b.0));
+        self.color_ramp = color_ramp;
+        self
+    }
+
+    fn ramp_color(&self, value: Real) -> Vec3 {
+        let Some(&(first_value, first_color)) = self.color_ramp.first() else {
+            return Vec3::one() * value;
+        };
+        if value <= first_value {
+            return first_color;
+        }
+        for pair in self.color_ramp.windows(2) {
+            let ((low, low_color), (high, high_color)) = (pair[0], pair[1]);
+            if value <= high {
+                let t = (value - low) / (high - low);
+                return low_color + t * (high_color - low_color);
+            }
+        }
+        self.color_ramp[self.color_ramp.len() - 1].1
+    }
+}
+
+/// Projects one texture per axis onto the planes facing down that axis and blends them by the
+/// squared components of the normal, so surfaces without UVs, such as terrain, procedural
+/// boxes or CSG results, can be textured without stretching.
+#[derive(Debug)]
+pub struct TriplanarTexture {
+    pub x: Box<dyn TextureSampler>,
+    pub y: Box<dyn TextureSampler>,
+    pub z: Box<dyn TextureSampler>,
+    /// World units per texture repeat.
+    pub scale: Real,
+    /// Exponent on the squared normal components when blending. 1 blends by the squares
+    /// alone, which already sum to one; higher values narrow the seams where the projections
+    /// overlap.
+    pub blend_sharpness: Real,
+}
+
+impl TriplanarTexture {
+    pub fn new(
+        x: Box<dyn TextureSampler>,
+        y: Box<dyn TextureSampler>,
+        z: Box<dyn TextureSampler>,
+        scale: Real,
+    ) -> Self {
+        Self {
+            x,
+            y,
+            z,
+            scale,
+            blend_sharpness: 1.0,
+        }
+    }
+
+    pub fn with_blend_sharpness(mut self, blend_sharpness: Real) -> Self {
+        self.blend_sharpness = blend_sharpness;
+        self
+    }
+
+    /// Blend of the three projections at `p` for a surface facing `normal`.
+    fn sample(&self, p: &Vec3, normal: &Vec3) -> Vec3 {
+        let weights = Vec3::new(
+            (normal.x * normal.x).powf(self.blend_sharpness),
+            (normal.y * normal.y).powf(self.blend_sharpness),
+            (normal.z * normal.z).powf(self.blend_sharpness),
+        );
+        let total = weights.x + weights.y + weights.z;
+        if total <= 0.0 {
+            return self.y.value(&(Vec2::new(p.x, p.z) / self.scale), p);
+        }
+        (weights.x * self.x.value(&(Vec2::new(p.z, p.y) / self.scale), p)
+            + weights.y * self.y.value(&(Vec2::new(p.x, p.z) / self.scale), p)
+            + weights.z * self.z.value(&(Vec2::new(p.x, p.y) / self.scale), p))
+            / total
+    }
+}
+
+/// Serializable stand-in for a `Box<dyn TextureSampler>`.
+#[cfg(feature = "serde")]
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TextureDesc {
+    Solid {
+        #[serde(deserialize_with = "crate::color::deserialize_color")]
+        color: Vec3,
+    },
+    Checker {
+        #[serde(deserialize_with = "crate::color::deserialize_color")]
+        odd: Vec3,
+        #[serde(deserialize_with = "crate::color::deserialize_color")]
+        even: Vec3,
+        scale: Real,
+        #[serde(default)]
+        smooth: bool,
+    },
+    Dots {
+        #[serde(deserialize_with = "crate::color::deserialize_color")]
+        background: Vec3,
+        #[serde(deserialize_with = "crate::color::deserialize_color")]
+        dot_color: Vec3,
+        scale: Real,
+        radius: Real,
+    },
+    Image {
+        path: String,
+        /// Encoding of the file. Left out, it is sRGB, except in a bump map, which holds
+        /// heights rather than colors and is read linearly.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        color_space: Option<ColorSpace>,
+    },
+    Noise {
+        scale: Real,
+        #[serde(default)]
+        kind: NoiseKind,
+        #[serde(default)]
+        color_ramp: Vec<(Real, Vec3)>,
+    },
+    Triplanar {
+        x: Box<TextureDesc>,
+        y: Box<TextureDesc>,
+        z: Box<TextureDesc>,
+        scale: Real,
+        blend_sharpness: Real,
+    },
+}
+
+#[cfg(feature = "serde")]
+impl TextureDesc {
+    pub fn build(&self) -> Box<dyn TextureSampler> {
+        self.build_images_in(ColorSpace::Srgb)
+    }
+
+    /// Builds a texture holding data rather than colors, such as a bump map, so images that
+    /// do not name their encoding are read linearly.
+    pub fn build_data(&self) -> Box<dyn TextureSampler> {
+        self.build_images_in(ColorSpace::Linear)
+    }
+
+    fn build_images_in(&self, image_space: ColorSpace) -> Box<dyn TextureSampler> {
+        match self {
+            TextureDesc::Solid { color } => Box::new(Texture::new(*color)),
+            TextureDesc::Checker {
+                odd,
+                even,
+                scale,
+                smooth,
+            } => Box::new(
+                CheckerTexture::new(Texture::new(*odd), Texture::new(*even), *scale)
+                    .with_smooth(*smooth),
+            ),
+            TextureDesc::Dots {
+                background,
+                dot_color,
+                scale,
+                radius,
+            } => Box::new(DotsTexture::new(
+                Texture::new(*background),
+                Texture::new(*dot_color),
+                *scale,
+                *radius,
+            )),
+            TextureDesc::Image { path, color_space } => {
+                Box::new(ImageTexture::new(path, color_space.unwrap_or(image_space)))
+            }
+            TextureDesc::Noise {
+                scale,
+                kind,
+                color_ramp,
+            } => Box::new(
+                NoiseTexture::new(*scale)
+                    .with_kind(*kind)
+                    .with_color_ramp(color_ramp.clone()),
+            ),
+            TextureDesc::Triplanar {
+                x,
+                y,
+                z,
+                scale,
+                blend_sharpness,
+            } => Box::new(
+                TriplanarTexture::new(
+                    x.build_images_in(image_space),
+                    y.build_images_in(image_space),
+                    z.build_images_in(image_space),
+                    *scale,
+                )
+                .with_blend_sharpness(*blend_sharpness),
+            ),
+        }
+    }
 }
 
 impl TextureSampler for Texture {
     fn value(&self, _uv: &Vec2, _p: &Vec3) -> Vec3 {
         self.color
     }
+
+    #[cfg(feature = "serde")]
+    fn describe(&self) -> Option<TextureDesc> {
+        Some(TextureDesc::Solid { color: self.color })
+    }
 }
 
 impl TextureSampler for CheckerTexture {
@@ -83,6 +490,58 @@ impl TextureSampler for CheckerTexture {
             self.even.value(uv, p)
         }
     }
+
+    /// The 3D checker is a product of per-axis square waves, so its box-filtered average is
+    /// the product of the per-axis averages.
+    fn value_filtered(
+        &self,
+        uv: &Vec2,
+        p: &Vec3,
+        _uv_footprint: Real,
+        world_footprint: Real,
+    ) -> Vec3 {
+        if !self.smooth || world_footprint <= 0.0 {
+            return self.value(uv, p);
+        }
+        let width = world_footprint * self.inv_scale;
+        let sign = filtered_square_wave(self.inv_scale * p.x, width)
+            * filtered_square_wave(self.inv_scale * p.y, width)
+            * filtered_square_wave(self.inv_scale * p.z, width);
+        let even_weight = 0.5 * (1.0 + sign);
+        even_weight * self.odd.value(uv, p) + (1.0 - even_weight) * self.even.value(uv, p)
+    }
+
+    #[cfg(feature = "serde")]
+    fn describe(&self) -> Option<TextureDesc> {
+        Some(TextureDesc::Checker {
+            odd: self.odd.color,
+            even: self.even.color,
+            scale: 1.0 / self.inv_scale,
+            smooth: self.smooth,
+        })
+    }
+}
+
+impl TextureSampler for DotsTexture {
+    fn value(&self, uv: &Vec2, p: &Vec3) -> Vec3 {
+        let x = (self.scale * uv.x).rem_euclid(1.0) - 0.5;
+        let y = (self.scale * uv.y).rem_euclid(1.0) - 0.5;
+        if x * x + y * y <= self.radius * self.radius {
+            self.dot_color.value(uv, p)
+        } else {
+            self.background.value(uv, p)
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    fn describe(&self) -> Option<TextureDesc> {
+        Some(TextureDesc::Dots {
+            background: self.background.color,
+            dot_color: self.dot_color.color,
+            scale: self.scale,
+            radius: self.radius,
+        })
+    }
 }
 
 impl TextureSampler for ImageTexture {
@@ -90,27 +549,298 @@ impl TextureSampler for ImageTexture {
         if self.image.is_empty() {
             return Vec3::new(0.0, 1.0, 1.0);
         }
-        let u = uv.x.clamp(0.0, 1.0);
-        let v = uv.y.clamp(0.0, 1.0);
+        let u = self.wrap_mode.apply(uv.x);
+        let v = self.wrap_mode.apply(uv.y);
         let x =
-            ((u * self.image.width() as f32) as usize).clamp(0, self.image.width() as usize - 1);
+            ((u * self.image.width() as Real) as usize).clamp(0, self.image.width() as usize - 1);
         let y =
-            ((v * self.image.height() as f32) as usize).clamp(0, self.image.height() as usize - 1);
-
-        let pixel = &self.image.get_pixel(x as u32, y as u32).0;
-        let color_scale = 1.0 / 255.0f32;
-        Vec3::new(
-            pixel[0] as f32 * color_scale,
-            pixel[1] as f32 * color_scale,
-            pixel[2] as f32 * color_scale,
-        )
+            ((v * self.image.height() as Real) as usize).clamp(0, self.image.height() as usize - 1);
+
+        let [r, g, b] = self.image.get_pixel(x as u32, y as u32).0;
+        Vec3::new(Real::from(r), Real::from(g), Real::from(b))
+    }
+
+    /// Trilinear lookup: the mip level whose texels match the footprint, blended with the
+    /// next coarser one.
+    fn value_filtered(
+        &self,
+        uv: &Vec2,
+        p: &Vec3,
+        uv_footprint: Real,
+        _world_footprint: Real,
+    ) -> Vec3 {
+        if uv_footprint <= 0.0 || self.image.is_empty() {
+            return self.value(uv, p);
+        }
+        let texels = uv_footprint * u32::max(self.image.width(), self.image.height()) as Real;
+        let lod = Real::clamp(texels.log2(), 0.0, self.mips.len() as Real);
+        let level = lod.floor() as usize;
+        let t = lod - level as Real;
+        if level == self.mips.len() {
+            return self.bilinear(level, uv);
+        }
+        (1.0 - t) * self.bilinear(level, uv) + t * self.bilinear(level + 1, uv)
+    }
+
+    #[cfg(feature = "serde")]
+    fn describe(&self) -> Option<TextureDesc> {
+        self.path.as_ref().map(|path| TextureDesc::Image {
+            path: path.clone(),
+            color_space: Some(self.color_space),
+        })
     }
 }
 
 impl TextureSampler for NoiseTexture {
     fn value(&self, _uv: &Vec2, p: &Vec3) -> Vec3 {
-        //let noise_value = 0.5 * (self.noise.noise(*p * self.scale) + 1.0);
-        let turb_value = self.noise.turbulence(*p, 7);
-        Vec3::one() * turb_value
+        let noise_value = match self.kind {
+            NoiseKind::Gradient => {
+                //let noise_value = 0.5 * (self.noise.noise(*p * self.scale) + 1.0);
+                self.noise.turbulence(*p, 7)
+            }
+            NoiseKind::Value => self.noise.value_noise(*p * self.scale),
+        };
+        self.ramp_color(noise_value)
+    }
+
+    #[cfg(feature = "serde")]
+    fn describe(&self) -> Option<TextureDesc> {
+        Some(TextureDesc::Noise {
+            scale: self.scale,
+            kind: self.kind,
+            color_ramp: self.color_ramp.clone(),
+        })
+    }
+}
+
+impl TextureSampler for TriplanarTexture {
+    /// Without a normal to blend by, only the top-down (y) projection is shown.
+    fn value(&self, _uv: &Vec2, p: &Vec3) -> Vec3 {
+        self.sample(p, &Vec3::new(0.0, 1.0, 0.0))
+    }
+
+    fn value_ctx(&self, record: &HitRecord) -> Vec3 {
+        self.sample(&record.position, &record.normal)
+    }
+
+    #[cfg(feature = "serde")]
+    fn describe(&self) -> Option<TextureDesc> {
+        Some(TextureDesc::Triplanar {
+            x: Box::new(self.x.describe()?),
+            y: Box::new(self.y.describe()?),
+            z: Box::new(self.z.describe()?),
+            scale: self.scale,
+            blend_sharpness: self.blend_sharpness,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pixel_checker(size: u32) -> ImageTexture {
+        ImageTexture::from_image(
+            image::RgbaImage::from_fn(size, size, |x, y| {
+                if (x + y) % 2 == 0 {
+                    image::Rgba([255, 255, 255, 255])
+                } else {
+                    image::Rgba([0, 0, 0, 255])
+                }
+            }),
+            ColorSpace::Srgb,
+        )
+        .with_wrap_mode(WrapMode::Repeat)
+    }
+
+    #[test]
+    fn test_mip_chain_reaches_single_texel() {
+        let texture = pixel_checker(64);
+        assert_eq!(texture.mips.len(), 6);
+        assert_eq!(texture.mips.last().unwrap().dimensions(), (1, 1));
+    }
+
+    #[test]
+    fn test_images_decode_from_their_color_space() {
+        let gray = image::RgbaImage::from_pixel(2, 2, image::Rgba([128, 128, 128, 255]));
+        let value = |space| {
+            ImageTexture::from_image(gray.clone(), space)
+                .value(&Vec2::new(0.5, 0.5), &Vec3::zero())
+                .x
+        };
+        assert!((value(ColorSpace::Srgb) - 0.2158605).abs() < 1e-6);
+        assert!((value(ColorSpace::Linear) - 128.0 / 255.0).abs() < 1e-6);
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_bump_map_images_default_to_linear() {
+        let path = std::env::temp_dir().join("rustrt_gray_bump.png");
+        image::RgbaImage::from_pixel(2, 2, image::Rgba([128, 128, 128, 255]))
+            .save(&path)
+            .unwrap();
+        let desc = TextureDesc::Image {
+            path: path.to_str().unwrap().to_string(),
+            color_space: None,
+        };
+        let value =
+            |texture: Box<dyn TextureSampler>| texture.value(&Vec2::new(0.5, 0.5), &Vec3::zero()).x;
+        assert!((value(desc.build()) - 0.2158605).abs() < 1e-6);
+        assert!((value(desc.build_data()) - 128.0 / 255.0).abs() < 1e-6);
+        // An explicit encoding wins either way
+        let srgb = TextureDesc::Image {
+            path: path.to_str().unwrap().to_string(),
+            color_space: Some(ColorSpace::Srgb),
+        };
+        assert!((value(srgb.build_data()) - 0.2158605).abs() < 1e-6);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_wide_footprint_averages_instead_of_aliasing() {
+        let texture = pixel_checker(64);
+        let p = Vec3::zero();
+        // Neighbouring pixels of a receding floor land on arbitrary texels: point sampling
+        // flips between black and white, the filtered lookup stays near the average.
+        let point: Vec<Real> = (0..8)
+            .map(|i| {
+                texture
+                    .value(&Vec2::new(0.01 + i as Real * 0.117, 0.3), &p)
+                    .x
+            })
+            .collect();
+        assert!(point.iter().any(|&v| v < 0.1) && point.iter().any(|&v| v > 0.9));
+        for i in 0..8 {
+            let uv = Vec2::new(0.01 + i as Real * 0.117, 0.3);
+            let filtered = texture.value_filtered(&uv, &p, 0.1, 0.0).x;
+            assert!((filtered - 0.5).abs() < 0.05, "filtered value {}", filtered);
+        }
+    }
+
+    #[test]
+    fn test_smooth_checker_matches_point_sampling_up_close() {
+        let checker =
+            CheckerTexture::new(Texture::new(Vec3::one()), Texture::new(Vec3::zero()), 1.0)
+                .with_smooth(true);
+        let uv = Vec2::zero();
+        for p in [Vec3::new(0.3, 0.2, 0.7), Vec3::new(1.4, 0.6, 0.1)] {
+            let point = checker.value(&uv, &p).x;
+            let filtered = checker.value_filtered(&uv, &p, 0.0, 0.01).x;
+            assert!((point - filtered).abs() < 1e-4, "{} vs {}", point, filtered);
+        }
+    }
+
+    #[test]
+    fn test_smooth_checker_averages_distant_floor() {
+        // Points along a receding floor where each pixel covers several checker cells
+        let checker =
+            CheckerTexture::new(Texture::new(Vec3::one()), Texture::new(Vec3::zero()), 1.0);
+        let uv = Vec2::zero();
+        let points: Vec<Vec3> = (0..16)
+            .map(|i| Vec3::new(0.37 * i as Real, 0.5, 40.0 + 3.1 * i as Real))
+            .collect();
+        let point: Vec<Real> = points.iter().map(|p| checker.value(&uv, p).x).collect();
+        assert!(point.iter().any(|&v| v < 0.1) && point.iter().any(|&v| v > 0.9));
+
+        let smooth = checker.with_smooth(true);
+        for p in &points {
+            let filtered = smooth.value_filtered(&uv, p, 0.0, 6.0).x;
+            assert!((filtered - 0.5).abs() < 0.05, "filtered value {}", filtered);
+        }
+    }
+
+    #[test]
+    fn test_dots_fill_cell_centers_and_leave_corners() {
+        let background = Vec3::new(0.9, 0.9, 0.8);
+        let dot_color = Vec3::new(0.8, 0.1, 0.1);
+        let dots = DotsTexture::new(Texture::new(background), Texture::new(dot_color), 4.0, 0.3);
+        let p = Vec3::zero();
+        for (cx, cy) in [(0, 0), (1, 2), (3, 3), (-1, 5)] {
+            let center = Vec2::new((cx as Real + 0.5) / 4.0, (cy as Real + 0.5) / 4.0);
+            assert_eq!(dots.value(&center, &p), dot_color);
+            let corner = Vec2::new(cx as Real / 4.0, cy as Real / 4.0);
+            assert_eq!(dots.value(&corner, &p), background);
+        }
+        // Just inside and outside the rim along u
+        assert_eq!(
+            dots.value(&Vec2::new((0.5 + 0.29) / 4.0, 0.5 / 4.0), &p),
+            dot_color
+        );
+        assert_eq!(
+            dots.value(&Vec2::new((0.5 + 0.31) / 4.0, 0.5 / 4.0), &p),
+            background
+        );
+    }
+
+    #[test]
+    fn test_color_ramp_interpolates_between_stops() {
+        let black = Vec3::zero();
+        let red = Vec3::new(1.0, 0.0, 0.0);
+        let yellow = Vec3::new(1.0, 1.0, 0.0);
+        let lava =
+            NoiseTexture::new(1.0).with_color_ramp(vec![(1.0, yellow), (0.0, black), (0.5, red)]);
+        assert_eq!(lava.ramp_color(0.25), Vec3::new(0.5, 0.0, 0.0));
+        assert_eq!(lava.ramp_color(0.75), Vec3::new(1.0, 0.5, 0.0));
+        assert_eq!(lava.ramp_color(0.5), red);
+        // Past the ends the outer stops hold
+        assert_eq!(lava.ramp_color(-0.2), black);
+        assert_eq!(lava.ramp_color(1.3), yellow);
+        // Without a ramp the noise stays grayscale
+        assert_eq!(NoiseTexture::new(1.0).ramp_color(0.3), Vec3::one() * 0.3);
+    }
+
+    #[test]
+    fn test_triplanar_follows_the_dominant_normal_axis() {
+        let (red, green, blue) = (
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+        );
+        let triplanar = TriplanarTexture::new(
+            Box::new(Texture::new(red)),
+            Box::new(Texture::new(green)),
+            Box::new(Texture::new(blue)),
+            1.0,
+        )
+        .with_blend_sharpness(2.0);
+        let mut record = HitRecord::new();
+        record.position = Vec3::new(0.3, -1.2, 4.0);
+        for (normal, expected) in [
+            (Vec3::new(-0.9, 0.3, 0.2), red),
+            (Vec3::new(0.2, 0.95, -0.1), green),
+            (Vec3::new(0.1, -0.3, -0.9), blue),
+        ] {
+            record.normal = normal.normalize();
+            let color = triplanar.value_ctx(&record);
+            let dominant = (0..3)
+                .max_by(|&a, &b| color[a].total_cmp(&color[b]))
+                .unwrap();
+            assert_eq!(expected[dominant], 1.0, "{:?} gave {:?}", normal, color);
+            assert!(color[dominant] > 0.9, "{:?} gave {:?}", normal, color);
+        }
+        // Halfway between two axes the projections blend evenly
+        record.normal = Vec3::new(1.0, 0.0, 1.0).normalize();
+        assert!(triplanar
+            .value_ctx(&record)
+            .approx_eq(&Vec3::new(0.5, 0.0, 0.5), 1e-5));
+    }
+
+    #[test]
+    fn test_triplanar_blends_by_squared_normal() {
+        let triplanar = TriplanarTexture::new(
+            Box::new(Texture::new(Vec3::new(1.0, 0.0, 0.0))),
+            Box::new(Texture::new(Vec3::new(0.0, 1.0, 0.0))),
+            Box::new(Texture::new(Vec3::new(0.0, 0.0, 1.0))),
+            1.0,
+        );
+        let mut record = HitRecord::new();
+        record.position = Vec3::new(2.5, 0.0, -1.5);
+        // A floor facing +y shows only the top-down projection
+        record.normal = Vec3::new(0.0, 1.0, 0.0);
+        assert_eq!(triplanar.value_ctx(&record), Vec3::new(0.0, 1.0, 0.0));
+        record.normal = Vec3::new(0.6, 0.8, 0.0);
+        assert!(triplanar
+            .value_ctx(&record)
+            .approx_eq(&Vec3::new(0.36, 0.64, 0.0), 1e-5));
     }
 }
@@ -0,0 +1,244 @@
+use crate::math::consts::PI;
+use std::sync::Arc;
+
+use crate::aabb::AABB;
+use crate::camera::Camera;
+use crate::entities::{
+    constant_medium::ConstantMedium,
+    entity::{EntityList, HitRecord, Hittable},
+    quad::{create_box, Quad},
+    sphere::Sphere,
+};
+use crate::interval::Interval;
+use crate::material::{Dielectric, DiffuseSampling, Isotropic, Lambertian, Material, Metal};
+use crate::math::{rand::rand_f32_range, vec3::Vec3, Real};
+use crate::pdf::{CosinePDF, HittableListPDF, HittablePDF, SpherePDF, PDF};
+use crate::ray::Ray;
+use crate::render::{render, CancelToken};
+use crate::texture::Texture;
+
+struct Check {
+    name: String,
+    error: Real,
+    tolerance: Real,
+}
+
+impl Check {
+    fn new(name: &str, measured: Real, expected: Real, tolerance: Real) -> Self {
+        Self {
+            name: name.to_string(),
+            error: (measured - expected).abs(),
+            tolerance,
+        }
+    }
+
+    fn passed(&self) -> bool {
+        self.error <= self.tolerance
+    }
+}
+
+fn all_checks(thread_count: u32) -> Vec<Check> {
+    let mut checks = Vec::new();
+    checks.extend(furnace_checks(thread_count));
+    checks.extend(pdf_checks());
+    checks.push(aabb_check());
+    checks
+}
+
+/// Runs every analytic check, prints one line per check and returns whether all passed.
+pub fn run(thread_count: u32) -> bool {
+    let checks = all_checks(thread_count);
+    for check in &checks {
+        println!(
+            "[{}] {}: error {:.5} (tolerance {:.5})",
+            if check.passed() { "PASS" } else { "FAIL" },
+            check.name,
+            check.error,
+            check.tolerance
+        );
+    }
+    let failed = checks.iter().filter(|check| !check.passed()).count();
+    println!(
+        "{} of {} checks passed",
+        checks.len() - failed,
+        checks.len()
+    );
+    failed == 0
+}
+
+/// Furnace test: a sphere inside a uniform white environment. A material that reflects a
+/// fraction `expected` of the incoming light must come out at exactly that value.
+fn furnace_checks(thread_count: u32) -> Vec<Check> {
+    let white = || Box::new(Texture::new(Vec3::one()));
+    let cases: Vec<(&str, Arc<dyn Material>, Real, bool)> = vec![
+        (
+            "furnace lambertian",
+            Arc::new(Lambertian::new(Box::new(Texture::new(Vec3::new(
+                0.5, 0.5, 0.5,
+            ))))),
+            0.5,
+            false,
+        ),
+        (
+            "furnace lambertian uniform",
+            Arc::new(Lambertian {
+                sampling: DiffuseSampling::Uniform,
+                ..Lambertian::new(Box::new(Texture::new(Vec3::new(0.5, 0.5, 0.5))))
+            }),
+            0.5,
+            false,
+        ),
+        (
+            "furnace metal",
+            Arc::new(Metal {
+                albedo: Vec3::new(0.8, 0.8, 0.8),
+                fuzz: 0.0,
+            }),
+            0.8,
+            false,
+        ),
+        (
+            "furnace dielectric",
+            Arc::new(Dielectric {
+                refraction_index: 1.5,
+            }),
+            1.0,
+            false,
+        ),
+        (
+            "furnace isotropic medium",
+            Arc::new(Isotropic { albedo: white() }),
+            1.0,
+            true,
+        ),
+    ];
+
+    cases
+        .into_iter()
+        .map(|(name, material, expected, is_medium)| {
+            let mut entities = EntityList::new();
+            if is_medium {
+                let boundary = Sphere::new(Vec3::zero(), 1.0, Arc::clone(&material));
+                entities.add(Box::new(ConstantMedium::new(
+                    Box::new(boundary),
+                    2.0,
+                    material,
+                )));
+            } else {
+                entities.add(Box::new(Sphere::new(Vec3::zero(), 1.0, material)));
+            }
+            let measured = furnace_radiance(thread_count, &entities);
+            Check::new(name, measured, expected, 0.05)
+        })
+        .collect()
+}
+
+fn furnace_radiance(thread_count: u32, entities: &EntityList) -> Real {
+    const SIZE: u32 = 8;
+    // A narrow field of view keeps every pixel on the unit sphere
+    let mut camera = Camera::new(SIZE, SIZE, 10.0, &Vec3::new(0.0, 0.0, 4.0), &Vec3::zero());
+    camera.set_background_color(&Vec3::one());
+    camera.set_samples_per_pixel(256);
+    camera.seed = 0x5EED;
+
+    let output = render(
+        thread_count,
+        (SIZE, SIZE),
+        entities,
+        &camera,
+        &CancelToken::new(),
+        |_| 256,
+    );
+    let total: Real = output.colors.iter().map(|color| color.luminance()).sum();
+    total / output.colors.len() as Real
+}
+
+/// Every PDF must integrate to one over the directions it can generate.
+fn pdf_checks() -> Vec<Check> {
+    let light = Quad::new(
+        Vec3::new(-1.0, 1.0, -1.0),
+        Vec3::new(2.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, 2.0),
+        Arc::new(Lambertian::new(Box::new(Texture::new(Vec3::one())))),
+    );
+    let second_light = Quad::new(
+        Vec3::new(2.0, -0.5, -0.5),
+        Vec3::new(0.0, 1.0, 0.0),
+        Vec3::new(0.0, 0.0, 1.0),
+        Arc::clone(&light.material),
+    );
+    let lights: Vec<Arc<dyn Hittable>> = vec![Arc::new(light.clone()), Arc::new(second_light)];
+    let pdfs: Vec<(&str, Box<dyn PDF>)> = vec![
+        ("pdf sphere", Box::new(SpherePDF)),
+        (
+            "pdf cosine",
+            Box::new(CosinePDF::new(&Vec3::new(0.3, 1.0, -0.2))),
+        ),
+        (
+            "pdf hittable quad",
+            Box::new(HittablePDF::new(Vec3::zero(), Box::new(light))),
+        ),
+        (
+            "pdf two quad lights",
+            Box::new(HittableListPDF::new(Vec3::zero(), &lights)),
+        ),
+    ];
+
+    const SAMPLES: u32 = 400_000;
+    pdfs.into_iter()
+        .map(|(name, pdf)| {
+            let sum: f64 = (0..SAMPLES)
+                .map(|_| pdf.value(&Vec3::random_unit().normalize()) as f64)
+                .sum();
+            let integral = 4.0 * PI as f64 * sum / SAMPLES as f64;
+            Check::new(name, integral as Real, 1.0, 0.02)
+        })
+        .collect()
+}
+
+/// Compares the slab test against intersecting the six faces of the same box.
+fn aabb_check() -> Check {
+    const RAYS: u32 = 20_000;
+    let material: Arc<dyn Material> =
+        Arc::new(Lambertian::new(Box::new(Texture::new(Vec3::one()))));
+    let mut mismatches = 0;
+    for _ in 0..RAYS {
+        let a = Vec3::random_range(-2.0, 2.0);
+        let b = Vec3::random_range(-2.0, 2.0);
+        let faces = create_box(a, b, Arc::clone(&material));
+        let bbox = AABB::construct(a, b);
+
+        let origin = Vec3::random_range(-5.0, 5.0);
+        let ray = Ray::new(origin, Vec3::random_unit());
+        let t_interval = Interval::new(0.001, rand_f32_range(1.0, 10.0));
+        let mut record = HitRecord::new();
+        let brute_force = faces.hit(&ray, &t_interval, &mut record);
+        // A ray starting inside the box only meets a face on the way out
+        let inside =
+            bbox.x.contains(origin.x) && bbox.y.contains(origin.y) && bbox.z.contains(origin.z);
+        if bbox.hit(&ray, t_interval) != (brute_force || inside) {
+            mismatches += 1;
+        }
+    }
+    Check::new(
+        "aabb hit vs box faces",
+        mismatches as Real / RAYS as Real,
+        0.0,
+        0.001,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_every_check_passes() {
+        let failed: Vec<String> = all_checks(4)
+            .into_iter()
+            .filter(|check| !check.passed())
+            .map(|check| format!("{} (error {})", check.name, check.error))
+            .collect();
+        assert!(failed.is_empty(), "failed checks: {:?}", failed);
+    }
+}
@@ -31,6 +31,8 @@ pub struct Win32BackBuffer {
 pub struct Window {
     pub handle: HWND,
     pub shutdown_requested: bool,
+    /// Image pixel under the last left click, until the caller takes it.
+    pub clicked: Option<(u32, u32)>,
     pub buffer: Win32BackBuffer,
     pub dim: WindowDim,
 }
@@ -51,6 +53,7 @@ impl Window {
             let mut window = Box::new(Window {
                 handle: HWND::default(),
                 shutdown_requested: false,
+                clicked: None,
                 buffer: Win32BackBuffer::default(),
                 dim: WindowDim::default(),
             });
@@ -114,6 +117,13 @@ impl Window {
                 (*this).dim = Window::get_client_dimensions(window);
                 LRESULT(0)
             }
+            WM_LBUTTONDOWN => {
+                let this = GetWindowLongPtrA(window, GWLP_USERDATA) as *mut Self;
+                let x = (lparam.0 & 0xffff) as i16 as i32;
+                let y = ((lparam.0 >> 16) & 0xffff) as i16 as i32;
+                (*this).clicked = (*this).client_to_image(x, y);
+                LRESULT(0)
+            }
             WM_CLOSE => {
                 let this = GetWindowLongPtrA(window, GWLP_USERDATA) as *mut Self;
                 (*this).shutdown_requested = true;
@@ -123,6 +133,22 @@ impl Window {
         }
     }
 
+    /// Maps a client-area position to the image pixel stretched over it.
+    fn client_to_image(&self, x: i32, y: i32) -> Option<(u32, u32)> {
+        let header = &self.buffer.info.bmiHeader;
+        let (width, height) = (header.biWidth, -header.biHeight);
+        if self.dim.width <= 0 || self.dim.height <= 0 {
+            return None;
+        }
+        let image_x = x * width / self.dim.width;
+        let image_y = y * height / self.dim.height;
+        if (0..width).contains(&image_x) && (0..height).contains(&image_y) {
+            Some((image_x as u32, image_y as u32))
+        } else {
+            None
+        }
+    }
+
     pub fn display(&self) {
         unsafe {
             let device_ctx = GetDC(Some(self.handle));
@@ -153,7 +179,7 @@ impl Window {
         if result == 0 {
             let error_code = GetLastError();
             if error_code.is_err() {
-                eprintln!("StretchDIBits failed with error code: {}", error_code.0);
+                log::error!("StretchDIBits failed with error code: {}", error_code.0);
             }
         }
     }